@@ -178,6 +178,22 @@ pub fn contains_duplicates<T: Ord>(vec: &Vec<T>) -> bool {
     false
 }
 
+// The smallest key that's missing from one map or has a different value in the other.
+pub fn first_difference<'a, K: Ord, V: PartialEq>(
+    map1: &'a BTreeMap<K, V>,
+    map2: &'a BTreeMap<K, V>,
+) -> Option<&'a K> {
+    let k1 = map1
+        .iter()
+        .find(|(k, v)| map2.get(k) != Some(v))
+        .map(|(k, _)| k);
+    let k2 = map2.keys().find(|k| !map1.contains_key(k));
+    match (k1, k2) {
+        (Some(k1), Some(k2)) => Some(k1.min(k2)),
+        (k1, k2) => k1.or(k2),
+    }
+}
+
 // Use when your key is just PartialEq, not Ord or Hash.
 pub struct VecMap<K, V> {
     inner: Vec<(K, V)>,
@@ -112,6 +112,14 @@ impl App {
                     "- bus_passengers_waiting: {} bytes",
                     prettyprint_usize(serialized_size_bytes(&a.bus_passengers_waiting))
                 );
+                println!(
+                    "- bus_loads: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.bus_loads))
+                );
+                println!(
+                    "- bus_pass_ups: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.bus_pass_ups))
+                );
                 println!(
                     "- started_trips: {} bytes",
                     prettyprint_usize(serialized_size_bytes(&a.started_trips))
@@ -290,10 +290,9 @@ pub fn prebake_all() {
     let mut timer = Timer::new("prebake all challenge results");
 
     {
-        let map = map_model::Map::new(abstutil::path_map("montlake"), &mut timer);
-        let scenario: Scenario =
-            abstutil::read_binary(abstutil::path_scenario("montlake", "weekday"), &mut timer);
-        prebake(&map, scenario, None, &mut timer);
+        let mut map = map_model::Map::new(abstutil::path_map("montlake"), &mut timer);
+        let scenario = Scenario::load(abstutil::path_scenario("montlake", "weekday"), &mut timer);
+        prebake(&mut map, scenario, None, &mut timer);
 
         for generator in TutorialState::scenarios_to_prebake(&map) {
             let scenario = generator.generate(
@@ -301,15 +300,14 @@ pub fn prebake_all() {
                 &mut SimFlags::for_test("prebaked").make_rng(),
                 &mut timer,
             );
-            prebake(&map, scenario, None, &mut timer);
+            prebake(&mut map, scenario, None, &mut timer);
         }
     }
 
     for name in vec!["lakeslice"] {
-        let map = map_model::Map::new(abstutil::path_map(name), &mut timer);
-        let scenario: Scenario =
-            abstutil::read_binary(abstutil::path_scenario(name, "weekday"), &mut timer);
-        prebake(&map, scenario, None, &mut timer);
+        let mut map = map_model::Map::new(abstutil::path_map(name), &mut timer);
+        let scenario = Scenario::load(abstutil::path_scenario(name, "weekday"), &mut timer);
+        prebake(&mut map, scenario, None, &mut timer);
     }
 }
 
@@ -329,7 +327,7 @@ pub fn generic_prebake_all() {
     }
     for (map_path, list) in per_map {
         timer.start(format!("prebake for {}", map_path));
-        let map = map_model::Map::new(map_path.clone(), &mut timer);
+        let mut map = map_model::Map::new(map_path.clone(), &mut timer);
 
         let mut done_scenarios = HashSet::new();
         for challenge in list {
@@ -345,7 +343,7 @@ pub fn generic_prebake_all() {
                 }
                 done_scenarios.insert(scenario.scenario_name.clone());
 
-                prebake(&map, scenario, None, &mut timer);
+                prebake(&mut map, scenario, None, &mut timer);
             }
         }
         // TODO A weird hack to glue up tutorial scenarios.
@@ -356,7 +354,7 @@ pub fn generic_prebake_all() {
                     &mut SimFlags::for_test("prebaked").make_rng(),
                     &mut timer,
                 );
-                prebake(&map, scenario, None, &mut timer);
+                prebake(&mut map, scenario, None, &mut timer);
             }
         }
 
@@ -364,7 +362,7 @@ pub fn generic_prebake_all() {
     }
 }
 
-fn prebake(map: &Map, scenario: Scenario, time_limit: Option<Duration>, timer: &mut Timer) {
+fn prebake(map: &mut Map, scenario: Scenario, time_limit: Option<Duration>, timer: &mut Timer) {
     timer.start(format!(
         "prebake for {} / {}",
         scenario.map_name, scenario.scenario_name
@@ -372,19 +370,19 @@ fn prebake(map: &Map, scenario: Scenario, time_limit: Option<Duration>, timer: &
 
     let mut opts = SimOptions::new("prebaked");
     opts.alerts = AlertHandler::Silence;
-    let mut sim = Sim::new(&map, opts, timer);
+    let mut sim = Sim::new(map, opts, timer);
     // Bit of an abuse of this, but just need to fix the rng seed.
     let mut rng = SimFlags::for_test("prebaked").make_rng();
-    scenario.instantiate(&mut sim, &map, &mut rng, timer);
-    if let Some(dt) = time_limit {
-        sim.timed_step(&map, dt, &mut None, timer);
+    scenario.instantiate(&mut sim, map, &mut rng, timer);
+    let end_time = if let Some(dt) = time_limit {
+        Time::START_OF_DAY + dt
     } else {
-        sim.timed_step(
-            &map,
-            sim.get_end_of_day() - Time::START_OF_DAY,
-            &mut None,
-            timer,
-        );
+        sim.get_end_of_day()
+    };
+    sim.timed_step_with_edits(map, end_time - sim.time(), &mut None, timer);
+    if let Some(edits) = sim.remove_temporary_edits(map) {
+        map.apply_edits(edits, timer);
+        map.recalculate_pathfinding_after_edits(timer);
     }
 
     abstutil::write_binary(
@@ -101,6 +101,7 @@ pub struct ColorScheme {
     pub turn_arrow: Color,
     pub brake_light: Color,
     pub bus_body: Color,
+    pub train_body: Color,
     pub bus_label: Color,
     pub ped_head: Color,
     pub ped_foot: Color,
@@ -206,6 +207,7 @@ impl ColorScheme {
             turn_arrow: hex("#DF8C3D"),
             brake_light: hex("#FF1300"),
             bus_body: Color::rgb(50, 133, 117),
+            train_body: hex("#42B6E9"),
             bus_label: Color::rgb(249, 206, 24),
             ped_head: Color::rgb(139, 69, 19),
             ped_foot: Color::BLACK,
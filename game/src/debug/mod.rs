@@ -58,6 +58,7 @@ impl DebugMode {
                             (hotkey(Key::Slash), "search OSM metadata"),
                             (lctrl(Key::Slash), "clear OSM search results"),
                             (hotkey(Key::O), "save sim state"),
+                            (None, "save sim state with analytics"),
                             (hotkey(Key::Y), "load previous sim state"),
                             (hotkey(Key::U), "load next sim state"),
                             (None, "pick a savestate to load"),
@@ -129,6 +130,13 @@ impl State for DebugMode {
                         timer.stop("save sim state");
                     });
                 }
+                "save sim state with analytics" => {
+                    ctx.loading_screen("savestate", |_, timer| {
+                        timer.start("save sim state with analytics");
+                        app.primary.sim.save_with_analytics();
+                        timer.stop("save sim state with analytics");
+                    });
+                }
                 "load previous sim state" => {
                     if let Some(t) =
                         ctx.loading_screen("load previous savestate", |ctx, mut timer| {
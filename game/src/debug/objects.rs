@@ -56,6 +56,7 @@ impl ObjectDebugger {
                         PathConstraints::Car,
                         PathConstraints::Bike,
                         PathConstraints::Bus,
+                        PathConstraints::Train,
                     ] {
                         if constraint.can_use(l, map) {
                             println!(
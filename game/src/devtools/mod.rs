@@ -16,6 +16,7 @@ use ezgui::{
     VerticalAlignment, Widget, Wizard,
 };
 use geom::LonLat;
+use sim::Scenario;
 
 pub struct DevToolsMode {
     composite: Composite,
@@ -119,7 +120,7 @@ fn load_scenario(wiz: &mut Wizard, ctx: &mut EventCtx, app: &mut App) -> Option<
     let s = wiz.wrap(ctx).choose_string("Load which scenario?", || {
         abstutil::list_all_objects(abstutil::path_all_scenarios(&map_name))
     })?;
-    let scenario = abstutil::read_binary(
+    let scenario = Scenario::load(
         abstutil::path_scenario(&map_name, &s),
         &mut Timer::throwaway(),
     );
@@ -256,7 +256,9 @@ fn can_change_lane_type(l: LaneID, new_lt: LaneType, map: &Map) -> Option<String
     }
 
     // Don't let players orphan a bus stop.
-    if !r.all_bus_stops(map).is_empty()
+    if r.all_bus_stops(map)
+        .into_iter()
+        .any(|bs| !map.get_l(map.get_bs(bs).driving_pos.lane()).is_light_rail())
         && !proposed_lts
             .iter()
             .any(|lt| *lt == LaneType::Driving || *lt == LaneType::Bus)
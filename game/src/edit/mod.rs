@@ -25,7 +25,7 @@ use ezgui::{
 use geom::Speed;
 use map_model::{
     connectivity, EditCmd, EditIntersection, IntersectionID, LaneID, LaneType, MapEdits,
-    PathConstraints, PermanentMapEdits,
+    PathConstraints, PermanentMapEdits, RoadID,
 };
 use sim::DontDrawAgents;
 use std::collections::BTreeSet;
@@ -51,6 +51,8 @@ impl EditMode {
     pub fn new(ctx: &mut EventCtx, app: &mut App, mode: GameplayMode) -> EditMode {
         let orig_dirty = app.primary.dirty_from_edits;
         assert!(app.suspended_sim.is_none());
+        // The player shouldn't see or change these. If the simulation resumes, they come back.
+        remove_temporary_edits(ctx, app);
         app.suspended_sim = Some(app.primary.clear_sim());
         let edits = app.primary.map.get_edits();
         let layer = crate::layer::map::Static::edits(ctx, app);
@@ -84,6 +86,20 @@ impl EditMode {
         }
 
         ctx.loading_screen("apply edits", move |ctx, mut timer| {
+            // To patch up the old simulation, we need to know what changed since it was
+            // suspended. Going back to the original edits, then applying the new ones again
+            // figures that out.
+            let live = app.opts.resume_after_edit && !self.mode.reset_after_edits();
+            let mut changes = None;
+            if live {
+                let new_edits = app.primary.map.get_edits().clone();
+                app.primary
+                    .map
+                    .apply_edits(self.orig_edits.clone(), &mut timer);
+                let (roads, _, _, intersections) =
+                    app.primary.map.apply_edits(new_edits, &mut timer);
+                changes = Some((roads, intersections));
+            }
             app.primary
                 .map
                 .recalculate_pathfinding_after_edits(&mut timer);
@@ -96,7 +112,10 @@ impl EditMode {
                         TimeWarpScreen::new(ctx, app, old_sim.time(), false),
                     )
                 } else {
-                    app.primary.sim = old_sim;
+                    let (roads, intersections) = changes.unwrap();
+                    let mut sim = old_sim;
+                    sim.handle_live_edits(&app.primary.map, &roads, &intersections, &mut timer);
+                    app.primary.sim = sim;
                     app.primary.dirty_from_edits = true;
                     Transition::Pop
                 }
@@ -411,9 +430,51 @@ fn make_topcenter(ctx: &mut EventCtx, app: &App, mode: &GameplayMode) -> Composi
 
 pub fn apply_map_edits(ctx: &mut EventCtx, app: &mut App, edits: MapEdits) {
     let mut timer = Timer::new("apply map edits");
+    redraw_edits(ctx, app, edits, &mut timer);
+
+    // Autosave
+    if app.primary.map.get_edits().edits_name != "untitled edits" {
+        app.primary.map.save_edits();
+    }
+}
+
+// Temporary edits from the scenario come and go as the simulation runs. They aren't the player's,
+// so don't autosave them.
+pub fn apply_temporary_edits(ctx: &mut EventCtx, app: &mut App) {
+    if let Some(edits) = app.primary.sim.update_temporary_edits(&app.primary.map) {
+        let mut timer = Timer::new("apply temporary edits");
+        let (roads, intersections) = redraw_edits(ctx, app, edits, &mut timer);
+        app.primary
+            .map
+            .recalculate_pathfinding_after_edits(&mut timer);
+        app.primary
+            .sim
+            .handle_live_edits(&app.primary.map, &roads, &intersections, &mut timer);
+    }
+}
 
-    let (roads_changed, turns_deleted, turns_added, mut modified_intersections) =
-        app.primary.map.apply_edits(edits, &mut timer);
+// Before resetting the simulation or editing the map, take away temporary edits from the scenario.
+pub fn remove_temporary_edits(ctx: &mut EventCtx, app: &mut App) {
+    if let Some(edits) = app.primary.sim.remove_temporary_edits(&app.primary.map) {
+        let mut timer = Timer::new("remove temporary edits");
+        redraw_edits(ctx, app, edits, &mut timer);
+        app.primary
+            .map
+            .recalculate_pathfinding_after_edits(&mut timer);
+    }
+}
+
+// Returns the roads and intersections that changed.
+fn redraw_edits(
+    ctx: &mut EventCtx,
+    app: &mut App,
+    edits: MapEdits,
+    timer: &mut Timer,
+) -> (BTreeSet<RoadID>, BTreeSet<IntersectionID>) {
+    let (roads_changed, turns_deleted, turns_added, changed_intersections) =
+        app.primary.map.apply_edits(edits, timer);
+    let result = (roads_changed.clone(), changed_intersections.clone());
+    let mut modified_intersections = changed_intersections;
 
     for r in roads_changed {
         let road = app.primary.map.get_r(r);
@@ -429,7 +490,7 @@ pub fn apply_map_edits(ctx: &mut EventCtx, app: &mut App, edits: MapEdits) {
                 &app.primary.map,
                 app.primary.current_flags.draw_lane_markings,
                 &app.cs,
-                &mut timer,
+                timer,
             )
             .finish(ctx.prerender, &app.cs, lane);
         }
@@ -451,7 +512,7 @@ pub fn apply_map_edits(ctx: &mut EventCtx, app: &mut App, edits: MapEdits) {
             &app.primary.map,
             &app.cs,
             ctx.prerender,
-            &mut timer,
+            timer,
         );
     }
 
@@ -459,10 +520,7 @@ pub fn apply_map_edits(ctx: &mut EventCtx, app: &mut App, edits: MapEdits) {
         app.layer = Some(Box::new(crate::layer::map::Static::edits(ctx, app)));
     }
 
-    // Autosave
-    if app.primary.map.get_edits().edits_name != "untitled edits" {
-        app.primary.map.save_edits();
-    }
+    result
 }
 
 pub fn can_edit_lane(mode: &GameplayMode, l: LaneID, app: &App) -> bool {
@@ -14,8 +14,8 @@ use ezgui::{
 };
 use geom::{ArrowCap, Distance, Duration};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Phase,
-    TurnGroupID, TurnPriority,
+    Actuated, ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID,
+    PedestrianTiming, Phase, TurnGroupID, TurnPriority,
 };
 use std::collections::BTreeSet;
 
@@ -504,6 +504,12 @@ fn edit_entire_signal(app: &App, i: IntersectionID, mode: GameplayMode) -> Box<d
         .iter()
         .any(|t| t.between_sidewalks());
     let current_offset = app.primary.map.get_traffic_signal(i).offset;
+    let current_ped_timing = app
+        .primary
+        .map
+        .get_traffic_signal(i)
+        .pedestrian_timing
+        .clone();
 
     WizardState::new(Box::new(move |wiz, ctx, app| {
         let use_template = "use template";
@@ -511,6 +517,7 @@ fn edit_entire_signal(app: &App, i: IntersectionID, mode: GameplayMode) -> Box<d
         let stop_sign = "convert to stop signs";
         let close = "close intersection for construction";
         let offset = "edit signal offset";
+        let ped_timing = "edit pedestrian timing";
         let reset = "reset to default";
 
         let mut choices = vec![use_template];
@@ -523,6 +530,9 @@ fn edit_entire_signal(app: &App, i: IntersectionID, mode: GameplayMode) -> Box<d
             choices.push(close);
         }
         choices.push(offset);
+        if has_sidewalks {
+            choices.push(ped_timing);
+        }
         choices.push(reset);
 
         let mut wizard = wiz.wrap(ctx);
@@ -593,6 +603,45 @@ fn edit_entire_signal(app: &App, i: IntersectionID, mode: GameplayMode) -> Box<d
                     editor.change_phase(editor.current_phase, ctx, app);
                 })))
             }
+            x if x == ped_timing => {
+                let leading_interval = wizard.input_usize_prefilled(
+                    "How long should pedestrians get a head start before conflicting vehicles \
+                     (seconds)?",
+                    format!(
+                        "{}",
+                        current_ped_timing.leading_interval.inner_seconds() as usize
+                    ),
+                )?;
+                let flashing_dont_walk = wizard.input_usize_prefilled(
+                    "How long before the end of each phase should pedestrians stop starting to \
+                     cross (seconds)?",
+                    format!(
+                        "{}",
+                        current_ped_timing.flashing_dont_walk.inner_seconds() as usize
+                    ),
+                )?;
+                let scramble_clearance = wizard.input_usize_prefilled(
+                    "After an all-walk phase, how long should vehicles wait (seconds)?",
+                    format!(
+                        "{}",
+                        current_ped_timing.scramble_clearance.inner_seconds() as usize
+                    ),
+                )?;
+                Some(Transition::PopWithData(Box::new(move |state, ctx, app| {
+                    let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
+                    let mut signal = app.primary.map.get_traffic_signal(editor.i).clone();
+                    editor.command_stack.push(signal.clone());
+                    editor.redo_stack.clear();
+                    editor.top_panel = make_top_panel(ctx, app, true, false);
+                    signal.pedestrian_timing = PedestrianTiming {
+                        leading_interval: Duration::seconds(leading_interval as f64),
+                        flashing_dont_walk: Duration::seconds(flashing_dont_walk as f64),
+                        scramble_clearance: Duration::seconds(scramble_clearance as f64),
+                    };
+                    change_traffic_signal(signal, ctx, app);
+                    editor.change_phase(editor.current_phase, ctx, app);
+                })))
+            }
             x if x == reset => {
                 Some(Transition::PopWithData(Box::new(move |state, ctx, app| {
                     let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
@@ -620,24 +669,77 @@ fn edit_entire_signal(app: &App, i: IntersectionID, mode: GameplayMode) -> Box<d
 }
 
 fn change_duration(app: &App, i: IntersectionID, idx: usize) -> Box<dyn State> {
-    let current_duration = app.primary.map.get_traffic_signal(i).phases[idx].duration;
+    let current = app.primary.map.get_traffic_signal(i).phases[idx].clone();
 
     WizardState::new(Box::new(move |wiz, ctx, _| {
-        let new_duration = wiz.wrap(ctx).input_something(
-            "How long should this phase be (seconds)?",
-            Some(format!("{}", current_duration.inner_seconds() as usize)),
-            Box::new(|line| {
-                line.parse::<usize>()
-                    .ok()
-                    .and_then(|n| if n != 0 { Some(n) } else { None })
-            }),
+        let fixed = "fixed duration";
+        let actuated = "actuated: extend while vehicles keep arriving";
+        let was_actuated = current.actuated.is_some();
+        let mut wizard = wiz.wrap(ctx);
+        let choice = wizard.choose_string("What kind of phase is this?", move || {
+            if was_actuated {
+                vec![actuated, fixed]
+            } else {
+                vec![fixed, actuated]
+            }
+        })?;
+
+        let new_actuated = choice == actuated;
+        let new_duration = wizard.input_something(
+            if new_actuated {
+                "What's the minimum length of this phase (seconds)?"
+            } else {
+                "How long should this phase be (seconds)?"
+            },
+            Some(format!("{}", current.duration.inner_seconds() as usize)),
+            Box::new(parse_seconds),
         )?;
+        let new_actuated = if new_actuated {
+            let defaults = current.actuated.clone().unwrap_or_else(|| Actuated {
+                max_duration: current.duration * 2.0,
+                gap: Duration::seconds(3.0),
+                skip_if_no_demand: false,
+            });
+            let max_duration = wizard.input_something(
+                "What's the maximum length of this phase (seconds)?",
+                Some(format!(
+                    "{}",
+                    defaults.max_duration.inner_seconds() as usize
+                )),
+                Box::new(parse_seconds),
+            )?;
+            let gap = wizard.input_something(
+                "End the phase after nobody's started a turn for how long (seconds)?",
+                Some(format!("{}", defaults.gap.inner_seconds() as usize)),
+                Box::new(parse_seconds),
+            )?;
+            let skip_default = defaults.skip_if_no_demand;
+            let skip = wizard.choose_string(
+                "Skip this phase when nobody's waiting for it?",
+                move || {
+                    if skip_default {
+                        vec!["yes", "no"]
+                    } else {
+                        vec!["no", "yes"]
+                    }
+                },
+            )?;
+            Some(Actuated {
+                max_duration: Duration::seconds(max_duration.max(new_duration) as f64),
+                gap: Duration::seconds(gap as f64),
+                skip_if_no_demand: skip == "yes",
+            })
+        } else {
+            None
+        };
+
         Some(Transition::PopWithData(Box::new(move |state, ctx, app| {
             let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
             let orig_signal = app.primary.map.get_traffic_signal(editor.i);
 
             let mut new_signal = orig_signal.clone();
             new_signal.phases[idx].duration = Duration::seconds(new_duration as f64);
+            new_signal.phases[idx].actuated = new_actuated;
             editor.command_stack.push(orig_signal.clone());
             editor.redo_stack.clear();
             editor.top_panel = make_top_panel(ctx, app, true, false);
@@ -647,6 +749,12 @@ fn change_duration(app: &App, i: IntersectionID, idx: usize) -> Box<dyn State> {
     }))
 }
 
+fn parse_seconds(line: String) -> Option<usize> {
+    line.parse::<usize>()
+        .ok()
+        .and_then(|n| if n != 0 { Some(n) } else { None })
+}
+
 fn check_for_missing_groups(
     mut signal: ControlTrafficSignal,
     composite: &mut Composite,
@@ -718,8 +826,8 @@ fn make_previewer(i: IntersectionID, phase: usize) -> Box<dyn State> {
                 for idx in 0..phase {
                     step += signal.phases[idx].duration;
                 }
-                app.primary.sim.timed_step(
-                    &app.primary.map,
+                app.primary.sim.timed_step_with_edits(
+                    &mut app.primary.map,
                     step,
                     &mut app.primary.sim_cb,
                     &mut Timer::throwaway(),
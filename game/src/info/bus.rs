@@ -34,8 +34,8 @@ pub fn stop(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID)
 
         let arrivals: Vec<(Time, CarID)> = all_arrivals
             .iter()
-            .filter(|(_, _, route, stop)| r.id == *route && id == *stop)
-            .map(|(t, car, _, _)| (*t, *car))
+            .filter(|(_, _, route, stop, _)| r.id == *route && id == *stop)
+            .map(|(t, car, _, _, _)| (*t, *car))
             .collect();
         let mut txt = Text::new();
         if let Some((t, _)) = arrivals.last() {
@@ -52,15 +52,39 @@ pub fn stop(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID)
         {
             txt.add(Line(format!("  Waiting: {}", hgram.describe())).secondary());
         }
+        if let Some((early, on_time, late)) = sim
+            .get_analytics()
+            .schedule_adherence(sim.time(), r.id)
+            .remove(&id)
+        {
+            txt.add(
+                Line(format!(
+                    "  {}% on time ({} early, {} late)",
+                    (100.0 * (on_time as f64) / ((early + on_time + late) as f64)).round(),
+                    early,
+                    late
+                ))
+                .secondary(),
+            );
+        }
+        if let Some(n) = sim
+            .get_analytics()
+            .bus_pass_ups(sim.time(), r.id)
+            .remove(&id)
+        {
+            txt.add(Line(format!("  Passed up by full buses {} times", n)).secondary());
+        }
         rows.push(txt.draw(ctx));
     }
 
     rows
 }
 
-// TODO For now, this conflates a single bus with the whole route, but that's fine, since the sim
-// only spawns one per route anyway.
+// TODO For now, this conflates a single bus with the whole route.
 pub fn bus_status(ctx: &mut EventCtx, app: &App, details: &mut Details, id: CarID) -> Vec<Widget> {
+    if app.primary.sim.bus_route_id(id).is_none() {
+        return finished_run(ctx, id);
+    }
     let mut rows = bus_header(ctx, app, details, id, Tab::BusStatus(id));
 
     let kv = app.primary.sim.bus_properties(id, &app.primary.map);
@@ -68,17 +92,32 @@ pub fn bus_status(ctx: &mut EventCtx, app: &App, details: &mut Details, id: CarI
 
     let route = app.primary.sim.bus_route_id(id).unwrap();
     rows.push(passenger_delay(ctx, app, details, route));
+    rows.push(crowding(ctx, app, route));
 
     rows
 }
 
 pub fn bus_delays(ctx: &mut EventCtx, app: &App, details: &mut Details, id: CarID) -> Vec<Widget> {
+    if app.primary.sim.bus_route_id(id).is_none() {
+        return finished_run(ctx, id);
+    }
     let mut rows = bus_header(ctx, app, details, id, Tab::BusDelays(id));
     let route = app.primary.sim.bus_route_id(id).unwrap();
     rows.push(delays_over_time(ctx, app, route));
     rows
 }
 
+// Buses following a timetable vanish after their last stop.
+fn finished_run(ctx: &mut EventCtx, id: CarID) -> Vec<Widget> {
+    vec![
+        Widget::row(vec![
+            Line(format!("{}", id)).small_heading().draw(ctx),
+            header_btns(ctx),
+        ]),
+        "This bus finished its run".draw_text(ctx),
+    ]
+}
+
 fn bus_header(
     ctx: &mut EventCtx,
     app: &App,
@@ -149,6 +188,45 @@ fn delays_over_time(ctx: &mut EventCtx, app: &App, id: BusRouteID) -> Widget {
     ])
 }
 
+fn crowding(ctx: &mut EventCtx, app: &App, id: BusRouteID) -> Widget {
+    let route = app.primary.map.get_br(id);
+    let sim = &app.primary.sim;
+    let mut loads = sim.get_analytics().bus_loads(sim.time(), id);
+    let mut pass_ups = sim.get_analytics().bus_pass_ups(sim.time(), id);
+
+    let heading = match sim.bus_capacity(id) {
+        Some(n) => format!("Crowding (capacity {})", n),
+        None => "Crowding".to_string(),
+    };
+    let mut col = vec![Line(heading).small_heading().draw(ctx)];
+    for idx1 in 0..route.stops.len() {
+        // Vehicles following a timetable don't run every segment
+        if !route.continues_after(idx1) {
+            continue;
+        }
+        let idx2 = if idx1 == route.stops.len() - 1 {
+            0
+        } else {
+            idx1 + 1
+        };
+        let mut txt = Text::from(Line(format!("Stop {}->{}: ", idx1 + 1, idx2 + 1)));
+        if let Some(hgram) = loads.remove(&route.stops[idx1]) {
+            txt.append(Line(format!(
+                "avg {}, max {}",
+                hgram.select(Statistic::Mean),
+                hgram.select(Statistic::Max)
+            )));
+        } else {
+            txt.append(Line("no buses yet").secondary());
+        }
+        if let Some(n) = pass_ups.remove(&route.stops[idx1]) {
+            txt.append(Line(format!(", {} passed up", n)));
+        }
+        col.push(txt.draw(ctx));
+    }
+    Widget::col(col)
+}
+
 fn passenger_delay(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRouteID) -> Widget {
     let route = app.primary.map.get_br(id);
     let mut master_col = vec![Line("Passengers waiting").small_heading().draw(ctx)];
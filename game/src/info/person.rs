@@ -515,7 +515,7 @@ fn header(
                         VehicleType::Bike => {
                             ("biking", Some("../data/system/assets/meters/bike.svg"))
                         }
-                        VehicleType::Bus => unreachable!(),
+                        VehicleType::Bus | VehicleType::Train => unreachable!(),
                     },
                     AgentID::BusPassenger(_, _) => {
                         ("riding a bus", Some("../data/system/assets/meters/bus.svg"))
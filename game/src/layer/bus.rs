@@ -82,24 +82,23 @@ impl ShowBusRoute {
         }
 
         let mut colorer = ColorDiscrete::new(app, vec![("route", app.cs.unzoomed_bus)]);
-        for (stop1, stop2) in
-            route
-                .stops
-                .iter()
-                .zip(route.stops.iter().skip(1))
-                .chain(std::iter::once((
-                    route.stops.last().unwrap(),
-                    &route.stops[0],
-                )))
-        {
-            let bs1 = map.get_bs(*stop1);
-            let bs2 = map.get_bs(*stop2);
+        // Vehicles following a timetable don't loop from the last stop back to the first, or
+        // between the two directions.
+        for idx1 in 0..route.stops.len() {
+            if !route.continues_after(idx1) {
+                continue;
+            }
+            let bs1 = map.get_bs(route.stops[idx1]);
+            let bs2 = map.get_bs(route.stops[(idx1 + 1) % route.stops.len()]);
             for step in map
-                .pathfind(PathRequest {
-                    start: bs1.driving_pos,
-                    end: bs2.driving_pos,
-                    constraints: PathConstraints::Bus,
-                })
+                .pathfind(
+                    PathRequest {
+                        start: bs1.driving_pos,
+                        end: bs2.driving_pos,
+                        constraints: route.route_type,
+                    },
+                    app.primary.sim.time(),
+                )
                 .unwrap()
                 .get_steps()
             {
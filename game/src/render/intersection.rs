@@ -145,8 +145,11 @@ impl Renderable for DrawIntersection {
                 .map(|(t, _)| *t != app.primary.sim.time())
                 .unwrap_or(true);
             if recalc {
-                let (idx, phase, t) =
-                    signal.current_phase_and_remaining_time(app.primary.sim.time());
+                let (idx, t) = app
+                    .primary
+                    .sim
+                    .current_phase_and_remaining_time(self.id, &app.primary.map);
+                let phase = &signal.phases[idx];
                 let mut batch = GeomBatch::new();
                 draw_signal_phase(
                     g.prerender,
@@ -508,7 +508,7 @@ impl AgentColorScheme {
         let category = match agent.vehicle_type {
             Some(VehicleType::Car) => "Car".to_string(),
             Some(VehicleType::Bike) => "Bike".to_string(),
-            Some(VehicleType::Bus) => "Bus".to_string(),
+            Some(VehicleType::Bus) | Some(VehicleType::Train) => "Bus".to_string(),
             None => "Pedestrian".to_string(),
         };
         for (name, color, enabled) in &self.rows {
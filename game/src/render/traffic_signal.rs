@@ -379,7 +379,7 @@ pub fn make_signal_diagram(
         let phase_col = if edit_mode {
             Widget::col(vec![
                 Widget::row(vec![
-                    Line(format!("Phase {}: {}", idx + 1, phase.duration))
+                    Line(format!("Phase {}: {}", idx + 1, describe_timing(phase)))
                         .small_heading()
                         .draw(ctx)
                         .margin_right(10),
@@ -426,7 +426,7 @@ pub fn make_signal_diagram(
             ])
         } else {
             Widget::col(vec![
-                format!("Phase {}: {}", idx + 1, phase.duration).draw_text(ctx),
+                format!("Phase {}: {}", idx + 1, describe_timing(phase)).draw_text(ctx),
                 phase_btn,
             ])
         }
@@ -461,3 +461,19 @@ pub fn make_signal_diagram(
         .exact_size_percent(30, 85)
         .build(ctx)
 }
+
+fn describe_timing(phase: &Phase) -> String {
+    match phase.actuated {
+        Some(ref a) => format!(
+            "{} to {} (actuated{})",
+            phase.duration,
+            a.max_duration,
+            if a.skip_if_no_demand {
+                ", skipped with no demand"
+            } else {
+                ""
+            }
+        ),
+        None => phase.duration.to_string(),
+    }
+}
@@ -1,7 +1,7 @@
 use crate::app::{App, ShowEverything};
 use crate::common::{CityPicker, CommonState};
 use crate::edit::EditMode;
-use crate::game::{State, Transition, WizardState};
+use crate::game::{msg, State, Transition, WizardState};
 use crate::helpers::{nice_map_name, ID};
 use crate::sandbox::gameplay::{GameplayMode, GameplayState};
 use crate::sandbox::SandboxControls;
@@ -251,18 +251,26 @@ impl State for AgentSpawner {
                     let mut scenario = Scenario::empty(map, "one-shot");
                     let from = self.source.take().unwrap();
                     let to = self.goal.take().unwrap().0;
+                    let mode: TripMode = self.composite.dropdown_value("mode");
+                    let trip = match SpawnTrip::new(from, to, mode, map) {
+                        Some(trip) => trip,
+                        None => {
+                            return Transition::Replace(msg(
+                                "Can't spawn these trips",
+                                vec![format!(
+                                    "The start and end don't work for trips that {}",
+                                    mode.verb()
+                                )],
+                            ));
+                        }
+                    };
                     for i in 0..self.composite.spinner("number") {
                         scenario.people.push(PersonSpec {
                             id: PersonID(app.primary.sim.get_all_people().len() + i),
                             orig_id: None,
                             trips: vec![IndividTrip {
                                 depart: app.primary.sim.time(),
-                                trip: SpawnTrip::new(
-                                    from.clone(),
-                                    to.clone(),
-                                    self.composite.dropdown_value("mode"),
-                                    map,
-                                ),
+                                trip: trip.clone(),
                                 cancelled: false,
                             }],
                         });
@@ -292,7 +300,7 @@ impl State for AgentSpawner {
                 self.composite.dropdown_value("mode"),
                 &app.primary.map,
             )
-            .and_then(|req| app.primary.map.pathfind(req))
+            .and_then(|req| app.primary.map.pathfind(req, app.primary.sim.time()))
             {
                 self.goal = Some((
                     to,
@@ -364,7 +372,7 @@ impl State for AgentSpawner {
                         self.composite.dropdown_value("mode"),
                         &app.primary.map,
                     )
-                    .and_then(|req| app.primary.map.pathfind(req))
+                    .and_then(|req| app.primary.map.pathfind(req, app.primary.sim.time()))
                     {
                         self.goal = Some((
                             hovering,
@@ -116,7 +116,7 @@ impl GameplayMode {
             .generate(map, &mut rng, &mut Timer::new("generate scenario"))
         } else {
             let path = abstutil::path_scenario(map.get_name(), &name);
-            let mut scenario = match abstutil::maybe_read_binary(path.clone(), timer) {
+            let mut scenario = match Scenario::maybe_load(path.clone(), timer) {
                 Ok(s) => s,
                 Err(err) => {
                     println!("\n\n{} is missing or corrupt. Check https://github.com/dabreegster/abstreet/blob/master/docs/dev.md and file an issue if you have trouble.", path);
@@ -126,7 +126,7 @@ impl GameplayMode {
             };
             if let GameplayMode::PlayScenario(_, _, ref modifiers) = self {
                 for m in modifiers {
-                    scenario = m.apply(scenario, &mut rng);
+                    scenario = m.apply(map, scenario, &mut rng);
                 }
             }
             scenario
@@ -7,11 +7,11 @@ use crate::sandbox::gameplay::freeform::make_change_traffic;
 use crate::sandbox::gameplay::{GameplayMode, GameplayState};
 use crate::sandbox::{SandboxControls, SandboxMode};
 use ezgui::{
-    hotkey, lctrl, Btn, Color, Composite, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key,
-    Line, Outcome, Text, TextExt, VerticalAlignment, Widget,
+    hotkey, lctrl, Btn, Choice, Color, Composite, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment,
+    Key, Line, Outcome, Text, TextExt, VerticalAlignment, Widget,
 };
-use geom::Polygon;
-use sim::ScenarioModifier;
+use geom::{Duration, LonLat, Polygon};
+use sim::{ScenarioModifier, TripMode};
 
 pub struct PlayScenario {
     top_center: Composite,
@@ -260,7 +260,15 @@ fn new_modifier(scenario_name: String, modifiers: Vec<ScenarioModifier>) -> Box<
         let mut wizard = wiz.wrap(ctx);
         let new_mod = match wizard
             .choose_string("", || {
-                vec!["repeat days", "cancel all trips for some people"]
+                vec![
+                    "repeat days",
+                    "cancel all trips for some people",
+                    "scale demand",
+                    "shift departure times",
+                    "change trip modes",
+                    "restrict to an area",
+                    "change transit capacity",
+                ]
             })?
             .as_str()
         {
@@ -270,6 +278,56 @@ fn new_modifier(scenario_name: String, modifiers: Vec<ScenarioModifier>) -> Box<
             x if x == "cancel all trips for some people" => ScenarioModifier::CancelPeople(
                 wizard.input_percent("What percent of people should cancel trips? (0 to 100)")?,
             ),
+            x if x == "scale demand" => ScenarioModifier::ScaleDemand(wizard.input_usize(
+                "Scale demand to what percent? (100 keeps everybody, 200 doubles it)",
+            )?),
+            x if x == "shift departure times" => ScenarioModifier::ShiftDepartures {
+                mean: Duration::f64_minutes(wizard.input_something(
+                    "Shift trips by how many minutes on average? (negative is earlier)",
+                    None,
+                    Box::new(|line| line.parse::<f64>().ok()),
+                )?),
+                std_dev: Duration::minutes(
+                    wizard.input_usize("With what standard deviation, in minutes?")?,
+                ),
+            },
+            x if x == "change trip modes" => {
+                let pct =
+                    wizard.input_percent("What percent of people should change? (0 to 100)")?;
+                let (_, from) = wizard.choose("Change trips of which mode?", choose_mode)?;
+                let (_, to) = wizard.choose("To which mode?", choose_mode)?;
+                ScenarioModifier::ChangeMode { pct, from, to }
+            }
+            x if x == "restrict to an area" => {
+                let dir = format!("../data/input/{}/polygons", app.primary.map.get_city_name());
+                let name = wizard
+                    .choose_string("Only keep people with trips in which area?", || {
+                        abstutil::list_all_objects(dir.clone())
+                    })?;
+                match LonLat::read_osmosis_polygon(format!("{}/{}.poly", dir, name)) {
+                    Ok(pts) => ScenarioModifier::RestrictToArea { name, pts },
+                    Err(err) => {
+                        println!("Bad polygon {}: {}", name, err);
+                        return Some(Transition::Pop);
+                    }
+                }
+            }
+            x if x == "change transit capacity" => {
+                let route = wizard.choose_string("Change the capacity of which route?", || {
+                    let mut names: Vec<String> = app
+                        .primary
+                        .map
+                        .get_all_bus_routes()
+                        .iter()
+                        .map(|r| r.name.clone())
+                        .collect();
+                    names.sort();
+                    names.dedup();
+                    names
+                })?;
+                let capacity = wizard.input_usize("How many riders fit on each vehicle?")?;
+                ScenarioModifier::ChangeTransitCapacity { route, capacity }
+            }
             _ => unreachable!(),
         };
         let mut mods = modifiers.clone();
@@ -282,3 +340,10 @@ fn new_modifier(scenario_name: String, modifiers: Vec<ScenarioModifier>) -> Box<
         )))
     }))
 }
+
+fn choose_mode() -> Vec<Choice<TripMode>> {
+    TripMode::all()
+        .into_iter()
+        .map(|m| Choice::new(m.ongoing_verb(), m))
+        .collect()
+}
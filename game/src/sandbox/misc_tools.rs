@@ -70,11 +70,10 @@ pub struct ShowTrafficSignal {
 
 impl ShowTrafficSignal {
     pub fn new(ctx: &mut EventCtx, app: &App, i: IntersectionID) -> Box<dyn State> {
-        let (idx, _, _) = app
+        let (idx, _) = app
             .primary
-            .map
-            .get_traffic_signal(i)
-            .current_phase_and_remaining_time(app.primary.sim.time());
+            .sim
+            .current_phase_and_remaining_time(i, &app.primary.map);
         return Box::new(ShowTrafficSignal {
             i,
             composite: make_signal_diagram(ctx, app, i, idx, false),
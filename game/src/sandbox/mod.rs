@@ -9,8 +9,8 @@ use crate::app::App;
 use crate::common::{tool_panel, CommonState, ContextualActions, Minimap};
 use crate::debug::DebugMode;
 use crate::edit::{
-    apply_map_edits, can_edit_lane, save_edits_as, EditMode, LaneEditor, StopSignEditor,
-    TrafficSignalEditor,
+    apply_map_edits, can_edit_lane, remove_temporary_edits, save_edits_as, EditMode, LaneEditor,
+    StopSignEditor, TrafficSignalEditor,
 };
 use crate::game::{State, Transition, WizardState};
 use crate::helpers::ID;
@@ -48,6 +48,7 @@ pub struct SandboxControls {
 
 impl SandboxMode {
     pub fn new(ctx: &mut EventCtx, app: &mut App, mode: GameplayMode) -> SandboxMode {
+        remove_temporary_edits(ctx, app);
         app.primary.clear_sim();
         let gameplay = mode.initialize(ctx, app);
 
@@ -402,7 +403,7 @@ impl ContextualActions for Actions {
                     }
                 }
                 ID::Car(c) => {
-                    if c.1 == VehicleType::Bus {
+                    if c.1 == VehicleType::Bus || c.1 == VehicleType::Train {
                         // TODO Hide the button if the layer is open
                         actions.push((Key::R, "show route".to_string()));
                     }
@@ -445,11 +446,9 @@ impl ContextualActions for Actions {
             ),
             (ID::Car(c), "show route") => {
                 *close_panel = false;
-                app.layer = Some(Box::new(crate::layer::bus::ShowBusRoute::new(
-                    ctx,
-                    app,
-                    app.primary.sim.bus_route_id(c).unwrap(),
-                )));
+                if let Some(r) = app.primary.sim.bus_route_id(c) {
+                    app.layer = Some(Box::new(crate::layer::bus::ShowBusRoute::new(ctx, app, r)));
+                }
                 Transition::Keep
             }
             (_, "follow (run the simulation)") => {
@@ -1,5 +1,6 @@
 use crate::app::{App, FindDelayedIntersections};
 use crate::common::Warping;
+use crate::edit::apply_temporary_edits;
 use crate::game::{msg, State, Transition};
 use crate::helpers::ID;
 use crate::sandbox::{GameplayMode, SandboxMode};
@@ -191,6 +192,7 @@ impl SpeedControls {
                         app.primary
                             .sim
                             .tiny_step(&app.primary.map, &mut app.primary.sim_cb);
+                        apply_temporary_edits(ctx, app);
                         app.recalculate_current_selection(ctx);
                         return Some(Transition::KeepWithMouseover);
                     }
@@ -268,6 +270,7 @@ impl SpeedControls {
                     Duration::seconds(0.033),
                     &mut app.primary.sim_cb,
                 );
+                apply_temporary_edits(ctx, app);
                 app.recalculate_current_selection(ctx);
             }
         }
@@ -530,6 +533,7 @@ impl State for TimeWarpScreen {
                 Duration::seconds(0.033),
                 &mut app.primary.sim_cb,
             );
+            apply_temporary_edits(ctx, app);
             for (t, maybe_i, alert) in app.primary.sim.clear_alerts() {
                 // TODO Just the first :(
                 return Transition::Replace(msg(
@@ -58,6 +58,14 @@ impl Duration {
         self.0
     }
 
+    pub fn abs(self) -> Duration {
+        if self.0 > 0.0 {
+            self
+        } else {
+            Duration(-self.0)
+        }
+    }
+
     // TODO Could share some of this with Time -- the representations are the same
     // (hours, minutes, seconds, centiseconds)
     fn get_parts(self) -> (usize, usize, usize, usize) {
@@ -1,3 +1,4 @@
+mod acceleration;
 mod angle;
 mod bounds;
 mod circle;
@@ -14,6 +15,7 @@ mod speed;
 mod stats;
 mod time;
 
+pub use crate::acceleration::Acceleration;
 pub use crate::angle::Angle;
 pub use crate::bounds::{Bounds, GPSBounds};
 pub use crate::circle::Circle;
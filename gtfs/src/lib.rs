@@ -1,39 +1,51 @@
-use geom::LonLat;
-use itertools::Itertools;
+use geom::{Duration, LonLat, Time};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeSet, HashMap};
-use transitfeed::GTFSIterator;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Route {
     pub name: String,
     pub stops: Vec<LonLat>,
+    // How long after leaving the first stop a bus is scheduled to reach each stop. Same length as
+    // stops.
+    pub stop_offsets: Vec<Duration>,
+    // When buses leave the first stop on a typical weekday, sorted. The timetable repeats every
+    // day, so these are all within one day. Empty if the feed doesn't schedule any trips this way.
+    pub departures: Vec<Time>,
+    // For routes going both ways, stops from this index on are the way back. Buses leaving at
+    // departures only run to the stop before this one.
+    pub return_stop: Option<usize>,
+    // When buses leave stops[return_stop] and run to the end of the route. Same rules as
+    // departures.
+    pub return_departures: Vec<Time>,
+    // Trams, streetcars, and light rail run on tracks instead of roads
+    pub is_light_rail: bool,
 }
 
 pub fn load(dir_path: &str) -> Vec<Route> {
     println!("Loading GTFS from {}", dir_path);
 
     let mut route_id_to_name: HashMap<String, String> = HashMap::new();
-    for rec in GTFSIterator::<_, transitfeed::Route>::from_path(&format!("{}/routes.txt", dir_path))
-        .unwrap()
-    {
-        let rec = rec.unwrap();
-        route_id_to_name.insert(rec.route_id.clone(), rec.route_short_name.clone());
+    // The routes run by trams or light rail (route_type 0), or by subway or metro (1), which also
+    // usually show up on light rail tracks in OSM.
+    let mut rail_routes: HashSet<String> = HashSet::new();
+    for rec in read_required::<GTFSRoute>(dir_path, "routes.txt") {
+        if rec.route_type == 0 || rec.route_type == 1 {
+            rail_routes.insert(rec.route_id.clone());
+        }
+        route_id_to_name.insert(rec.route_id, rec.route_short_name);
     }
 
     let mut stop_id_to_pt: HashMap<String, LonLat> = HashMap::new();
-    for rec in
-        GTFSIterator::<_, transitfeed::Stop>::from_path(&format!("{}/stops.txt", dir_path)).unwrap()
-    {
-        let rec = rec.unwrap();
-        stop_id_to_pt.insert(rec.stop_id.clone(), LonLat::new(rec.stop_lon, rec.stop_lat));
+    for rec in read_required::<Stop>(dir_path, "stops.txt") {
+        stop_id_to_pt.insert(rec.stop_id, LonLat::new(rec.stop_lon, rec.stop_lat));
     }
 
+    let active_services = load_calendar(dir_path);
     let mut trip_id_to_route_id_and_direction: HashMap<String, (String, bool)> = HashMap::new();
-    for rec in
-        GTFSIterator::<_, transitfeed::Trip>::from_path(&format!("{}/trips.txt", dir_path)).unwrap()
-    {
-        let rec = rec.unwrap();
+    let mut active_trips: HashSet<String> = HashSet::new();
+    for rec in read_required::<Trip>(dir_path, "trips.txt") {
         trip_id_to_route_id_and_direction.insert(
             rec.trip_id.clone(),
             (
@@ -41,50 +53,389 @@ pub fn load(dir_path: &str) -> Vec<Route> {
                 rec.direction_id.map(|d| d == "0").unwrap_or(true),
             ),
         );
+        if active_services
+            .as_ref()
+            .map(|services| services.contains(&rec.service_id))
+            .unwrap_or(true)
+        {
+            active_trips.insert(rec.trip_id.clone());
+        }
     }
 
-    // Each (directed) route has many trips. Ignore all but the first and assume the list of stops
-    // is the same. Also assume that records with the same trip are contiguous and that
-    // stop_sequence is monotonic.
-    let mut directed_routes: HashMap<(String, bool), Vec<LonLat>> = HashMap::new();
-    for (key, group) in
-        GTFSIterator::<_, transitfeed::StopTime>::from_path(&format!("{}/stop_times.txt", dir_path))
-            .unwrap()
-            .map(|rec| rec.unwrap())
-            // TODO This only groups records with consecutive same trip ID. Might be a bug.
-            .group_by(|rec| rec.trip_id.clone())
-            .into_iter()
-    {
-        let (route_id, forwards) = trip_id_to_route_id_and_direction[&key].clone();
-        if directed_routes.contains_key(&(route_id.clone(), forwards)) {
+    let frequencies = load_frequencies(dir_path);
+
+    // Each (directed) route has many trips. Take the list of stops and the time between them from
+    // the first, and assume the rest follow the same pattern. Every active trip contributes
+    // departures.
+    let mut directed_routes: HashMap<(String, bool), (Vec<LonLat>, Vec<Duration>)> = HashMap::new();
+    let mut directed_departures: HashMap<(String, bool), Vec<Duration>> = HashMap::new();
+    for (trip_id, times) in load_stop_times(dir_path) {
+        let key = match trip_id_to_route_id_and_direction.get(&trip_id) {
+            Some(key) => key.clone(),
+            None => {
+                println!("Skipping stop times for unknown trip {}", trip_id);
+                continue;
+            }
+        };
+        let first_departure = times.iter().find_map(|(_, t)| *t);
+        if !directed_routes.contains_key(&key) {
+            directed_routes.insert(
+                key.clone(),
+                (
+                    times.iter().map(|(stop, _)| stop_id_to_pt[stop]).collect(),
+                    interpolate_offsets(times.iter().map(|(_, t)| *t).collect()),
+                ),
+            );
+        }
+        if !active_trips.contains(&trip_id) {
             continue;
         }
-        directed_routes.insert(
-            (route_id, forwards),
-            group.map(|rec| stop_id_to_pt[&rec.stop_id]).collect(),
-        );
+        let departures = directed_departures.entry(key).or_insert_with(Vec::new);
+        if let Some(list) = frequencies.get(&trip_id) {
+            for (start, end, headway) in list {
+                let mut t = *start;
+                while t < *end {
+                    departures.push(t);
+                    t += *headway;
+                }
+            }
+        } else if let Some(t) = first_departure {
+            departures.push(t);
+        }
     }
 
-    // Group together the pairs of directed routes
+    // Group together the pairs of directed routes. Without a timetable, a bus runs the forwards
+    // direction, then immediately turns around and runs the backwards direction. With one, each
+    // direction has its own departures.
     let route_ids: BTreeSet<String> = directed_routes
         .keys()
         .map(|(id, _)| id.to_string())
         .collect();
     let mut results = Vec::new();
     for route_id in route_ids {
-        let mut stops = directed_routes
-            .remove(&(route_id.clone(), true))
-            .unwrap_or_else(Vec::new);
-        if let Some(more_stops) = directed_routes.remove(&(route_id.clone(), false)) {
-            stops.extend(more_stops);
-        }
+        let forwards = (route_id.clone(), true);
+        let backwards = (route_id.clone(), false);
+        let mut departures =
+            |key: &(String, bool)| daily(directed_departures.remove(key).unwrap_or_else(Vec::new));
+        let forward_departures = departures(&forwards);
+        let backward_departures = departures(&backwards);
+        let (stops, stop_offsets, departures, return_stop, return_departures) = match (
+            directed_routes.remove(&forwards),
+            directed_routes.remove(&backwards),
+        ) {
+            (Some((mut stops, mut stop_offsets)), Some((more_stops, more_offsets))) => {
+                let turnaround = stop_offsets.last().cloned().unwrap_or(Duration::ZERO);
+                let return_stop = stops.len();
+                stops.extend(more_stops);
+                stop_offsets.extend(more_offsets.into_iter().map(|dt| turnaround + dt));
+                (
+                    stops,
+                    stop_offsets,
+                    forward_departures,
+                    Some(return_stop),
+                    backward_departures,
+                )
+            }
+            (Some((stops, stop_offsets)), None) => {
+                (stops, stop_offsets, forward_departures, None, Vec::new())
+            }
+            (None, Some((stops, stop_offsets))) => {
+                (stops, stop_offsets, backward_departures, None, Vec::new())
+            }
+            (None, None) => unreachable!(),
+        };
         assert!(!stops.is_empty());
         results.push(Route {
             name: route_id_to_name[&route_id].to_string(),
             stops,
+            stop_offsets,
+            departures,
+            return_stop,
+            return_departures,
+            is_light_rail: rail_routes.contains(&route_id),
         });
     }
     assert!(directed_routes.is_empty());
 
     results
 }
+
+// The columns of each GTFS file that matter here
+
+#[derive(Deserialize)]
+struct GTFSRoute {
+    route_id: String,
+    route_short_name: String,
+    route_type: usize,
+}
+
+#[derive(Deserialize)]
+struct Stop {
+    stop_id: String,
+    stop_lat: f64,
+    stop_lon: f64,
+}
+
+#[derive(Deserialize)]
+struct Trip {
+    trip_id: String,
+    route_id: String,
+    service_id: String,
+    direction_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StopTime {
+    trip_id: String,
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: usize,
+}
+
+#[derive(Deserialize)]
+struct Frequency {
+    trip_id: String,
+    start_time: String,
+    end_time: String,
+    headway_secs: usize,
+}
+
+#[derive(Deserialize)]
+struct Calendar {
+    service_id: String,
+    wednesday: usize,
+}
+
+// Every row of a GTFS file, or None if the file is missing. Rows that don't parse are skipped.
+fn read_optional<T: DeserializeOwned>(dir_path: &str, file: &str) -> Option<Vec<T>> {
+    let path = format!("{}/{}", dir_path, file);
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(&path)
+        .ok()?;
+    let mut results = Vec::new();
+    for rec in reader.deserialize() {
+        match rec {
+            Ok(rec) => results.push(rec),
+            Err(err) => println!("Skipping bad row in {}: {}", path, err),
+        }
+    }
+    Some(results)
+}
+
+fn read_required<T: DeserializeOwned>(dir_path: &str, file: &str) -> Vec<T> {
+    read_optional(dir_path, file).unwrap_or_else(|| panic!("Can't read {}/{}", dir_path, file))
+}
+
+// Per trip, in the order they first appear, the stops and departure times (since midnight) in
+// order. Not every stop has a time.
+fn load_stop_times(dir_path: &str) -> Vec<(String, Vec<(String, Option<Duration>)>)> {
+    let mut order: Vec<String> = Vec::new();
+    let mut per_trip: HashMap<String, Vec<(usize, String, Option<Duration>)>> = HashMap::new();
+    for rec in read_required::<StopTime>(dir_path, "stop_times.txt") {
+        let time = if rec.departure_time.is_empty() {
+            None
+        } else {
+            match parse_time(&rec.departure_time) {
+                Ok(t) => Some(t),
+                Err(err) => {
+                    println!("Skipping stop time for {}: {}", rec.trip_id, err);
+                    continue;
+                }
+            }
+        };
+        if !per_trip.contains_key(&rec.trip_id) {
+            order.push(rec.trip_id.clone());
+        }
+        per_trip.entry(rec.trip_id).or_insert_with(Vec::new).push((
+            rec.stop_sequence,
+            rec.stop_id,
+            time,
+        ));
+    }
+    order
+        .into_iter()
+        .map(|trip_id| {
+            let mut times = per_trip.remove(&trip_id).unwrap();
+            times.sort_by_key(|(seq, _, _)| *seq);
+            (
+                trip_id,
+                times.into_iter().map(|(_, stop, t)| (stop, t)).collect(),
+            )
+        })
+        .collect()
+}
+
+// Trips that repeat through the day: (start, end, headway). frequencies.txt is optional.
+fn load_frequencies(dir_path: &str) -> HashMap<String, Vec<(Duration, Duration, Duration)>> {
+    let mut results = HashMap::new();
+    for rec in read_optional::<Frequency>(dir_path, "frequencies.txt").unwrap_or_else(Vec::new) {
+        if rec.headway_secs == 0 {
+            println!("Skipping frequency for {} with no headway", rec.trip_id);
+            continue;
+        }
+        let (start, end) = match (parse_time(&rec.start_time), parse_time(&rec.end_time)) {
+            (Ok(start), Ok(end)) => (start, end),
+            (Err(err), _) | (_, Err(err)) => {
+                println!("Skipping frequency for {}: {}", rec.trip_id, err);
+                continue;
+            }
+        };
+        results.entry(rec.trip_id).or_insert_with(Vec::new).push((
+            start,
+            end,
+            Duration::seconds(rec.headway_secs as f64),
+        ));
+    }
+    results
+}
+
+// The services running on a typical weekday. None means every trip runs, either because
+// calendar.txt is missing or because nothing runs on Wednesdays.
+fn load_calendar(dir_path: &str) -> Option<HashSet<String>> {
+    let mut services = HashSet::new();
+    for rec in read_optional::<Calendar>(dir_path, "calendar.txt")? {
+        if rec.wednesday == 1 {
+            services.insert(rec.service_id);
+        }
+    }
+    if services.is_empty() {
+        println!(
+            "{}/calendar.txt doesn't run anything on Wednesdays, using all trips",
+            dir_path
+        );
+        return None;
+    }
+    Some(services)
+}
+
+// The timetable repeats every day, so a bus leaving at 25:30:00 also leaves at 01:30:00 every day.
+// Returns sorted times within one day.
+fn daily(times: Vec<Duration>) -> Vec<Time> {
+    let mut results: Vec<Time> = times
+        .into_iter()
+        .map(|t| Time::START_OF_DAY + t % Duration::hours(24))
+        .collect();
+    results.sort();
+    results.dedup();
+    results
+}
+
+// GTFS times look like 25:30:00 and can go past midnight.
+fn parse_time(x: &str) -> Result<Duration, String> {
+    let parts = x
+        .trim()
+        .split(':')
+        .map(|part| part.parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| format!("Bad GTFS time {}", x))?;
+    if parts.len() != 3 {
+        return Err(format!("Bad GTFS time {}", x));
+    }
+    Ok(Duration::seconds(
+        (parts[0] * 3600 + parts[1] * 60 + parts[2]) as f64,
+    ))
+}
+
+// Times relative to the first stop. Stops without a time are interpolated between the
+// surrounding stops that have one.
+fn interpolate_offsets(times: Vec<Option<Duration>>) -> Vec<Duration> {
+    let known: Vec<(usize, Duration)> = times
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, t)| t.map(|t| (idx, t)))
+        .collect();
+    if known.is_empty() {
+        return vec![Duration::ZERO; times.len()];
+    }
+    let start = known[0].1;
+    (0..times.len())
+        .map(|idx| {
+            let t = match known.iter().position(|(i, _)| *i >= idx) {
+                None => known.last().unwrap().1,
+                Some(0) => known[0].1,
+                Some(next) => {
+                    let (i1, t1) = known[next - 1];
+                    let (i2, t2) = known[next];
+                    t1 + (t2 - t1) * (((idx - i1) as f64) / ((i2 - i1) as f64))
+                }
+            };
+            t - start
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hms(h: usize, m: usize, s: usize) -> Duration {
+        Duration::hours(h) + Duration::minutes(m) + Duration::seconds(s as f64)
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("00:00:00"), Ok(Duration::ZERO));
+        assert_eq!(parse_time("07:05:09"), Ok(hms(7, 5, 9)));
+        // Some feeds don't pad the hour
+        assert_eq!(parse_time(" 7:05:09"), Ok(hms(7, 5, 9)));
+        // Trips starting late in the service day go past midnight
+        assert_eq!(parse_time("24:00:00"), Ok(Duration::hours(24)));
+        assert_eq!(parse_time("25:30:00"), Ok(hms(25, 30, 0)));
+
+        assert!(parse_time("07:05").is_err());
+        assert!(parse_time("7am").is_err());
+        assert!(parse_time("07::09").is_err());
+    }
+
+    #[test]
+    fn test_daily() {
+        assert_eq!(
+            daily(vec![
+                hms(25, 30, 0),
+                hms(7, 0, 0),
+                hms(1, 30, 0),
+                Duration::hours(24)
+            ]),
+            vec![
+                Time::START_OF_DAY,
+                Time::START_OF_DAY + hms(1, 30, 0),
+                Time::START_OF_DAY + hms(7, 0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interpolate_offsets() {
+        let min = |m| Some(Duration::minutes(m));
+        assert_eq!(
+            interpolate_offsets(vec![min(10), min(12), min(20)]),
+            vec![Duration::ZERO, Duration::minutes(2), Duration::minutes(10)]
+        );
+        // Stops without times are spread out evenly
+        assert_eq!(
+            interpolate_offsets(vec![min(10), None, None, min(16)]),
+            vec![
+                Duration::ZERO,
+                Duration::minutes(2),
+                Duration::minutes(4),
+                Duration::minutes(6)
+            ]
+        );
+        // Before the first time and after the last, the nearest time is used
+        assert_eq!(
+            interpolate_offsets(vec![None, min(5), None, min(9), None]),
+            vec![
+                Duration::ZERO,
+                Duration::ZERO,
+                Duration::minutes(2),
+                Duration::minutes(4),
+                Duration::minutes(4)
+            ]
+        );
+        assert_eq!(
+            interpolate_offsets(vec![None, None]),
+            vec![Duration::ZERO, Duration::ZERO]
+        );
+    }
+}
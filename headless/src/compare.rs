@@ -0,0 +1,197 @@
+use crate::report::write_report;
+use abstutil::prettyprint_usize;
+use geom::{Duration, Time};
+use map_model::{IntersectionID, Map};
+use serde::Serialize;
+use sim::{Sim, TripID, TripMode};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Compares a run with map edits against a baseline run of the same scenario without edits.
+#[derive(Serialize)]
+pub struct Comparison {
+    pub map_name: String,
+    pub scenario_name: String,
+    pub edits_name: String,
+    pub rng_seed: u64,
+    pub end_time: Time,
+
+    // Only trips that finished in both runs
+    pub trips: Vec<TripDelta>,
+    // None means all modes
+    pub summaries: Vec<(Option<TripMode>, ModeSummary)>,
+    // Only intersections where the total delay changed, sorted by the biggest change first.
+    // Negative means less delay with the edits.
+    pub intersections: Vec<(IntersectionID, Duration)>,
+}
+
+#[derive(Serialize)]
+pub struct TripDelta {
+    pub trip: TripID,
+    pub mode: TripMode,
+    pub before: Duration,
+    pub after: Duration,
+    // Negative means faster with the edits
+    pub delta: Duration,
+}
+
+#[derive(Serialize)]
+pub struct ModeSummary {
+    pub count: usize,
+    pub num_faster: usize,
+    pub num_slower: usize,
+    pub num_same: usize,
+    pub total_before: Duration,
+    pub total_after: Duration,
+    pub delta_p10: Duration,
+    pub delta_p50: Duration,
+    pub delta_p90: Duration,
+}
+
+impl Comparison {
+    pub fn new(
+        map: &Map,
+        after: &Sim,
+        before: &Sim,
+        scenario_name: &str,
+        rng_seed: u64,
+    ) -> Comparison {
+        // The runs might end at different times if they're run until done. Don't cut off either.
+        let now = after.time().max(before.time());
+
+        let trips: Vec<TripDelta> = after
+            .get_analytics()
+            .both_finished_trips(now, before.get_analytics())
+            .into_iter()
+            .map(|(trip, before, after, mode)| TripDelta {
+                trip,
+                mode,
+                before,
+                after,
+                delta: after - before,
+            })
+            .collect();
+
+        let mut summaries = Vec::new();
+        if let Some(summary) = ModeSummary::new(trips.iter().collect()) {
+            summaries.push((None, summary));
+        }
+        for mode in TripMode::all() {
+            if let Some(summary) =
+                ModeSummary::new(trips.iter().filter(|t| t.mode == mode).collect())
+            {
+                summaries.push((Some(mode), summary));
+            }
+        }
+
+        let mut intersections = after
+            .get_analytics()
+            .compare_delay(now, before.get_analytics());
+        intersections.sort_by_key(|(i, dt)| (std::cmp::Reverse(dt.abs()), *i));
+
+        Comparison {
+            map_name: map.get_name().to_string(),
+            scenario_name: scenario_name.to_string(),
+            edits_name: map.get_edits().edits_name.clone(),
+            rng_seed,
+            end_time: now,
+
+            trips,
+            summaries,
+            intersections,
+        }
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (mode, s) in &self.summaries {
+            lines.push(format!(
+                "{}: {} trips, {} faster, {} slower, {} same. Total time {} before, {} after. \
+                 Change 10%ile {}, 50%ile {}, 90%ile {}",
+                mode.map(|m| m.ongoing_verb()).unwrap_or("all modes"),
+                prettyprint_usize(s.count),
+                prettyprint_usize(s.num_faster),
+                prettyprint_usize(s.num_slower),
+                prettyprint_usize(s.num_same),
+                s.total_before,
+                s.total_after,
+                s.delta_p10,
+                s.delta_p50,
+                s.delta_p90
+            ));
+        }
+        for (i, dt) in self.intersections.iter().take(10) {
+            lines.push(format!("Delay at {} changed by {}", i, dt));
+        }
+        lines
+    }
+
+    // The intersections go in a second file for .csv.
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        write_report(path, self, "intersections", |main, second| {
+            self.write_csv(main, second)
+        })
+    }
+
+    fn write_csv(&self, trips_path: &str, intersections_path: &str) -> std::io::Result<()> {
+        let mut f = BufWriter::new(File::create(trips_path)?);
+        writeln!(f, "trip,mode,before,after,delta")?;
+        for t in &self.trips {
+            writeln!(
+                f,
+                "{},{:?},{},{},{}",
+                t.trip.0,
+                t.mode,
+                t.before.inner_seconds(),
+                t.after.inner_seconds(),
+                t.delta.inner_seconds()
+            )?;
+        }
+
+        let mut f = BufWriter::new(File::create(intersections_path)?);
+        writeln!(f, "intersection,delay_change")?;
+        for (i, dt) in &self.intersections {
+            writeln!(f, "{},{}", i.0, dt.inner_seconds())?;
+        }
+        Ok(())
+    }
+}
+
+impl ModeSummary {
+    fn new(trips: Vec<&TripDelta>) -> Option<ModeSummary> {
+        if trips.is_empty() {
+            return None;
+        }
+        let mut summary = ModeSummary {
+            count: trips.len(),
+            num_faster: 0,
+            num_slower: 0,
+            num_same: 0,
+            total_before: Duration::ZERO,
+            total_after: Duration::ZERO,
+            delta_p10: Duration::ZERO,
+            delta_p50: Duration::ZERO,
+            delta_p90: Duration::ZERO,
+        };
+        // Histogram can't handle negative values, so just sort the deltas.
+        let mut deltas = Vec::new();
+        for t in trips {
+            if t.delta < Duration::ZERO {
+                summary.num_faster += 1;
+            } else if t.delta > Duration::ZERO {
+                summary.num_slower += 1;
+            } else {
+                summary.num_same += 1;
+            }
+            summary.total_before += t.before;
+            summary.total_after += t.after;
+            deltas.push(t.delta);
+        }
+        deltas.sort();
+        let percentile = |p: f64| deltas[((p / 100.0) * ((deltas.len() - 1) as f64)) as usize];
+        summary.delta_p10 = percentile(10.0);
+        summary.delta_p50 = percentile(50.0);
+        summary.delta_p90 = percentile(90.0);
+        Some(summary)
+    }
+}
@@ -1,58 +1,545 @@
-use abstutil::{prettyprint_usize, CmdArgs, Timer};
-use map_model::Map;
+mod compare;
+mod montecarlo;
+mod report;
+mod server;
+
+use crate::compare::Comparison;
+use crate::montecarlo::{MonteCarlo, RunSummary};
+use crate::report::Report;
+use abstutil::{CmdArgs, Timer};
+use geom::{Duration, LonLat, Time};
+use map_model::{IntersectionID, Map, MapEdits, PermanentMapEdits};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
-use sim::{AlertHandler, Scenario, ScenarioModifier, Sim, SimFlags};
+use sim::{
+    assign_traffic, optimize_signals, Scenario, ScenarioGenerator, ScenarioModifier, Sim, SimFlags,
+    TripMode,
+};
+
+// Runs one scenario without any UI, optionally with map edits and scenario modifiers applied, then
+// writes a report of what happened. Meant for running many experiments in batch.
+//
+// Example:
+//   headless ../data/system/maps/montlake.bin --scenario=weekday --edits=my_proposal \
+//     --modifiers=cancel_people:10 --rng_seed=7 --end_time=12:00:00 --report=results.json
+//
+// With --compare, the scenario is run twice, once without and once with the edits, and the report
+// describes how trip times and intersection delays changed.
+//
+// With --num_seeds=N, the scenario is run N times in parallel, with seeds counting up from
+// --rng_seed, and the report estimates how much the results depend on the random seed.
+//
+// With --optimize_signals=12,34 (intersection IDs), the scenario runs many times until --end_time
+// while searching for signal timing that reduces delay at those intersections. The result is saved
+// as new edits.
+//
+// With --assign_traffic, the scenario runs up to --iterations times until --end_time, each time
+// letting drivers pick routes based on the congestion measured in earlier runs, until their choices
+// settle down. Then the scenario runs once more with those routes, like usual. Add --hourly to learn
+// separate travel times for each hour of the day, so rush hour and midday routes can differ.
+//
+// With --port, nothing runs right away. Instead, a server listens on localhost and other tools
+// load scenarios, step the simulation, and query it. See server.rs for the protocol.
 
-// This is specialized to experiment with running the pandemic model over long time periods.
-// Original functionality for profiling and debugging gridlock have been removed.
+struct Job {
+    flags: SimFlags,
+    scenario: String,
+    // Either the name of edits saved by the player, or a path to a .json file with
+    // PermanentMapEdits, like the proposals in data/system/proposals/.
+    edits: Option<String>,
+    modifiers: Vec<ScenarioModifier>,
+    // If None, run until every trip is done.
+    end_time: Option<Time>,
+    // Ends in .json or .csv
+    report: Option<String>,
+    compare: bool,
+    num_seeds: Option<usize>,
+    optimize_signals: Option<Vec<IntersectionID>>,
+    // How many rounds of signal optimization to try at most
+    rounds: usize,
+    assign_traffic: bool,
+    // How many runs of traffic assignment to try at most
+    iterations: usize,
+    hourly: bool,
+    port: Option<u16>,
+}
 
 fn main() {
     let mut args = CmdArgs::new();
-    let num_days = args
-        .optional_parse("--days", |s| s.parse::<usize>())
-        .unwrap_or(1);
+    let job = Job {
+        // The free argument is the path to the map. This also handles --rng_seed, --pandemic,
+        // --alerts, and the other usual simulation flags.
+        flags: SimFlags::from_args(&mut args),
+        scenario: args
+            .optional("--scenario")
+            .unwrap_or_else(|| "weekday".to_string()),
+        edits: args.optional("--edits"),
+        // Comma-separated, applied in order
+        modifiers: args
+            .optional("--modifiers")
+            .map(|list| {
+                list.split(',')
+                    .map(|x| parse_modifier(x).unwrap_or_else(|err| panic!("{}", err)))
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new),
+        end_time: args.optional_parse("--end_time", Time::parse),
+        report: args.optional("--report"),
+        compare: args.enabled("--compare"),
+        num_seeds: args.optional_parse("--num_seeds", |s| s.parse()),
+        optimize_signals: args.optional_parse("--optimize_signals", |list| {
+            list.split(',')
+                .map(|x| x.parse::<usize>().map(IntersectionID))
+                .collect::<Result<Vec<_>, _>>()
+        }),
+        rounds: args.optional_parse("--rounds", |s| s.parse()).unwrap_or(5),
+        assign_traffic: args.enabled("--assign_traffic"),
+        iterations: args
+            .optional_parse("--iterations", |s| s.parse())
+            .unwrap_or(10),
+        hourly: args.enabled("--hourly"),
+        port: args.optional_parse("--port", |s| s.parse()),
+    };
     args.done();
 
-    let mut sim_flags = SimFlags::synthetic_test("montlake", "pandemic");
-    sim_flags.opts.enable_pandemic_model = Some(XorShiftRng::from_seed([sim_flags.rng_seed; 16]));
-    // Less spam
-    sim_flags.opts.alerts = AlertHandler::Silence;
+    if let Some(port) = job.port {
+        server::run(port, job.flags);
+        return;
+    }
+
+    if job.compare {
+        if job.edits.is_none() {
+            panic!("--compare needs --edits");
+        }
+        let (_, before) = run(&job, false);
+        let (map, after) = run(&job, true);
+        let comparison = Comparison::new(&map, &after, &before, &job.scenario, job.flags.rng_seed);
+        for line in comparison.describe() {
+            println!("{}", line);
+        }
+        if let Some(ref path) = job.report {
+            if let Err(err) = comparison.write(path) {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(ref intersections) = job.optimize_signals {
+        optimize(&job, intersections.clone());
+        return;
+    }
+
+    if let Some(num_seeds) = job.num_seeds {
+        let mc = run_seeds(&job, num_seeds);
+        for line in mc.describe() {
+            println!("{}", line);
+        }
+        if let Some(ref path) = job.report {
+            if let Err(err) = mc.write(path) {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let (map, sim) = if job.assign_traffic {
+        assign(&job)
+    } else {
+        run(&job, true)
+    };
+    let report = Report::new(
+        &map,
+        &sim,
+        &job.scenario,
+        &job.modifiers,
+        job.flags.rng_seed,
+    );
+    println!("{}", report.describe());
+    if let Some(ref path) = job.report {
+        if let Err(err) = report.write(path) {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(job: &Job, use_edits: bool) -> (Map, Sim) {
     let mut timer = Timer::new("setup headless");
-    let (mut map, _, mut rng) = sim_flags.load(&mut timer);
-    map.hack_override_offstreet_spots(num_days);
-    // After the map changes, have to re-create the (empty) Sim, because things like
-    // ParkingSimState are out of sync.
-    let mut sim = Sim::new(&map, sim_flags.opts.clone(), &mut timer);
-
-    let base_scenario: Scenario = abstutil::read_binary(
-        abstutil::path_scenario(map.get_name(), "weekday"),
+    let (mut map, mut sim) = setup(job, use_edits, &mut timer);
+    timer.done();
+
+    let timer = Timer::new("run sim");
+    run_sim(&mut map, &mut sim, job.end_time);
+    timer.done();
+    println!("Done at {}", sim.time());
+
+    (map, sim)
+}
+
+fn run_seeds(job: &Job, num_seeds: usize) -> MonteCarlo {
+    if job.compare {
+        panic!("--compare and --num_seeds can't be used together");
+    }
+    // With only one run, there's no spread to measure
+    if num_seeds < 2 {
+        panic!("--num_seeds must be at least 2, not {}", num_seeds);
+    }
+
+    let mut timer = Timer::new("run many seeds");
+    let map = setup_map(job, true, &mut timer);
+    let seeds: Vec<u64> = (0..num_seeds)
+        .map(|i| job.flags.rng_seed.wrapping_add(i as u64))
+        .collect();
+    // Each run only depends on its own seed, so the order the threads finish in doesn't matter.
+    let runs = timer.parallelize("run each seed", seeds, |seed| {
+        let mut timer = Timer::throwaway();
+        let flags = flags_for_seed(&job.flags, seed);
+        let scenario = load_scenario(&map, &job.scenario, &flags, &mut timer)
+            .unwrap_or_else(|err| panic!("{}", err));
+        let mut sim = instantiate(&map, &flags, scenario, &job.modifiers, &mut timer);
+        if sim.has_temporary_edits() {
+            // Temporary edits change the map, so this run needs its own copy
+            let mut map = setup_map(job, true, &mut timer);
+            run_sim(&mut map, &mut sim, job.end_time);
+        } else {
+            step_sim(&map, &mut sim, job.end_time);
+        }
+        RunSummary::new(&sim, seed)
+    });
+    timer.done();
+
+    MonteCarlo::new(&map, &job.scenario, &job.modifiers, runs)
+}
+
+fn optimize(job: &Job, intersections: Vec<IntersectionID>) {
+    if job.compare || job.num_seeds.is_some() {
+        panic!("--optimize_signals can't be used with --compare or --num_seeds");
+    }
+    // Running every trip to completion for every candidate would take forever
+    let end_time = job
+        .end_time
+        .unwrap_or_else(|| panic!("--optimize_signals needs --end_time"));
+
+    let mut timer = Timer::new("optimize signals");
+    let mut map = setup_map(job, true, &mut timer);
+    let mut scenario = load_scenario(&map, &job.scenario, &job.flags, &mut timer)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let mut rng = job.flags.make_rng();
+    for m in &job.modifiers {
+        scenario = m.apply(&map, scenario, &mut rng);
+    }
+
+    let mut edits = optimize_signals(
+        &mut map,
+        &scenario,
+        &job.flags,
+        intersections,
+        end_time,
+        job.rounds,
         &mut timer,
     );
-    ScenarioModifier::RepeatDays(num_days)
-        .apply(base_scenario)
-        .instantiate(&mut sim, &map, &mut rng, &mut timer);
+    edits.edits_name = if edits.edits_name == "untitled edits" {
+        "optimized signals".to_string()
+    } else {
+        format!("{} with optimized signals", edits.edits_name)
+    };
+    abstutil::write_json(
+        abstutil::path_edits(map.get_name(), &edits.edits_name),
+        &PermanentMapEdits::to_permanent(&edits, &map),
+    );
     timer.done();
-
-    run_experiment(&map, &mut sim);
 }
 
-fn run_experiment(map: &Map, sim: &mut Sim) {
-    let timer = Timer::new("run sim until done");
-    sim.run_until_done(
-        &map,
-        |sim, _map| {
-            // This'll run every 30 sim seconds
-            if false {
-                println!(
-                    "At {}, {} infected",
-                    sim.time(),
-                    prettyprint_usize(sim.get_pandemic_model().unwrap().count_infected())
-                );
-            }
-        },
-        None,
+fn assign(job: &Job) -> (Map, Sim) {
+    if job.compare || job.num_seeds.is_some() || job.optimize_signals.is_some() {
+        panic!("--assign_traffic can't be used with --compare, --num_seeds, or --optimize_signals");
+    }
+    let end_time = job
+        .end_time
+        .unwrap_or_else(|| panic!("--assign_traffic needs --end_time"));
+
+    let mut timer = Timer::new("assign traffic");
+    let mut map = setup_map(job, true, &mut timer);
+    let scenario = load_scenario(&map, &job.scenario, &job.flags, &mut timer)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let mut rng = job.flags.make_rng();
+    let mut assigned = scenario.clone();
+    for m in &job.modifiers {
+        assigned = m.apply(&map, assigned, &mut rng);
+    }
+    let iterations = assign_traffic(
+        &mut map,
+        &assigned,
+        &job.flags,
+        end_time,
+        job.iterations,
+        job.hourly,
+        &mut timer,
     );
+    for iteration in &iterations {
+        println!("{}", iteration.describe());
+    }
+
+    // The map now has the assigned costs
+    let mut sim = instantiate(&map, &job.flags, scenario, &job.modifiers, &mut timer);
     timer.done();
+    run_sim(&mut map, &mut sim, job.end_time);
     println!("Done at {}", sim.time());
+    (map, sim)
+}
+
+fn run_sim(map: &mut Map, sim: &mut Sim, end_time: Option<Time>) {
+    if let Some(end_time) = end_time {
+        sim.timed_step_with_edits(
+            map,
+            end_time - sim.time(),
+            &mut None,
+            &mut Timer::throwaway(),
+        );
+        return;
+    }
+    loop {
+        sim.run_until_done(map, |_, _| {}, None);
+        if !sim.apply_temporary_edits(map, &mut Timer::throwaway()) {
+            break;
+        }
+    }
+}
+
+// Stops early when temporary edits from the scenario start or end
+fn step_sim(map: &Map, sim: &mut Sim, end_time: Option<Time>) {
+    if let Some(end_time) = end_time {
+        sim.timed_step(
+            map,
+            end_time - sim.time(),
+            &mut None,
+            &mut Timer::throwaway(),
+        );
+    } else {
+        sim.run_until_done(map, |_, _| {}, None);
+    }
+}
+
+fn setup(job: &Job, use_edits: bool, timer: &mut Timer) -> (Map, Sim) {
+    let map = setup_map(job, use_edits, timer);
+    let scenario = load_scenario(&map, &job.scenario, &job.flags, timer)
+        .unwrap_or_else(|err| panic!("{}", err));
+    let sim = instantiate(&map, &job.flags, scenario, &job.modifiers, timer);
+    (map, sim)
+}
+
+fn setup_map(job: &Job, use_edits: bool, timer: &mut Timer) -> Map {
+    if !job.flags.load.starts_with(&abstutil::path_all_maps()) {
+        panic!("headless needs a path to a map, not {}", job.flags.load);
+    }
+    let mut map = Map::new(job.flags.load.clone(), timer);
+
+    if let Some(ref name) = job.edits {
+        if use_edits {
+            let edits = load_edits(&map, name, timer);
+            map.apply_edits(edits, timer);
+            map.recalculate_pathfinding_after_edits(timer);
+        }
+    }
+
+    prepare_map(&mut map, &job.modifiers);
+    map
+}
+
+// Repeating days blindly makes people need lots of cars. Make room for them.
+// TODO Remove this hack after fixing repeat_days.
+fn prepare_map(map: &mut Map, modifiers: &Vec<ScenarioModifier>) {
+    for m in modifiers {
+        if let ScenarioModifier::RepeatDays(n) = m {
+            map.hack_override_offstreet_spots(*n);
+        }
+    }
+}
+
+// Besides scenarios saved for the map, "empty" and "random" work like they do in the game.
+fn load_scenario(
+    map: &Map,
+    name: &str,
+    flags: &SimFlags,
+    timer: &mut Timer,
+) -> Result<Scenario, String> {
+    match name {
+        "empty" => {
+            let mut s = Scenario::empty(map, "empty");
+            s.only_seed_buses = None;
+            Ok(s)
+        }
+        "random" => {
+            Ok(ScenarioGenerator::small_run(map).generate(map, &mut flags.make_rng(), timer))
+        }
+        _ => Scenario::maybe_load(abstutil::path_scenario(map.get_name(), name), timer)
+            .map_err(|err| format!("Can't load scenario {}: {}", name, err)),
+    }
+}
+
+// Call prepare_map first.
+fn instantiate(
+    map: &Map,
+    flags: &SimFlags,
+    mut scenario: Scenario,
+    modifiers: &Vec<ScenarioModifier>,
+    timer: &mut Timer,
+) -> Sim {
+    let mut rng = flags.make_rng();
+    for m in modifiers {
+        scenario = m.apply(map, scenario, &mut rng);
+    }
+
+    // After the map changes, have to create the Sim, because things like ParkingSimState depend
+    // on it.
+    let mut sim = Sim::new(map, flags.opts.clone(), timer);
+    scenario.instantiate(&mut sim, map, &mut rng, timer);
+    sim
+}
+
+// Everything random about a run is derived from this seed.
+fn flags_for_seed(flags: &SimFlags, seed: u64) -> SimFlags {
+    let mut flags = flags.clone();
+    flags.rng_seed = seed;
+    if flags.opts.enable_pandemic_model.is_some() {
+        flags.opts.enable_pandemic_model = Some(XorShiftRng::seed_from_u64(seed));
+    }
+    // Each run gets its own event log, like events.json becoming events_seed3.json
+    if let Some(path) = flags.opts.event_log.take() {
+        let (base, ext) = path.split_at(path.rfind('.').unwrap_or_else(|| path.len()));
+        flags.opts.event_log = Some(format!("{}_seed{}{}", base, seed, ext));
+    }
+    flags
+}
+
+fn load_edits(map: &Map, name: &str, timer: &mut Timer) -> MapEdits {
+    let result = if name.ends_with(".json") {
+        let perma: PermanentMapEdits = abstutil::read_json(name.to_string(), timer);
+        PermanentMapEdits::from_permanent(perma, map)
+    } else {
+        MapEdits::load(map, name, timer)
+    };
+    match result {
+        Ok(edits) => edits,
+        Err(err) => panic!("Couldn't load edits {}: {}", name, err),
+    }
+}
+
+// Modifiers look like:
+//   repeat_days:3
+//   cancel_people:10 (percent of people)
+//   scale_demand:150 (percent of the original demand)
+//   shift_departures:-30:10 (mean and standard deviation in minutes; negative is earlier)
+//   change_mode:20:drive:transit (percent of people, from mode, to mode)
+//   restrict_to_area:../data/input/seattle/polygons/montlake.poly
+//   transit_capacity:100:44 (riders per vehicle, then the route name)
+fn parse_modifier(x: &str) -> Result<ScenarioModifier, String> {
+    let parts: Vec<&str> = x.splitn(2, ':').collect();
+    if parts.len() != 2 {
+        return Err(format!("Bad modifier {}; should look like name:value", x));
+    }
+    let values: Vec<&str> = parts[1].split(':').collect();
+    let num = |idx: usize| -> Result<usize, String> {
+        values[idx]
+            .parse::<usize>()
+            .map_err(|_| format!("Bad modifier {}; {} isn't a number", x, values[idx]))
+    };
+    let minutes = |idx: usize| -> Result<Duration, String> {
+        values[idx]
+            .parse::<f64>()
+            .map(Duration::f64_minutes)
+            .map_err(|_| format!("Bad modifier {}; {} isn't a number", x, values[idx]))
+    };
+    let mode = |idx: usize| -> Result<TripMode, String> {
+        TripMode::all()
+            .into_iter()
+            .find(|m| format!("{:?}", m).to_lowercase() == values[idx])
+            .ok_or_else(|| format!("Bad modifier {}; {} isn't a mode", x, values[idx]))
+    };
+    let expect = |n: usize| -> Result<(), String> {
+        if values.len() == n {
+            Ok(())
+        } else {
+            Err(format!(
+                "Bad modifier {}; {} needs {} values",
+                x, parts[0], n
+            ))
+        }
+    };
+
+    match parts[0] {
+        "repeat_days" => {
+            expect(1)?;
+            Ok(ScenarioModifier::RepeatDays(num(0)?))
+        }
+        "cancel_people" => {
+            expect(1)?;
+            let pct = num(0)?;
+            if pct > 100 {
+                return Err(format!("Bad modifier {}; the percent must be 0 to 100", x));
+            }
+            Ok(ScenarioModifier::CancelPeople(pct))
+        }
+        "scale_demand" => {
+            expect(1)?;
+            Ok(ScenarioModifier::ScaleDemand(num(0)?))
+        }
+        "shift_departures" => {
+            expect(2)?;
+            let std_dev = minutes(1)?;
+            if std_dev < Duration::ZERO {
+                return Err(format!("Bad modifier {}; the std dev can't be negative", x));
+            }
+            Ok(ScenarioModifier::ShiftDepartures {
+                mean: minutes(0)?,
+                std_dev,
+            })
+        }
+        "change_mode" => {
+            expect(3)?;
+            let pct = num(0)?;
+            if pct > 100 {
+                return Err(format!("Bad modifier {}; the percent must be 0 to 100", x));
+            }
+            Ok(ScenarioModifier::ChangeMode {
+                pct,
+                from: mode(1)?,
+                to: mode(2)?,
+            })
+        }
+        "restrict_to_area" => {
+            let path = parts[1];
+            let pts = LonLat::read_osmosis_polygon(path.to_string())
+                .map_err(|err| format!("Bad modifier {}: {}", x, err))?;
+            let name = std::path::Path::new(path)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.to_string());
+            Ok(ScenarioModifier::RestrictToArea { name, pts })
+        }
+        "transit_capacity" => {
+            // Route names might contain colons
+            let values: Vec<&str> = parts[1].splitn(2, ':').collect();
+            if values.len() != 2 {
+                return Err(format!(
+                    "Bad modifier {}; transit_capacity needs a capacity and a route",
+                    x
+                ));
+            }
+            let capacity = values[0]
+                .parse::<usize>()
+                .map_err(|_| format!("Bad modifier {}; {} isn't a number", x, values[0]))?;
+            if capacity == 0 {
+                return Err(format!("Bad modifier {}; nobody could ride", x));
+            }
+            Ok(ScenarioModifier::ChangeTransitCapacity {
+                route: values[1].to_string(),
+                capacity,
+            })
+        }
+        _ => Err(format!("Unknown modifier {}", parts[0])),
+    }
 }
@@ -0,0 +1,277 @@
+use crate::report::write_report;
+use abstutil::prettyprint_usize;
+use geom::{Duration, Histogram, Statistic, Time};
+use map_model::Map;
+use serde::Serialize;
+use sim::{ScenarioModifier, Sim, TripMode};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// The same scenario run many times with different random seeds. Estimates how much each metric
+// depends on the seed.
+#[derive(Serialize)]
+pub struct MonteCarlo {
+    pub map_name: String,
+    pub scenario_name: String,
+    pub edits_name: String,
+    pub modifiers: Vec<String>,
+
+    pub runs: Vec<RunSummary>,
+    pub estimates: Vec<Estimate>,
+}
+
+// A few numbers describing one run. Durations are in seconds.
+#[derive(Serialize)]
+pub struct RunSummary {
+    pub rng_seed: u64,
+    pub end_time: Time,
+    pub metrics: Vec<(String, f64)>,
+}
+
+#[derive(Serialize)]
+pub struct Estimate {
+    pub metric: String,
+    // Some metrics, like the trip time for one mode, might not exist in every run
+    pub num_runs: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    // A 95% confidence interval for the mean
+    pub ci_low: f64,
+    pub ci_high: f64,
+}
+
+impl RunSummary {
+    pub fn new(sim: &Sim, rng_seed: u64) -> RunSummary {
+        let analytics = sim.get_analytics();
+        let mut metrics = Vec::new();
+
+        let mut all_trips = Histogram::new();
+        let mut per_mode: BTreeMap<TripMode, Histogram<Duration>> = BTreeMap::new();
+        let mut num_aborted = 0;
+        for (_, _, maybe_mode, dt) in &analytics.finished_trips {
+            if let Some(mode) = maybe_mode {
+                all_trips.add(*dt);
+                per_mode
+                    .entry(*mode)
+                    .or_insert_with(Histogram::new)
+                    .add(*dt);
+            } else {
+                num_aborted += 1;
+            }
+        }
+        metrics.push(("finished_trips".to_string(), all_trips.count() as f64));
+        metrics.push(("aborted_trips".to_string(), num_aborted as f64));
+        if all_trips.count() > 0 {
+            for (name, stat) in vec![
+                ("mean", Statistic::Mean),
+                ("p50", Statistic::P50),
+                ("p90", Statistic::P90),
+            ] {
+                metrics.push((
+                    format!("trip_time_{}", name),
+                    all_trips.select(stat).inner_seconds(),
+                ));
+            }
+        }
+        for (mode, hgram) in per_mode {
+            metrics.push((
+                format!("trip_time_mean_{:?}", mode).to_lowercase(),
+                hgram.select(Statistic::Mean).inner_seconds(),
+            ));
+        }
+
+        let boardings = analytics.bus_boardings(sim.time());
+        if !boardings.is_empty() {
+            metrics.push((
+                "bus_transfers".to_string(),
+                boardings.values().map(|n| n - 1).sum::<usize>() as f64,
+            ));
+        }
+        if !analytics.bus_loads.is_empty() {
+            metrics.push((
+                "bus_pass_ups".to_string(),
+                analytics.bus_pass_ups.len() as f64,
+            ));
+        }
+
+        metrics.push((
+            "road_thruput".to_string(),
+            analytics.road_thruput.counts.values().sum::<usize>() as f64,
+        ));
+        metrics.push((
+            "intersection_thruput".to_string(),
+            analytics
+                .intersection_thruput
+                .counts
+                .values()
+                .sum::<usize>() as f64,
+        ));
+
+        let mut delays = Histogram::new();
+        let mut total_delay = Duration::ZERO;
+        for list in analytics.intersection_delays.values() {
+            for (_, dt, _) in list {
+                delays.add(*dt);
+                total_delay += *dt;
+            }
+        }
+        metrics.push((
+            "intersection_delay_total".to_string(),
+            total_delay.inner_seconds(),
+        ));
+        if delays.count() > 0 {
+            metrics.push((
+                "intersection_delay_mean".to_string(),
+                delays.select(Statistic::Mean).inner_seconds(),
+            ));
+        }
+
+        RunSummary {
+            rng_seed,
+            end_time: sim.time(),
+            metrics,
+        }
+    }
+}
+
+impl MonteCarlo {
+    pub fn new(
+        map: &Map,
+        scenario_name: &str,
+        modifiers: &Vec<ScenarioModifier>,
+        mut runs: Vec<RunSummary>,
+    ) -> MonteCarlo {
+        runs.sort_by_key(|r| r.rng_seed);
+
+        // Keep the metrics in the order they were first seen
+        let mut order = Vec::new();
+        let mut values: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for run in &runs {
+            for (metric, value) in &run.metrics {
+                if !values.contains_key(metric) {
+                    order.push(metric.clone());
+                }
+                values
+                    .entry(metric.clone())
+                    .or_insert_with(Vec::new)
+                    .push(*value);
+            }
+        }
+        let estimates = order
+            .into_iter()
+            .map(|metric| {
+                let samples = values.remove(&metric).unwrap();
+                Estimate::new(metric, samples)
+            })
+            .collect();
+
+        MonteCarlo {
+            map_name: map.get_name().to_string(),
+            scenario_name: scenario_name.to_string(),
+            edits_name: map.get_edits().edits_name.clone(),
+            modifiers: modifiers.iter().map(|m| m.describe()).collect(),
+
+            runs,
+            estimates,
+        }
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{} runs of {} on {}",
+            prettyprint_usize(self.runs.len()),
+            self.scenario_name,
+            self.map_name
+        )];
+        for e in &self.estimates {
+            lines.push(format!(
+                "{}: {:.2} (95% CI {:.2} to {:.2}, std dev {:.2}, {} runs)",
+                e.metric, e.mean, e.ci_low, e.ci_high, e.std_dev, e.num_runs
+            ));
+        }
+        lines
+    }
+
+    // The individual runs go in a second file for .csv.
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        write_report(path, self, "runs", |main, second| {
+            self.write_csv(main, second)
+        })
+    }
+
+    fn write_csv(&self, estimates_path: &str, runs_path: &str) -> std::io::Result<()> {
+        let mut f = BufWriter::new(File::create(estimates_path)?);
+        writeln!(f, "metric,num_runs,mean,std_dev,ci_low,ci_high")?;
+        for e in &self.estimates {
+            writeln!(
+                f,
+                "{},{},{},{},{},{}",
+                e.metric, e.num_runs, e.mean, e.std_dev, e.ci_low, e.ci_high
+            )?;
+        }
+
+        let mut f = BufWriter::new(File::create(runs_path)?);
+        writeln!(f, "rng_seed,end_time,metric,value")?;
+        for run in &self.runs {
+            for (metric, value) in &run.metrics {
+                writeln!(
+                    f,
+                    "{},{},{},{}",
+                    run.rng_seed,
+                    run.end_time.inner_seconds(),
+                    metric,
+                    value
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Estimate {
+    fn new(metric: String, samples: Vec<f64>) -> Estimate {
+        let n = samples.len();
+        let mean = samples.iter().sum::<f64>() / (n as f64);
+        if n < 2 {
+            return Estimate {
+                metric,
+                num_runs: n,
+                mean,
+                std_dev: 0.0,
+                ci_low: mean,
+                ci_high: mean,
+            };
+        }
+        // Sample standard deviation
+        let std_dev =
+            (samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / ((n - 1) as f64)).sqrt();
+        let half_width = t_critical_95(n - 1) * std_dev / (n as f64).sqrt();
+        Estimate {
+            metric,
+            num_runs: n,
+            mean,
+            std_dev,
+            ci_low: mean - half_width,
+            ci_high: mean + half_width,
+        }
+    }
+}
+
+// Two-sided 95% critical values of Student's t distribution. With only a handful of runs, the
+// normal approximation would make the interval too narrow.
+fn t_critical_95(degrees_of_freedom: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    if degrees_of_freedom == 0 {
+        panic!("Need at least 2 samples for a confidence interval");
+    }
+    if degrees_of_freedom <= TABLE.len() {
+        TABLE[degrees_of_freedom - 1]
+    } else {
+        1.96
+    }
+}
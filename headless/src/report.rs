@@ -0,0 +1,193 @@
+use abstutil::prettyprint_usize;
+use geom::{Duration, Histogram, Statistic, Time};
+use map_model::{IntersectionID, Map};
+use serde::Serialize;
+use sim::{ScenarioModifier, Sim, TripID, TripMode};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// A summary of one finished run, meant to be consumed by other tools.
+#[derive(Serialize)]
+pub struct Report {
+    pub map_name: String,
+    pub scenario_name: String,
+    pub edits_name: String,
+    pub modifiers: Vec<String>,
+    pub rng_seed: u64,
+    pub end_time: Time,
+
+    pub finished_trips: Vec<FinishedTrip>,
+    pub aborted_trips: Vec<AbortedTrip>,
+    pub intersection_delays: Vec<IntersectionDelay>,
+}
+
+#[derive(Serialize)]
+pub struct FinishedTrip {
+    pub trip: TripID,
+    pub mode: TripMode,
+    pub finished_at: Time,
+    pub duration: Duration,
+}
+
+#[derive(Serialize)]
+pub struct AbortedTrip {
+    pub trip: TripID,
+    pub aborted_at: Time,
+}
+
+#[derive(Serialize)]
+pub struct IntersectionDelay {
+    pub intersection: IntersectionID,
+    pub count: usize,
+    pub total: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub max: Duration,
+}
+
+impl Report {
+    pub fn new(
+        map: &Map,
+        sim: &Sim,
+        scenario_name: &str,
+        modifiers: &Vec<ScenarioModifier>,
+        rng_seed: u64,
+    ) -> Report {
+        let analytics = sim.get_analytics();
+
+        let mut finished_trips = Vec::new();
+        let mut aborted_trips = Vec::new();
+        for (t, id, maybe_mode, dt) in &analytics.finished_trips {
+            if let Some(mode) = maybe_mode {
+                finished_trips.push(FinishedTrip {
+                    trip: *id,
+                    mode: *mode,
+                    finished_at: *t,
+                    duration: *dt,
+                });
+            } else {
+                aborted_trips.push(AbortedTrip {
+                    trip: *id,
+                    aborted_at: *t,
+                });
+            }
+        }
+
+        let mut intersection_delays = Vec::new();
+        for (i, delays) in &analytics.intersection_delays {
+            if delays.is_empty() {
+                continue;
+            }
+            let mut hgram = Histogram::new();
+            let mut total = Duration::ZERO;
+            for (_, dt, _) in delays {
+                hgram.add(*dt);
+                total += *dt;
+            }
+            intersection_delays.push(IntersectionDelay {
+                intersection: *i,
+                count: hgram.count(),
+                total,
+                mean: hgram.select(Statistic::Mean),
+                p50: hgram.select(Statistic::P50),
+                p90: hgram.select(Statistic::P90),
+                max: hgram.select(Statistic::Max),
+            });
+        }
+
+        Report {
+            map_name: map.get_name().to_string(),
+            scenario_name: scenario_name.to_string(),
+            edits_name: map.get_edits().edits_name.clone(),
+            modifiers: modifiers.iter().map(|m| m.describe()).collect(),
+            rng_seed,
+            end_time: sim.time(),
+
+            finished_trips,
+            aborted_trips,
+            intersection_delays,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut hgram = Histogram::new();
+        for trip in &self.finished_trips {
+            hgram.add(trip.duration);
+        }
+        format!(
+            "{} finished trips, {} aborted trips. Trip times: {}",
+            prettyprint_usize(self.finished_trips.len()),
+            prettyprint_usize(self.aborted_trips.len()),
+            hgram.describe()
+        )
+    }
+
+    // The intersection delays go in a second file for .csv.
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        write_report(path, self, "intersections", |main, second| {
+            self.write_csv(main, second)
+        })
+    }
+
+    fn write_csv(&self, trips_path: &str, intersections_path: &str) -> std::io::Result<()> {
+        let mut f = BufWriter::new(File::create(trips_path)?);
+        writeln!(f, "trip,mode,end_time,duration,aborted")?;
+        for trip in &self.finished_trips {
+            writeln!(
+                f,
+                "{},{:?},{},{},false",
+                trip.trip.0,
+                trip.mode,
+                trip.finished_at.inner_seconds(),
+                trip.duration.inner_seconds()
+            )?;
+        }
+        for trip in &self.aborted_trips {
+            writeln!(
+                f,
+                "{},,{},,true",
+                trip.trip.0,
+                trip.aborted_at.inner_seconds()
+            )?;
+        }
+
+        let mut f = BufWriter::new(File::create(intersections_path)?);
+        writeln!(f, "intersection,count,total,mean,p50,p90,max")?;
+        for delay in &self.intersection_delays {
+            writeln!(
+                f,
+                "{},{},{},{},{},{},{}",
+                delay.intersection.0,
+                delay.count,
+                delay.total.inner_seconds(),
+                delay.mean.inner_seconds(),
+                delay.p50.inner_seconds(),
+                delay.p90.inner_seconds(),
+                delay.max.inner_seconds()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// A .json path gets everything in one file. A .csv path gets the main table, and write_csv puts a
+// second table in a file next to it, ending in _<suffix>.csv.
+pub fn write_report<T: Serialize, F: Fn(&str, &str) -> std::io::Result<()>>(
+    path: &str,
+    obj: &T,
+    suffix: &str,
+    write_csv: F,
+) -> Result<(), String> {
+    if path.ends_with(".json") {
+        abstutil::write_json(path.to_string(), obj);
+        Ok(())
+    } else if path.ends_with(".csv") {
+        let second_path = format!("{}_{}.csv", path.trim_end_matches(".csv"), suffix);
+        write_csv(path, &second_path).map_err(|err| format!("Can't write {}: {}", path, err))?;
+        println!("Wrote {} and {}", path, second_path);
+        Ok(())
+    } else {
+        Err(format!("Report {} must end with .json or .csv", path))
+    }
+}
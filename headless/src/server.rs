@@ -0,0 +1,381 @@
+use crate::report::Report;
+use crate::{instantiate, load_scenario, parse_modifier, prepare_map};
+use abstutil::Timer;
+use geom::{Pt2D, Time};
+use map_model::{Map, PermanentMapEdits};
+use serde::{Deserialize, Serialize};
+use sim::{AgentID, GetDrawAgents, Scenario, ScenarioModifier, Sim, SimFlags};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+// Lets other tools (like calibration scripts written in Python) drive a simulation over a socket
+// on localhost. Each request is one line of JSON, and so is each response.
+//
+// Requests look like {"cmd": "StepUntil", "time": "07:30:00"}. Responses look like
+// {"Ok": ...} or {"Error": "what went wrong"}.
+//
+// Only one client is served at a time. The simulation stays around between clients.
+
+#[derive(Deserialize)]
+#[serde(tag = "cmd")]
+enum Request {
+    // Restarts the simulation from midnight. The scenario name can also be "empty" or "random",
+    // like in the game. Modifiers use the same format as --modifiers.
+    LoadScenario {
+        scenario: String,
+        #[serde(default)]
+        modifiers: Vec<String>,
+    },
+    // Time looks like "07:30:00"
+    StepUntil {
+        time: String,
+    },
+    // Replaces any previous edits, then restarts the current scenario from midnight. With live,
+    // the simulation keeps going instead, rerouting or removing agents that're affected.
+    ApplyEdits {
+        edits: PermanentMapEdits,
+        #[serde(default)]
+        live: bool,
+    },
+    GetStatus,
+    // Returns the same thing as headless --report
+    GetAnalytics,
+    // Every car, bike, bus, and pedestrian currently on the map
+    GetAgentPositions,
+    GetAgentPosition {
+        agent: AgentID,
+    },
+}
+
+#[derive(Serialize)]
+enum Response {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+#[derive(Serialize)]
+struct Status {
+    map_name: String,
+    // None until a scenario is loaded
+    scenario_name: Option<String>,
+    edits_name: String,
+    time: Time,
+    is_done: bool,
+}
+
+#[derive(Serialize)]
+struct AgentPosition {
+    agent: AgentID,
+    x: f64,
+    y: f64,
+}
+
+struct Server {
+    flags: SimFlags,
+    map: Map,
+    sim: Sim,
+    // Remember the original scenario, so it can be restarted after edits.
+    scenario: Option<(Scenario, Vec<ScenarioModifier>)>,
+}
+
+pub fn run(port: u16, flags: SimFlags) {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .unwrap_or_else(|err| panic!("Can't listen on port {}: {}", port, err));
+    let mut timer = Timer::new("setup headless server");
+    let server = Server::new(flags, &mut timer);
+    timer.done();
+    println!("Listening on {}", listener.local_addr().unwrap());
+    serve(listener, server);
+}
+
+fn serve(listener: TcpListener, mut server: Server) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(err) = server.handle_client(stream) {
+                    println!("Lost client: {}", err);
+                }
+            }
+            Err(err) => println!("Couldn't accept client: {}", err),
+        }
+    }
+}
+
+impl Server {
+    fn new(flags: SimFlags, timer: &mut Timer) -> Server {
+        let map = Map::new(flags.load.clone(), timer);
+        let sim = Sim::new(&map, flags.opts.clone(), timer);
+        Server {
+            flags,
+            map,
+            sim,
+            scenario: None,
+        }
+    }
+
+    fn handle_client(&mut self, stream: TcpStream) -> std::io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str(&line) {
+                Ok(req) => match self.handle(req) {
+                    Ok(value) => Response::Ok(value),
+                    Err(err) => Response::Error(err),
+                },
+                Err(err) => Response::Error(format!("Bad request {}: {}", line, err)),
+            };
+            writeln!(writer, "{}", serde_json::to_string(&response).unwrap())?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, req: Request) -> Result<serde_json::Value, String> {
+        match req {
+            Request::LoadScenario {
+                scenario,
+                modifiers,
+            } => {
+                let modifiers = modifiers
+                    .iter()
+                    .map(|x| parse_modifier(x))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let scenario =
+                    load_scenario(&self.map, &scenario, &self.flags, &mut Timer::throwaway())?;
+                self.scenario = Some((scenario, modifiers));
+                self.restart();
+                to_json(&self.status())
+            }
+            Request::StepUntil { time } => {
+                let time = Time::parse(&time).map_err(|err| err.to_string())?;
+                if time < self.sim.time() {
+                    return Err(format!(
+                        "Can't step backwards to {}; it's already {}",
+                        time,
+                        self.sim.time()
+                    ));
+                }
+                self.sim.timed_step_with_edits(
+                    &mut self.map,
+                    time - self.sim.time(),
+                    &mut None,
+                    &mut Timer::throwaway(),
+                );
+                to_json(&self.status())
+            }
+            Request::ApplyEdits { edits, live } => {
+                if &edits.map_name != self.map.get_name() {
+                    return Err(format!(
+                        "Edits are for map {}, but {} is loaded",
+                        edits.map_name,
+                        self.map.get_name()
+                    ));
+                }
+                let edits = PermanentMapEdits::from_permanent(edits, &self.map)?;
+                let mut timer = Timer::throwaway();
+                // The new edits replace any temporary ones from the scenario too
+                self.sim.remove_temporary_edits(&self.map);
+                let (roads, _, _, intersections) = self.map.apply_edits(edits, &mut timer);
+                self.map.recalculate_pathfinding_after_edits(&mut timer);
+                if live {
+                    self.sim
+                        .handle_live_edits(&self.map, &roads, &intersections, &mut timer);
+                    self.sim.apply_temporary_edits(&mut self.map, &mut timer);
+                } else {
+                    self.restart();
+                }
+                to_json(&self.status())
+            }
+            Request::GetStatus => to_json(&self.status()),
+            Request::GetAnalytics => {
+                let (name, modifiers) = match self.scenario {
+                    Some((ref s, ref modifiers)) => (s.scenario_name.clone(), modifiers.clone()),
+                    None => ("none".to_string(), Vec::new()),
+                };
+                to_json(&Report::new(
+                    &self.map,
+                    &self.sim,
+                    &name,
+                    &modifiers,
+                    self.flags.rng_seed,
+                ))
+            }
+            Request::GetAgentPositions => {
+                let mut positions = Vec::new();
+                for car in self.sim.get_all_draw_cars(&self.map) {
+                    positions.push(AgentPosition::new(AgentID::Car(car.id), car.body.last_pt()));
+                }
+                for ped in self.sim.get_all_draw_peds(&self.map) {
+                    positions.push(AgentPosition::new(AgentID::Pedestrian(ped.id), ped.pos));
+                }
+                to_json(&positions)
+            }
+            Request::GetAgentPosition { agent } => {
+                match self.sim.canonical_pt_for_agent(agent, &self.map) {
+                    Some(pt) => to_json(&AgentPosition::new(agent, pt)),
+                    None => Err(format!("{} isn't on the map right now", agent)),
+                }
+            }
+        }
+    }
+
+    // Start the current scenario over, or just clear everything if there isn't one.
+    fn restart(&mut self) {
+        let mut timer = Timer::throwaway();
+        if let Some(edits) = self.sim.remove_temporary_edits(&self.map) {
+            self.map.apply_edits(edits, &mut timer);
+            self.map.recalculate_pathfinding_after_edits(&mut timer);
+        }
+        self.sim = if let Some((ref scenario, ref modifiers)) = self.scenario {
+            prepare_map(&mut self.map, modifiers);
+            instantiate(
+                &self.map,
+                &self.flags,
+                scenario.clone(),
+                modifiers,
+                &mut timer,
+            )
+        } else {
+            Sim::new(&self.map, self.flags.opts.clone(), &mut timer)
+        };
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            map_name: self.map.get_name().to_string(),
+            scenario_name: self.scenario.as_ref().map(|(s, _)| s.scenario_name.clone()),
+            edits_name: self.map.get_edits().edits_name.clone(),
+            time: self.sim.time(),
+            is_done: self.sim.is_done(),
+        }
+    }
+}
+
+impl AgentPosition {
+    fn new(agent: AgentID, pt: Pt2D) -> AgentPosition {
+        AgentPosition {
+            agent,
+            x: pt.x(),
+            y: pt.y(),
+        }
+    }
+}
+
+fn to_json<T: Serialize>(obj: &T) -> Result<serde_json::Value, String> {
+    serde_json::to_value(obj).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    struct Client {
+        writer: TcpStream,
+        reader: BufReader<TcpStream>,
+    }
+
+    impl Client {
+        fn send(&mut self, req: Value) -> Value {
+            writeln!(self.writer, "{}", req).unwrap();
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        fn ok(&mut self, req: Value) -> Value {
+            let resp = self.send(req.clone());
+            match resp.get("Ok") {
+                Some(value) => value.clone(),
+                None => panic!("{} failed: {}", req, resp),
+            }
+        }
+    }
+
+    #[test]
+    fn drive_sim_over_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut flags = SimFlags::synthetic_test("signal_single", "server_test");
+            flags.load = abstutil::path_synthetic_map("signal_single");
+            serve(listener, Server::new(flags, &mut Timer::throwaway()));
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut client = Client {
+            writer: stream.try_clone().unwrap(),
+            reader: BufReader::new(stream),
+        };
+
+        let status = client.ok(json!({"cmd": "GetStatus"}));
+        assert_eq!(status["map_name"], "signal_single");
+        assert_eq!(status["scenario_name"], Value::Null);
+        assert_eq!(status["time"], 0.0);
+
+        let status = client.ok(json!({"cmd": "LoadScenario", "scenario": "empty"}));
+        assert_eq!(status["scenario_name"], "empty");
+
+        let status = client.ok(json!({"cmd": "StepUntil", "time": "00:30:00"}));
+        assert_eq!(status["time"], 1800.0);
+
+        // Bad requests don't kill the server
+        assert!(client
+            .send(json!({"cmd": "StepUntil", "time": "00:10:00"}))
+            .get("Error")
+            .is_some());
+        assert!(client
+            .send(json!({"cmd": "LoadScenario", "scenario": "does not exist"}))
+            .get("Error")
+            .is_some());
+        assert!(client.send(json!({"cmd": "Fly"})).get("Error").is_some());
+
+        assert!(client
+            .ok(json!({"cmd": "GetAgentPositions"}))
+            .as_array()
+            .is_some());
+        let report = client.ok(json!({"cmd": "GetAnalytics"}));
+        assert_eq!(report["scenario_name"], "empty");
+        assert_eq!(report["end_time"], 1800.0);
+
+        // Applying edits restarts the scenario
+        let status = client.ok(json!({"cmd": "ApplyEdits", "edits": {
+            "map_name": "signal_single",
+            "edits_name": "server test",
+            "commands": [],
+            "proposal_description": [],
+            "proposal_link": null
+        }}));
+        assert_eq!(status["edits_name"], "server test");
+        assert_eq!(status["scenario_name"], "empty");
+        assert_eq!(status["time"], 0.0);
+
+        // Unless they're applied live
+        client.ok(json!({"cmd": "StepUntil", "time": "00:10:00"}));
+        let status = client.ok(json!({"cmd": "ApplyEdits", "live": true, "edits": {
+            "map_name": "signal_single",
+            "edits_name": "live server test",
+            "commands": [],
+            "proposal_description": [],
+            "proposal_link": null
+        }}));
+        assert_eq!(status["edits_name"], "live server test");
+        assert_eq!(status["time"], 600.0);
+
+        // Edits for a different map are rejected
+        assert!(client
+            .send(json!({"cmd": "ApplyEdits", "edits": {
+                "map_name": "signal_double",
+                "edits_name": "wrong map",
+                "commands": [],
+                "proposal_description": [],
+                "proposal_link": null
+            }}))
+            .get("Error")
+            .is_some());
+        let status = client.ok(json!({"cmd": "GetStatus"}));
+        assert_eq!(status["edits_name"], "live server test");
+    }
+}
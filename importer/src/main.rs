@@ -1,7 +1,9 @@
 mod austin;
 mod seattle;
+mod signal_timing;
 #[cfg(feature = "scenarios")]
 mod soundcast;
+mod trip_table;
 mod utils;
 
 // TODO Might be cleaner to express as a dependency graph?
@@ -14,12 +16,16 @@ struct Job {
     scenario_everyone: bool,
 
     skip_ch: bool,
+    signal_timing: Option<String>,
 
     only_map: Option<String>,
 
     oneshot: Option<String>,
     oneshot_clip: Option<String>,
     oneshot_drive_on_left: bool,
+
+    trip_table: Option<String>,
+    scenario_name: String,
 }
 
 fn main() {
@@ -37,6 +43,9 @@ fn main() {
         // Skip the most expensive step of --map, building contraction hierarchies. The resulting
         // map won't be usable for simulation; as soon as you try to pathfind, it'll crash.
         skip_ch: args.enabled("--skip_ch"),
+        // Part of --map. Use real traffic signal timing from this .json file, instead of guessing.
+        // See signal_timing.rs for the format.
+        signal_timing: args.optional("--signal_timing"),
 
         // Only process one map. If not specified, process all maps defined by clipping polygons in
         // data/input/$city/polygons/.
@@ -46,6 +55,13 @@ fn main() {
         oneshot: args.optional("--oneshot"),
         oneshot_clip: args.optional("--oneshot_clip"),
         oneshot_drive_on_left: args.enabled("--oneshot_drive_on_left"),
+
+        // Ignore other arguments and just turn a .csv or .geojson table of trips into a scenario
+        // for one map. See trip_table.rs for the format.
+        trip_table: args.optional("--trip_table"),
+        scenario_name: args
+            .optional("--scenario_name")
+            .unwrap_or_else(|| "imported".to_string()),
     };
     args.done();
     if !job.osm_to_raw
@@ -53,10 +69,11 @@ fn main() {
         && !job.scenario
         && !job.scenario_everyone
         && job.oneshot.is_none()
+        && job.trip_table.is_none()
     {
         println!(
             "Nothing to do! Pass some combination of --raw, --map, --scenario, \
-             --scenario_everyone or --oneshot"
+             --scenario_everyone, --oneshot, or --trip_table"
         );
         std::process::exit(1);
     }
@@ -66,6 +83,20 @@ fn main() {
         return;
     }
 
+    if let Some(path) = job.trip_table {
+        let name = job
+            .only_map
+            .unwrap_or_else(|| panic!("--trip_table needs the name of one map"));
+        let mut timer = abstutil::Timer::new(format!("import {}", path));
+        let map = map_model::Map::new(abstutil::path_map(&name), &mut timer);
+        trip_table::import(&path, &map, &job.scenario_name, &mut timer).save();
+        println!(
+            "{} has been created",
+            abstutil::path_scenario(&name, &job.scenario_name)
+        );
+        return;
+    }
+
     let names = if let Some(n) = job.only_map {
         println!("- Just working on {}", n);
         vec![n]
@@ -108,7 +139,12 @@ fn main() {
         }
 
         let mut maybe_map = if job.raw_to_map {
-            Some(utils::raw_to_map(&name, !job.skip_ch, &mut timer))
+            Some(utils::raw_to_map(
+                &name,
+                !job.skip_ch,
+                job.signal_timing.as_ref().map(|x| x.as_str()),
+                &mut timer,
+            ))
         } else if job.scenario || job.scenario_everyone {
             Some(map_model::Map::new(abstutil::path_map(&name), &mut timer))
         } else {
@@ -0,0 +1,41 @@
+use abstutil::{prettyprint_usize, Timer};
+use map_model::Map;
+
+// Replaces the guessed timing of traffic signals with real timing plans. The .json file is a list
+// of signals in the same format as https://github.com/dabreegster/seattle_traffic_signals, which
+// is also what the traffic signal editor exports.
+//
+// Signals that aren't in this map are skipped. If a plan doesn't match the turns at its
+// intersection, the problem is reported and that signal keeps its guessed timing. The map remembers
+// the imported plans, so they still apply after edits regenerate a signal.
+pub fn import(path: &str, map: &mut Map, timer: &mut Timer) {
+    let plans: Vec<seattle_traffic_signals::TrafficSignal> =
+        abstutil::maybe_read_json(path.to_string(), timer)
+            .unwrap_or_else(|err| panic!("Can't read signal timing {}: {}", path, err));
+
+    let mut imported = 0;
+    let mut failed = 0;
+    for plan in plans {
+        let node = plan.intersection_osm_node_id;
+        // Timing sheets usually cover a whole city
+        if map.find_i_by_osm_id(node).is_err() {
+            continue;
+        }
+        match map.import_traffic_signal(plan) {
+            Ok(_) => {
+                imported += 1;
+            }
+            Err(err) => {
+                timer.warn(format!("OSM node {}: {}", node, err));
+                failed += 1;
+            }
+        }
+    }
+
+    timer.note(format!(
+        "{} traffic signals use timing from {}, {} plans couldn't be matched",
+        prettyprint_usize(imported),
+        path,
+        prettyprint_usize(failed)
+    ));
+}
@@ -1,12 +1,12 @@
 use crate::soundcast::popdat::{Endpoint, OrigTrip, PopDat};
 use abstutil::{prettyprint_usize, MultiMap, Timer};
-use geom::LonLat;
+use geom::{LonLat, Time};
 use map_model::{BuildingID, IntersectionID, Map, PathConstraints, PathRequest, PathStep};
 use sim::{
     IndividTrip, OffMapLocation, OrigPersonID, PersonID, PersonSpec, Scenario, SpawnTrip,
     TripEndpoint, TripMode,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Clone, Debug)]
 struct Trip {
@@ -26,6 +26,7 @@ fn endpoints(
         &Vec<(IntersectionID, LonLat)>,
     ),
     constraints: PathConstraints,
+    depart_at: Time,
     maybe_huge_map: Option<&(&Map, HashMap<i64, BuildingID>)>,
 ) -> Option<(TripEndpoint, TripEndpoint)> {
     let from_bldg = from
@@ -82,11 +83,14 @@ fn endpoints(
             };
             if let Some(path) = start.and_then(|start| {
                 end.and_then(|end| {
-                    huge_map.pathfind(PathRequest {
-                        start,
-                        end,
-                        constraints,
-                    })
+                    huge_map.pathfind(
+                        PathRequest {
+                            start,
+                            end,
+                            constraints,
+                        },
+                        depart_at,
+                    )
                 })
             }) {
                 // Do any of the usable borders match the path?
@@ -215,6 +219,7 @@ fn clip_trips(map: &Map, popdat: &PopDat, huge_map: &Map, timer: &mut Timer) ->
                     TripMode::Drive => PathConstraints::Car,
                     TripMode::Bike => PathConstraints::Bike,
                 },
+                orig.depart_at,
                 maybe_huge_map.as_ref(),
             )?;
             Some(Trip {
@@ -247,7 +252,8 @@ pub fn make_weekday_scenario(
     // person -> (trip seq, index into individ_trips)
     let mut trips_per_person: MultiMap<OrigPersonID, ((usize, bool, usize), usize)> =
         MultiMap::new();
-    for (trip, depart, person, seq) in
+    let mut impossible = 0;
+    for (maybe_trip, depart, person, seq) in
         timer.parallelize("turn Soundcast trips into SpawnTrips", trips, |trip| {
             (
                 SpawnTrip::new(trip.from, trip.to, trip.orig.mode, map),
@@ -257,6 +263,14 @@ pub fn make_weekday_scenario(
             )
         })
     {
+        // If this leaves a gap in somebody's schedule, remove_weird_schedules drops them later.
+        let trip = match maybe_trip {
+            Some(trip) => trip,
+            None => {
+                impossible += 1;
+                continue;
+            }
+        };
         let idx = individ_trips.len();
         individ_trips.push(Some(IndividTrip {
             depart,
@@ -265,6 +279,12 @@ pub fn make_weekday_scenario(
         }));
         trips_per_person.insert(person, (seq, idx));
     }
+    if impossible > 0 {
+        timer.note(format!(
+            "{} trips can't be made with their original mode; skipping them",
+            prettyprint_usize(impossible)
+        ));
+    }
     timer.note(format!(
         "{} clipped trips down to {}, over {} people",
         prettyprint_usize(orig_trips),
@@ -300,6 +320,8 @@ pub fn make_weekday_scenario(
         map_name: map.get_name().to_string(),
         people,
         only_seed_buses: None,
+        temporary_edits: Vec::new(),
+        transit_capacity: BTreeMap::new(),
     }
     .remove_weird_schedules(map)
 }
@@ -371,6 +393,8 @@ pub fn make_weekday_scenario_with_everyone(
         map_name: map.get_name().to_string(),
         people,
         only_seed_buses: None,
+        temporary_edits: Vec::new(),
+        transit_capacity: BTreeMap::new(),
     }
     .remove_weird_schedules(map)
 }
@@ -0,0 +1,446 @@
+use abstutil::{prettyprint_usize, Timer};
+use geom::{Distance, FindClosest, LonLat, Pt2D, Time};
+use map_model::{BuildingID, IntersectionID, Map, PathConstraints};
+use serde::Deserialize;
+use sim::{
+    IndividTrip, OffMapLocation, PersonID, PersonSpec, Scenario, SpawnTrip, TripEndpoint, TripMode,
+};
+use std::collections::BTreeMap;
+use std::fs::File;
+
+// Turns a generic table of trips, like the output of some travel demand model, into a Scenario.
+//
+// A .csv file needs these columns:
+//   person,departure,origin_lon,origin_lat,destination_lon,destination_lat,mode
+// A .geojson file needs a FeatureCollection of LineStrings from the origin to the destination,
+// with person, departure, and mode properties.
+//
+// Departure looks like 07:30:00. Mode is walk, bike, transit, or drive. Every row with the same
+// person is one person's schedule for the day.
+//
+// Endpoints inside the map snap to the nearest building. Endpoints outside of it snap to the
+// nearest border. Rows that can't be matched, and people whose schedules don't make sense, are
+// reported and skipped.
+pub fn import(path: &str, map: &Map, scenario_name: &str, timer: &mut Timer) -> Scenario {
+    let rows = if path.ends_with(".csv") {
+        read_csv(path)
+    } else if path.ends_with(".geojson") || path.ends_with(".json") {
+        read_geojson(path)
+    } else {
+        Err(format!("{} must end with .csv or .geojson", path))
+    }
+    .unwrap_or_else(|err| panic!("Can't read trip table {}: {}", path, err));
+    let num_rows = rows.len();
+
+    let mut snapper = Snapper::new(map);
+    // Keep people in the order they first appear
+    let mut people: Vec<(String, Vec<IndividTrip>)> = Vec::new();
+    let mut person_idx: BTreeMap<String, usize> = BTreeMap::new();
+    let mut unmatched = 0;
+    timer.start_iter("match trips to the map", num_rows);
+    for (idx, row) in rows.into_iter().enumerate() {
+        timer.next();
+        let row_num = idx + 1;
+        let row = match row {
+            Ok(row) => row,
+            Err(err) => {
+                timer.warn(format!("Row {}: {}", row_num, err));
+                unmatched += 1;
+                continue;
+            }
+        };
+        match snapper.spawn_trip(&row, map) {
+            Ok(trip) => {
+                let idx = *person_idx.entry(row.person.clone()).or_insert_with(|| {
+                    people.push((row.person.clone(), Vec::new()));
+                    people.len() - 1
+                });
+                people[idx].1.push(IndividTrip {
+                    depart: row.departure,
+                    trip,
+                    cancelled: false,
+                });
+            }
+            Err(err) => {
+                timer.warn(format!("Row {} (person {}): {}", row_num, row.person, err));
+                unmatched += 1;
+            }
+        }
+    }
+    timer.note(format!(
+        "{} of {} rows couldn't be matched to {}",
+        prettyprint_usize(unmatched),
+        prettyprint_usize(num_rows),
+        map.get_name()
+    ));
+
+    let mut scenario = Scenario::empty(map, scenario_name);
+    scenario.only_seed_buses = None;
+    let mut bad_schedules = 0;
+    for (orig_person, mut trips) in people {
+        trips.sort_by_key(|t| t.depart);
+        let person = PersonSpec {
+            id: PersonID(scenario.people.len()),
+            orig_id: None,
+            trips,
+        };
+        if let Err(err) = person.check_schedule(map) {
+            timer.warn(format!("Person {}: {}", orig_person, err));
+            bad_schedules += 1;
+            continue;
+        }
+        scenario.people.push(person);
+    }
+    timer.note(format!(
+        "{} people imported, {} skipped because their schedules don't make sense",
+        prettyprint_usize(scenario.people.len()),
+        prettyprint_usize(bad_schedules)
+    ));
+    scenario
+}
+
+struct Row {
+    person: String,
+    departure: Time,
+    origin: LonLat,
+    destination: LonLat,
+    mode: TripMode,
+}
+
+#[derive(Deserialize)]
+struct CsvRow {
+    person: String,
+    departure: String,
+    origin_lon: f64,
+    origin_lat: f64,
+    destination_lon: f64,
+    destination_lat: f64,
+    mode: String,
+}
+
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    geometry: LineString,
+    properties: Properties,
+}
+
+#[derive(Deserialize)]
+struct LineString {
+    coordinates: Vec<Vec<f64>>,
+}
+
+#[derive(Deserialize)]
+struct Properties {
+    // Some tools write numeric IDs
+    person: serde_json::Value,
+    departure: String,
+    mode: String,
+}
+
+// A bad row doesn't stop the import, so each row gets its own result.
+fn read_csv(path: &str) -> Result<Vec<Result<Row, String>>, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    Ok(csv::Reader::from_reader(file)
+        .deserialize()
+        .map(|rec: Result<CsvRow, csv::Error>| -> Result<Row, String> {
+            let rec = rec.map_err(|err| err.to_string())?;
+            Ok(Row {
+                person: rec.person,
+                departure: parse_time(&rec.departure)?,
+                origin: LonLat::new(rec.origin_lon, rec.origin_lat),
+                destination: LonLat::new(rec.destination_lon, rec.destination_lat),
+                mode: parse_mode(&rec.mode)?,
+            })
+        })
+        .collect())
+}
+
+fn read_geojson(path: &str) -> Result<Vec<Result<Row, String>>, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let collection: FeatureCollection =
+        serde_json::from_reader(file).map_err(|err| err.to_string())?;
+    Ok(collection
+        .features
+        .into_iter()
+        .map(|f| -> Result<Row, String> {
+            let pts = &f.geometry.coordinates;
+            if pts.len() < 2 || pts.iter().any(|pt| pt.len() < 2) {
+                return Err("need a LineString from origin to destination".to_string());
+            }
+            let last = pts.len() - 1;
+            Ok(Row {
+                person: match f.properties.person {
+                    serde_json::Value::String(s) => s,
+                    x => x.to_string(),
+                },
+                departure: parse_time(&f.properties.departure)?,
+                origin: LonLat::new(pts[0][0], pts[0][1]),
+                destination: LonLat::new(pts[last][0], pts[last][1]),
+                mode: parse_mode(&f.properties.mode)?,
+            })
+        })
+        .collect())
+}
+
+fn parse_time(x: &str) -> Result<Time, String> {
+    Time::parse(x).map_err(|err| format!("bad departure {}: {}", x, err))
+}
+
+fn parse_mode(x: &str) -> Result<TripMode, String> {
+    TripMode::all()
+        .into_iter()
+        .find(|m| format!("{:?}", m).to_lowercase() == x.to_lowercase())
+        .ok_or_else(|| format!("unknown mode {}", x))
+}
+
+struct Snapper {
+    closest_bldg: FindClosest<BuildingID>,
+    // Per mode, the borders where trips can enter and leave the map
+    incoming_borders: BTreeMap<TripMode, Vec<(IntersectionID, LonLat)>>,
+    outgoing_borders: BTreeMap<TripMode, Vec<(IntersectionID, LonLat)>>,
+    // A generic trip table doesn't have parcels, so every distinct place off the map gets its own
+    // ID, counting up from 0.
+    parcels: BTreeMap<LonLat, usize>,
+}
+
+impl Snapper {
+    fn new(map: &Map) -> Snapper {
+        let mut closest_bldg = FindClosest::new(map.get_bounds());
+        for b in map.all_buildings() {
+            closest_bldg.add(b.id, b.polygon.points());
+        }
+
+        let bounds = map.get_gps_bounds();
+        let mut incoming_borders = BTreeMap::new();
+        let mut outgoing_borders = BTreeMap::new();
+        for mode in TripMode::all() {
+            let constraints = constraints(mode);
+            incoming_borders.insert(
+                mode,
+                map.all_incoming_borders()
+                    .into_iter()
+                    .filter(|i| !i.get_outgoing_lanes(map, constraints).is_empty())
+                    .filter_map(|i| i.polygon.center().to_gps(bounds).map(|pt| (i.id, pt)))
+                    .collect(),
+            );
+            outgoing_borders.insert(
+                mode,
+                map.all_outgoing_borders()
+                    .into_iter()
+                    .filter(|i| !i.get_incoming_lanes(map, constraints).is_empty())
+                    .filter_map(|i| i.polygon.center().to_gps(bounds).map(|pt| (i.id, pt)))
+                    .collect(),
+            );
+        }
+
+        Snapper {
+            closest_bldg,
+            incoming_borders,
+            outgoing_borders,
+            parcels: BTreeMap::new(),
+        }
+    }
+
+    fn spawn_trip(&mut self, row: &Row, map: &Map) -> Result<SpawnTrip, String> {
+        let from = self.snap(row.origin, row.mode, true, map)?;
+        let to = self.snap(row.destination, row.mode, false, map)?;
+        match (&from, &to) {
+            (TripEndpoint::Border(_, _), TripEndpoint::Border(_, _)) => {
+                // TODO Handle trips passing through the map
+                return Err("both endpoints are off the map".to_string());
+            }
+            (TripEndpoint::Bldg(b1), TripEndpoint::Bldg(b2)) if b1 == b2 => {
+                return Err(format!("both endpoints are at {}", b1));
+            }
+            _ => {}
+        }
+        SpawnTrip::new(from, to, row.mode, map).ok_or_else(|| {
+            format!(
+                "can't {} between {} and {}",
+                row.mode.verb(),
+                row.origin,
+                row.destination
+            )
+        })
+    }
+
+    // Off the map, trips starting somewhere enter by an incoming border, and trips ending somewhere
+    // leave by an outgoing border.
+    fn snap(
+        &mut self,
+        gps: LonLat,
+        mode: TripMode,
+        starting: bool,
+        map: &Map,
+    ) -> Result<TripEndpoint, String> {
+        if map.get_gps_bounds().contains(gps) {
+            let pt = Pt2D::forcibly_from_gps(gps, map.get_gps_bounds());
+            return self
+                .closest_bldg
+                .closest_pt(pt, Distance::meters(100.0))
+                .map(|(b, _)| TripEndpoint::Bldg(b))
+                .ok_or_else(|| format!("no building near {}", gps));
+        }
+        let borders = if starting {
+            &self.incoming_borders[&mode]
+        } else {
+            &self.outgoing_borders[&mode]
+        };
+        let i = borders
+            .iter()
+            .min_by_key(|(_, pt)| pt.fast_dist(gps))
+            .map(|(i, _)| *i)
+            .ok_or_else(|| format!("no border to reach {}", gps))?;
+        let next_id = self.parcels.len();
+        let parcel_id = *self.parcels.entry(gps).or_insert(next_id);
+        Ok(TripEndpoint::Border(
+            i,
+            Some(OffMapLocation { gps, parcel_id }),
+        ))
+    }
+}
+
+fn constraints(mode: TripMode) -> PathConstraints {
+    match mode {
+        TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
+        TripMode::Drive => PathConstraints::Car,
+        TripMode::Bike => PathConstraints::Bike,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::Pt2D;
+
+    fn write_tmp(name: &str, contents: &str) -> String {
+        let path = format!("{}/{}", std::env::temp_dir().display(), name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let path = write_tmp(
+            "trip_table_test.csv",
+            "person,departure,origin_lon,origin_lat,destination_lon,destination_lat,mode\n\
+             p1,07:30:00,-122.3,47.6,-122.31,47.61,Drive\n\
+             p1,25:00:00,-122.31,47.61,-122.3,47.6,walk\n\
+             p2,yesterday,-122.3,47.6,-122.31,47.61,walk\n\
+             p2,08:00:00,-122.3,47.6,-122.31,47.61,teleport\n\
+             p3,08:00:00,west,47.6,-122.31,47.61,bike\n",
+        );
+        let rows = read_csv(&path).unwrap();
+        assert_eq!(rows.len(), 5);
+
+        let row = rows[0].as_ref().unwrap();
+        assert_eq!(row.person, "p1");
+        assert_eq!(
+            row.departure,
+            Time::START_OF_DAY + geom::Duration::minutes(7 * 60 + 30)
+        );
+        assert_eq!(row.origin, LonLat::new(-122.3, 47.6));
+        assert_eq!(row.destination, LonLat::new(-122.31, 47.61));
+        assert_eq!(row.mode, TripMode::Drive);
+
+        // Times past midnight are the next day
+        let row = rows[1].as_ref().unwrap();
+        assert_eq!(
+            row.departure,
+            Time::START_OF_DAY + geom::Duration::hours(25)
+        );
+        assert_eq!(row.mode, TripMode::Walk);
+
+        // Bad rows don't stop the others from being read
+        assert!(rows[2].is_err());
+        assert!(rows[3].is_err());
+        assert!(rows[4].is_err());
+
+        assert!(read_csv("/does/not/exist.csv").is_err());
+    }
+
+    #[test]
+    fn test_read_geojson() {
+        let path = write_tmp(
+            "trip_table_test.geojson",
+            r#"{"type": "FeatureCollection", "features": [
+                {"type": "Feature",
+                 "geometry": {"type": "LineString", "coordinates": [[-122.3, 47.6], [-122.305, 47.605], [-122.31, 47.61]]},
+                 "properties": {"person": 7, "departure": "07:30:00", "mode": "transit"}},
+                {"type": "Feature",
+                 "geometry": {"type": "LineString", "coordinates": [[-122.3, 47.6]]},
+                 "properties": {"person": "8", "departure": "07:30:00", "mode": "walk"}}
+            ]}"#,
+        );
+        let rows = read_geojson(&path).unwrap();
+        assert_eq!(rows.len(), 2);
+        let row = rows[0].as_ref().unwrap();
+        assert_eq!(row.person, "7");
+        assert_eq!(row.origin, LonLat::new(-122.3, 47.6));
+        assert_eq!(row.destination, LonLat::new(-122.31, 47.61));
+        assert_eq!(row.mode, TripMode::Transit);
+        // Only one point
+        assert!(rows[1].is_err());
+    }
+
+    // The synthetic maps have no buildings, so this only checks endpoints off the map.
+    #[test]
+    fn test_snap_off_map() {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        let bounds = map.get_gps_bounds();
+        let center = map.get_bounds().center();
+        // Twice as far from the center of the map as each border
+        let beyond = |i: IntersectionID| {
+            let pt = map.get_i(i).polygon.center();
+            Pt2D::new(2.0 * pt.x() - center.x(), 2.0 * pt.y() - center.y()).forcibly_to_gps(bounds)
+        };
+        let borders: Vec<IntersectionID> =
+            map.all_incoming_borders().iter().map(|i| i.id).collect();
+        let mut snapper = Snapper::new(&map);
+
+        for i in &borders {
+            match snapper.snap(beyond(*i), TripMode::Drive, true, &map) {
+                Ok(TripEndpoint::Border(snapped, Some(_))) => assert_eq!(snapped, *i),
+                x => panic!("{} didn't snap to itself: {:?}", i, x),
+            }
+        }
+
+        // Each place off the map gets its own parcel, and returning to one reuses it
+        let parcel = |endpt: TripEndpoint| match endpt {
+            TripEndpoint::Border(_, Some(loc)) => loc.parcel_id,
+            x => panic!("{:?} isn't off the map", x),
+        };
+        let a = beyond(borders[0]);
+        let b = beyond(borders[1]);
+        let pa = parcel(snapper.snap(a, TripMode::Walk, true, &map).unwrap());
+        let pb = parcel(snapper.snap(b, TripMode::Walk, false, &map).unwrap());
+        assert_ne!(pa, pb);
+        assert_eq!(
+            parcel(snapper.snap(a, TripMode::Walk, false, &map).unwrap()),
+            pa
+        );
+
+        // Inside the map, but no buildings
+        assert!(snapper
+            .snap(center.forcibly_to_gps(bounds), TripMode::Walk, true, &map)
+            .is_err());
+
+        // Passing through the map isn't handled yet
+        let row = Row {
+            person: "p1".to_string(),
+            departure: Time::START_OF_DAY,
+            origin: a,
+            destination: b,
+            mode: TripMode::Drive,
+        };
+        assert!(snapper.spawn_trip(&row, &map).is_err());
+    }
+}
@@ -101,11 +101,21 @@ fn run(cmd: &mut Command) {
     }
 }
 
-// Converts a RawMap to a Map.
-pub fn raw_to_map(name: &str, build_ch: bool, timer: &mut Timer) -> map_model::Map {
+// Converts a RawMap to a Map. Optionally overrides traffic signals with real timing data.
+pub fn raw_to_map(
+    name: &str,
+    build_ch: bool,
+    signal_timing: Option<&str>,
+    timer: &mut Timer,
+) -> map_model::Map {
     timer.start(format!("Raw->Map for {}", name));
     let raw: map_model::raw::RawMap = abstutil::read_binary(abstutil::path_raw_map(name), timer);
-    let map = map_model::Map::create_from_raw(raw, build_ch, timer);
+    let mut map = map_model::Map::create_from_raw(raw, build_ch, timer);
+    if let Some(path) = signal_timing {
+        timer.start("import signal timing");
+        crate::signal_timing::import(path, &mut map, timer);
+        timer.stop("import signal timing");
+    }
     timer.start("save map");
     map.save();
     timer.stop("save map");
@@ -1,4 +1,5 @@
-use crate::{LaneID, Position};
+use crate::{LaneID, PathConstraints, Position};
+use geom::{Duration, Time};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -39,4 +40,59 @@ pub struct BusRoute {
     pub id: BusRouteID,
     pub name: String,
     pub stops: Vec<BusStopID>,
+    // How long after leaving the first stop a bus is scheduled to reach each stop. Same length as
+    // stops.
+    pub stop_offsets: Vec<Duration>,
+    // When buses leave the first stop, sorted and within one day; the timetable repeats daily.
+    // Each bus runs through the stops once. If there's no timetable in either direction, a single
+    // bus loops around the route forever instead.
+    pub departures: Vec<Time>,
+    // For routes going both ways, stops from this index on are the way back. Buses leaving at
+    // departures stop at the one before.
+    pub return_stop: Option<usize>,
+    // When buses leave stops[return_stop] and run to the last stop. Same rules as departures.
+    pub return_departures: Vec<Time>,
+    // Bus or Train. Trains stop on light rail tracks instead of next to the sidewalk.
+    pub route_type: PathConstraints,
+}
+
+impl BusRoute {
+    pub fn has_timetable(&self) -> bool {
+        !self.departures.is_empty() || !self.return_departures.is_empty()
+    }
+
+    // Where buses following the timetable start, and when they leave there
+    pub fn scheduled_starts(&self) -> Vec<(usize, &Vec<Time>)> {
+        let mut starts = vec![(0, &self.departures)];
+        if let Some(idx) = self.return_stop {
+            starts.push((idx, &self.return_departures));
+        }
+        starts
+    }
+
+    // Where a bus following the timetable from first_stop finishes its run
+    pub fn last_stop(&self, first_stop: usize) -> usize {
+        match self.return_stop {
+            Some(idx) if first_stop < idx => idx - 1,
+            _ => self.stops.len() - 1,
+        }
+    }
+
+    // The departures of the direction this stop is on
+    pub fn departures_through(&self, idx: usize) -> &Vec<Time> {
+        self.scheduled_starts()
+            .into_iter()
+            .rev()
+            .find(|(first, _)| *first <= idx)
+            .unwrap()
+            .1
+    }
+
+    // Does some bus go from this stop to the next one?
+    pub fn continues_after(&self, idx: usize) -> bool {
+        if !self.has_timetable() {
+            return true;
+        }
+        idx < self.last_stop(idx) && !self.departures_through(idx).is_empty()
+    }
 }
@@ -1,6 +1,7 @@
 use crate::raw::{OriginalIntersection, OriginalRoad};
 use crate::{
-    ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, LaneType, Map, RoadID, TurnID,
+    Actuated, ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, LaneType, Map,
+    PedestrianTiming, RoadID, TurnID,
 };
 use abstutil::{deserialize_btreemap, retain_btreemap, retain_btreeset, serialize_btreemap, Timer};
 use geom::Speed;
@@ -231,7 +232,15 @@ enum PermanentEditIntersection {
         )]
         must_stop: BTreeMap<OriginalRoad, bool>,
     },
-    TrafficSignal(seattle_traffic_signals::TrafficSignal),
+    TrafficSignal {
+        #[serde(flatten)]
+        signal: seattle_traffic_signals::TrafficSignal,
+        // The external format only describes fixed timing. One entry per phase.
+        #[serde(default)]
+        actuated: Vec<Option<Actuated>>,
+        #[serde(default)]
+        pedestrian_timing: PedestrianTiming,
+    },
     Closed,
 }
 
@@ -342,12 +351,12 @@ impl PermanentMapEdits {
                         let id = map.find_i_by_osm_id(i.osm_node_id)?;
                         Ok(EditCmd::ChangeIntersection {
                             i: id,
-                            new: new
-                                .from_permanent(id, map)
-                                .ok_or(format!("new ChangeIntersection of {} invalid", i))?,
-                            old: old
-                                .from_permanent(id, map)
-                                .ok_or(format!("old ChangeIntersection of {} invalid", i))?,
+                            new: new.from_permanent(id, map).map_err(|err| {
+                                format!("new ChangeIntersection of {} invalid: {}", i, err)
+                            })?,
+                            old: old.from_permanent(id, map).map_err(|err| {
+                                format!("old ChangeIntersection of {} invalid: {}", i, err)
+                            })?,
                         })
                     }
                 })
@@ -373,23 +382,24 @@ impl EditIntersection {
                     .map(|(r, val)| (map.get_r(*r).orig_id, val.must_stop))
                     .collect(),
             },
-            EditIntersection::TrafficSignal(ref ts) => {
-                PermanentEditIntersection::TrafficSignal(ts.export(map))
-            }
+            EditIntersection::TrafficSignal(ref ts) => PermanentEditIntersection::TrafficSignal {
+                signal: ts.export(map),
+                actuated: ts.phases.iter().map(|p| p.actuated.clone()).collect(),
+                pedestrian_timing: ts.pedestrian_timing.clone(),
+            },
             EditIntersection::Closed => PermanentEditIntersection::Closed,
         }
     }
 }
 
 impl PermanentEditIntersection {
-    fn from_permanent(self, i: IntersectionID, map: &Map) -> Option<EditIntersection> {
+    fn from_permanent(self, i: IntersectionID, map: &Map) -> Result<EditIntersection, String> {
         match self {
             PermanentEditIntersection::StopSign { must_stop } => {
                 let mut translated_must_stop = BTreeMap::new();
                 for (r, stop) in must_stop {
                     translated_must_stop.insert(
-                        map.find_r_by_osm_id(r.osm_way_id, (r.i1.osm_node_id, r.i2.osm_node_id))
-                            .ok()?,
+                        map.find_r_by_osm_id(r.osm_way_id, (r.i1.osm_node_id, r.i2.osm_node_id))?,
                         stop,
                     );
                 }
@@ -397,18 +407,46 @@ impl PermanentEditIntersection {
                 // Make sure the roads exactly match up
                 let mut ss = ControlStopSign::new(map, i);
                 if translated_must_stop.len() != ss.roads.len() {
-                    return None;
+                    return Err(format!(
+                        "stop sign has {} roads, but {} has {}",
+                        translated_must_stop.len(),
+                        i,
+                        ss.roads.len()
+                    ));
                 }
                 for (r, stop) in translated_must_stop {
-                    ss.roads.get_mut(&r)?.must_stop = stop;
+                    ss.roads
+                        .get_mut(&r)
+                        .ok_or(format!("{} doesn't touch {}", r, i))?
+                        .must_stop = stop;
                 }
 
-                Some(EditIntersection::StopSign(ss))
+                Ok(EditIntersection::StopSign(ss))
             }
-            PermanentEditIntersection::TrafficSignal(ts) => Some(EditIntersection::TrafficSignal(
-                ControlTrafficSignal::import(ts, i, map)?,
-            )),
-            PermanentEditIntersection::Closed => Some(EditIntersection::Closed),
+            PermanentEditIntersection::TrafficSignal {
+                signal,
+                actuated,
+                pedestrian_timing,
+            } => {
+                let mut ts = ControlTrafficSignal::import(signal, i, map).map_err(|err| {
+                    format!("traffic signal doesn't match {} anymore: {}", i, err)
+                })?;
+                ts.pedestrian_timing = pedestrian_timing;
+                if !actuated.is_empty() {
+                    if actuated.len() != ts.phases.len() {
+                        return Err(format!(
+                            "traffic signal has {} phases, but actuated settings for {}",
+                            ts.phases.len(),
+                            actuated.len()
+                        ));
+                    }
+                    for (phase, a) in ts.phases.iter_mut().zip(actuated) {
+                        phase.actuated = a;
+                    }
+                }
+                Ok(EditIntersection::TrafficSignal(ts))
+            }
+            PermanentEditIntersection::Closed => Ok(EditIntersection::Closed),
         }
     }
 }
@@ -442,3 +480,47 @@ impl OriginalLane {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_actuated_phases_must_match() {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        let i = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap()
+            .id;
+        let ts = map.get_traffic_signal(i).clone();
+        let mut edits = MapEdits::new();
+        edits.commands.push(EditCmd::ChangeIntersection {
+            i,
+            old: EditIntersection::TrafficSignal(ts.clone()),
+            new: EditIntersection::TrafficSignal(ts),
+        });
+
+        let mut perma = PermanentMapEdits::to_permanent(&edits, &map);
+        match perma.commands[0] {
+            PermanentEditCmd::ChangeIntersection {
+                new:
+                    PermanentEditIntersection::TrafficSignal {
+                        ref mut actuated, ..
+                    },
+                ..
+            } => {
+                actuated.push(None);
+            }
+            _ => unreachable!(),
+        }
+        let err = PermanentMapEdits::from_permanent(perma, &map)
+            .err()
+            .unwrap();
+        assert!(err.contains("actuated"), "wrong error: {}", err);
+    }
+}
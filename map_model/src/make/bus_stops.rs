@@ -4,7 +4,7 @@ use crate::{
     Position,
 };
 use abstutil::{MultiMap, Timer};
-use geom::{Bounds, Distance, GPSBounds, HashablePt2D, Pt2D};
+use geom::{Bounds, Distance, Duration, FindClosest, GPSBounds, HashablePt2D, Pt2D, Time};
 use gtfs;
 use std::collections::{BTreeMap, HashMap, HashSet};
 
@@ -17,21 +17,41 @@ pub fn make_bus_stops(
 ) -> (BTreeMap<BusStopID, BusStop>, Vec<BusRoute>) {
     timer.start("make bus stops");
     let mut bus_stop_pts: HashSet<HashablePt2D> = HashSet::new();
-    let mut route_lookups: HashMap<String, Vec<HashablePt2D>> = HashMap::new();
+    let mut train_stop_pts: HashSet<HashablePt2D> = HashSet::new();
+    // Each stop's offset and whether it's on the way back
+    let mut route_lookups: HashMap<String, Vec<(HashablePt2D, Duration, bool)>> = HashMap::new();
     for route in bus_routes {
-        for gps in &route.stops {
+        for (idx, (gps, offset)) in route
+            .stops
+            .iter()
+            .zip(route.stop_offsets.iter())
+            .enumerate()
+        {
             if let Some(pt) = Pt2D::from_gps(*gps, gps_bounds) {
                 let hash_pt = pt.to_hashable();
-                bus_stop_pts.insert(hash_pt);
+                if route.is_light_rail {
+                    train_stop_pts.insert(hash_pt);
+                } else {
+                    bus_stop_pts.insert(hash_pt);
+                }
                 route_lookups
                     .entry(route.name.clone())
                     .or_insert_with(Vec::new)
-                    .push(hash_pt);
+                    .push((
+                        hash_pt,
+                        *offset,
+                        route.return_stop.map(|i| idx >= i).unwrap_or(false),
+                    ));
             }
         }
     }
 
-    let mut stops_per_sidewalk: MultiMap<LaneID, (Distance, HashablePt2D)> = MultiMap::new();
+    // Stations are on the tracks, but riders still reach them from some nearby sidewalk. None of
+    // the track positions means a regular bus stop.
+    let mut stops_per_sidewalk: MultiMap<
+        LaneID,
+        (Distance, HashablePt2D, Option<(LaneID, Distance)>),
+    > = MultiMap::new();
     for (pt, pos) in find_sidewalk_points(
         bounds,
         bus_stop_pts,
@@ -42,100 +62,385 @@ pub fn make_bus_stops(
     )
     .into_iter()
     {
-        stops_per_sidewalk.insert(pos.lane(), (pos.dist_along(), pt));
+        stops_per_sidewalk.insert(pos.lane(), (pos.dist_along(), pt, None));
     }
+    let mut track_pts = find_track_points(bounds, &train_stop_pts, map, Distance::meters(30.0));
+    for (pt, pos) in find_sidewalk_points(
+        bounds,
+        train_stop_pts,
+        map.all_lanes(),
+        Distance::ZERO,
+        Distance::meters(100.0),
+        timer,
+    )
+    .into_iter()
+    {
+        for track_pos in track_pts.remove(&pt).unwrap_or_else(Vec::new) {
+            stops_per_sidewalk.insert(pos.lane(), (pos.dist_along(), pt, Some(track_pos)));
+        }
+    }
+
     let mut point_to_stop_id: HashMap<HashablePt2D, BusStopID> = HashMap::new();
+    // A station is next to a few tracks, and each can be used in both directions. The routes pick
+    // the one that works.
+    let mut point_to_station_ids: HashMap<HashablePt2D, Vec<BusStopID>> = HashMap::new();
     let mut bus_stops: BTreeMap<BusStopID, BusStop> = BTreeMap::new();
 
     for (sidewalk_id, dists_set) in stops_per_sidewalk.consume().into_iter() {
         let road = map.get_parent(sidewalk_id);
-        if let Ok(driving_lane) =
-            road.find_closest_lane(sidewalk_id, vec![LaneType::Driving, LaneType::Bus])
-        {
-            let mut dists: Vec<(Distance, HashablePt2D)> = dists_set.into_iter().collect();
-            dists.sort_by_key(|(dist, _)| *dist);
-            for (idx, (dist_along, orig_pt)) in dists.into_iter().enumerate() {
-                let stop_id = BusStopID {
-                    sidewalk: sidewalk_id,
-                    idx,
-                };
-                point_to_stop_id.insert(orig_pt, stop_id);
-                let sidewalk_pos = Position::new(sidewalk_id, dist_along);
-                let driving_pos = sidewalk_pos.equiv_pos(driving_lane, Distance::ZERO, map);
-                bus_stops.insert(
-                    stop_id,
-                    BusStop {
-                        id: stop_id,
-                        sidewalk_pos,
-                        driving_pos,
-                    },
-                );
-            }
-        } else {
+        let driving_lane = road
+            .find_closest_lane(sidewalk_id, vec![LaneType::Driving, LaneType::Bus])
+            .ok();
+        let mut dists: Vec<(Distance, HashablePt2D, Option<(LaneID, Distance)>)> =
+            dists_set.into_iter().collect();
+        if driving_lane.is_none() && dists.iter().any(|(_, _, track)| track.is_none()) {
             timer.warn(format!(
                 "Can't find driving lane next to {}: {:?} and {:?}",
                 sidewalk_id, road.children_forwards, road.children_backwards
             ));
         }
+        dists.sort_by_key(|(dist, _, _)| *dist);
+        let mut idx = 0;
+        for (dist_along, orig_pt, track) in dists {
+            let sidewalk_pos = Position::new(sidewalk_id, dist_along);
+            let driving_pos = if let Some((lane, dist)) = track {
+                Position::new(lane, dist)
+            } else if let Some(lane) = driving_lane {
+                sidewalk_pos.equiv_pos(lane, Distance::ZERO, map)
+            } else {
+                continue;
+            };
+            let stop_id = BusStopID {
+                sidewalk: sidewalk_id,
+                idx,
+            };
+            idx += 1;
+            if track.is_some() {
+                point_to_station_ids
+                    .entry(orig_pt)
+                    .or_insert_with(Vec::new)
+                    .push(stop_id);
+            } else {
+                point_to_stop_id.insert(orig_pt, stop_id);
+            }
+            bus_stops.insert(
+                stop_id,
+                BusStop {
+                    id: stop_id,
+                    sidewalk_pos,
+                    driving_pos,
+                },
+            );
+        }
     }
 
     let mut routes: Vec<BusRoute> = Vec::new();
     for route in bus_routes {
         let route_name = route.name.to_string();
-        let stops: Vec<BusStopID> = route_lookups
-            .remove(&route_name)
-            .unwrap_or_else(Vec::new)
-            .into_iter()
-            .filter_map(|pt| point_to_stop_id.get(&pt))
-            .cloned()
-            .collect();
-        let id = BusRouteID(routes.len());
-        routes.push(BusRoute {
-            id,
+        let lookups = route_lookups.remove(&route_name).unwrap_or_else(Vec::new);
+        let stops = if route.is_light_rail {
+            pick_stations(
+                lookups
+                    .into_iter()
+                    .filter_map(|(pt, offset, returning)| {
+                        point_to_station_ids
+                            .get(&pt)
+                            .map(|ids| (ids, offset, returning))
+                    })
+                    .collect(),
+                &bus_stops,
+                map,
+            )
+        } else {
+            lookups
+                .into_iter()
+                .filter_map(|(pt, offset, returning)| {
+                    point_to_stop_id.get(&pt).map(|id| (*id, offset, returning))
+                })
+                .collect()
+        };
+        let mut bus_route = BusRoute {
+            id: BusRouteID(routes.len()),
             name: route_name.to_string(),
-            stops,
-        });
+            stops: Vec::new(),
+            stop_offsets: Vec::new(),
+            departures: route.departures.clone(),
+            return_stop: None,
+            return_departures: route.return_departures.clone(),
+            route_type: if route.is_light_rail {
+                PathConstraints::Train
+            } else {
+                PathConstraints::Bus
+            },
+        };
+        // Some stops in the feed are off the map
+        let return_start = route
+            .return_stop
+            .map(|idx| route.stop_offsets[idx])
+            .unwrap_or(Duration::ZERO);
+        keep_stops(&mut bus_route, stops, return_start);
+        routes.push(bus_route);
     }
     timer.stop("make bus stops");
     (bus_stops, routes)
 }
 
+// For each point, the closest position on every light rail track nearby
+fn find_track_points(
+    bounds: &Bounds,
+    pts: &HashSet<HashablePt2D>,
+    map: &Map,
+    max_dist_away: Distance,
+) -> HashMap<HashablePt2D, Vec<(LaneID, Distance)>> {
+    let mut closest: FindClosest<LaneID> = FindClosest::new(bounds);
+    for l in map.all_lanes() {
+        if l.is_light_rail() {
+            closest.add(l.id, l.lane_center_pts.points());
+        }
+    }
+
+    let mut results = HashMap::new();
+    for pt in pts {
+        let mut positions = Vec::new();
+        for (l, track_pt, _) in closest.all_close_pts(pt.to_pt2d(), max_dist_away) {
+            if let Some(dist) = map.get_l(l).dist_along_of_point(track_pt) {
+                positions.push((l, dist));
+            }
+        }
+        positions.sort();
+        if !positions.is_empty() {
+            results.insert(*pt, positions);
+        }
+    }
+    results
+}
+
+// Each station has a few candidate stops. Greedily pick ones that a train can travel between in
+// order.
+fn pick_stations(
+    stations: Vec<(&Vec<BusStopID>, Duration, bool)>,
+    bus_stops: &BTreeMap<BusStopID, BusStop>,
+    map: &Map,
+) -> Vec<(BusStopID, Duration, bool)> {
+    let mut stops: Vec<(BusStopID, Duration, bool)> = Vec::new();
+    for (idx, (candidates, offset, returning)) in stations.iter().enumerate() {
+        let pos = |id: &BusStopID| bus_stops[id].driving_pos;
+        let choice = candidates.iter().find(|id| {
+            let from_prev = stops
+                .last()
+                .map(|(prev, _, _)| can_travel(pos(prev), pos(*id), PathConstraints::Train, map))
+                .unwrap_or(true);
+            let to_next = stations
+                .get(idx + 1)
+                .map(|(next, _, _)| {
+                    next.iter()
+                        .any(|next| can_travel(pos(*id), pos(next), PathConstraints::Train, map))
+                })
+                .unwrap_or(true);
+            from_prev && to_next
+        });
+        if let Some(id) = choice {
+            stops.push((*id, *offset, *returning));
+        }
+    }
+    stops
+}
+
 pub fn fix_bus_route(map: &Map, r: &mut BusRoute) -> bool {
     // Trim out stops if needed; map borders sometimes mean some paths don't work.
-    let mut stops = Vec::new();
-    for stop in r.stops.drain(..) {
+    let return_stop = r.return_stop;
+    let return_start = return_stop
+        .map(|idx| r.stop_offsets[idx])
+        .unwrap_or(Duration::ZERO);
+    let mut stops: Vec<(BusStopID, Duration, bool)> = Vec::new();
+    for (idx, (stop, offset)) in r.stops.drain(..).zip(r.stop_offsets.drain(..)).enumerate() {
+        let returning = return_stop.map(|i| idx >= i).unwrap_or(false);
         if stops.is_empty() {
-            stops.push(stop);
+            stops.push((stop, offset, returning));
         } else {
-            if check_stops(*stops.last().unwrap(), stop, map) {
-                stops.push(stop);
+            if check_stops(stops.last().unwrap().0, stop, r.route_type, map) {
+                stops.push((stop, offset, returning));
             }
         }
     }
     // Don't forget the last and first
     while stops.len() >= 2 {
-        if check_stops(*stops.last().unwrap(), stops[0], map) {
+        if check_stops(stops.last().unwrap().0, stops[0].0, r.route_type, map) {
             break;
         }
         // TODO Or the front one
         stops.pop();
     }
-    r.stops = stops;
+    keep_stops(r, stops, return_start);
     r.stops.len() >= 2
 }
 
-fn check_stops(stop1: BusStopID, stop2: BusStopID, map: &Map) -> bool {
-    let bs1 = map.get_bs(stop1);
-    let bs2 = map.get_bs(stop2);
+// Only keep some stops of a route, given with their offsets before trimming and whether they're on
+// the way back. return_start is the offset of the original return_stop. The timetable is shifted
+// to start from the first stop kept in each direction.
+fn keep_stops(r: &mut BusRoute, stops: Vec<(BusStopID, Duration, bool)>, return_start: Duration) {
+    let forward_shift = stops
+        .iter()
+        .find(|(_, _, returning)| !returning)
+        .map(|(_, offset, _)| *offset);
+    let return_shift = stops
+        .iter()
+        .find(|(_, _, returning)| *returning)
+        .map(|(_, offset, _)| *offset - return_start);
+    let (departures, return_departures) = match (forward_shift, return_shift) {
+        (Some(dt1), Some(dt2)) => (
+            shift_departures(&r.departures, dt1),
+            shift_departures(&r.return_departures, dt2),
+        ),
+        (Some(dt), None) => (shift_departures(&r.departures, dt), Vec::new()),
+        // Only the way back is left, so it becomes the main direction
+        (None, Some(dt)) => (shift_departures(&r.return_departures, dt), Vec::new()),
+        (None, None) => (Vec::new(), Vec::new()),
+    };
+    r.departures = departures;
+    r.return_departures = return_departures;
+    r.return_stop = if forward_shift.is_some() {
+        stops.iter().position(|(_, _, returning)| *returning)
+    } else {
+        None
+    };
+
+    let first = stops
+        .first()
+        .map(|(_, offset, _)| *offset)
+        .unwrap_or(Duration::ZERO);
+    r.stop_offsets = stops.iter().map(|(_, offset, _)| *offset - first).collect();
+    r.stops = stops.into_iter().map(|(id, _, _)| id).collect();
+}
+
+// The timetable repeats daily, so a shifted departure might wrap around to the next day.
+fn shift_departures(departures: &Vec<Time>, dt: Duration) -> Vec<Time> {
+    let day = Duration::hours(24);
+    let mut results: Vec<Time> = departures
+        .iter()
+        .map(|t| {
+            let mut since_midnight = (*t - Time::START_OF_DAY + dt) % day;
+            if since_midnight < Duration::ZERO {
+                since_midnight += day;
+            }
+            Time::START_OF_DAY + since_midnight
+        })
+        .collect();
+    results.sort();
+    results.dedup();
+    results
+}
+
+fn check_stops(
+    stop1: BusStopID,
+    stop2: BusStopID,
+    constraints: PathConstraints,
+    map: &Map,
+) -> bool {
+    can_travel(
+        map.get_bs(stop1).driving_pos,
+        map.get_bs(stop2).driving_pos,
+        constraints,
+        map,
+    )
+}
+
+fn can_travel(pos1: Position, pos2: Position, constraints: PathConstraints, map: &Map) -> bool {
     // This is coming up because the dist_along's are in a bad order. But why should
     // this happen at all?
-    let ok1 = bs1.driving_pos.lane() != bs2.driving_pos.lane();
+    let ok1 = pos1.lane() != pos2.lane();
     let ok2 = map
-        .pathfind(PathRequest {
-            start: bs1.driving_pos,
-            end: bs2.driving_pos,
-            constraints: PathConstraints::Bus,
-        })
+        .pathfind(
+            PathRequest {
+                start: pos1,
+                end: pos2,
+                constraints,
+            },
+            Time::START_OF_DAY,
+        )
         .is_some();
     ok1 && ok2
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(idx: usize) -> BusStopID {
+        BusStopID {
+            sidewalk: LaneID(0),
+            idx,
+        }
+    }
+
+    fn at(h: usize, m: usize) -> Time {
+        Time::START_OF_DAY + Duration::hours(h) + Duration::minutes(m)
+    }
+
+    // 4 stops out and 3 back, 10 minutes apart
+    fn two_way_route() -> BusRoute {
+        BusRoute {
+            id: BusRouteID(0),
+            name: "test".to_string(),
+            stops: (0..7).map(stop).collect(),
+            stop_offsets: (0..7).map(|i| Duration::minutes(10 * i)).collect(),
+            departures: vec![at(7, 0), at(23, 55)],
+            return_stop: Some(4),
+            return_departures: vec![at(8, 0)],
+            route_type: PathConstraints::Bus,
+        }
+    }
+
+    fn trimmed(r: &BusRoute, keep: Vec<usize>) -> BusRoute {
+        let mut result = two_way_route();
+        let stops = keep
+            .into_iter()
+            .map(|i| (r.stops[i], r.stop_offsets[i], i >= r.return_stop.unwrap()))
+            .collect();
+        keep_stops(&mut result, stops, r.stop_offsets[r.return_stop.unwrap()]);
+        result
+    }
+
+    #[test]
+    fn test_keep_stops_rebases_timetable() {
+        let r = two_way_route();
+        // Drop the first stop of each direction
+        let result = trimmed(&r, vec![1, 2, 3, 5, 6]);
+        assert_eq!(
+            result.stops,
+            vec![stop(1), stop(2), stop(3), stop(5), stop(6)]
+        );
+        assert_eq!(
+            result.stop_offsets,
+            vec![0, 10, 20, 40, 50]
+                .into_iter()
+                .map(Duration::minutes)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(result.return_stop, Some(3));
+        // The late bus reaches the new first stop after midnight
+        assert_eq!(result.departures, vec![at(0, 5), at(7, 10)]);
+        assert_eq!(result.return_departures, vec![at(8, 10)]);
+    }
+
+    #[test]
+    fn test_keep_stops_one_direction_left() {
+        let r = two_way_route();
+
+        let result = trimmed(&r, vec![0, 1, 2]);
+        assert_eq!(result.return_stop, None);
+        assert_eq!(result.departures, r.departures);
+        assert!(result.return_departures.is_empty());
+
+        // The way back becomes the only direction
+        let result = trimmed(&r, vec![5, 6]);
+        assert_eq!(result.stops, vec![stop(5), stop(6)]);
+        assert_eq!(
+            result.stop_offsets,
+            vec![Duration::ZERO, Duration::minutes(10)]
+        );
+        assert_eq!(result.return_stop, None);
+        assert_eq!(result.departures, vec![at(8, 10)]);
+        assert!(result.return_departures.is_empty());
+    }
+}
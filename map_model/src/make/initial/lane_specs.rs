@@ -14,8 +14,12 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
     }
 
     // Easy special cases first.
+    // OSM draws each physical track as its own way, but rarely tags which way trains run on it,
+    // and the way's direction doesn't tell us either. So let trains use each track in both
+    // directions. Stations have a stop on every nearby track and direction, and each route picks
+    // the ones it can travel between in order.
     if osm_tags.get("railway") == Some(&"light_rail".to_string()) {
-        return (vec![LaneType::LightRail], Vec::new());
+        return (vec![LaneType::LightRail], vec![LaneType::LightRail]);
     }
     if osm_tags.get("junction") == Some(&"roundabout".to_string()) {
         return (vec![LaneType::Driving, LaneType::Sidewalk], Vec::new());
@@ -1,6 +1,6 @@
 use crate::{
-    ControlTrafficSignal, IntersectionID, Map, Phase, RoadID, TurnGroup, TurnGroupID, TurnPriority,
-    TurnType,
+    ControlTrafficSignal, IntersectionID, Map, PedestrianTiming, Phase, RoadID, TurnGroup,
+    TurnGroupID, TurnPriority, TurnType,
 };
 use abstutil::Timer;
 use geom::Duration;
@@ -13,19 +13,37 @@ pub fn get_possible_policies(
 ) -> Vec<(String, ControlTrafficSignal)> {
     let mut results = Vec::new();
 
+    if let Some(raw) = map.get_imported_signal(id) {
+        match ControlTrafficSignal::import(raw.clone(), id, map) {
+            Ok(ts) => {
+                results.push(("imported timing plan".to_string(), ts));
+            }
+            Err(err) => {
+                timer.warn(format!(
+                    "The timing plan imported for {} doesn't match its turns anymore: {}",
+                    id, err
+                ));
+            }
+        }
+    }
+
     // TODO Cache with lazy_static. Don't serialize in Map; the repo of signal data may evolve
     // independently.
     if let Some(raw) = seattle_traffic_signals::load_all_data()
         .unwrap()
         .remove(&map.get_i(id).orig_id.osm_node_id)
     {
-        if let Some(ts) = ControlTrafficSignal::import(raw, id, map) {
-            results.push(("hand-mapped current real settings".to_string(), ts));
-        } else {
-            timer.error(format!(
-                "seattle_traffic_signals data for {} out of date, go update it",
-                map.get_i(id).orig_id.osm_node_id
-            ));
+        match ControlTrafficSignal::import(raw, id, map) {
+            Ok(ts) => {
+                results.push(("hand-mapped current real settings".to_string(), ts));
+            }
+            Err(err) => {
+                timer.error(format!(
+                    "seattle_traffic_signals data for {} out of date, go update it: {}",
+                    map.get_i(id).orig_id.osm_node_id,
+                    err
+                ));
+            }
         }
     }
 
@@ -95,6 +113,7 @@ fn greedy_assignment(map: &Map, intersection: IntersectionID) -> ControlTrafficS
         id: intersection,
         phases,
         offset: Duration::ZERO,
+        pedestrian_timing: PedestrianTiming::default(),
         turn_groups,
     };
     // This must succeed
@@ -123,6 +142,7 @@ fn degenerate(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
         id: i,
         phases,
         offset: Duration::ZERO,
+        pedestrian_timing: PedestrianTiming::default(),
         turn_groups: TurnGroup::for_i(i, map),
     };
     ts.validate().ok()
@@ -170,6 +190,7 @@ fn three_way(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
         id: i,
         phases,
         offset: Duration::ZERO,
+        pedestrian_timing: PedestrianTiming::default(),
         turn_groups,
     };
     ts.validate().ok()
@@ -213,6 +234,7 @@ fn four_way_four_phase(map: &Map, i: IntersectionID) -> Option<ControlTrafficSig
         id: i,
         phases,
         offset: Duration::ZERO,
+        pedestrian_timing: PedestrianTiming::default(),
         turn_groups: TurnGroup::for_i(i, map),
     };
     ts.validate().ok()
@@ -255,6 +277,7 @@ fn four_way_two_phase(map: &Map, i: IntersectionID) -> Option<ControlTrafficSign
         id: i,
         phases,
         offset: Duration::ZERO,
+        pedestrian_timing: PedestrianTiming::default(),
         turn_groups: TurnGroup::for_i(i, map),
     };
     ts.validate().ok()
@@ -308,6 +331,7 @@ fn four_oneways(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
         id: i,
         phases,
         offset: Duration::ZERO,
+        pedestrian_timing: PedestrianTiming::default(),
         turn_groups: TurnGroup::for_i(i, map),
     };
     ts.validate().ok()
@@ -334,6 +358,7 @@ fn all_walk_all_yield(map: &Map, i: IntersectionID) -> ControlTrafficSignal {
         id: i,
         phases: vec![all_walk, all_yield],
         offset: Duration::ZERO,
+        pedestrian_timing: PedestrianTiming::default(),
         turn_groups,
     };
     // This must succeed
@@ -371,6 +396,7 @@ fn phase_per_road(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal>
         id: i,
         phases,
         offset: Duration::ZERO,
+        pedestrian_timing: PedestrianTiming::default(),
         turn_groups,
     };
     ts.validate().ok()
@@ -500,3 +526,99 @@ fn helper(items: &[usize], max_size: usize) -> Vec<Partition> {
     }
     results
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{EditCmd, LaneType, Map};
+    use abstutil::Timer;
+    use geom::Duration;
+
+    #[test]
+    fn test_imported_plan_survives_edits() {
+        let mut timer = Timer::throwaway();
+        let mut map = Map::new(abstutil::path_synthetic_map("signal_single"), &mut timer);
+        let i = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap()
+            .id;
+
+        // Make up a plan that's nothing like the guessed ones
+        let mut raw = map.get_traffic_signal(i).export(&map);
+        raw.phases.reverse();
+        for p in raw.phases.iter_mut() {
+            p.duration_seconds = 17;
+        }
+        assert_eq!(map.import_traffic_signal(raw), Ok(i));
+        let imported = map.get_traffic_signal(i).clone();
+        assert_eq!(imported.phases[0].duration, Duration::seconds(17.0));
+
+        // Turning one of two driving lanes into a bus lane regenerates the signal, but the turns
+        // between roads stay the same.
+        let l = map
+            .all_lanes()
+            .iter()
+            .find(|l| {
+                l.lane_type == LaneType::Driving
+                    && (l.src_i == i || l.dst_i == i)
+                    && map
+                        .get_parent(l.id)
+                        .all_lanes()
+                        .into_iter()
+                        .filter(|other| {
+                            let other = map.get_l(*other);
+                            other.lane_type == LaneType::Driving
+                                && other.src_i == l.src_i
+                                && other.id != l.id
+                        })
+                        .count()
+                        > 0
+            })
+            .unwrap()
+            .id;
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeLaneType {
+            id: l,
+            lt: LaneType::Bus,
+            orig_lt: LaneType::Driving,
+        });
+        map.apply_edits(edits, &mut timer);
+        assert_eq!(map.get_traffic_signal(i).phases, imported.phases);
+        assert_eq!(map.get_traffic_signal(i).offset, imported.offset);
+    }
+
+    #[test]
+    fn test_unmatched_movements_listed() {
+        let mut timer = Timer::throwaway();
+        let mut map = Map::new(abstutil::path_synthetic_map("signal_single"), &mut timer);
+        let i = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap()
+            .id;
+        let guessed = map.get_traffic_signal(i).phases.clone();
+
+        let mut raw = map.get_traffic_signal(i).export(&map);
+        let mut bad_ways = Vec::new();
+        for (idx, p) in raw.phases.iter_mut().enumerate() {
+            if let Some(t) = p.protected_turns.get_mut(0) {
+                t.to.osm_way_id = -1 - (idx as i64);
+                bad_ways.push(t.to.osm_way_id);
+            }
+        }
+        assert!(!bad_ways.is_empty());
+        let err = map.import_traffic_signal(raw).unwrap_err();
+        for way in bad_ways {
+            assert!(
+                err.contains(&format!("to way {} ", way)),
+                "{} isn't listed in: {}",
+                way,
+                err
+            );
+        }
+        // The guessed timing stays
+        assert_eq!(map.get_traffic_signal(i).phases, guessed);
+    }
+}
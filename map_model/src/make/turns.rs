@@ -20,6 +20,7 @@ pub fn make_all_turns(
 
     let mut raw_turns: Vec<Turn> = Vec::new();
     raw_turns.extend(make_vehicle_turns(i, roads, lanes, timer));
+    raw_turns.extend(make_rail_turns(i, roads, lanes));
     raw_turns.extend(make_walking_turns(driving_side, i, roads, lanes, timer));
     let unique_turns = ensure_unique(raw_turns);
 
@@ -262,6 +263,36 @@ fn make_vehicle_turns(
     result.into_iter().filter_map(|x| x).collect()
 }
 
+// Tracks only connect to other tracks. Where a track ends, trains reverse onto the other direction
+// of the same track.
+fn make_rail_turns(i: &Intersection, all_roads: &Vec<Road>, lanes: &Vec<Lane>) -> Vec<Turn> {
+    let tracks: Vec<&Road> = i
+        .roads
+        .iter()
+        .map(|r| &all_roads[r.0])
+        .filter(|r| r.is_light_rail())
+        .collect();
+
+    let mut result = Vec::new();
+    for r1 in &tracks {
+        for l1 in filter_lanes(r1.incoming_lanes(i.id), LaneType::LightRail) {
+            for r2 in &tracks {
+                if r1.id == r2.id && tracks.len() > 1 {
+                    continue;
+                }
+                for l2 in filter_lanes(r2.outgoing_lanes(i.id), LaneType::LightRail) {
+                    let tt = TurnType::from_angles(
+                        lanes[l1.0].last_line().angle(),
+                        lanes[l2.0].first_line().angle(),
+                    );
+                    result.extend(make_vehicle_turn(lanes, i.id, l1, l2, tt));
+                }
+            }
+        }
+    }
+    result
+}
+
 fn make_vehicle_turns_for_dead_end(
     i: &Intersection,
     roads: &Vec<Road>,
@@ -4,11 +4,13 @@ use crate::{
     connectivity, make, osm, Area, AreaID, Building, BuildingID, BusRoute, BusRouteID, BusStop,
     BusStopID, ControlStopSign, ControlTrafficSignal, EditCmd, EditEffects, EditIntersection,
     Intersection, IntersectionID, IntersectionType, Lane, LaneID, LaneType, MapEdits, ParkingLot,
-    ParkingLotID, Path, PathConstraints, PathRequest, Position, Road, RoadID, Turn, TurnGroupID,
-    TurnID, TurnType, NORMAL_LANE_THICKNESS, SIDEWALK_THICKNESS,
+    ParkingLotID, Path, PathConstraints, PathRequest, Position, Road, RoadID, Traversable, Turn,
+    TurnGroupID, TurnID, TurnType, NORMAL_LANE_THICKNESS, SIDEWALK_THICKNESS,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Error, Timer, Warn};
-use geom::{Angle, Bounds, Distance, GPSBounds, Line, PolyLine, Polygon, Pt2D, Speed};
+use geom::{
+    Angle, Bounds, Distance, Duration, GPSBounds, Line, PolyLine, Polygon, Pt2D, Speed, Time,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
@@ -36,6 +38,9 @@ pub struct Map {
     // Note that border nodes belong in neither!
     stop_signs: BTreeMap<IntersectionID, ControlStopSign>,
     traffic_signals: BTreeMap<IntersectionID, ControlTrafficSignal>,
+    // Real timing plans imported when building the map. Edits that regenerate these signals use
+    // the plans again, instead of guessing.
+    imported_signals: BTreeMap<IntersectionID, seattle_traffic_signals::TrafficSignal>,
 
     gps_bounds: GPSBounds,
     bounds: Bounds,
@@ -132,6 +137,7 @@ impl Map {
             ]),
             stop_signs: BTreeMap::new(),
             traffic_signals: BTreeMap::new(),
+            imported_signals: BTreeMap::new(),
             gps_bounds: GPSBounds::new(),
             bounds: Bounds::new(),
             driving_side: DrivingSide::Right,
@@ -664,16 +670,74 @@ impl Map {
         &self.boundary_polygon
     }
 
-    pub fn pathfind(&self, req: PathRequest) -> Option<Path> {
+    // Cars leaving at different times of day might get different paths; see
+    // set_hourly_driving_costs. The whole path uses the costs for the hour of departure, even if
+    // the trip lasts into later hours.
+    pub fn pathfind(&self, req: PathRequest, departure: Time) -> Option<Path> {
+        assert!(!self.pathfinder_dirty);
+        self.pathfinder
+            .as_ref()
+            .unwrap()
+            .pathfind(req, self, departure)
+    }
+
+    // Slow; see VehiclePathfinder::pathfind_with_delays. Also returns the cost of the path.
+    pub fn pathfind_with_delays(
+        &self,
+        req: PathRequest,
+        delays: &BTreeMap<Traversable, Duration>,
+        departure: Time,
+    ) -> Option<(Path, Duration)> {
+        assert!(!self.pathfinder_dirty);
+        self.pathfinder
+            .as_ref()
+            .unwrap()
+            .pathfind_with_delays(req, self, delays, departure)
+    }
+
+    pub(crate) fn car_cost(&self, lane: LaneID, turn: TurnID, departure: Time) -> usize {
+        self.pathfinder
+            .as_ref()
+            .unwrap()
+            .car_cost(lane, turn, self, departure)
+    }
+
+    // From now on, cars pathfind using these travel times instead of free-flow estimates, wherever
+    // they're known. Pass an empty map to go back to free-flow costs. Slow; the car pathfinding
+    // graph is rebuilt.
+    pub fn set_measured_driving_costs(
+        &mut self,
+        costs: BTreeMap<Traversable, Duration>,
+        timer: &mut Timer,
+    ) {
+        assert!(!self.pathfinder_dirty);
+        let mut pathfinder = self.pathfinder.take().unwrap();
+        pathfinder.set_measured_car_costs(self, costs, timer);
+        self.pathfinder = Some(pathfinder);
+    }
+
+    // Like set_measured_driving_costs, but cars leaving during each hour of the day (0 to 23) use
+    // that hour's travel times. A path uses the costs for the hour it starts in, even if it lasts
+    // into the next. Hours missing here use the usual costs. Pass an empty map to go back to one
+    // set of costs. Slow and uses lots of memory; each hour gets its own pathfinding graph. If
+    // any hour is out of range, nothing changes.
+    pub fn set_hourly_driving_costs(
+        &mut self,
+        hourly: BTreeMap<usize, BTreeMap<Traversable, Duration>>,
+        timer: &mut Timer,
+    ) -> Result<(), String> {
         assert!(!self.pathfinder_dirty);
-        self.pathfinder.as_ref().unwrap().pathfind(req, self)
+        let mut pathfinder = self.pathfinder.take().unwrap();
+        let result = pathfinder.set_hourly_car_costs(self, hourly, timer);
+        self.pathfinder = Some(pathfinder);
+        result
     }
 
     pub fn should_use_transit(
         &self,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, BusStopID, BusRouteID)> {
+    ) -> Option<Vec<(BusRouteID, BusStopID, BusStopID)>> {
         self.pathfinder
             .as_ref()
             .unwrap()
@@ -771,6 +835,29 @@ impl Map {
             }
         }
     }
+
+    // When building the map, replace the guessed timing of a traffic signal with a real plan.
+    pub fn import_traffic_signal(
+        &mut self,
+        raw: seattle_traffic_signals::TrafficSignal,
+    ) -> Result<IntersectionID, String> {
+        let id = self.find_i_by_osm_id(raw.intersection_osm_node_id)?;
+        if !self.traffic_signals.contains_key(&id) {
+            return Err(format!("{} isn't a traffic signal", id));
+        }
+        let ts = ControlTrafficSignal::import(raw.clone(), id, self)
+            .map_err(|err| format!("the plan doesn't match the turns at {}: {}", id, err))?;
+        self.traffic_signals.insert(id, ts);
+        self.imported_signals.insert(id, raw);
+        Ok(id)
+    }
+
+    pub(crate) fn get_imported_signal(
+        &self,
+        id: IntersectionID,
+    ) -> Option<&seattle_traffic_signals::TrafficSignal> {
+        self.imported_signals.get(&id)
+    }
 }
 
 impl Map {
@@ -812,11 +899,32 @@ impl Map {
         BTreeSet<IntersectionID>,
     ) {
         // TODO More efficient ways to do this: given two sets of edits, produce a smaller diff.
-        // Simplest strategy: Remove common prefix.
         let mut effects = EditEffects::new();
 
-        // First undo all existing edits.
-        let mut undo = std::mem::replace(&mut self.edits.commands, Vec::new());
+        // Commands both edits start with don't need to be touched. Besides being faster, this
+        // keeps the effects precise, which matters when a live simulation has to adjust.
+        let mut common = self
+            .edits
+            .commands
+            .iter()
+            .zip(new_edits.commands.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        // Undoing a lane edit regenerates the stop sign or traffic signal on either end, which
+        // would clobber an earlier edit to that intersection. In that case, just start over.
+        let touched: BTreeSet<IntersectionID> = self.edits.commands[common..]
+            .iter()
+            .flat_map(|cmd| touched_intersections(cmd, self))
+            .collect();
+        if self.edits.commands[..common].iter().any(|cmd| match cmd {
+            EditCmd::ChangeIntersection { i, .. } => touched.contains(i),
+            _ => false,
+        }) {
+            common = 0;
+        }
+
+        // First undo the existing edits past that.
+        let mut undo = self.edits.commands.split_off(common);
         undo.reverse();
         let mut undid = 0;
         for cmd in &undo {
@@ -824,11 +932,16 @@ impl Map {
                 undid += 1;
             }
         }
-        timer.note(format!("Undid {} / {} existing edits", undid, undo.len()));
+        timer.note(format!(
+            "Undid {} / {} existing edits, keeping {} in common",
+            undid,
+            undo.len(),
+            common
+        ));
 
         // Apply new edits.
         let mut applied = 0;
-        for cmd in &new_edits.commands {
+        for cmd in &new_edits.commands[common..] {
             if cmd.apply(&mut effects, self, timer) {
                 applied += 1;
             }
@@ -836,13 +949,20 @@ impl Map {
         timer.note(format!(
             "Applied {} / {} new edits",
             applied,
-            new_edits.commands.len()
+            new_edits.commands.len() - common
         ));
 
         // Might need to update bus stops.
         for id in &effects.changed_roads {
             let stops = self.get_r(*id).all_bus_stops(self);
             for s in stops {
+                // Stations stay on the tracks
+                if self
+                    .get_l(self.get_bs(s).driving_pos.lane())
+                    .is_light_rail()
+                {
+                    continue;
+                }
                 let sidewalk_pos = self.get_bs(s).sidewalk_pos;
                 // Must exist, because we aren't allowed to orphan a bus stop.
                 let driving_lane = self
@@ -854,9 +974,17 @@ impl Map {
             }
         }
 
+        // Retiming a traffic signal doesn't change any paths
+        if undo
+            .iter()
+            .chain(new_edits.commands[common..].iter())
+            .any(|cmd| !only_retimes_signal(cmd))
+        {
+            self.pathfinder_dirty = true;
+        }
+
         new_edits.update_derived(self);
         self.edits = new_edits;
-        self.pathfinder_dirty = true;
         (
             // TODO We just care about contraflow roads here
             effects.changed_roads,
@@ -914,6 +1042,7 @@ fn make_half_map(
         boundary_polygon: raw.boundary_polygon.clone(),
         stop_signs: BTreeMap::new(),
         traffic_signals: BTreeMap::new(),
+        imported_signals: BTreeMap::new(),
         gps_bounds,
         bounds,
         driving_side: raw.driving_side,
@@ -1324,6 +1453,28 @@ impl EditCmd {
     }
 }
 
+fn touched_intersections(cmd: &EditCmd, map: &Map) -> Vec<IntersectionID> {
+    match cmd {
+        EditCmd::ChangeLaneType { id: l, .. } | EditCmd::ReverseLane { l, .. } => {
+            let lane = map.get_l(*l);
+            vec![lane.src_i, lane.dst_i]
+        }
+        EditCmd::ChangeSpeedLimit { .. } => Vec::new(),
+        EditCmd::ChangeIntersection { i, .. } => vec![*i],
+    }
+}
+
+fn only_retimes_signal(cmd: &EditCmd) -> bool {
+    matches!(
+        cmd,
+        EditCmd::ChangeIntersection {
+            old: EditIntersection::TrafficSignal(_),
+            new: EditIntersection::TrafficSignal(_),
+            ..
+        }
+    )
+}
+
 // This clobbers previously set traffic signal overrides.
 // TODO Step 1: Detect and warn about that
 // TODO Step 2: Avoid when possible
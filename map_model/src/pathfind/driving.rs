@@ -1,10 +1,15 @@
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
 use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn};
-use crate::{Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Turn, TurnID};
+use crate::{
+    Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Traversable, Turn, TurnID,
+};
 use abstutil::MultiMap;
 use fast_paths::{deserialize_32, serialize_32, FastGraph, InputGraph, PathCalculator};
+use geom::Duration;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
 use thread_local::ThreadLocal;
 
 #[derive(Serialize, Deserialize)]
@@ -15,6 +20,10 @@ pub struct VehiclePathfinder {
     nodes: NodeMap<Node>,
     uber_turns: Vec<UberTurn>,
     constraints: PathConstraints,
+    // Measured travel times that replace the usual costs of some lanes and turns. These aren't
+    // saved with the map.
+    #[serde(skip_serializing, skip_deserializing)]
+    measured_costs: BTreeMap<Traversable, Duration>,
 
     #[serde(skip_serializing, skip_deserializing)]
     path_calc: ThreadLocal<RefCell<PathCalculator>>,
@@ -49,7 +58,8 @@ impl VehiclePathfinder {
             }
         }
 
-        let input_graph = make_input_graph(map, &nodes, &uber_turns, constraints);
+        let measured_costs = BTreeMap::new();
+        let input_graph = make_input_graph(map, &nodes, &uber_turns, constraints, &measured_costs);
 
         // All VehiclePathfinders have the same nodes (lanes), so if we're not the first being
         // built, seed from the node ordering.
@@ -65,6 +75,7 @@ impl VehiclePathfinder {
             nodes,
             uber_turns,
             constraints,
+            measured_costs,
             path_calc: ThreadLocal::new(),
         }
     }
@@ -113,15 +124,150 @@ impl VehiclePathfinder {
         ))
     }
 
+    // Like pathfind, but crossing each lane or turn also costs some extra delay, like congestion
+    // observed right now. The contraction hierarchy can't handle weights that keep changing, so
+    // this is a much slower Dijkstra's search over the same edges.
+    pub fn pathfind_with_delays(
+        &self,
+        req: &PathRequest,
+        map: &Map,
+        delays: &BTreeMap<Traversable, Duration>,
+    ) -> Option<(Path, usize)> {
+        assert!(!map.get_l(req.start.lane()).is_sidewalk());
+        let delay = |on: Traversable| {
+            delays
+                .get(&on)
+                .map(|d| d.inner_seconds().round() as usize)
+                .unwrap_or(0)
+        };
+        let mut uber_turn_entrances: MultiMap<LaneID, usize> = MultiMap::new();
+        for (idx, ut) in self.uber_turns.iter().enumerate() {
+            if ut
+                .path
+                .iter()
+                .all(|t| self.constraints.can_use(map.get_l(t.dst), map))
+            {
+                uber_turn_entrances.insert(ut.entry(), idx);
+            }
+        }
+
+        // For each lane reached, the previous lane and the turns from there
+        let mut backrefs: HashMap<LaneID, (LaneID, Vec<TurnID>)> = HashMap::new();
+        let mut best: HashMap<LaneID, usize> = HashMap::new();
+        let mut queue: BinaryHeap<(Reverse<usize>, LaneID)> = BinaryHeap::new();
+        best.insert(req.start.lane(), 0);
+        queue.push((Reverse(0), req.start.lane()));
+
+        while let Some((Reverse(weight), current)) = queue.pop() {
+            if current == req.end.lane() {
+                let mut steps = vec![PathStep::Lane(current)];
+                let mut at = current;
+                while let Some((prev, turns)) = backrefs.get(&at) {
+                    for t in turns.iter().rev() {
+                        steps.push(PathStep::Turn(*t));
+                        if t.src != *prev {
+                            steps.push(PathStep::Lane(t.src));
+                        }
+                    }
+                    steps.push(PathStep::Lane(*prev));
+                    at = *prev;
+                }
+                steps.reverse();
+                return Some((Path::new(map, steps, req.end.dist_along()), weight));
+            }
+            if weight > best[&current] {
+                continue;
+            }
+
+            let lane = map.get_l(current);
+            if !self.constraints.can_use(lane, map) || map.get_r(lane.parent).is_private() {
+                continue;
+            }
+            let mut next: Vec<(LaneID, Vec<TurnID>, usize)> = Vec::new();
+            let indices = uber_turn_entrances.get(current);
+            if indices.is_empty() {
+                for turn in map.get_turns_for(current, self.constraints) {
+                    let cost = self.edge_cost(lane, turn, map)
+                        + delay(Traversable::Lane(current))
+                        + delay(Traversable::Turn(turn.id));
+                    next.push((turn.id.dst, vec![turn.id], cost));
+                }
+            } else {
+                for idx in indices {
+                    let ut = &self.uber_turns[*idx];
+                    let mut sum_cost = 0;
+                    for t in &ut.path {
+                        sum_cost += measured_cost(
+                            map.get_l(t.src),
+                            map.get_t(*t),
+                            self.constraints,
+                            map,
+                            &self.measured_costs,
+                        ) + delay(Traversable::Lane(t.src))
+                            + delay(Traversable::Turn(*t));
+                    }
+                    next.push((ut.exit(), ut.path.clone(), sum_cost.max(1)));
+                }
+            }
+
+            for (dst, turns, cost) in next {
+                let weight = weight + cost;
+                if best.get(&dst).map(|w| weight < *w).unwrap_or(true) {
+                    best.insert(dst, weight);
+                    backrefs.insert(dst, (current, turns));
+                    queue.push((Reverse(weight), dst));
+                }
+            }
+        }
+        None
+    }
+
     pub fn apply_edits(&mut self, map: &Map) {
         // The NodeMap is just all lanes and uber-turns -- it won't change. So we can also reuse
         // the node ordering.
         // TODO Make sure the result of this is deterministic and equivalent to computing from
         // scratch.
-        let input_graph = make_input_graph(map, &self.nodes, &self.uber_turns, self.constraints);
+        let input_graph = make_input_graph(
+            map,
+            &self.nodes,
+            &self.uber_turns,
+            self.constraints,
+            &self.measured_costs,
+        );
         let node_ordering = self.graph.get_node_ordering();
         self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
     }
+
+    // Only makes sense for constraints whose costs are in seconds. Lanes and turns missing from
+    // costs keep their usual cost. The graph gets rebuilt, so this is as slow as apply_edits.
+    pub fn set_measured_costs(&mut self, map: &Map, costs: BTreeMap<Traversable, Duration>) {
+        self.measured_costs = costs;
+        self.apply_edits(map);
+    }
+
+    // Like set_measured_costs, but makes a new copy, reusing the node ordering.
+    pub fn with_measured_costs(
+        &self,
+        map: &Map,
+        costs: BTreeMap<Traversable, Duration>,
+    ) -> VehiclePathfinder {
+        let input_graph =
+            make_input_graph(map, &self.nodes, &self.uber_turns, self.constraints, &costs);
+        let node_ordering = self.graph.get_node_ordering();
+        VehiclePathfinder {
+            graph: fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap(),
+            nodes: self.nodes.clone(),
+            uber_turns: self.uber_turns.clone(),
+            constraints: self.constraints,
+            measured_costs: costs,
+            path_calc: ThreadLocal::new(),
+        }
+    }
+
+    // The cost of crossing the lane and then the turn, the same as the graph uses
+    pub fn edge_cost(&self, lane: &Lane, turn: &Turn, map: &Map) -> usize {
+        measured_cost(lane, turn, self.constraints, map, &self.measured_costs).max(1)
+    }
 }
 
 fn make_input_graph(
@@ -129,6 +275,7 @@ fn make_input_graph(
     nodes: &NodeMap<Node>,
     uber_turns: &Vec<UberTurn>,
     constraints: PathConstraints,
+    measured_costs: &BTreeMap<Traversable, Duration>,
 ) -> InputGraph {
     let mut input_graph = InputGraph::new();
 
@@ -170,7 +317,7 @@ fn make_input_graph(
                         from,
                         nodes.get(Node::Lane(turn.id.dst)),
                         // Round up! 0 cost edges are ignored
-                        cost(l, turn, constraints, map).max(1),
+                        measured_cost(l, turn, constraints, map, measured_costs).max(1),
                     );
                 }
             } else {
@@ -180,7 +327,13 @@ fn make_input_graph(
 
                     let mut sum_cost = 0;
                     for t in &ut.path {
-                        sum_cost += cost(map.get_l(t.src), map.get_t(*t), constraints, map);
+                        sum_cost += measured_cost(
+                            map.get_l(t.src),
+                            map.get_t(*t),
+                            constraints,
+                            map,
+                            measured_costs,
+                        );
                     }
                     input_graph.add_edge(from, nodes.get(Node::UberTurn(*idx)), sum_cost.max(1));
                     input_graph.add_edge(
@@ -205,6 +358,28 @@ fn make_input_graph(
     input_graph
 }
 
+// Like cost, but measured travel times replace the free-flow estimates where they're known.
+fn measured_cost(
+    lane: &Lane,
+    turn: &Turn,
+    constraints: PathConstraints,
+    map: &Map,
+    measured_costs: &BTreeMap<Traversable, Duration>,
+) -> usize {
+    let lane_time = measured_costs.get(&Traversable::Lane(lane.id));
+    let turn_time = measured_costs.get(&Traversable::Turn(turn.id));
+    if lane_time.is_none() && turn_time.is_none() {
+        return cost(lane, turn, constraints, map);
+    }
+    let t1 = lane_time
+        .cloned()
+        .unwrap_or_else(|| lane.length() / map.get_r(lane.parent).speed_limit);
+    let t2 = turn_time
+        .cloned()
+        .unwrap_or_else(|| turn.geom.length() / map.get_parent(turn.id.dst).speed_limit);
+    (t1 + t2).inner_seconds().round() as usize
+}
+
 pub fn cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map: &Map) -> usize {
     // TODO Could cost turns differently.
 
@@ -248,6 +423,194 @@ pub fn cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map: &Map) -
             };
             (lt_penalty * (t1 + t2)).inner_seconds().round() as usize
         }
+        PathConstraints::Train => {
+            let t1 = lane.length() / map.get_r(lane.parent).speed_limit;
+            let t2 = turn.geom.length() / map.get_parent(turn.id.dst).speed_limit;
+            (t1 + t2).inner_seconds().round() as usize
+        }
         PathConstraints::Pedestrian => unreachable!(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LaneType, Position};
+    use abstutil::Timer;
+    use geom::{Distance, Time};
+
+    #[test]
+    fn test_pathfind_with_delays() {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_double"),
+            &mut Timer::throwaway(),
+        );
+        let now = Time::START_OF_DAY;
+        // Is there another driving lane going the same way?
+        let has_sibling = |l: LaneID| {
+            let r = map.get_parent(l);
+            vec![&r.children_forwards, &r.children_backwards]
+                .into_iter()
+                .any(|lanes| {
+                    lanes.iter().any(|(id, _)| *id == l)
+                        && lanes
+                            .iter()
+                            .filter(|(_, lt)| *lt == LaneType::Driving)
+                            .count()
+                            > 1
+                })
+        };
+        let no_delays = BTreeMap::new();
+        let mut detours = 0;
+        for start in map.all_lanes() {
+            for end in map.all_lanes() {
+                if start.lane_type != LaneType::Driving
+                    || end.lane_type != LaneType::Driving
+                    || !map.get_i(start.src_i).is_border()
+                    || !map.get_i(end.dst_i).is_border()
+                {
+                    continue;
+                }
+                let req = PathRequest {
+                    start: Position::new(start.id, Distance::ZERO),
+                    end: Position::new(end.id, end.length()),
+                    constraints: PathConstraints::Car,
+                };
+                let (path, cost) = match map.pathfind_with_delays(req.clone(), &no_delays, now) {
+                    Some(pair) => pair,
+                    None => {
+                        continue;
+                    }
+                };
+                // Without any delays, this finds something as good as the usual pathfinding
+                assert_eq!(cost, path.cost_with_delays(0, &map, &no_delays, now));
+                assert_eq!(
+                    cost,
+                    map.pathfind(req.clone(), now)
+                        .unwrap()
+                        .cost_with_delays(0, &map, &no_delays, now)
+                );
+
+                // Jam a lane in the middle of the path. If there's another lane next to it, going
+                // around is better than waiting an hour.
+                let steps = path.get_steps();
+                for step in steps.iter().skip(1).take(steps.len().saturating_sub(2)) {
+                    let busy = match step {
+                        PathStep::Lane(l) if has_sibling(*l) => *l,
+                        _ => {
+                            continue;
+                        }
+                    };
+                    let mut delays = BTreeMap::new();
+                    delays.insert(Traversable::Lane(busy), Duration::hours(1));
+                    let (detour, detour_cost) =
+                        map.pathfind_with_delays(req.clone(), &delays, now).unwrap();
+                    assert_eq!(detour_cost, detour.cost_with_delays(0, &map, &delays, now));
+                    if detour.get_steps().contains(&PathStep::Lane(busy)) {
+                        // No way around it
+                        assert_eq!(detour_cost, cost + Duration::hours(1));
+                    } else {
+                        assert!(detour_cost < cost + Duration::hours(1));
+                        detours += 1;
+                    }
+                }
+            }
+        }
+        assert!(detours > 0);
+    }
+
+    #[test]
+    fn test_hourly_costs() {
+        let mut map = Map::new(
+            abstutil::path_synthetic_map("signal_double"),
+            &mut Timer::throwaway(),
+        );
+        // Two driving lanes going the same way between the signals
+        let (a, b) = map
+            .all_roads()
+            .iter()
+            .find_map(|r| {
+                if map.get_i(r.src_i).is_border() || map.get_i(r.dst_i).is_border() {
+                    return None;
+                }
+                let lanes: Vec<LaneID> = r
+                    .children_forwards
+                    .iter()
+                    .filter(|(_, lt)| *lt == LaneType::Driving)
+                    .map(|(l, _)| *l)
+                    .collect();
+                if lanes.len() == 2 {
+                    Some((lanes[0], lanes[1]))
+                } else {
+                    None
+                }
+            })
+            .unwrap();
+        let mut reqs = Vec::new();
+        for start in map.all_lanes() {
+            for end in map.all_lanes() {
+                if start.lane_type == LaneType::Driving
+                    && end.lane_type == LaneType::Driving
+                    && map.get_i(start.src_i).is_border()
+                    && map.get_i(end.dst_i).is_border()
+                {
+                    reqs.push(PathRequest {
+                        start: Position::new(start.id, Distance::ZERO),
+                        end: Position::new(end.id, end.length()),
+                        constraints: PathConstraints::Car,
+                    });
+                }
+            }
+        }
+        let at = |hour: usize| Time::START_OF_DAY + Duration::hours(hour) + Duration::minutes(30);
+        let before: Vec<Option<Path>> = reqs
+            .iter()
+            .map(|req| map.pathfind(req.clone(), at(12)))
+            .collect();
+
+        // One lane is jammed in the morning, the other in the evening
+        let mut hourly = BTreeMap::new();
+        hourly.insert(
+            8,
+            vec![(Traversable::Lane(a), Duration::hours(1))]
+                .into_iter()
+                .collect(),
+        );
+        hourly.insert(
+            17,
+            vec![(Traversable::Lane(b), Duration::hours(1))]
+                .into_iter()
+                .collect(),
+        );
+        map.set_hourly_driving_costs(hourly, &mut Timer::throwaway())
+            .unwrap();
+
+        let mut differ = Vec::new();
+        for (req, before) in reqs.into_iter().zip(before) {
+            // Hours without their own costs don't change
+            assert_eq!(map.pathfind(req.clone(), at(12)), before);
+            let morning = map.pathfind(req.clone(), at(8));
+            let evening = map.pathfind(req.clone(), at(17));
+            if morning != evening {
+                assert!(!morning
+                    .as_ref()
+                    .unwrap()
+                    .get_steps()
+                    .contains(&PathStep::Lane(a)));
+                assert!(!evening.unwrap().get_steps().contains(&PathStep::Lane(b)));
+                differ.push((req, morning));
+            }
+        }
+        assert!(!differ.is_empty());
+
+        // Bad hours don't change anything
+        let mut bad = BTreeMap::new();
+        bad.insert(24, BTreeMap::new());
+        assert!(map
+            .set_hourly_driving_costs(bad, &mut Timer::throwaway())
+            .is_err());
+        for (req, morning) in differ {
+            assert_eq!(map.pathfind(req, at(8)), morning);
+        }
+    }
+}
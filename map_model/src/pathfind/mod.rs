@@ -11,9 +11,9 @@ use crate::{
     osm, BusRouteID, BusStopID, Lane, LaneID, LaneType, Map, Position, Traversable, TurnID,
 };
 use abstutil::Timer;
-use geom::{Distance, PolyLine, EPSILON_DIST};
+use geom::{Distance, Duration, PolyLine, Time, EPSILON_DIST};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -208,6 +208,18 @@ impl Path {
         }
     }
 
+    // Swap the current lane for another one on the same road. Trusting the caller to keep the
+    // rest of the path connected.
+    pub fn change_current_lane(&mut self, lane: LaneID, map: &Map) {
+        assert_eq!(
+            map.get_parent(self.steps[0].as_lane()).id,
+            map.get_l(lane).parent
+        );
+        self.total_length -= self.steps[0].as_traversable().length(map);
+        self.steps[0] = PathStep::Lane(lane);
+        self.total_length += self.steps[0].as_traversable().length(map);
+    }
+
     pub fn current_step(&self) -> PathStep {
         self.steps[0]
     }
@@ -317,6 +329,109 @@ impl Path {
     pub fn get_steps(&self) -> &VecDeque<PathStep> {
         &self.steps
     }
+
+    // After the map is edited mid-simulation, the first step that can't be used anymore, if any.
+    pub fn find_broken_step(&self, constraints: PathConstraints, map: &Map) -> Option<usize> {
+        for (idx, step) in self.steps.iter().enumerate() {
+            let ok = match step {
+                PathStep::Lane(l) | PathStep::ContraflowLane(l) => {
+                    constraints.can_use(map.get_l(*l), map)
+                }
+                PathStep::Turn(t) => map.maybe_get_t(*t).is_some(),
+            };
+            // A lane might've been reversed, so make sure it still leads to the next turn.
+            let connected = match (step, self.steps.get(idx + 1)) {
+                (PathStep::Lane(l), Some(PathStep::Turn(t))) => map.get_l(*l).dst_i == t.parent,
+                (PathStep::ContraflowLane(l), Some(PathStep::Turn(t))) => {
+                    map.get_l(*l).src_i == t.parent
+                }
+                _ => true,
+            };
+            if !ok || !connected {
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    // Keep the steps up to the lane at idx, then find a new way from the end of that lane to the
+    // same destination, leaving at the given time. False if there isn't one, or if it doesn't start
+    // with that lane.
+    pub fn reroute(
+        &mut self,
+        idx: usize,
+        constraints: PathConstraints,
+        map: &Map,
+        now: Time,
+    ) -> bool {
+        match map.pathfind(self.reroute_request(idx, constraints, map), now) {
+            Some(path) => self.replace_from(idx, path, map),
+            None => false,
+        }
+    }
+
+    // From the end of the lane at idx to the same destination
+    pub fn reroute_request(
+        &self,
+        idx: usize,
+        constraints: PathConstraints,
+        map: &Map,
+    ) -> PathRequest {
+        let start = match self.steps[idx] {
+            PathStep::Lane(l) => Position::new(l, map.get_l(l).length()),
+            PathStep::ContraflowLane(l) => Position::new(l, Distance::ZERO),
+            PathStep::Turn(t) => panic!("Can't reroute from the middle of {}", t),
+        };
+        PathRequest {
+            start,
+            end: Position::new(self.last_step().as_lane(), self.end_dist),
+            constraints,
+        }
+    }
+
+    // Replace the steps from idx onwards with a path from reroute_request. False if it doesn't
+    // start with the same lane.
+    pub fn replace_from(&mut self, idx: usize, new_path: Path, map: &Map) -> bool {
+        if new_path.steps[0] != self.steps[idx] {
+            return false;
+        }
+
+        for step in self.steps.drain(idx..) {
+            self.total_length -= step.as_traversable().length(map);
+            if let PathStep::Lane(_) | PathStep::ContraflowLane(_) = step {
+                self.total_lanes -= 1;
+            }
+        }
+        for step in new_path.steps {
+            self.add(step, map);
+        }
+        self.end_dist = new_path.end_dist;
+        true
+    }
+
+    // What pathfind_with_delays thinks the steps from the lane at idx onwards cost, for cars
+    // leaving at the given time.
+    pub fn cost_with_delays(
+        &self,
+        idx: usize,
+        map: &Map,
+        delays: &BTreeMap<Traversable, Duration>,
+        now: Time,
+    ) -> Duration {
+        let mut total = Duration::ZERO;
+        for pair in self.steps.range(idx..).collect::<Vec<_>>().windows(2) {
+            if let (PathStep::Lane(l), PathStep::Turn(t)) = (pair[0], pair[1]) {
+                let cost = map.car_cost(*l, *t, now);
+                total += Duration::seconds(cost as f64);
+                for on in vec![Traversable::Lane(*l), Traversable::Turn(*t)] {
+                    if let Some(d) = delays.get(&on) {
+                        total += *d;
+                    }
+                }
+            }
+        }
+        total
+    }
 }
 
 // Who's asking for a path?
@@ -327,6 +442,7 @@ pub enum PathConstraints {
     Car,
     Bike,
     Bus,
+    Train,
 }
 
 impl PathConstraints {
@@ -337,6 +453,7 @@ impl PathConstraints {
             LaneType::Driving => PathConstraints::Car,
             LaneType::Biking => PathConstraints::Bike,
             LaneType::Bus => PathConstraints::Bus,
+            LaneType::LightRail => PathConstraints::Train,
             _ => panic!("PathConstraints::from_lt({:?}) doesn't make sense", lt),
         }
     }
@@ -359,6 +476,7 @@ impl PathConstraints {
                 }
             }
             PathConstraints::Bus => l.is_driving() || l.is_bus(),
+            PathConstraints::Train => l.is_light_rail(),
         }
     }
 
@@ -473,9 +591,14 @@ pub struct Pathfinder {
     car_graph: VehiclePathfinder,
     bike_graph: VehiclePathfinder,
     bus_graph: VehiclePathfinder,
+    train_graph: VehiclePathfinder,
     walking_graph: SidewalkPathfinder,
     // TODO Option just during initialization! Ewww.
     walking_with_transit_graph: Option<SidewalkPathfinder>,
+    // Cars leaving during some hours of the day use these instead of car_graph. They aren't saved
+    // with the map.
+    #[serde(skip_serializing, skip_deserializing)]
+    hourly_car_graphs: BTreeMap<usize, VehiclePathfinder>,
 }
 
 impl Pathfinder {
@@ -494,30 +617,106 @@ impl Pathfinder {
         let bus_graph = VehiclePathfinder::new(map, PathConstraints::Bus, Some(&car_graph));
         timer.stop("prepare pathfinding for buses");
 
+        timer.start("prepare pathfinding for trains");
+        let train_graph = VehiclePathfinder::new(map, PathConstraints::Train, None);
+        timer.stop("prepare pathfinding for trains");
+
         timer.start("prepare pathfinding for pedestrians");
-        let walking_graph = SidewalkPathfinder::new(map, false, &bus_graph);
+        let walking_graph = SidewalkPathfinder::new(map, false, &bus_graph, &train_graph);
         timer.stop("prepare pathfinding for pedestrians");
 
         Pathfinder {
             car_graph,
             bike_graph,
             bus_graph,
+            train_graph,
             walking_graph,
             walking_with_transit_graph: None,
+            hourly_car_graphs: BTreeMap::new(),
         }
     }
 
     pub fn setup_walking_with_transit(&mut self, map: &Map) {
-        self.walking_with_transit_graph = Some(SidewalkPathfinder::new(map, true, &self.bus_graph));
+        self.walking_with_transit_graph = Some(SidewalkPathfinder::new(
+            map,
+            true,
+            &self.bus_graph,
+            &self.train_graph,
+        ));
     }
 
-    pub fn pathfind(&self, req: PathRequest, map: &Map) -> Option<Path> {
+    pub fn pathfind(&self, req: PathRequest, map: &Map, departure: Time) -> Option<Path> {
         match req.constraints {
             PathConstraints::Pedestrian => self.walking_graph.pathfind(&req, map),
-            PathConstraints::Car => self.car_graph.pathfind(&req, map).map(|(p, _)| p),
+            PathConstraints::Car => self
+                .car_graph_at(departure)
+                .pathfind(&req, map)
+                .map(|(p, _)| p),
             PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
+            PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
+        }
+    }
+
+    // Only for cars, whose costs are in seconds
+    pub fn pathfind_with_delays(
+        &self,
+        req: PathRequest,
+        map: &Map,
+        delays: &BTreeMap<Traversable, Duration>,
+        departure: Time,
+    ) -> Option<(Path, Duration)> {
+        assert_eq!(req.constraints, PathConstraints::Car);
+        self.car_graph_at(departure)
+            .pathfind_with_delays(&req, map, delays)
+            .map(|(path, cost)| (path, Duration::seconds(cost as f64)))
+    }
+
+    // The cost of a car crossing the lane and then the turn, leaving at some time
+    pub fn car_cost(&self, lane: LaneID, turn: TurnID, map: &Map, departure: Time) -> usize {
+        self.car_graph_at(departure)
+            .edge_cost(map.get_l(lane), map.get_t(turn), map)
+    }
+
+    fn car_graph_at(&self, departure: Time) -> &VehiclePathfinder {
+        self.hourly_car_graphs
+            .get(&(departure.get_parts().0 % 24))
+            .unwrap_or(&self.car_graph)
+    }
+
+    // Only for cars. Rebuilds the graph.
+    pub fn set_measured_car_costs(
+        &mut self,
+        map: &Map,
+        costs: BTreeMap<Traversable, Duration>,
+        timer: &mut Timer,
+    ) {
+        timer.start("apply measured costs to car pathfinding");
+        self.car_graph.set_measured_costs(map, costs);
+        timer.stop("apply measured costs to car pathfinding");
+    }
+
+    // Only for cars. Hours (0 to 23) without costs use car_graph. Builds a graph for every hour.
+    pub fn set_hourly_car_costs(
+        &mut self,
+        map: &Map,
+        hourly: BTreeMap<usize, BTreeMap<Traversable, Duration>>,
+        timer: &mut Timer,
+    ) -> Result<(), String> {
+        if let Some(hour) = hourly.keys().find(|hour| **hour >= 24) {
+            return Err(format!(
+                "Hourly costs for hour {}; the day only has 24",
+                hour
+            ));
         }
+        self.hourly_car_graphs.clear();
+        timer.start_iter("apply hourly costs to car pathfinding", hourly.len());
+        for (hour, costs) in hourly {
+            timer.next();
+            let graph = self.car_graph.with_measured_costs(map, costs);
+            self.hourly_car_graphs.insert(hour, graph);
+        }
+        Ok(())
     }
 
     pub fn should_use_transit(
@@ -525,7 +724,7 @@ impl Pathfinder {
         map: &Map,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, BusStopID, BusRouteID)> {
+    ) -> Option<Vec<(BusRouteID, BusStopID, BusStopID)>> {
         self.walking_with_transit_graph
             .as_ref()
             .unwrap()
@@ -537,6 +736,14 @@ impl Pathfinder {
         self.car_graph.apply_edits(map);
         timer.stop("apply edits to car pathfinding");
 
+        if !self.hourly_car_graphs.is_empty() {
+            timer.start("apply edits to hourly car pathfinding");
+            for graph in self.hourly_car_graphs.values_mut() {
+                graph.apply_edits(map);
+            }
+            timer.stop("apply edits to hourly car pathfinding");
+        }
+
         timer.start("apply edits to bike pathfinding");
         self.bike_graph.apply_edits(map);
         timer.stop("apply edits to bike pathfinding");
@@ -545,15 +752,20 @@ impl Pathfinder {
         self.bus_graph.apply_edits(map);
         timer.stop("apply edits to bus pathfinding");
 
+        timer.start("apply edits to train pathfinding");
+        self.train_graph.apply_edits(map);
+        timer.stop("apply edits to train pathfinding");
+
         timer.start("apply edits to pedestrian pathfinding");
-        self.walking_graph.apply_edits(map, &self.bus_graph);
+        self.walking_graph
+            .apply_edits(map, &self.bus_graph, &self.train_graph);
         timer.stop("apply edits to pedestrian pathfinding");
 
         timer.start("apply edits to pedestrian using transit pathfinding");
         self.walking_with_transit_graph
             .as_mut()
             .unwrap()
-            .apply_edits(map, &self.bus_graph);
+            .apply_edits(map, &self.bus_graph, &self.train_graph);
         timer.stop("apply edits to pedestrian using transit pathfinding");
     }
 }
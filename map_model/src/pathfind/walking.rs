@@ -4,7 +4,7 @@ use crate::{
     BusRouteID, BusStopID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Position,
 };
 use fast_paths::{deserialize_32, serialize_32, FastGraph, InputGraph, PathCalculator};
-use geom::{Distance, Speed};
+use geom::{Distance, Duration, Speed, Time};
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use thread_local::ThreadLocal;
@@ -25,11 +25,19 @@ pub struct SidewalkPathfinder {
 enum Node {
     // false is src_i, true is dst_i
     SidewalkEndpoint(LaneID, bool),
+    // Waiting at a bus stop
     RideBus(BusStopID),
+    // On a bus, at some index of the route's stops
+    OnRoute(BusRouteID, usize),
 }
 
 impl SidewalkPathfinder {
-    pub fn new(map: &Map, use_transit: bool, bus_graph: &VehiclePathfinder) -> SidewalkPathfinder {
+    pub fn new(
+        map: &Map,
+        use_transit: bool,
+        bus_graph: &VehiclePathfinder,
+        train_graph: &VehiclePathfinder,
+    ) -> SidewalkPathfinder {
         let mut nodes = NodeMap::new();
         // We're assuming that to start with, no sidewalks are closed for construction!
         for l in map.all_lanes() {
@@ -43,9 +51,20 @@ impl SidewalkPathfinder {
             for stop in map.all_bus_stops().values() {
                 nodes.get_or_insert(Node::RideBus(stop.id));
             }
+            for route in map.get_all_bus_routes() {
+                for idx in 0..route.stops.len() {
+                    nodes.get_or_insert(Node::OnRoute(route.id, idx));
+                }
+            }
         }
 
-        let graph = fast_paths::prepare(&make_input_graph(map, &nodes, use_transit, bus_graph));
+        let graph = fast_paths::prepare(&make_input_graph(
+            map,
+            &nodes,
+            use_transit,
+            bus_graph,
+            train_graph,
+        ));
         SidewalkPathfinder {
             graph,
             nodes,
@@ -54,10 +73,16 @@ impl SidewalkPathfinder {
         }
     }
 
-    pub fn apply_edits(&mut self, map: &Map, bus_graph: &VehiclePathfinder) {
+    pub fn apply_edits(
+        &mut self,
+        map: &Map,
+        bus_graph: &VehiclePathfinder,
+        train_graph: &VehiclePathfinder,
+    ) {
         // The NodeMap is all sidewalks and bus stops -- it won't change. So we can also reuse the
         // node ordering.
-        let input_graph = make_input_graph(map, &self.nodes, self.use_transit, bus_graph);
+        let input_graph =
+            make_input_graph(map, &self.nodes, self.use_transit, bus_graph, train_graph);
         let node_ordering = self.graph.get_node_ordering();
         self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
     }
@@ -105,11 +130,11 @@ impl SidewalkPathfinder {
         for pair in path.windows(2) {
             let (l1, l1_endpt) = match pair[0] {
                 Node::SidewalkEndpoint(l, endpt) => (l, endpt),
-                Node::RideBus(_) => unreachable!(),
+                Node::RideBus(_) | Node::OnRoute(_, _) => unreachable!(),
             };
             let l2 = match pair[1] {
                 Node::SidewalkEndpoint(l, _) => l,
-                Node::RideBus(_) => unreachable!(),
+                Node::RideBus(_) | Node::OnRoute(_, _) => unreachable!(),
             };
 
             if l1 == l2 {
@@ -154,40 +179,40 @@ impl SidewalkPathfinder {
         Some(Path::new(map, steps, req.end.dist_along()))
     }
 
-    // Attempt the pathfinding and see if we should ride a bus.
+    // Attempt the pathfinding and see if we should ride buses. Returns every (route, board at,
+    // alight at) in order; consecutive rides mean transferring, maybe after walking a bit.
     pub fn should_use_transit(
         &self,
         map: &Map,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, BusStopID, BusRouteID)> {
+    ) -> Option<Vec<(BusRouteID, BusStopID, BusStopID)>> {
         let raw_path = fast_paths::calc_path(
             &self.graph,
             self.nodes.get(closest_node(start, map)),
             self.nodes.get(closest_node(end, map)),
         )?;
 
-        let mut nodes = self.nodes.translate(&raw_path);
-        let mut first_stop = None;
-        for n in &nodes {
-            if let Node::RideBus(stop) = n {
-                first_stop = Some(*stop);
-                break;
-            }
-        }
-        let first_stop = first_stop?;
-        let possible_routes = map.get_routes_serving_stop(first_stop);
-
-        nodes.reverse();
-        for n in nodes {
-            if let Node::RideBus(stop2) = n {
-                if let Some(route) = possible_routes.iter().find(|r| r.stops.contains(&stop2)) {
-                    assert_ne!(first_stop, stop2);
-                    return Some((first_stop, stop2, route.id));
+        let mut rides = Vec::new();
+        let mut boarded_at = None;
+        for pair in self.nodes.translate(&raw_path).windows(2) {
+            match (pair[0], pair[1]) {
+                (Node::RideBus(stop1), Node::OnRoute(_, _)) => {
+                    boarded_at = Some(stop1);
                 }
+                (Node::OnRoute(route, _), Node::RideBus(stop2)) => {
+                    let stop1 = boarded_at.take().unwrap();
+                    assert_ne!(stop1, stop2);
+                    rides.push((route, stop1, stop2));
+                }
+                _ => {}
             }
         }
-        unreachable!()
+        if rides.is_empty() {
+            None
+        } else {
+            Some(rides)
+        }
     }
 }
 
@@ -201,6 +226,7 @@ fn make_input_graph(
     nodes: &NodeMap<Node>,
     use_transit: bool,
     bus_graph: &VehiclePathfinder,
+    train_graph: &VehiclePathfinder,
 ) -> InputGraph {
     let mut input_graph = InputGraph::new();
 
@@ -243,38 +269,56 @@ fn make_input_graph(
         }
 
         // Connect each adjacent stop along a route, with the cost based on how long it'll take a
-        // bus to drive between the stops. Optimistically assume no waiting time at a stop.
+        // bus or train to drive between the stops. Vehicles following a timetable don't loop back
+        // to the first stop, and don't go from one direction of the route to the other.
         for route in map.get_all_bus_routes() {
-            for (stop1, stop2) in
-                route
-                    .stops
-                    .iter()
-                    .zip(route.stops.iter().skip(1))
-                    .chain(std::iter::once((
-                        route.stops.last().unwrap(),
-                        &route.stops[0],
-                    )))
-            {
-                if let Some((_, driving_cost)) = bus_graph.pathfind(
+            let graph = if route.route_type == PathConstraints::Train {
+                train_graph
+            } else {
+                bus_graph
+            };
+            let num_stops = route.stops.len();
+            let mut cycle_cost = 0;
+            for idx1 in 0..num_stops {
+                if !route.continues_after(idx1) {
+                    continue;
+                }
+                let idx2 = (idx1 + 1) % num_stops;
+                let stop1 = map.get_bs(route.stops[idx1]);
+                let stop2 = map.get_bs(route.stops[idx2]);
+                if let Some((_, driving_cost)) = graph.pathfind(
                     &PathRequest {
-                        start: map.get_bs(*stop1).driving_pos,
-                        end: map.get_bs(*stop2).driving_pos,
-                        constraints: PathConstraints::Bus,
+                        start: stop1.driving_pos,
+                        end: stop2.driving_pos,
+                        constraints: route.route_type,
                     },
                     map,
                 ) {
                     input_graph.add_edge(
-                        nodes.get(Node::RideBus(*stop1)),
-                        nodes.get(Node::RideBus(*stop2)),
+                        nodes.get(Node::OnRoute(route.id, idx1)),
+                        nodes.get(Node::OnRoute(route.id, idx2)),
                         driving_cost,
                     );
+                    cycle_cost += driving_cost;
                 } else {
                     panic!(
-                        "No bus route from {} to {} now! Prevent this edit",
-                        map.get_bs(*stop1).driving_pos,
-                        map.get_bs(*stop2).driving_pos
+                        "No {:?} route from {} to {} now! Prevent this edit",
+                        route.route_type, stop1.driving_pos, stop2.driving_pos
+                    );
+                }
+            }
+
+            // Boarding costs the expected wait. Getting off is free, except edges can't be 0.
+            for (idx, stop) in route.stops.iter().enumerate() {
+                let on_bus = nodes.get(Node::OnRoute(route.id, idx));
+                if route.continues_after(idx) {
+                    input_graph.add_edge(
+                        nodes.get(Node::RideBus(*stop)),
+                        on_bus,
+                        expected_wait(route.departures_through(idx), cycle_cost),
                     );
                 }
+                input_graph.add_edge(on_bus, nodes.get(Node::RideBus(*stop)), 1);
             }
         }
     }
@@ -282,6 +326,18 @@ fn make_input_graph(
     input_graph
 }
 
+// Riders show up whenever, so on average they wait half the time between buses leaving at these
+// times. If there's no timetable, one bus loops around the whole route.
+fn expected_wait(departures: &Vec<Time>, cycle_cost: usize) -> usize {
+    let headway = match departures.len() {
+        0 => Duration::seconds(cycle_cost as f64),
+        // TODO Riders could plan around a single daily bus, but pathfinding doesn't know the time
+        1 => Duration::hours(1),
+        n => (*departures.last().unwrap() - departures[0]) / ((n - 1) as f64),
+    };
+    ((headway / 2.0).inner_seconds().round() as usize).max(1)
+}
+
 fn to_s(dist: Distance) -> usize {
     let walking_speed = Speed::meters_per_second(1.34);
     let time = dist / walking_speed;
@@ -217,6 +217,9 @@ impl Road {
             }
         }
 
+        if self.is_light_rail() {
+            return Speed::miles_per_hour(35.0);
+        }
         if self.osm_tags.get(osm::HIGHWAY) == Some(&"primary".to_string())
             || self.osm_tags.get(osm::HIGHWAY) == Some(&"secondary".to_string())
         {
@@ -78,6 +78,14 @@ impl ControlStopSign {
             return ss;
         }
 
+        // Trains never stop at crossings; everybody else does.
+        if ss.roads.keys().any(|r| map.get_r(*r).is_light_rail()) {
+            for (r, cfg) in ss.roads.iter_mut() {
+                cfg.must_stop = !map.get_r(*r).is_light_rail();
+            }
+            return ss;
+        }
+
         // What's the rank of each road?
         let mut rank: HashMap<RoadID, usize> = HashMap::new();
         for r in ss.roads.keys() {
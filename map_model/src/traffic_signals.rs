@@ -12,6 +12,7 @@ pub struct ControlTrafficSignal {
     pub id: IntersectionID,
     pub phases: Vec<Phase>,
     pub offset: Duration,
+    pub pedestrian_timing: PedestrianTiming,
 
     #[serde(
         serialize_with = "serialize_btreemap",
@@ -20,11 +21,49 @@ pub struct ControlTrafficSignal {
     pub turn_groups: BTreeMap<TurnGroupID, TurnGroup>,
 }
 
+// Everything is zero by default, so pedestrians just follow the phases like vehicles do.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PedestrianTiming {
+    // A leading pedestrian interval. When a phase with crosswalks starts, vehicle turns conflicting
+    // with them wait this long, so pedestrians can get a head start.
+    pub leading_interval: Duration,
+    // Flashing don't walk. Pedestrians don't start crossing during this last part of a phase.
+    pub flashing_dont_walk: Duration,
+    // After an all-walk phase, vehicles wait this long before starting turns, so pedestrians
+    // crossing diagonally can clear out.
+    pub scramble_clearance: Duration,
+}
+
+impl Default for PedestrianTiming {
+    fn default() -> PedestrianTiming {
+        PedestrianTiming {
+            leading_interval: Duration::ZERO,
+            flashing_dont_walk: Duration::ZERO,
+            scramble_clearance: Duration::ZERO,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Phase {
     pub protected_groups: BTreeSet<TurnGroupID>,
     pub yield_groups: BTreeSet<TurnGroupID>,
+    // For actuated phases, this is the minimum green time.
     pub duration: Duration,
+    pub actuated: Option<Actuated>,
+}
+
+// Instead of always lasting a fixed duration, an actuated phase keeps going while agents keep
+// arriving for its turns.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Actuated {
+    // The phase never lasts longer than this, even if agents are still arriving.
+    pub max_duration: Duration,
+    // After the minimum, the phase ends ("gaps out") once nobody has started a turn for this long.
+    pub gap: Duration,
+    // If nobody's waiting for any of this phase's turns when it would start, move on to the next
+    // phase.
+    pub skip_if_no_demand: bool,
 }
 
 impl ControlTrafficSignal {
@@ -56,6 +95,7 @@ impl ControlTrafficSignal {
         cycle_length
     }
 
+    // Only accurate for fixed timing. The simulation tracks the real state of actuated phases.
     pub fn current_phase_and_remaining_time(&self, now: Time) -> (usize, &Phase, Duration) {
         let mut now_offset = ((now + self.offset) - Time::START_OF_DAY) % self.cycle_length();
         for (idx, p) in self.phases.iter().enumerate() {
@@ -166,6 +206,7 @@ impl Phase {
             protected_groups: BTreeSet::new(),
             yield_groups: BTreeSet::new(),
             duration: Duration::seconds(30.0),
+            actuated: None,
         }
     }
 
@@ -194,6 +235,13 @@ impl Phase {
         self.get_priority_of_group(g)
     }
 
+    // Only crosswalks are allowed
+    pub fn is_all_walk(&self) -> bool {
+        self.yield_groups.is_empty()
+            && !self.protected_groups.is_empty()
+            && self.protected_groups.iter().all(|g| g.crosswalk)
+    }
+
     pub fn get_priority_of_group(&self, g: TurnGroupID) -> TurnPriority {
         if self.protected_groups.contains(&g) {
             TurnPriority::Protected
@@ -250,43 +298,49 @@ impl ControlTrafficSignal {
         }
     }
 
+    // Fails if any movement in the plan doesn't exist in the map, listing every one that doesn't.
     pub fn import(
         raw: seattle_traffic_signals::TrafficSignal,
         id: IntersectionID,
         map: &Map,
-    ) -> Option<ControlTrafficSignal> {
+    ) -> Result<ControlTrafficSignal, String> {
         let mut phases = Vec::new();
+        let mut unmatched = BTreeSet::new();
         for p in raw.phases {
-            let num_protected = p.protected_turns.len();
-            let num_permitted = p.permitted_turns.len();
-            let protected_groups = p
-                .protected_turns
-                .into_iter()
-                .filter_map(|t| import_turn_group(t, map))
-                .collect::<BTreeSet<_>>();
-            let yield_groups = p
-                .permitted_turns
-                .into_iter()
-                .filter_map(|t| import_turn_group(t, map))
-                .collect::<BTreeSet<_>>();
-            if protected_groups.len() == num_protected && yield_groups.len() == num_permitted {
-                phases.push(Phase {
-                    protected_groups,
-                    yield_groups,
-                    duration: Duration::seconds(p.duration_seconds as f64),
-                });
-            } else {
-                return None;
-            }
+            let mut import_all = |turns: Vec<seattle_traffic_signals::Turn>| {
+                let mut groups = BTreeSet::new();
+                for t in turns {
+                    if let Some(g) = import_turn_group(&t, map) {
+                        groups.insert(g);
+                    } else {
+                        unmatched.insert(describe_turn(&t));
+                    }
+                }
+                groups
+            };
+            let protected_groups = import_all(p.protected_turns);
+            let yield_groups = import_all(p.permitted_turns);
+            phases.push(Phase {
+                protected_groups,
+                yield_groups,
+                duration: Duration::seconds(p.duration_seconds as f64),
+                actuated: None,
+            });
+        }
+        if !unmatched.is_empty() {
+            return Err(format!(
+                "no movement {}",
+                unmatched.into_iter().collect::<Vec<_>>().join(", ")
+            ));
         }
         ControlTrafficSignal {
             id,
             phases,
             offset: Duration::ZERO,
+            pedestrian_timing: PedestrianTiming::default(),
             turn_groups: TurnGroup::for_i(id, map),
         }
         .validate()
-        .ok()
     }
 }
 
@@ -312,16 +366,30 @@ fn export_turn_group(id: &TurnGroupID, map: &Map) -> seattle_traffic_signals::Tu
     }
 }
 
-fn import_turn_group(id: seattle_traffic_signals::Turn, map: &Map) -> Option<TurnGroupID> {
+fn import_turn_group(id: &seattle_traffic_signals::Turn, map: &Map) -> Option<TurnGroupID> {
     Some(TurnGroupID {
-        from: find_r(id.from, map)?,
-        to: find_r(id.to, map)?,
+        from: find_r(&id.from, map)?,
+        to: find_r(&id.to, map)?,
         parent: map.find_i_by_osm_id(id.intersection_osm_node_id).ok()?,
         crosswalk: id.is_crosswalk,
     })
 }
 
-fn find_r(id: seattle_traffic_signals::DirectedRoad, map: &Map) -> Option<DirectedRoadID> {
+// Using OSM IDs, since the movement might not exist in the map
+fn describe_turn(id: &seattle_traffic_signals::Turn) -> String {
+    format!(
+        "{}from way {} ({} to {}) to way {} ({} to {})",
+        if id.is_crosswalk { "crosswalk " } else { "" },
+        id.from.osm_way_id,
+        id.from.osm_node1,
+        id.from.osm_node2,
+        id.to.osm_way_id,
+        id.to.osm_node1,
+        id.to.osm_node2
+    )
+}
+
+fn find_r(id: &seattle_traffic_signals::DirectedRoad, map: &Map) -> Option<DirectedRoadID> {
     Some(DirectedRoadID {
         id: map
             .find_r_by_osm_id(id.osm_way_id, (id.osm_node1, id.osm_node2))
@@ -329,3 +397,52 @@ fn find_r(id: seattle_traffic_signals::DirectedRoad, map: &Map) -> Option<Direct
         forwards: id.is_forwards,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EditCmd, EditIntersection, MapEdits, PermanentMapEdits};
+
+    #[test]
+    fn test_pedestrian_timing_saved_with_edits() {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        let i = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap()
+            .id;
+        let orig = map.get_traffic_signal(i).clone();
+        // Guessed signals don't do anything special for pedestrians
+        assert_eq!(orig.pedestrian_timing, PedestrianTiming::default());
+
+        let mut ts = orig.clone();
+        ts.pedestrian_timing = PedestrianTiming {
+            leading_interval: Duration::seconds(3.0),
+            flashing_dont_walk: Duration::seconds(7.0),
+            scramble_clearance: Duration::seconds(2.0),
+        };
+        let mut edits = MapEdits::new();
+        edits.commands.push(EditCmd::ChangeIntersection {
+            i,
+            old: EditIntersection::TrafficSignal(orig),
+            new: EditIntersection::TrafficSignal(ts.clone()),
+        });
+
+        let loaded =
+            PermanentMapEdits::from_permanent(PermanentMapEdits::to_permanent(&edits, &map), &map)
+                .unwrap();
+        match loaded.commands[0] {
+            EditCmd::ChangeIntersection {
+                new: EditIntersection::TrafficSignal(ref loaded_ts),
+                ..
+            } => {
+                assert_eq!(loaded_ts.pedestrian_timing, ts.pedestrian_timing);
+            }
+            _ => panic!("The edit didn't load as a traffic signal"),
+        }
+    }
+}
@@ -1,5 +1,7 @@
-use crate::{AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType};
-use abstutil::Counter;
+use crate::{
+    AgentID, AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType, VehicleType,
+};
+use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use geom::{Distance, Duration, Histogram, Time};
 use map_model::{
     BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID, Path, PathRequest, RoadID,
@@ -14,9 +16,18 @@ pub struct Analytics {
     pub intersection_thruput: TimeSeriesCount<IntersectionID>,
 
     // Unlike everything else in Analytics, this is just for a moment in time.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
     pub demand: BTreeMap<TurnGroupID, usize>,
-    pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID)>,
+    // The last value is how late a scheduled bus was. Negative means early.
+    pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID, Option<Duration>)>,
     pub bus_passengers_waiting: Vec<(Time, BusStopID, BusRouteID)>,
+    // How many passengers were aboard as a bus left each stop
+    pub bus_loads: Vec<(Time, BusRouteID, BusStopID, usize)>,
+    // Every time a full bus left somebody behind
+    pub bus_pass_ups: Vec<(Time, BusRouteID, BusStopID)>,
     pub started_trips: BTreeMap<TripID, Time>,
     // TODO Hack: No TripMode means aborted
     // Finish time, ID, mode (or None as aborted), trip duration
@@ -28,6 +39,17 @@ pub struct Analytics {
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
+    // For cars, the total time spent crossing each lane and turn, and how many times that happened,
+    // grouped by the hour of the day (0 to 23) they started crossing. Only lanes crossed completely
+    // count, not the ones where trips start and end.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub traversal_times: BTreeMap<(usize, Traversable), (Duration, usize)>,
+    // Where each car is now, and since when
+    #[serde(skip_serializing, skip_deserializing)]
+    entered_traversable: BTreeMap<CarID, (Traversable, Time)>,
 
     // After we restore from a savestate, don't record anything. This is only going to make sense
     // if savestates are only used for quickly previewing against prebaked results, where we have
@@ -43,6 +65,8 @@ impl Analytics {
             demand: BTreeMap::new(),
             bus_arrivals: Vec::new(),
             bus_passengers_waiting: Vec::new(),
+            bus_loads: Vec::new(),
+            bus_pass_ups: Vec::new(),
             started_trips: BTreeMap::new(),
             finished_trips: Vec::new(),
             trip_log: Vec::new(),
@@ -50,6 +74,8 @@ impl Analytics {
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
             alerts: Vec::new(),
+            traversal_times: BTreeMap::new(),
+            entered_traversable: BTreeMap::new(),
             record_anything: true,
         }
     }
@@ -70,7 +96,10 @@ impl Analytics {
                     self.intersection_thruput.record(time, t.parent, mode);
 
                     if let Some(id) = map.get_turn_group(t) {
-                        *self.demand.entry(id).or_insert(0) -= 1;
+                        // A replayed event log doesn't know about the demand recorded when agents
+                        // spawned
+                        let count = self.demand.entry(id).or_insert(0);
+                        *count = count.saturating_sub(1);
                     }
                 }
             };
@@ -82,9 +111,45 @@ impl Analytics {
             _ => {}
         }
 
+        // Traversal times
+        if let Event::AgentEntersTraversable(AgentID::Car(car), to) = ev {
+            if car.1 == VehicleType::Car {
+                if let Some((from, since)) = self.entered_traversable.insert(car, (to, time)) {
+                    // A car's last stop on some earlier trip doesn't count
+                    let continued = match (from, to) {
+                        (Traversable::Lane(l), Traversable::Turn(t)) => t.src == l,
+                        (Traversable::Turn(t), Traversable::Lane(l)) => t.dst == l,
+                        _ => false,
+                    };
+                    if continued {
+                        let entry = self
+                            .traversal_times
+                            .entry((since.get_parts().0 % 24, from))
+                            .or_insert((Duration::ZERO, 0));
+                        entry.0 += time - since;
+                        entry.1 += 1;
+                    }
+                }
+            }
+        }
+        match ev {
+            Event::CarReachedParkingSpot(car, _) | Event::CarLeftParkingSpot(car, _) => {
+                self.entered_traversable.remove(&car);
+            }
+            _ => {}
+        }
+
         // Bus arrivals
-        if let Event::BusArrivedAtStop(bus, route, stop) = ev {
-            self.bus_arrivals.push((time, bus, route, stop));
+        if let Event::BusArrivedAtStop(bus, route, stop, lateness) = ev {
+            self.bus_arrivals.push((time, bus, route, stop, lateness));
+        }
+
+        // Bus crowding
+        if let Event::BusDepartedFromStop(_, route, stop, load) = ev {
+            self.bus_loads.push((time, route, stop, load));
+        }
+        if let Event::BusPassedUpRider(_, route, stop) = ev {
+            self.bus_pass_ups.push((time, route, stop));
         }
 
         // Bus passengers
@@ -182,6 +247,32 @@ impl Analytics {
         }
     }
 
+    // The average time cars took to cross each lane and turn
+    pub fn mean_traversal_times(&self) -> BTreeMap<Traversable, Duration> {
+        let mut sums: BTreeMap<Traversable, (Duration, usize)> = BTreeMap::new();
+        for ((_, on), (total, count)) in &self.traversal_times {
+            let entry = sums.entry(*on).or_insert((Duration::ZERO, 0));
+            entry.0 += *total;
+            entry.1 += *count;
+        }
+        sums.into_iter()
+            .map(|(on, (total, count))| (on, total / (count as f64)))
+            .collect()
+    }
+
+    // Like mean_traversal_times, but separately for each hour of the day. This can be passed to
+    // Map::set_hourly_driving_costs.
+    pub fn hourly_traversal_times(&self) -> BTreeMap<usize, BTreeMap<Traversable, Duration>> {
+        let mut results = BTreeMap::new();
+        for ((hour, on), (total, count)) in &self.traversal_times {
+            results
+                .entry(*hour)
+                .or_insert_with(BTreeMap::new)
+                .insert(*on, *total / (*count as f64));
+        }
+        results
+    }
+
     // TODO If these ever need to be speeded up, just cache the histogram and index in the events
     // list.
 
@@ -200,12 +291,12 @@ impl Analytics {
         None
     }
 
-    // Returns pairs of trip times for finished trips in both worlds. (before, after, mode)
+    // Returns pairs of trip times for finished trips in both worlds. (ID, before, after, mode)
     pub fn both_finished_trips(
         &self,
         now: Time,
         before: &Analytics,
-    ) -> Vec<(Duration, Duration, TripMode)> {
+    ) -> Vec<(TripID, Duration, Duration, TripMode)> {
         let mut a = BTreeMap::new();
         for (t, id, maybe_mode, dt) in &self.finished_trips {
             if *t > now {
@@ -223,7 +314,7 @@ impl Analytics {
             }
             if let Some(mode) = maybe_mode {
                 if let Some(dt1) = a.remove(id) {
-                    results.push((*dt, dt1, *mode));
+                    results.push((*id, *dt, dt1, *mode));
                 }
             }
         }
@@ -265,7 +356,7 @@ impl Analytics {
         r: BusRouteID,
     ) -> BTreeMap<BusStopID, Histogram<Duration>> {
         let mut per_bus: BTreeMap<CarID, Vec<(Time, BusStopID)>> = BTreeMap::new();
-        for (t, car, route, stop) in &self.bus_arrivals {
+        for (t, car, route, stop, _) in &self.bus_arrivals {
             if *t > now {
                 break;
             }
@@ -296,7 +387,7 @@ impl Analytics {
         r: BusRouteID,
     ) -> BTreeMap<BusStopID, Vec<(Time, Duration)>> {
         let mut per_bus: BTreeMap<CarID, Vec<(Time, BusStopID)>> = BTreeMap::new();
-        for (t, car, route, stop) in &self.bus_arrivals {
+        for (t, car, route, stop, _) in &self.bus_arrivals {
             if *t > now {
                 break;
             }
@@ -319,6 +410,66 @@ impl Analytics {
         delays_to_stop
     }
 
+    // For buses following a timetable, how many arrivals at each stop were (early, on time, late).
+    // On time means no more than 1 minute early or 5 minutes late.
+    pub fn schedule_adherence(
+        &self,
+        now: Time,
+        r: BusRouteID,
+    ) -> BTreeMap<BusStopID, (usize, usize, usize)> {
+        let mut results: BTreeMap<BusStopID, (usize, usize, usize)> = BTreeMap::new();
+        for (t, _, route, stop, lateness) in &self.bus_arrivals {
+            if *t > now {
+                break;
+            }
+            if *route != r {
+                continue;
+            }
+            if let Some(dt) = lateness {
+                let counts = results.entry(*stop).or_insert((0, 0, 0));
+                if *dt < Duration::seconds(-60.0) {
+                    counts.0 += 1;
+                } else if *dt <= Duration::minutes(5) {
+                    counts.1 += 1;
+                } else {
+                    counts.2 += 1;
+                }
+            }
+        }
+        results
+    }
+
+    // The load on each segment of a route, keyed by the stop starting the segment.
+    pub fn bus_loads(&self, now: Time, r: BusRouteID) -> BTreeMap<BusStopID, Histogram<usize>> {
+        let mut per_stop = BTreeMap::new();
+        for (t, route, stop, load) in &self.bus_loads {
+            if *t > now {
+                break;
+            }
+            if *route == r {
+                per_stop
+                    .entry(*stop)
+                    .or_insert_with(Histogram::new)
+                    .add(*load);
+            }
+        }
+        per_stop
+    }
+
+    // How many times a full bus left somebody waiting at each stop
+    pub fn bus_pass_ups(&self, now: Time, r: BusRouteID) -> BTreeMap<BusStopID, usize> {
+        let mut counts = BTreeMap::new();
+        for (t, route, stop) in &self.bus_pass_ups {
+            if *t > now {
+                break;
+            }
+            if *route == r {
+                *counts.entry(*stop).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
     // At some moment in time, what's the distribution of passengers waiting for a route like?
     pub fn bus_passenger_delays(
         &self,
@@ -338,7 +489,7 @@ impl Analytics {
             }
         }
 
-        for (t, _, route, stop) in &self.bus_arrivals {
+        for (t, _, route, stop, _) in &self.bus_arrivals {
             if *t > now {
                 break;
             }
@@ -365,6 +516,20 @@ impl Analytics {
             .collect()
     }
 
+    // How many buses each transit trip has boarded so far. More than 1 means transferring.
+    pub fn bus_boardings(&self, now: Time) -> BTreeMap<TripID, usize> {
+        let mut counts = BTreeMap::new();
+        for (t, trip, _, phase_type) in &self.trip_log {
+            if *t > now {
+                break;
+            }
+            if let TripPhaseType::RidingBus(_, _, _) = phase_type {
+                *counts.entry(*trip).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
     pub fn get_trip_phases(&self, trip: TripID, map: &Map) -> Vec<TripPhase> {
         let mut phases: Vec<TripPhase> = Vec::new();
         for (t, id, maybe_req, phase_type) in &self.trip_log {
@@ -383,7 +548,7 @@ impl Analytics {
                 // Unwrap should be safe, because this is the request that was actually done...
                 // TODO Not if this is prebaked data and we've made edits. Woops.
                 path: maybe_req.as_ref().and_then(|req| {
-                    map.pathfind(req.clone())
+                    map.pathfind(req.clone(), *t)
                         .map(|path| (req.start.dist_along(), path))
                 }),
                 has_path_req: maybe_req.is_some(),
@@ -545,6 +710,10 @@ pub struct TripPhase {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct TimeSeriesCount<X: Ord + Clone> {
     // (Road or intersection, mode, hour block) -> count for that hour
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
     pub counts: BTreeMap<(X, TripMode, usize), usize>,
 
     // Very expensive to store, so it's optional. But useful to flag on to experiment with
@@ -679,3 +848,31 @@ impl Window {
         self.times.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hourly_traversal_times() {
+        let a = Traversable::Lane(LaneID(0));
+        let b = Traversable::Lane(LaneID(1));
+        let secs = Duration::seconds;
+        let mut analytics = Analytics::new();
+        analytics.traversal_times.insert((8, a), (secs(60.0), 2));
+        analytics.traversal_times.insert((8, b), (secs(10.0), 1));
+        analytics.traversal_times.insert((9, a), (secs(90.0), 3));
+
+        let hourly = analytics.hourly_traversal_times();
+        assert_eq!(hourly.keys().cloned().collect::<Vec<_>>(), vec![8, 9]);
+        assert_eq!(hourly[&8][&a], secs(30.0));
+        assert_eq!(hourly[&8][&b], secs(10.0));
+        assert_eq!(hourly[&9][&a], secs(30.0));
+        assert!(!hourly[&9].contains_key(&b));
+
+        // The same crossings, ignoring the hour
+        let mean = analytics.mean_traversal_times();
+        assert_eq!(mean[&a], secs(30.0));
+        assert_eq!(mean[&b], secs(10.0));
+    }
+}
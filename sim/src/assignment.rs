@@ -0,0 +1,250 @@
+use crate::{Analytics, Scenario, Sim, SimFlags, TripMode, TripPhaseType};
+use abstutil::{prettyprint_usize, Timer};
+use geom::{Duration, Time};
+use map_model::{Map, PathConstraints, PathRequest, Traversable};
+use std::collections::BTreeMap;
+
+// Stop once fewer than this fraction of drivers would pick a different route.
+const CONVERGED: f64 = 0.01;
+
+// How one round of assignment went
+pub struct AssignmentIteration {
+    pub iteration: usize,
+    // How many driving trips would pick a different route with the updated costs
+    pub changed_routes: usize,
+    pub total_routes: usize,
+    // Summed over driving trips that finished before end_time in this round's run
+    pub total_driving_time: Duration,
+    pub finished_driving_trips: usize,
+}
+
+impl AssignmentIteration {
+    pub fn pct_changed(&self) -> f64 {
+        if self.total_routes == 0 {
+            0.0
+        } else {
+            100.0 * (self.changed_routes as f64) / (self.total_routes as f64)
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "Iteration {}: {} of {} driving routes changed ({:.1}%), {} driving trips took {} \
+             total",
+            self.iteration,
+            prettyprint_usize(self.changed_routes),
+            prettyprint_usize(self.total_routes),
+            self.pct_changed(),
+            prettyprint_usize(self.finished_driving_trips),
+            self.total_driving_time
+        )
+    }
+}
+
+// Normally drivers pick routes assuming every road is empty. This looks for routes that account
+// for congestion. Each iteration runs the scenario until end_time, measures how long cars took to
+// cross each lane and turn, and makes car pathfinding use those times. The next run's drivers pick
+// routes with those costs, and so on, until route choices barely change or max_iterations pass.
+//
+// To keep drivers from all jumping between the same two routes, the costs are a running average
+// over every iteration, not just the latest run (the method of successive averages).
+//
+// If hourly is true, the times are measured separately for each hour of the day, and drivers use
+// the costs for the hour they leave. Otherwise, one set of costs covers the whole run.
+//
+// Afterwards, the map keeps the final costs, so later simulations use the assigned routes.
+pub fn assign_traffic(
+    map: &mut Map,
+    scenario: &Scenario,
+    flags: &SimFlags,
+    end_time: Time,
+    max_iterations: usize,
+    hourly: bool,
+    timer: &mut Timer,
+) -> Vec<AssignmentIteration> {
+    let mut costs: BTreeMap<Traversable, Duration> = BTreeMap::new();
+    let mut hourly_costs: BTreeMap<usize, BTreeMap<Traversable, Duration>> = BTreeMap::new();
+    let mut results = Vec::new();
+    for iteration in 1..=max_iterations {
+        let name = format!("assign traffic, iteration {}", iteration);
+        timer.start(&name);
+        let analytics = run(map, scenario, flags, end_time);
+
+        let requests = driving_requests(&analytics);
+        let before: Vec<_> = requests
+            .iter()
+            .map(|(t, req)| map.pathfind(req.clone(), *t).map(|p| p.get_steps().clone()))
+            .collect();
+
+        if hourly {
+            for (hour, measured) in analytics.hourly_traversal_times() {
+                let costs = hourly_costs.entry(hour).or_insert_with(BTreeMap::new);
+                average(costs, measured, iteration);
+            }
+            // Analytics only measures hours 0 to 23
+            map.set_hourly_driving_costs(hourly_costs.clone(), timer)
+                .unwrap();
+        } else {
+            average(&mut costs, analytics.mean_traversal_times(), iteration);
+            map.set_measured_driving_costs(costs.clone(), timer);
+        }
+
+        let changed_routes = requests
+            .iter()
+            .zip(before)
+            .filter(|((t, req), before)| {
+                map.pathfind(req.clone(), *t).map(|p| p.get_steps().clone()) != *before
+            })
+            .count();
+        let mut total_driving_time = Duration::ZERO;
+        let mut finished_driving_trips = 0;
+        for (_, _, mode, dt) in &analytics.finished_trips {
+            if *mode == Some(TripMode::Drive) {
+                total_driving_time += *dt;
+                finished_driving_trips += 1;
+            }
+        }
+        timer.stop(&name);
+
+        let result = AssignmentIteration {
+            iteration,
+            changed_routes,
+            total_routes: requests.len(),
+            total_driving_time,
+            finished_driving_trips,
+        };
+        timer.note(result.describe());
+        let converged = result.pct_changed() / 100.0 < CONVERGED;
+        results.push(result);
+        if converged {
+            break;
+        }
+    }
+    results
+}
+
+// Fold this iteration's measurements into the running average
+fn average(
+    costs: &mut BTreeMap<Traversable, Duration>,
+    measured: BTreeMap<Traversable, Duration>,
+    iteration: usize,
+) {
+    for (on, dt) in measured {
+        let avg = match costs.get(&on) {
+            Some(prev) => *prev + (dt - *prev) / (iteration as f64),
+            None => dt,
+        };
+        costs.insert(on, avg);
+    }
+}
+
+fn run(map: &mut Map, scenario: &Scenario, flags: &SimFlags, end_time: Time) -> Analytics {
+    let mut timer = Timer::throwaway();
+    let mut sim = Sim::new(map, flags.opts.clone(), &mut timer);
+    scenario.instantiate(&mut sim, map, &mut flags.make_rng(), &mut timer);
+    sim.timed_step_with_edits(map, end_time - sim.time(), &mut None, &mut timer);
+    let analytics = sim.get_analytics().clone();
+
+    if let Some(edits) = sim.remove_temporary_edits(map) {
+        map.apply_edits(edits, &mut timer);
+        map.recalculate_pathfinding_after_edits(&mut timer);
+    }
+    analytics
+}
+
+// Every time somebody started driving, and from where to where
+fn driving_requests(analytics: &Analytics) -> Vec<(Time, PathRequest)> {
+    analytics
+        .trip_log
+        .iter()
+        .filter_map(|(t, _, maybe_req, phase)| match (maybe_req, phase) {
+            (Some(req), TripPhaseType::Driving) if req.constraints == PathConstraints::Car => {
+                Some((*t, req.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IndividTrip, PersonID, PersonSpec, SpawnTrip, TripEndpoint};
+    use map_model::LaneID;
+
+    #[test]
+    fn test_average() {
+        let a = Traversable::Lane(LaneID(0));
+        let b = Traversable::Lane(LaneID(1));
+        let secs = Duration::seconds;
+        let mut costs = BTreeMap::new();
+
+        average(&mut costs, vec![(a, secs(10.0))].into_iter().collect(), 1);
+        assert_eq!(costs[&a], secs(10.0));
+
+        // Something measured for the first time just uses that measurement
+        average(
+            &mut costs,
+            vec![(a, secs(20.0)), (b, secs(5.0))].into_iter().collect(),
+            2,
+        );
+        assert_eq!(costs[&a], secs(15.0));
+        assert_eq!(costs[&b], secs(5.0));
+
+        // Later measurements count for less, and things nobody crossed keep their old cost
+        average(&mut costs, vec![(a, secs(30.0))].into_iter().collect(), 3);
+        assert_eq!(costs[&a], secs(20.0));
+        assert_eq!(costs[&b], secs(5.0));
+    }
+
+    #[test]
+    fn test_assign_traffic() {
+        let mut timer = Timer::throwaway();
+        let mut flags = SimFlags::synthetic_test("signal_single", "assign_traffic");
+        flags.load = abstutil::path_synthetic_map("signal_single");
+        let mut map = Map::new(flags.load.clone(), &mut timer);
+
+        // Somebody drives between every pair of borders, one every half minute
+        let mut scenario = Scenario::empty(&map, "assign_traffic");
+        for from in map.all_incoming_borders() {
+            for to in map.all_outgoing_borders() {
+                if from.id == to.id {
+                    continue;
+                }
+                if let Some(trip) = SpawnTrip::new(
+                    TripEndpoint::Border(from.id, None),
+                    TripEndpoint::Border(to.id, None),
+                    TripMode::Drive,
+                    &map,
+                ) {
+                    let idx = scenario.people.len();
+                    scenario.people.push(PersonSpec {
+                        id: PersonID(idx),
+                        orig_id: None,
+                        trips: vec![IndividTrip {
+                            depart: Time::START_OF_DAY + Duration::seconds(30.0 * idx as f64),
+                            trip,
+                            cancelled: false,
+                        }],
+                    });
+                }
+            }
+        }
+        assert!(!scenario.people.is_empty());
+
+        let end_time = Time::START_OF_DAY + Duration::minutes(30);
+        let results = assign_traffic(&mut map, &scenario, &flags, end_time, 10, false, &mut timer);
+        // Without much traffic, the measured times quickly settle down
+        assert!(results.len() < 10);
+        let last = results.last().unwrap();
+        assert_eq!(last.changed_routes, 0);
+        for result in &results {
+            assert_eq!(result.total_routes, scenario.people.len());
+            assert_eq!(result.finished_driving_trips, scenario.people.len());
+        }
+
+        // The map keeps the final costs, and drivers still get everywhere
+        let analytics = run(&mut map, &scenario, &flags, end_time);
+        assert_eq!(driving_requests(&analytics).len(), scenario.people.len());
+    }
+}
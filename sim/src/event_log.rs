@@ -0,0 +1,162 @@
+use crate::{Analytics, Event};
+use geom::Time;
+use map_model::Map;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+
+// Streams every Event the simulation produces to a file, so other tools can analyze a run offline.
+// Paths ending in .json get one JSON object per line. Paths ending in .bin get a more compact
+// binary encoding.
+pub(crate) struct EventLog {
+    path: String,
+    binary: bool,
+    out: BufWriter<File>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    time: Time,
+    event: Event,
+}
+
+impl EventLog {
+    pub fn new(path: String) -> EventLog {
+        let binary = if path.ends_with(".bin") {
+            true
+        } else if path.ends_with(".json") {
+            false
+        } else {
+            panic!("Event log {} must end with .json or .bin", path);
+        };
+        let file = File::create(&path)
+            .unwrap_or_else(|err| panic!("Can't create event log {}: {}", path, err));
+        EventLog {
+            path,
+            binary,
+            out: BufWriter::new(file),
+        }
+    }
+
+    pub fn record(&mut self, time: Time, event: &Event) {
+        // Avoid cloning every event just to serialize it
+        #[derive(Serialize)]
+        struct EntryRef<'a> {
+            time: Time,
+            event: &'a Event,
+        }
+        let entry = EntryRef { time, event };
+
+        let result = if self.binary {
+            bincode::serialize_into(&mut self.out, &entry)
+                .map_err(|err| Error::new(ErrorKind::Other, err))
+        } else {
+            serde_json::to_writer(&mut self.out, &entry)
+                .map_err(Error::from)
+                .and_then(|_| writeln!(self.out))
+        };
+        if let Err(err) = result {
+            panic!("Can't write to event log {}: {}", self.path, err);
+        }
+    }
+}
+
+// A Sim can be cloned, but two simulations shouldn't write to the same log. The clone doesn't log
+// anything.
+#[derive(Default)]
+pub(crate) struct EventLogSink(pub Option<EventLog>);
+
+impl Clone for EventLogSink {
+    fn clone(&self) -> EventLogSink {
+        EventLogSink(None)
+    }
+}
+
+// Rebuilds Analytics from a log written by a previous run, without running the simulation again.
+// The map must have the same edits as the original run. Demand isn't rebuilt, since it's only
+// recorded as agents spawn.
+pub fn replay_event_log(path: &str, map: &Map) -> Result<Analytics, Error> {
+    let mut analytics = Analytics::new();
+    let mut reader = BufReader::new(File::open(path)?);
+    if path.ends_with(".bin") {
+        loop {
+            match bincode::deserialize_from::<_, Entry>(&mut reader) {
+                Ok(entry) => {
+                    analytics.event(entry.event, entry.time, map);
+                }
+                Err(err) => {
+                    if let bincode::ErrorKind::Io(ref io) = *err {
+                        if io.kind() == ErrorKind::UnexpectedEof {
+                            break;
+                        }
+                    }
+                    return Err(Error::new(ErrorKind::Other, err));
+                }
+            }
+        }
+    } else if path.ends_with(".json") {
+        for line in reader.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(&line)?;
+            analytics.event(entry.event, entry.time, map);
+        }
+    } else {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("Event log {} must end with .json or .bin", path),
+        ));
+    }
+    Ok(analytics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AlertHandler, ScenarioGenerator, Sim, SimOptions};
+    use abstutil::Timer;
+    use geom::Duration;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    fn analytics_bytes(analytics: &Analytics) -> Vec<u8> {
+        bincode::serialize(analytics).unwrap()
+    }
+
+    #[test]
+    fn test_replay_event_log() {
+        let mut timer = Timer::throwaway();
+        let map = Map::new(abstutil::path_synthetic_map("signal_single"), &mut timer);
+        for ext in vec!["json", "bin"] {
+            let path = format!("{}/event_log.{}", std::env::temp_dir().display(), ext);
+            let mut opts = SimOptions::new("event_log");
+            opts.event_log = Some(path.clone());
+            opts.alerts = AlertHandler::Silence;
+            let mut sim = Sim::new(&map, opts, &mut timer);
+            let mut rng = XorShiftRng::from_seed([42; 16]);
+            ScenarioGenerator::small_run(&map)
+                .generate(&map, &mut rng, &mut timer)
+                .instantiate(&mut sim, &map, &mut rng, &mut timer);
+            sim.timed_step(&map, Duration::minutes(10), &mut None, &mut timer);
+            let mut live = sim.get_analytics().clone();
+            // Finish writing the log
+            drop(sim);
+
+            let mut replayed = replay_event_log(&path, &map).unwrap();
+            // The live sim throws away alerts as it goes
+            for analytics in vec![&mut live, &mut replayed] {
+                analytics.demand.clear();
+                analytics.alerts.clear();
+            }
+            assert!(!live.finished_trips.is_empty());
+            assert_eq!(
+                analytics_bytes(&replayed),
+                analytics_bytes(&live),
+                "Replaying {} doesn't match the live run",
+                path
+            );
+        }
+    }
+}
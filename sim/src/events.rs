@@ -15,8 +15,12 @@ pub enum Event {
     CarReachedParkingSpot(CarID, ParkingSpot),
     CarLeftParkingSpot(CarID, ParkingSpot),
 
-    BusArrivedAtStop(CarID, BusRouteID, BusStopID),
-    BusDepartedFromStop(CarID, BusRouteID, BusStopID),
+    // For buses following a timetable, how late they are. Negative means early.
+    BusArrivedAtStop(CarID, BusRouteID, BusStopID, Option<Duration>),
+    // The last value is how many passengers are aboard
+    BusDepartedFromStop(CarID, BusRouteID, BusStopID, usize),
+    // A full bus left somebody waiting at the stop
+    BusPassedUpRider(CarID, BusRouteID, BusStopID),
 
     PersonEntersBuilding(PersonID, BuildingID),
     PersonLeavesBuilding(PersonID, BuildingID),
@@ -46,6 +50,14 @@ pub enum Event {
     PathAmended(Path),
 
     Alert(AlertLocation, String),
+
+    // The map was edited in the middle of the simulation. Some agents got a new path, and others
+    // couldn't continue and had their trip aborted.
+    MapEdited {
+        edits_name: String,
+        rerouted: Vec<AgentID>,
+        evacuated: Vec<AgentID>,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -1,4 +1,6 @@
 mod analytics;
+mod assignment;
+mod event_log;
 mod events;
 mod make;
 mod mechanics;
@@ -6,16 +8,22 @@ mod pandemic;
 mod render;
 mod router;
 mod scheduler;
+mod signal_optimizer;
 mod sim;
+mod temporary_edits;
 mod transit;
 mod trips;
 
 pub use self::analytics::{Analytics, TripPhase};
+pub use self::assignment::{assign_traffic, AssignmentIteration};
+pub use self::event_log::replay_event_log;
+pub(crate) use self::event_log::{EventLog, EventLogSink};
 pub(crate) use self::events::Event;
 pub use self::events::{AlertLocation, TripPhaseType};
 pub use self::make::{
     BorderSpawnOverTime, IndividTrip, OffMapLocation, OriginDestination, PersonSpec, Scenario,
-    ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime, SpawnTrip, TripSpawner, TripSpec,
+    ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime, SpawnTrip, TemporaryChange,
+    TemporaryEdit, TripSpawner, TripSpec,
 };
 pub(crate) use self::mechanics::{
     DrivingSimState, IntersectionSimState, ParkingSimState, WalkingSimState,
@@ -23,7 +31,9 @@ pub(crate) use self::mechanics::{
 pub(crate) use self::pandemic::PandemicModel;
 pub(crate) use self::router::{ActionAtEnd, Router};
 pub(crate) use self::scheduler::{Command, Scheduler};
+pub use self::signal_optimizer::optimize_signals;
 pub use self::sim::{AgentProperties, AlertHandler, Sim, SimCallback, SimOptions};
+pub(crate) use self::temporary_edits::TemporaryEditsState;
 pub(crate) use self::transit::TransitSimState;
 pub use self::trips::{Person, PersonState, TripResult};
 pub use self::trips::{TripEndpoint, TripMode};
@@ -33,7 +43,7 @@ pub use crate::render::{
     PedCrowdLocation, UnzoomedAgent,
 };
 use abstutil::Cloneable;
-use geom::{Distance, Pt2D, Speed, Time};
+use geom::{Acceleration, Distance, Pt2D, Speed, Time};
 use map_model::{
     BuildingID, BusStopID, DirectedRoadID, IntersectionID, LaneID, Map, ParkingLotID, Path,
     PathConstraints, PathRequest, Position,
@@ -48,6 +58,22 @@ pub const MIN_CAR_LENGTH: Distance = Distance::const_meters(4.5);
 pub const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
 // Note this is more than MAX_CAR_LENGTH
 pub const BUS_LENGTH: Distance = Distance::const_meters(12.5);
+// Seated and standing
+pub const BUS_CAPACITY: usize = 70;
+// Real trains are often a few cars long, but the light rail tracks from OSM are split into short
+// pieces, and longer vehicles won't fit. So just model one car.
+pub const TRAIN_LENGTH: Distance = Distance::const_meters(29.0);
+pub const TRAIN_CAPACITY: usize = 200;
+
+// Comfortable rates of speeding up and braking, not what the vehicles are physically capable of
+pub const CAR_ACCEL: Acceleration = Acceleration::const_meters_per_second_squared(2.5);
+pub const CAR_DECEL: Acceleration = Acceleration::const_meters_per_second_squared(3.0);
+pub const BIKE_ACCEL: Acceleration = Acceleration::const_meters_per_second_squared(1.0);
+pub const BIKE_DECEL: Acceleration = Acceleration::const_meters_per_second_squared(2.0);
+pub const BUS_ACCEL: Acceleration = Acceleration::const_meters_per_second_squared(1.2);
+pub const BUS_DECEL: Acceleration = Acceleration::const_meters_per_second_squared(1.5);
+pub const TRAIN_ACCEL: Acceleration = Acceleration::const_meters_per_second_squared(1.0);
+pub const TRAIN_DECEL: Acceleration = Acceleration::const_meters_per_second_squared(1.2);
 
 // At all speeds (including at rest), cars must be at least this far apart, measured from front of
 // one car to the back of the other.
@@ -65,6 +91,7 @@ impl fmt::Display for CarID {
             VehicleType::Car => write!(f, "Car #{}", self.0),
             VehicleType::Bus => write!(f, "Bus #{}", self.0),
             VehicleType::Bike => write!(f, "Bike #{}", self.0),
+            VehicleType::Train => write!(f, "Train #{}", self.0),
         }
     }
 }
@@ -130,6 +157,7 @@ pub enum VehicleType {
     Car,
     Bus,
     Bike,
+    Train,
 }
 
 impl fmt::Display for VehicleType {
@@ -138,6 +166,7 @@ impl fmt::Display for VehicleType {
             VehicleType::Car => write!(f, "car"),
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::Train => write!(f, "train"),
         }
     }
 }
@@ -148,6 +177,25 @@ impl VehicleType {
             VehicleType::Car => PathConstraints::Car,
             VehicleType::Bus => PathConstraints::Bus,
             VehicleType::Bike => PathConstraints::Bike,
+            VehicleType::Train => PathConstraints::Train,
+        }
+    }
+
+    pub fn max_accel(self) -> Acceleration {
+        match self {
+            VehicleType::Car => CAR_ACCEL,
+            VehicleType::Bus => BUS_ACCEL,
+            VehicleType::Bike => BIKE_ACCEL,
+            VehicleType::Train => TRAIN_ACCEL,
+        }
+    }
+
+    pub fn max_decel(self) -> Acceleration {
+        match self {
+            VehicleType::Car => CAR_DECEL,
+            VehicleType::Bus => BUS_DECEL,
+            VehicleType::Bike => BIKE_DECEL,
+            VehicleType::Train => TRAIN_DECEL,
         }
     }
 }
@@ -230,7 +278,9 @@ impl DrivingGoal {
                     let l = map.find_biking_lane_near_building(*b);
                     Position::new(l, map.get_l(l).length() / 2.0)
                 }
-                PathConstraints::Bus | PathConstraints::Pedestrian => unreachable!(),
+                PathConstraints::Bus | PathConstraints::Train | PathConstraints::Pedestrian => {
+                    unreachable!()
+                }
             },
             DrivingGoal::Border(_, l, _) => Position::new(*l, map.get_l(*l).length()),
         }
@@ -436,13 +486,6 @@ impl TimeInterval {
         assert!(x >= 0.0 && x <= 1.0);
         x
     }
-
-    pub fn percent_clamp_end(&self, t: Time) -> f64 {
-        if t > self.end {
-            return 1.0;
-        }
-        self.percent(t)
-    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -129,6 +129,11 @@ impl ScenarioGenerator {
                 percent_use_transit: 0.5,
             });
         }
+        // SpawnOverTime starts everyone at a building. Synthetic maps don't have any, so only
+        // spawn from borders there.
+        if map.all_buildings().is_empty() {
+            s.spawn_over_time.clear();
+        }
         s
     }
 
@@ -221,7 +226,7 @@ impl SpawnOverTime {
             if rng.gen_bool(self.percent_use_transit) {
                 // TODO This throws away some work. It also sequentially does expensive
                 // work right here.
-                if let Some((stop1, stop2, route)) =
+                if let Some(rides) =
                     map.should_use_transit(start_spot.sidewalk_pos, goal.sidewalk_pos)
                 {
                     scenario.people.push(PersonSpec {
@@ -229,7 +234,7 @@ impl SpawnOverTime {
                         orig_id: None,
                         trips: vec![IndividTrip {
                             depart,
-                            trip: SpawnTrip::UsingTransit(start_spot, goal, route, stop1, stop2),
+                            trip: SpawnTrip::UsingTransit(start_spot, goal, rides),
                             cancelled: false,
                         }],
                     });
@@ -284,7 +289,7 @@ impl BorderSpawnOverTime {
                 if rng.gen_bool(self.percent_use_transit) {
                     // TODO This throws away some work. It also sequentially does expensive
                     // work right here.
-                    if let Some((stop1, stop2, route)) =
+                    if let Some(rides) =
                         map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
                     {
                         scenario.people.push(PersonSpec {
@@ -292,13 +297,7 @@ impl BorderSpawnOverTime {
                             orig_id: None,
                             trips: vec![IndividTrip {
                                 depart,
-                                trip: SpawnTrip::UsingTransit(
-                                    start.clone(),
-                                    goal,
-                                    route,
-                                    stop1,
-                                    stop2,
-                                ),
+                                trip: SpawnTrip::UsingTransit(start.clone(), goal, rides),
                                 cancelled: false,
                             }],
                         });
@@ -367,9 +366,19 @@ impl OriginDestination {
         timer: &mut Timer,
     ) -> Option<DrivingGoal> {
         match self {
-            OriginDestination::Anywhere => Some(DrivingGoal::ParkNear(
-                map.all_buildings().choose(rng).unwrap().id,
-            )),
+            OriginDestination::Anywhere => {
+                if let Some(b) = map.all_buildings().choose(rng) {
+                    Some(DrivingGoal::ParkNear(b.id))
+                } else {
+                    // No buildings; leave the map somewhere instead
+                    OriginDestination::random_border(map, rng)?.pick_driving_goal(
+                        constraints,
+                        map,
+                        rng,
+                        timer,
+                    )
+                }
+            }
             OriginDestination::GotoBldg(b) => Some(DrivingGoal::ParkNear(*b)),
             OriginDestination::EndOfRoad(dr) => {
                 let goal = DrivingGoal::end_at_border(*dr, constraints, None, map);
@@ -391,10 +400,13 @@ impl OriginDestination {
         timer: &mut Timer,
     ) -> Option<SidewalkSpot> {
         match self {
-            OriginDestination::Anywhere => Some(SidewalkSpot::building(
-                map.all_buildings().choose(rng).unwrap().id,
-                map,
-            )),
+            OriginDestination::Anywhere => {
+                if let Some(b) = map.all_buildings().choose(rng) {
+                    Some(SidewalkSpot::building(b.id, map))
+                } else {
+                    OriginDestination::random_border(map, rng)?.pick_walking_goal(map, rng, timer)
+                }
+            }
             OriginDestination::EndOfRoad(dr) => {
                 let goal = SidewalkSpot::end_at_border(dr.dst_i(map), None, map);
                 if goal.is_none() {
@@ -405,6 +417,14 @@ impl OriginDestination {
             OriginDestination::GotoBldg(b) => Some(SidewalkSpot::building(*b, map)),
         }
     }
+
+    fn random_border(map: &Map, rng: &mut XorShiftRng) -> Option<OriginDestination> {
+        let i = map
+            .all_outgoing_borders()
+            .choose(rng)?
+            .some_incoming_road(map)?;
+        Some(OriginDestination::EndOfRoad(i))
+    }
 }
 
 fn rand_time(rng: &mut XorShiftRng, low: Time, high: Time) -> Time {
@@ -4,12 +4,12 @@ use map_model::{Map, MapEdits};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 
-const RNG_SEED: u8 = 42;
+const RNG_SEED: u64 = 42;
 
 #[derive(Clone)]
 pub struct SimFlags {
     pub load: String,
-    pub rng_seed: u8,
+    pub rng_seed: u64,
     pub opts: SimOptions,
 }
 
@@ -31,9 +31,10 @@ impl SimFlags {
                 use_freeform_policy_everywhere: args.enabled("--freeform_policy"),
                 dont_block_the_box: !args.enabled("--disable_block_the_box"),
                 recalc_lanechanging: !args.enabled("--disable_recalc_lc"),
+                midblock_lanechanging: args.enabled("--midblock_lc"),
                 break_turn_conflict_cycles: !args.enabled("--disable_break_turn_conflict_cycles"),
                 enable_pandemic_model: if args.enabled("--pandemic") {
-                    Some(XorShiftRng::from_seed([rng_seed; 16]))
+                    Some(XorShiftRng::seed_from_u64(rng_seed))
                 } else {
                     None
                 },
@@ -47,6 +48,10 @@ impl SimFlags {
                     })
                     .unwrap_or(AlertHandler::Print),
                 pathfinding_upfront: args.enabled("--pathfinding_upfront"),
+                event_log: args.optional("--event_log"),
+                rerouting_pct: args
+                    .optional_parse("--rerouting_pct", |s| s.parse())
+                    .unwrap_or(0),
             },
         }
     }
@@ -65,7 +70,7 @@ impl SimFlags {
     }
 
     pub fn make_rng(&self) -> XorShiftRng {
-        XorShiftRng::from_seed([self.rng_seed; 16])
+        XorShiftRng::seed_from_u64(self.rng_seed)
     }
 
     // Convenience method to setup everything.
@@ -77,7 +82,8 @@ impl SimFlags {
         if self.load.starts_with("../data/player/saves/") {
             timer.note(format!("Resuming from {}", self.load));
 
-            let mut sim: Sim = abstutil::read_binary(self.load.clone(), timer);
+            let mut sim = Sim::read_savestate(self.load.clone(), timer)
+                .unwrap_or_else(|err| panic!("Couldn't load savestate {}: {}", self.load, err));
 
             let mut map = Map::new(abstutil::path_map(&sim.map_name), timer);
             if sim.edits_name != "untitled edits" {
@@ -93,7 +99,7 @@ impl SimFlags {
                 self.load
             ));
 
-            let scenario: Scenario = abstutil::read_binary(self.load.clone(), timer);
+            let scenario = Scenario::load(self.load.clone(), timer);
 
             let map = Map::new(abstutil::path_map(&scenario.map_name), timer);
 
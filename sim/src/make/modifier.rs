@@ -1,21 +1,67 @@
-use crate::{IndividTrip, Scenario};
-use geom::Duration;
+use crate::{IndividTrip, PersonID, Scenario, SpawnTrip, TemporaryEdit, TripEndpoint, TripMode};
+use geom::{Duration, LonLat, Polygon, Pt2D, Time};
+use map_model::Map;
 use rand::Rng;
+use rand_distr::{Distribution, Normal};
 use rand_xorshift::XorShiftRng;
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum ScenarioModifier {
     RepeatDays(usize),
     CancelPeople(usize),
+    // A percent of the original demand. 50 drops about half of the people, 200 clones everybody.
+    ScaleDemand(usize),
+    // Each person draws their own shift from a normal distribution, and all of their trips move by
+    // that amount.
+    ShiftDepartures {
+        mean: Duration,
+        std_dev: Duration,
+    },
+    // A percent of the people taking any trips with the from mode. All of their trips with that
+    // mode change.
+    ChangeMode {
+        pct: usize,
+        from: TripMode,
+        to: TripMode,
+    },
+    // Only keep people with some trip starting or ending in the area. The name is just for
+    // describing the modifier.
+    RestrictToArea {
+        name: String,
+        pts: Vec<LonLat>,
+    },
+    // How many riders fit on each bus or train of a route, by name
+    ChangeTransitCapacity {
+        route: String,
+        capacity: usize,
+    },
 }
 
 impl ScenarioModifier {
     // If this modifies scenario_name, then that means prebaked results don't match up and
     // shouldn't be used.
-    pub fn apply(&self, s: Scenario, rng: &mut XorShiftRng) -> Scenario {
+    pub fn apply(&self, map: &Map, s: Scenario, rng: &mut XorShiftRng) -> Scenario {
         match self {
             ScenarioModifier::RepeatDays(n) => repeat_days(s, *n),
             ScenarioModifier::CancelPeople(pct) => cancel_people(s, *pct, rng),
+            ScenarioModifier::ScaleDemand(pct) => scale_demand(s, *pct, rng),
+            ScenarioModifier::ShiftDepartures { mean, std_dev } => {
+                shift_departures(s, *mean, *std_dev, rng)
+            }
+            ScenarioModifier::ChangeMode { pct, from, to } => {
+                change_mode(s, *pct, *from, *to, map, rng)
+            }
+            ScenarioModifier::RestrictToArea { ref name, ref pts } => {
+                restrict_to_area(s, name, pts, map)
+            }
+            ScenarioModifier::ChangeTransitCapacity {
+                ref route,
+                capacity,
+            } => {
+                let mut s = s;
+                s.transit_capacity.insert(route.clone(), *capacity);
+                s
+            }
         }
     }
 
@@ -25,6 +71,26 @@ impl ScenarioModifier {
             ScenarioModifier::CancelPeople(pct) => {
                 format!("cancel all trips for {}% of people", pct)
             }
+            ScenarioModifier::ScaleDemand(pct) => format!("scale demand to {}%", pct),
+            ScenarioModifier::ShiftDepartures { mean, std_dev } => format!(
+                "shift departures by {}{} on average, with a standard deviation of {}",
+                if *mean > Duration::ZERO { "+" } else { "" },
+                mean,
+                std_dev
+            ),
+            ScenarioModifier::ChangeMode { pct, from, to } => format!(
+                "{}% of people who {} instead {}",
+                pct,
+                from.verb(),
+                to.verb()
+            ),
+            ScenarioModifier::RestrictToArea { ref name, .. } => {
+                format!("only people with trips to or from {}", name)
+            }
+            ScenarioModifier::ChangeTransitCapacity {
+                ref route,
+                capacity,
+            } => format!("fit {} riders on each vehicle of route {}", capacity, route),
         }
     }
 }
@@ -54,6 +120,18 @@ fn repeat_days(mut s: Scenario, days: usize) -> Scenario {
         }
         person.trips = trips;
     }
+    let mut temporary_edits = Vec::new();
+    for day in 0..days {
+        let offset = Duration::hours(24) * (day as f64);
+        for edit in &s.temporary_edits {
+            temporary_edits.push(TemporaryEdit {
+                start: edit.start + offset,
+                end: edit.end + offset,
+                change: edit.change.clone(),
+            });
+        }
+    }
+    s.temporary_edits = temporary_edits;
     s
 }
 
@@ -70,3 +148,299 @@ fn cancel_people(mut s: Scenario, pct: usize, rng: &mut XorShiftRng) -> Scenario
     }
     s
 }
+
+fn scale_demand(mut s: Scenario, pct: usize, rng: &mut XorShiftRng) -> Scenario {
+    s.scenario_name = format!("{} (scaled to {}%)", s.scenario_name, pct);
+    let scale = (pct as f64) / 100.0;
+    let mut people = Vec::new();
+    for person in s.people {
+        // 250% means 2 copies of everybody, and a third copy for half of them.
+        let mut copies = scale.trunc() as usize;
+        if rng.gen_bool(scale.fract()) {
+            copies += 1;
+        }
+        for _ in 0..copies {
+            people.push(person.clone());
+        }
+    }
+    // Fix up IDs
+    for (idx, person) in people.iter_mut().enumerate() {
+        person.id = PersonID(idx);
+    }
+    s.people = people;
+    s
+}
+
+fn shift_departures(
+    mut s: Scenario,
+    mean: Duration,
+    std_dev: Duration,
+    rng: &mut XorShiftRng,
+) -> Scenario {
+    let normal = Normal::new(mean.inner_seconds(), std_dev.inner_seconds()).unwrap();
+    for person in &mut s.people {
+        if person.trips.is_empty() {
+            continue;
+        }
+        // Shifting every trip by the same amount keeps the schedule in order. Don't push anything
+        // before midnight.
+        let earliest = person.trips[0].depart - Time::START_OF_DAY;
+        let offset = Duration::seconds(normal.sample(rng)).max(Duration::ZERO - earliest);
+        for trip in &mut person.trips {
+            trip.depart = trip.depart + offset;
+        }
+    }
+    s
+}
+
+fn change_mode(
+    mut s: Scenario,
+    pct: usize,
+    from: TripMode,
+    to: TripMode,
+    map: &Map,
+    rng: &mut XorShiftRng,
+) -> Scenario {
+    if pct > 100 {
+        panic!("change_mode needs a percent from 0 to 100, not {}", pct);
+    }
+    let pct = (pct as f64) / 100.0;
+    for person in &mut s.people {
+        if !person.trips.iter().any(|t| t.trip.mode() == from) || !rng.gen_bool(pct) {
+            continue;
+        }
+        // Somebody who already drives or bikes would need their vehicle in two places at once.
+        if (to == TripMode::Drive || to == TripMode::Bike)
+            && person.trips.iter().any(|t| match t.trip {
+                SpawnTrip::Remote { .. } => false,
+                _ => t.trip.mode() == to,
+            })
+        {
+            continue;
+        }
+
+        // Changing only some of somebody's trips would leave their car or bike behind somewhere,
+        // so change all of them or none.
+        let changes: Option<Vec<(usize, SpawnTrip)>> = person
+            .trips
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.trip.mode() == from)
+            .map(|(idx, t)| match t.trip {
+                // Trips that never touch the map or that start in the middle of it can't change.
+                SpawnTrip::Remote { .. } | SpawnTrip::VehicleAppearing { .. } => None,
+                // Neither can trips between places without the right lanes for the new mode, like
+                // a border without a sidewalk.
+                _ => SpawnTrip::new(t.trip.start(map), t.trip.end(map), to, map)
+                    .map(|new_trip| (idx, new_trip)),
+            })
+            .collect();
+        if let Some(changes) = changes {
+            for (idx, new_trip) in changes {
+                person.trips[idx].trip = new_trip;
+            }
+        }
+    }
+    s
+}
+
+fn restrict_to_area(mut s: Scenario, name: &str, pts: &Vec<LonLat>, map: &Map) -> Scenario {
+    s.scenario_name = format!("{} (only {})", s.scenario_name, name);
+    let area = Polygon::new(&map.get_gps_bounds().forcibly_convert(pts));
+    // TODO Like cancel_people, it's not obvious how to drop individual trips from somebody's
+    // schedule, so keep everything they do.
+    s.people.retain(|person| {
+        person.trips.iter().any(|trip| {
+            if let SpawnTrip::Remote { .. } = trip.trip {
+                return false;
+            }
+            area.contains_pt(endpoint_pt(trip.trip.start(map), map))
+                || area.contains_pt(endpoint_pt(trip.trip.end(map), map))
+        })
+    });
+    // Fix up IDs
+    for (idx, person) in s.people.iter_mut().enumerate() {
+        person.id = PersonID(idx);
+    }
+    s
+}
+
+fn endpoint_pt(endpt: TripEndpoint, map: &Map) -> Pt2D {
+    match endpt {
+        TripEndpoint::Bldg(b) => map.get_b(b).label_center,
+        TripEndpoint::Border(i, _) => map.get_i(i).polygon.center(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OffMapLocation, PersonSpec};
+    use abstutil::Timer;
+    use rand::SeedableRng;
+
+    // There and back between two borders of a synthetic map
+    fn commuters(map: &Map, modes: Vec<TripMode>) -> Scenario {
+        let borders = map.all_outgoing_borders();
+        let a = TripEndpoint::Border(borders[0].id, None);
+        let b = TripEndpoint::Border(borders[1].id, None);
+        let mut s = Scenario::empty(map, "commuters");
+        for id in 0..100 {
+            let mut trips = Vec::new();
+            for (idx, mode) in modes.iter().enumerate() {
+                let (from, to) = if idx % 2 == 0 {
+                    (a.clone(), b.clone())
+                } else {
+                    (b.clone(), a.clone())
+                };
+                trips.push(IndividTrip {
+                    depart: Time::START_OF_DAY + Duration::hours(1 + 8 * idx),
+                    trip: SpawnTrip::new(from, to, *mode, map).unwrap(),
+                    cancelled: false,
+                });
+            }
+            s.people.push(PersonSpec {
+                id: PersonID(id),
+                orig_id: None,
+                trips,
+            });
+        }
+        s
+    }
+
+    fn modes(s: &Scenario) -> Vec<Vec<TripMode>> {
+        s.people
+            .iter()
+            .map(|p| p.trips.iter().map(|t| t.trip.mode()).collect())
+            .collect()
+    }
+
+    fn setup() -> (Map, XorShiftRng) {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        (map, XorShiftRng::from_seed([42; 16]))
+    }
+
+    #[test]
+    fn test_change_mode() {
+        let (map, mut rng) = setup();
+        let s = commuters(&map, vec![TripMode::Drive, TripMode::Drive]);
+
+        let none = change_mode(
+            s.clone(),
+            0,
+            TripMode::Drive,
+            TripMode::Walk,
+            &map,
+            &mut rng,
+        );
+        assert_eq!(modes(&none), modes(&s));
+
+        let all = change_mode(
+            s.clone(),
+            100,
+            TripMode::Drive,
+            TripMode::Walk,
+            &map,
+            &mut rng,
+        );
+        assert!(modes(&all)
+            .into_iter()
+            .all(|m| m == vec![TripMode::Walk, TripMode::Walk]));
+
+        // Nobody drives one way and walks back
+        let half = change_mode(
+            s.clone(),
+            50,
+            TripMode::Drive,
+            TripMode::Walk,
+            &map,
+            &mut rng,
+        );
+        let mut walkers = 0;
+        for m in modes(&half) {
+            if m == vec![TripMode::Walk, TripMode::Walk] {
+                walkers += 1;
+            } else {
+                assert_eq!(m, vec![TripMode::Drive, TripMode::Drive]);
+            }
+        }
+        assert!(walkers > 0 && walkers < 100);
+        for person in &half.people {
+            person.check_schedule(&map).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_change_mode_keeps_vehicles_in_one_place() {
+        let (map, mut rng) = setup();
+        // Walking there and driving back would need a second car
+        let s = commuters(&map, vec![TripMode::Walk, TripMode::Drive]);
+        let changed = change_mode(
+            s.clone(),
+            100,
+            TripMode::Walk,
+            TripMode::Drive,
+            &map,
+            &mut rng,
+        );
+        assert_eq!(modes(&changed), modes(&s));
+
+        // But biking there and back is fine
+        let changed = change_mode(s, 100, TripMode::Walk, TripMode::Bike, &map, &mut rng);
+        assert!(modes(&changed)
+            .into_iter()
+            .all(|m| m == vec![TripMode::Bike, TripMode::Drive]));
+    }
+
+    #[test]
+    fn test_change_mode_skips_remote_trips() {
+        let (map, mut rng) = setup();
+        let mut s = Scenario::empty(&map, "remote");
+        let place = |parcel_id| OffMapLocation {
+            parcel_id,
+            gps: LonLat::new(0.0, 0.0),
+        };
+        s.people.push(PersonSpec {
+            id: PersonID(0),
+            orig_id: None,
+            trips: vec![IndividTrip {
+                depart: Time::START_OF_DAY,
+                trip: SpawnTrip::Remote {
+                    from: place(1),
+                    to: place(2),
+                    trip_time: Duration::minutes(10),
+                    mode: TripMode::Drive,
+                },
+                cancelled: false,
+            }],
+        });
+        let changed = change_mode(s, 100, TripMode::Drive, TripMode::Walk, &map, &mut rng);
+        assert_eq!(modes(&changed), vec![vec![TripMode::Drive]]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_change_mode_bad_pct() {
+        let (map, mut rng) = setup();
+        let s = commuters(&map, vec![TripMode::Drive]);
+        change_mode(s, 101, TripMode::Drive, TripMode::Walk, &map, &mut rng);
+    }
+
+    #[test]
+    fn test_shift_departures_per_person() {
+        let (map, mut rng) = setup();
+        let s = commuters(&map, vec![TripMode::Drive, TripMode::Drive]);
+        let shifted = shift_departures(s.clone(), Duration::ZERO, Duration::minutes(30), &mut rng);
+        let mut offsets = Vec::new();
+        for (before, after) in s.people.iter().zip(shifted.people.iter()) {
+            let offset = after.trips[0].depart - before.trips[0].depart;
+            // Everybody's own trips move together
+            assert_eq!(after.trips[1].depart - before.trips[1].depart, offset);
+            offsets.push(offset);
+        }
+        assert!(offsets.iter().any(|dt| *dt != offsets[0]));
+    }
+}
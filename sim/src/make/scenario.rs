@@ -6,7 +6,8 @@ use crate::{
 use abstutil::{prettyprint_usize, Counter, Timer};
 use geom::{Distance, Duration, LonLat, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, DirectedRoadID, Map, PathConstraints, Position, RoadID,
+    BuildingID, BusRouteID, BusStopID, DirectedRoadID, IntersectionID, LaneID, Map,
+    PathConstraints, Position, RoadID,
 };
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
@@ -23,6 +24,53 @@ pub struct Scenario {
     pub people: Vec<PersonSpec>,
     // None means seed all buses. Otherwise the route name must be present here.
     pub only_seed_buses: Option<BTreeSet<String>>,
+    #[serde(default)]
+    pub temporary_edits: Vec<TemporaryEdit>,
+    // How many riders fit on each vehicle of these routes, keyed by route name. Other routes use
+    // the default.
+    #[serde(default)]
+    pub transit_capacity: BTreeMap<String, usize>,
+}
+
+// How scenarios were stored before temporary edits and transit capacity. Binary files store fields
+// by position, so old files just end early and have to be read separately.
+#[derive(Serialize, Deserialize)]
+struct OldScenario {
+    scenario_name: String,
+    map_name: String,
+    people: Vec<PersonSpec>,
+    only_seed_buses: Option<BTreeSet<String>>,
+}
+
+impl From<OldScenario> for Scenario {
+    fn from(x: OldScenario) -> Scenario {
+        Scenario {
+            scenario_name: x.scenario_name,
+            map_name: x.map_name,
+            people: x.people,
+            only_seed_buses: x.only_seed_buses,
+            temporary_edits: Vec::new(),
+            transit_capacity: BTreeMap::new(),
+        }
+    }
+}
+
+// A change to the map that only lasts for part of the day, like construction, a bridge closure, or
+// a street festival. The simulation applies it at start and reverts it at end.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct TemporaryEdit {
+    pub start: Time,
+    pub end: Time,
+    pub change: TemporaryChange,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum TemporaryChange {
+    // Every lane except for sidewalks and light rail becomes construction. Buildings along the road
+    // still need the sidewalks.
+    CloseRoad(RoadID),
+    CloseLane(LaneID),
+    CloseIntersection(IntersectionID),
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -41,6 +89,7 @@ pub struct IndividTrip {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "SpawnTripFormat", into = "SpawnTripFormat")]
 pub enum SpawnTrip {
     // Only for interactive / debug trips
     VehicleAppearing {
@@ -58,7 +107,12 @@ pub enum SpawnTrip {
     UsingParkedCar(BuildingID, DrivingGoal),
     UsingBike(SidewalkSpot, DrivingGoal),
     JustWalking(SidewalkSpot, SidewalkSpot),
-    UsingTransit(SidewalkSpot, SidewalkSpot, BusRouteID, BusStopID, BusStopID),
+    // Each ride is (route, board at, alight at). Riders walk between rides to transfer.
+    UsingTransit(
+        SidewalkSpot,
+        SidewalkSpot,
+        Vec<(BusRouteID, BusStopID, BusStopID)>,
+    ),
     // Completely off-map trip. Don't really simulate much of it.
     Remote {
         from: OffMapLocation,
@@ -68,6 +122,130 @@ pub enum SpawnTrip {
     },
 }
 
+// How SpawnTrip is stored in scenario files. Variants in binary files are stored by position, so
+// the single-ride UsingTransit from before transfers keeps its spot, and trips with any number of
+// rides go at the end. Old files still load.
+#[derive(Clone, Serialize, Deserialize)]
+enum SpawnTripFormat {
+    VehicleAppearing {
+        start: Position,
+        goal: DrivingGoal,
+        is_bike: bool,
+    },
+    FromBorder {
+        dr: DirectedRoadID,
+        goal: DrivingGoal,
+        is_bike: bool,
+        origin: Option<OffMapLocation>,
+    },
+    UsingParkedCar(BuildingID, DrivingGoal),
+    UsingBike(SidewalkSpot, DrivingGoal),
+    JustWalking(SidewalkSpot, SidewalkSpot),
+    UsingTransit(SidewalkSpot, SidewalkSpot, BusRouteID, BusStopID, BusStopID),
+    Remote {
+        from: OffMapLocation,
+        to: OffMapLocation,
+        trip_time: Duration,
+        mode: TripMode,
+    },
+    UsingTransitWithTransfers(
+        SidewalkSpot,
+        SidewalkSpot,
+        Vec<(BusRouteID, BusStopID, BusStopID)>,
+    ),
+}
+
+impl From<SpawnTripFormat> for SpawnTrip {
+    fn from(x: SpawnTripFormat) -> SpawnTrip {
+        match x {
+            SpawnTripFormat::VehicleAppearing {
+                start,
+                goal,
+                is_bike,
+            } => SpawnTrip::VehicleAppearing {
+                start,
+                goal,
+                is_bike,
+            },
+            SpawnTripFormat::FromBorder {
+                dr,
+                goal,
+                is_bike,
+                origin,
+            } => SpawnTrip::FromBorder {
+                dr,
+                goal,
+                is_bike,
+                origin,
+            },
+            SpawnTripFormat::UsingParkedCar(b, goal) => SpawnTrip::UsingParkedCar(b, goal),
+            SpawnTripFormat::UsingBike(start, goal) => SpawnTrip::UsingBike(start, goal),
+            SpawnTripFormat::JustWalking(start, goal) => SpawnTrip::JustWalking(start, goal),
+            SpawnTripFormat::UsingTransit(start, goal, route, stop1, stop2) => {
+                SpawnTrip::UsingTransit(start, goal, vec![(route, stop1, stop2)])
+            }
+            SpawnTripFormat::Remote {
+                from,
+                to,
+                trip_time,
+                mode,
+            } => SpawnTrip::Remote {
+                from,
+                to,
+                trip_time,
+                mode,
+            },
+            SpawnTripFormat::UsingTransitWithTransfers(start, goal, rides) => {
+                SpawnTrip::UsingTransit(start, goal, rides)
+            }
+        }
+    }
+}
+
+impl From<SpawnTrip> for SpawnTripFormat {
+    fn from(x: SpawnTrip) -> SpawnTripFormat {
+        match x {
+            SpawnTrip::VehicleAppearing {
+                start,
+                goal,
+                is_bike,
+            } => SpawnTripFormat::VehicleAppearing {
+                start,
+                goal,
+                is_bike,
+            },
+            SpawnTrip::FromBorder {
+                dr,
+                goal,
+                is_bike,
+                origin,
+            } => SpawnTripFormat::FromBorder {
+                dr,
+                goal,
+                is_bike,
+                origin,
+            },
+            SpawnTrip::UsingParkedCar(b, goal) => SpawnTripFormat::UsingParkedCar(b, goal),
+            SpawnTrip::UsingBike(start, goal) => SpawnTripFormat::UsingBike(start, goal),
+            SpawnTrip::JustWalking(start, goal) => SpawnTripFormat::JustWalking(start, goal),
+            SpawnTrip::UsingTransit(start, goal, rides) => {
+                SpawnTripFormat::UsingTransitWithTransfers(start, goal, rides)
+            }
+            SpawnTrip::Remote {
+                from,
+                to,
+                trip_time,
+                mode,
+            } => SpawnTripFormat::Remote {
+                from,
+                to,
+                trip_time,
+                mode,
+            },
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct OffMapLocation {
     pub parcel_id: usize,
@@ -84,13 +262,23 @@ impl Scenario {
         if let Some(ref routes) = self.only_seed_buses {
             for route in map.get_all_bus_routes() {
                 if routes.contains(&route.name) {
-                    sim.seed_bus_route(route, map, timer);
+                    sim.seed_bus_route(
+                        route,
+                        self.transit_capacity.get(&route.name).cloned(),
+                        map,
+                        timer,
+                    );
                 }
             }
         } else {
             // All of them
             for route in map.get_all_bus_routes() {
-                sim.seed_bus_route(route, map, timer);
+                sim.seed_bus_route(
+                    route,
+                    self.transit_capacity.get(&route.name).cloned(),
+                    map,
+                    timer,
+                );
             }
         }
 
@@ -134,6 +322,14 @@ impl Scenario {
         seed_parked_cars(parked_cars, sim, map, rng, timer);
 
         sim.flush_spawner(spawner, map, timer);
+        for edit in &self.temporary_edits {
+            if let Err(err) = sim.schedule_temporary_edit(edit.clone(), map) {
+                timer.error(format!(
+                    "Skipping a temporary edit from {}: {}",
+                    self.scenario_name, err
+                ));
+            }
+        }
         timer.stop(format!("Instantiating {}", self.scenario_name));
     }
 
@@ -144,12 +340,33 @@ impl Scenario {
         );
     }
 
+    // Also reads files saved in the old format. Always try the current format first; an old
+    // reader would happily stop partway through a new file.
+    pub fn maybe_load(path: String, timer: &mut Timer) -> Result<Scenario, std::io::Error> {
+        match abstutil::maybe_read_binary(path.clone(), timer) {
+            Ok(s) => Ok(s),
+            Err(err) => match abstutil::maybe_read_binary::<OldScenario>(path, timer) {
+                Ok(s) => Ok(s.into()),
+                Err(_) => Err(err),
+            },
+        }
+    }
+
+    pub fn load(path: String, timer: &mut Timer) -> Scenario {
+        match Scenario::maybe_load(path.clone(), timer) {
+            Ok(s) => s,
+            Err(err) => panic!("Couldn't load scenario {}: {}", path, err),
+        }
+    }
+
     pub fn empty(map: &Map, name: &str) -> Scenario {
         Scenario {
             scenario_name: name.to_string(),
             map_name: map.get_name().to_string(),
             people: Vec::new(),
             only_seed_buses: Some(BTreeSet::new()),
+            temporary_edits: Vec::new(),
+            transit_capacity: BTreeMap::new(),
         }
     }
 
@@ -402,13 +619,9 @@ impl SpawnTrip {
                 goal,
             },
             SpawnTrip::JustWalking(start, goal) => TripSpec::JustWalking { start, goal },
-            SpawnTrip::UsingTransit(start, goal, route, stop1, stop2) => TripSpec::UsingTransit {
-                start,
-                goal,
-                route,
-                stop1,
-                stop2,
-            },
+            SpawnTrip::UsingTransit(start, goal, rides) => {
+                TripSpec::UsingTransit { start, goal, rides }
+            }
             SpawnTrip::Remote {
                 from,
                 to,
@@ -434,7 +647,7 @@ impl SpawnTrip {
             SpawnTrip::UsingParkedCar(b, _) => TripEndpoint::Bldg(*b),
             SpawnTrip::UsingBike(ref spot, _)
             | SpawnTrip::JustWalking(ref spot, _)
-            | SpawnTrip::UsingTransit(ref spot, _, _, _, _) => match spot.connection {
+            | SpawnTrip::UsingTransit(ref spot, _, _) => match spot.connection {
                 SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
                 SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
                 SidewalkPOI::SuddenlyAppear => {
@@ -458,7 +671,7 @@ impl SpawnTrip {
                 DrivingGoal::ParkNear(b) => TripEndpoint::Bldg(*b),
                 DrivingGoal::Border(i, _, ref loc) => TripEndpoint::Border(*i, loc.clone()),
             },
-            SpawnTrip::JustWalking(_, ref spot) | SpawnTrip::UsingTransit(_, ref spot, _, _, _) => {
+            SpawnTrip::JustWalking(_, ref spot) | SpawnTrip::UsingTransit(_, ref spot, _) => {
                 match spot.connection {
                     SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
                     SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
@@ -472,15 +685,21 @@ impl SpawnTrip {
         }
     }
 
-    pub fn new(from: TripEndpoint, to: TripEndpoint, mode: TripMode, map: &Map) -> SpawnTrip {
-        match mode {
+    // None if the endpoints don't have the right lanes for this mode.
+    pub fn new(
+        from: TripEndpoint,
+        to: TripEndpoint,
+        mode: TripMode,
+        map: &Map,
+    ) -> Option<SpawnTrip> {
+        Some(match mode {
             TripMode::Drive => match from {
                 TripEndpoint::Bldg(b) => {
-                    SpawnTrip::UsingParkedCar(b, to.driving_goal(PathConstraints::Car, map))
+                    SpawnTrip::UsingParkedCar(b, to.driving_goal(PathConstraints::Car, map)?)
                 }
                 TripEndpoint::Border(i, ref origin) => SpawnTrip::FromBorder {
-                    dr: map.get_i(i).some_outgoing_road(map).unwrap(),
-                    goal: to.driving_goal(PathConstraints::Car, map),
+                    dr: map.get_i(i).some_outgoing_road(map)?,
+                    goal: to.driving_goal(PathConstraints::Car, map)?,
                     is_bike: false,
                     origin: origin.clone(),
                 },
@@ -488,38 +707,53 @@ impl SpawnTrip {
             TripMode::Bike => match from {
                 TripEndpoint::Bldg(b) => SpawnTrip::UsingBike(
                     SidewalkSpot::building(b, map),
-                    to.driving_goal(PathConstraints::Bike, map),
+                    to.driving_goal(PathConstraints::Bike, map)?,
                 ),
                 TripEndpoint::Border(i, ref origin) => SpawnTrip::FromBorder {
-                    dr: map.get_i(i).some_outgoing_road(map).unwrap(),
-                    goal: to.driving_goal(PathConstraints::Bike, map),
+                    dr: map.get_i(i).some_outgoing_road(map)?,
+                    goal: to.driving_goal(PathConstraints::Bike, map)?,
                     is_bike: true,
                     origin: origin.clone(),
                 },
             },
             TripMode::Walk => {
-                SpawnTrip::JustWalking(from.start_sidewalk_spot(map), to.end_sidewalk_spot(map))
+                SpawnTrip::JustWalking(from.start_sidewalk_spot(map)?, to.end_sidewalk_spot(map)?)
             }
             TripMode::Transit => {
-                let start = from.start_sidewalk_spot(map);
-                let goal = to.end_sidewalk_spot(map);
-                if let Some((stop1, stop2, route)) =
-                    map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
-                {
-                    SpawnTrip::UsingTransit(start, goal, route, stop1, stop2)
+                let start = from.start_sidewalk_spot(map)?;
+                let goal = to.end_sidewalk_spot(map)?;
+                if let Some(rides) = map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos) {
+                    SpawnTrip::UsingTransit(start, goal, rides)
                 } else {
                     //timer.warn(format!("{:?} not actually using transit, because pathfinding
                     // didn't find any useful route", trip));
                     SpawnTrip::JustWalking(start, goal)
                 }
             }
+        })
+    }
+
+    pub fn mode(&self) -> TripMode {
+        match self {
+            SpawnTrip::VehicleAppearing { is_bike, .. } | SpawnTrip::FromBorder { is_bike, .. } => {
+                if *is_bike {
+                    TripMode::Bike
+                } else {
+                    TripMode::Drive
+                }
+            }
+            SpawnTrip::UsingParkedCar(_, _) => TripMode::Drive,
+            SpawnTrip::UsingBike(_, _) => TripMode::Bike,
+            SpawnTrip::JustWalking(_, _) => TripMode::Walk,
+            SpawnTrip::UsingTransit(_, _, _) => TripMode::Transit,
+            SpawnTrip::Remote { mode, .. } => *mode,
         }
     }
 }
 
 impl PersonSpec {
     // Verify that the trip start/endpoints of the person match up
-    fn check_schedule(&self, map: &Map) -> Result<(), String> {
+    pub fn check_schedule(&self, map: &Map) -> Result<(), String> {
         for pair in self.trips.iter().zip(self.trips.iter().skip(1)) {
             if pair.0.depart >= pair.1.depart {
                 return Err(format!(
@@ -656,7 +890,7 @@ impl PersonSpec {
                     }
                     bike_idx
                 }
-                SpawnTrip::JustWalking(_, _) | SpawnTrip::UsingTransit(_, _, _, _, _) => None,
+                SpawnTrip::JustWalking(_, _) | SpawnTrip::UsingTransit(_, _, _) => None,
                 SpawnTrip::Remote { .. } => None,
             };
             vehicle_foreach_trip.push(use_for_trip);
@@ -680,3 +914,139 @@ impl PersonSpec {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spot(idx: usize) -> SidewalkSpot {
+        SidewalkSpot {
+            connection: SidewalkPOI::SuddenlyAppear,
+            sidewalk_pos: Position::new(LaneID(idx), Distance::ZERO),
+        }
+    }
+
+    fn stop(idx: usize) -> BusStopID {
+        BusStopID {
+            sidewalk: LaneID(idx),
+            idx: 0,
+        }
+    }
+
+    fn from_binary(x: &SpawnTripFormat) -> SpawnTrip {
+        bincode::deserialize(&bincode::serialize(x).unwrap()).unwrap()
+    }
+
+    fn from_json(x: &SpawnTripFormat) -> SpawnTrip {
+        serde_json::from_str(&abstutil::to_json(x)).unwrap()
+    }
+
+    fn rides(trip: SpawnTrip) -> Vec<(BusRouteID, BusStopID, BusStopID)> {
+        match trip {
+            SpawnTrip::UsingTransit(start, goal, rides) => {
+                assert_eq!(start, spot(0));
+                assert_eq!(goal, spot(1));
+                rides
+            }
+            x => panic!("Loaded as {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_old_transit_trips_load() {
+        let old = SpawnTripFormat::UsingTransit(spot(0), spot(1), BusRouteID(2), stop(3), stop(4));
+        let expected = vec![(BusRouteID(2), stop(3), stop(4))];
+        assert_eq!(rides(from_binary(&old)), expected);
+        assert_eq!(rides(from_json(&old)), expected);
+
+        // Old files stored Remote right after UsingTransit
+        let remote = SpawnTripFormat::Remote {
+            from: OffMapLocation {
+                parcel_id: 0,
+                gps: LonLat::new(0.0, 0.0),
+            },
+            to: OffMapLocation {
+                parcel_id: 1,
+                gps: LonLat::new(1.0, 1.0),
+            },
+            trip_time: Duration::minutes(5),
+            mode: TripMode::Drive,
+        };
+        match from_binary(&remote) {
+            SpawnTrip::Remote { to, .. } => assert_eq!(to.parcel_id, 1),
+            x => panic!("Loaded as {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_old_scenarios_load() {
+        let path = format!("{}/old_scenario.bin", std::env::temp_dir().display());
+        let people = vec![PersonSpec {
+            id: PersonID(0),
+            orig_id: None,
+            trips: vec![IndividTrip {
+                depart: Time::START_OF_DAY,
+                trip: SpawnTrip::JustWalking(spot(0), spot(1)),
+                cancelled: false,
+            }],
+        }];
+        abstutil::write_binary(
+            path.clone(),
+            &OldScenario {
+                scenario_name: "old".to_string(),
+                map_name: "signal_single".to_string(),
+                people,
+                only_seed_buses: None,
+            },
+        );
+
+        let s = Scenario::load(path, &mut Timer::throwaway());
+        assert_eq!(s.scenario_name, "old");
+        assert_eq!(s.map_name, "signal_single");
+        assert_eq!(s.people.len(), 1);
+        assert_eq!(s.people[0].trips[0].depart, Time::START_OF_DAY);
+        assert_eq!(s.only_seed_buses, None);
+        assert!(s.temporary_edits.is_empty());
+        assert!(s.transit_capacity.is_empty());
+    }
+
+    #[test]
+    fn test_new_scenarios_keep_edits() {
+        let path = format!("{}/new_scenario.bin", std::env::temp_dir().display());
+        let mut transit_capacity = BTreeMap::new();
+        transit_capacity.insert("44".to_string(), 10);
+        let edit = TemporaryEdit {
+            start: Time::START_OF_DAY + Duration::hours(1),
+            end: Time::START_OF_DAY + Duration::hours(2),
+            change: TemporaryChange::CloseIntersection(IntersectionID(3)),
+        };
+        abstutil::write_binary(
+            path.clone(),
+            &Scenario {
+                scenario_name: "new".to_string(),
+                map_name: "signal_single".to_string(),
+                people: Vec::new(),
+                only_seed_buses: None,
+                temporary_edits: vec![edit.clone()],
+                transit_capacity: transit_capacity.clone(),
+            },
+        );
+
+        // The old layout could read the start of this file, so make sure nothing is dropped
+        let s = Scenario::load(path, &mut Timer::throwaway());
+        assert_eq!(s.temporary_edits, vec![edit]);
+        assert_eq!(s.transit_capacity, transit_capacity);
+    }
+
+    #[test]
+    fn test_transfers_round_trip() {
+        let expected = vec![
+            (BusRouteID(0), stop(1), stop(2)),
+            (BusRouteID(3), stop(4), stop(5)),
+        ];
+        let trip: SpawnTripFormat =
+            SpawnTrip::UsingTransit(spot(0), spot(1), expected.clone()).into();
+        assert_eq!(rides(from_binary(&trip)), expected);
+        assert_eq!(rides(from_json(&trip)), expected);
+    }
+}
@@ -47,9 +47,8 @@ pub enum TripSpec {
     UsingTransit {
         start: SidewalkSpot,
         goal: SidewalkSpot,
-        route: BusRouteID,
-        stop1: BusStopID,
-        stop2: BusStopID,
+        // (route, board at, alight at)
+        rides: Vec<(BusRouteID, BusStopID, BusStopID)>,
     },
     // Completely off-map trip. Don't really simulate much of it.
     Remote {
@@ -200,7 +199,7 @@ impl TripSpawner {
                     tuple,
                     req.clone(),
                     if pathfinding_upfront {
-                        req.and_then(|r| map.pathfind(r))
+                        req.and_then(|r| map.pathfind(r, tuple.1))
                     } else {
                         None
                     },
@@ -304,24 +303,19 @@ impl TripSpawner {
                     };
                     trips.new_trip(person.id, start_time, trip_start, TripMode::Bike, legs, map)
                 }
-                TripSpec::UsingTransit {
-                    route,
-                    stop1,
-                    stop2,
-                    goal,
-                    ..
-                } => {
-                    let walk_to = SidewalkSpot::bus_stop(stop1, map);
+                TripSpec::UsingTransit { rides, goal, .. } => {
+                    let mut legs = Vec::new();
+                    for (route, stop1, stop2) in rides {
+                        legs.push(TripLeg::Walk(SidewalkSpot::bus_stop(stop1, map)));
+                        legs.push(TripLeg::RideBus(route, stop2));
+                    }
+                    legs.push(TripLeg::Walk(goal));
                     trips.new_trip(
                         person.id,
                         start_time,
                         trip_start,
                         TripMode::Transit,
-                        vec![
-                            TripLeg::Walk(walk_to.clone()),
-                            TripLeg::RideBus(route, stop2),
-                            TripLeg::Walk(goal),
-                        ],
+                        legs,
                         map,
                     )
                 }
@@ -400,9 +394,9 @@ impl TripSpec {
                     .sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
             }),
-            TripSpec::UsingTransit { start, stop1, .. } => Some(PathRequest {
+            TripSpec::UsingTransit { start, rides, .. } => Some(PathRequest {
                 start: start.sidewalk_pos,
-                end: SidewalkSpot::bus_stop(*stop1, map).sidewalk_pos,
+                end: SidewalkSpot::bus_stop(rides[0].1, map).sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
             }),
             TripSpec::Remote { .. } => None,
@@ -2,8 +2,8 @@ use crate::{
     CarStatus, DistanceInterval, DrawCarInput, ParkingSpot, PersonID, Router, TimeInterval,
     TransitSimState, TripID, Vehicle, VehicleType,
 };
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{Map, Traversable};
+use geom::{Acceleration, Distance, Duration, PolyLine, Speed, Time};
+use map_model::{Map, Traversable, TurnPriority};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
@@ -25,31 +25,91 @@ pub struct Car {
 
 impl Car {
     // Assumes the current head of the path is the thing to cross.
-    pub fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> CarState {
-        let dist_int = DistanceInterval::new_driving(
-            start_dist,
-            if self.router.last_step() {
-                self.router.get_end_dist()
-            } else {
-                self.router.head().length(map)
-            },
-        );
-        self.crossing_state_with_end_dist(dist_int, start_time, map)
+    pub fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_speed: Speed,
+        start_time: Time,
+        map: &Map,
+    ) -> CarState {
+        let on = self.router.head();
+        let (end_dist, end_speed) = if self.router.last_step() {
+            (
+                self.router.get_end_dist(),
+                if self.router.stops_at_end() {
+                    Speed::ZERO
+                } else {
+                    self.cruise_speed(on, map)
+                },
+            )
+        } else {
+            // Slow down first if the next step is slower. Stop signs always mean stopping, so brake
+            // for them ahead of time. A signal might change before the car gets there, so if it
+            // winds up having to stop anyway, it brakes instantly.
+            let next = self.router.next();
+            (
+                on.length(map),
+                if must_stop_before(next, map) {
+                    Speed::ZERO
+                } else {
+                    self.cruise_speed(on, map).min(self.cruise_speed(next, map))
+                },
+            )
+        };
+        self.crossing_state_with_end_dist(
+            DistanceInterval::new_driving(start_dist, end_dist),
+            start_speed,
+            end_speed,
+            start_time,
+            map,
+        )
     }
 
     pub fn crossing_state_with_end_dist(
         &self,
         dist_int: DistanceInterval,
+        start_speed: Speed,
+        end_speed: Speed,
         start_time: Time,
         map: &Map,
     ) -> CarState {
-        let on = self.router.head();
+        let (profile, dt) = SpeedProfile::new(
+            dist_int.length(),
+            start_speed,
+            self.cruise_speed(self.router.head(), map),
+            end_speed,
+            self.vehicle.vehicle_type.max_accel(),
+            self.vehicle.vehicle_type.max_decel(),
+        );
+        CarState::Crossing(
+            TimeInterval::new(start_time, start_time + dt),
+            dist_int,
+            profile,
+        )
+    }
+
+    // The fastest this car will go on something
+    pub fn cruise_speed(&self, on: Traversable, map: &Map) -> Speed {
         let mut speed = on.speed_limit(map);
         if let Some(s) = self.vehicle.max_speed {
             speed = speed.min(s);
         }
-        let dt = (dist_int.end - dist_int.start) / speed;
-        CarState::Crossing(TimeInterval::new(start_time, start_time + dt), dist_int)
+        speed
+    }
+
+    // The caller knows where the front of the car really is; a Crossing car might be stuck behind
+    // somebody.
+    pub fn current_speed(&self, front: Distance, now: Time) -> Speed {
+        match self.state {
+            CarState::Crossing(ref time_int, ref dist_int, ref profile) => {
+                if front < profile.dist_at(time_int, dist_int, now) {
+                    Speed::ZERO
+                } else {
+                    profile.speed_at(time_int, now)
+                }
+            }
+            _ => Speed::ZERO,
+        }
     }
 
     pub fn get_draw_car(
@@ -174,7 +234,7 @@ impl Car {
             status: match self.state {
                 CarState::Queued { .. } => CarStatus::Moving,
                 CarState::WaitingToAdvance { .. } => CarStatus::Moving,
-                CarState::Crossing(_, _) => CarStatus::Moving,
+                CarState::Crossing(_, _, _) => CarStatus::Moving,
                 // Eh they're technically moving, but this is a bit easier to spot
                 CarState::Unparking(_, _, _) => CarStatus::Parked,
                 CarState::Parking(_, _, _) => CarStatus::Parked,
@@ -182,9 +242,11 @@ impl Car {
                 CarState::Idling(_, _) => CarStatus::Parked,
             },
             on: self.router.head(),
-            label: if self.vehicle.vehicle_type == VehicleType::Bus {
+            label: if self.vehicle.vehicle_type == VehicleType::Bus
+                || self.vehicle.vehicle_type == VehicleType::Train
+            {
                 Some(
-                    map.get_br(transit.bus_route(self.vehicle.id))
+                    map.get_br(transit.bus_route(self.vehicle.id).unwrap())
                         .name
                         .to_string(),
                 )
@@ -196,9 +258,22 @@ impl Car {
     }
 }
 
+// Cars always wait at a stop sign before making this turn
+fn must_stop_before(next: Traversable, map: &Map) -> bool {
+    match next {
+        Traversable::Turn(t) => map
+            .maybe_get_stop_sign(t.parent)
+            .map(|sign| sign.get_priority(t, map) == TurnPriority::Yield)
+            .unwrap_or(false),
+        Traversable::Lane(_) => false,
+    }
+}
+
+// Crossing gained a SpeedProfile when vehicles started speeding up and braking gradually.
+// Savestates from before that don't load.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum CarState {
-    Crossing(TimeInterval, DistanceInterval),
+    Crossing(TimeInterval, DistanceInterval, SpeedProfile),
     Queued { blocked_since: Time },
     WaitingToAdvance { blocked_since: Time },
     // Where's the front of the car while this is happening?
@@ -210,7 +285,7 @@ pub enum CarState {
 impl CarState {
     pub fn get_end_time(&self) -> Time {
         match self {
-            CarState::Crossing(ref time_int, _) => time_int.end,
+            CarState::Crossing(ref time_int, _, _) => time_int.end,
             CarState::Queued { .. } => unreachable!(),
             CarState::WaitingToAdvance { .. } => unreachable!(),
             CarState::Unparking(_, _, ref time_int) => time_int.end,
@@ -219,3 +294,251 @@ impl CarState {
         }
     }
 }
+
+// How a car moves while Crossing: speed up from start_speed to peak_speed, hold that for a while,
+// then slow down to end_speed, right as the car reaches the end of the DistanceInterval. This keeps
+// the event-driven design -- the car only needs an update at the end of the interval, and its
+// position at any time in between is easy to calculate.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SpeedProfile {
+    start_speed: Speed,
+    peak_speed: Speed,
+    end_speed: Speed,
+    accel: Acceleration,
+    decel: Acceleration,
+    cruise_time: Duration,
+}
+
+impl SpeedProfile {
+    // Also returns the total time to cover the distance.
+    fn new(
+        dist: Distance,
+        start_speed: Speed,
+        cruise_speed: Speed,
+        end_speed: Speed,
+        accel: Acceleration,
+        decel: Acceleration,
+    ) -> (SpeedProfile, Duration) {
+        let d = dist.inner_meters();
+        let a = accel.inner_meters_per_second_squared();
+        let b = decel.inner_meters_per_second_squared();
+        assert!(a > 0.0 && b > 0.0);
+        // Entering a slower road means braking instantly.
+        let v0 = start_speed.min(cruise_speed).inner_meters_per_second();
+        let mut ve = end_speed.min(cruise_speed).inner_meters_per_second();
+        let vc = cruise_speed.inner_meters_per_second();
+
+        let mut vp = if (vc * vc - v0 * v0) / (2.0 * a) + (vc * vc - ve * ve) / (2.0 * b) <= d {
+            vc
+        } else {
+            // Not enough room to reach cruising speed
+            ((2.0 * d + v0 * v0 / a + ve * ve / b) / (1.0 / a + 1.0 / b)).sqrt()
+        };
+        if vp < v0 {
+            // Not enough room to slow down to end_speed. Brake the whole way and arrive too fast.
+            vp = v0;
+            ve = (v0 * v0 - 2.0 * b * d).max(0.0).sqrt();
+        } else if vp < ve {
+            // Not enough room to speed up to end_speed. Accelerate the whole way.
+            vp = (v0 * v0 + 2.0 * a * d).sqrt();
+            ve = vp;
+        }
+
+        let t1 = (vp - v0) / a;
+        let t3 = (vp - ve) / b;
+        let d1 = (v0 + vp) / 2.0 * t1;
+        let d3 = (vp + ve) / 2.0 * t3;
+        let t2 = if vp > 0.0 {
+            (d - d1 - d3).max(0.0) / vp
+        } else {
+            0.0
+        };
+
+        (
+            SpeedProfile {
+                start_speed: Speed::meters_per_second(v0),
+                peak_speed: Speed::meters_per_second(vp),
+                end_speed: Speed::meters_per_second(ve),
+                accel,
+                decel,
+                cruise_time: Duration::seconds(t2),
+            },
+            Duration::seconds(t1 + t2 + t3),
+        )
+    }
+
+    // Seconds spent speeding up, cruising, and braking
+    fn phase_times(&self) -> (f64, f64, f64) {
+        let v0 = self.start_speed.inner_meters_per_second();
+        let vp = self.peak_speed.inner_meters_per_second();
+        let ve = self.end_speed.inner_meters_per_second();
+        (
+            (vp - v0) / self.accel.inner_meters_per_second_squared(),
+            self.cruise_time.inner_seconds(),
+            (vp - ve) / self.decel.inner_meters_per_second_squared(),
+        )
+    }
+
+    pub fn dist_at(
+        &self,
+        time_int: &TimeInterval,
+        dist_int: &DistanceInterval,
+        now: Time,
+    ) -> Distance {
+        if now >= time_int.end {
+            return dist_int.end;
+        }
+        let (t1, t2, t3) = self.phase_times();
+        let v0 = self.start_speed.inner_meters_per_second();
+        let vp = self.peak_speed.inner_meters_per_second();
+        let t = (now - time_int.start).inner_seconds();
+        let dist = if t <= t1 {
+            v0 * t + self.accel.inner_meters_per_second_squared() * t * t / 2.0
+        } else if t <= t1 + t2 {
+            (v0 + vp) / 2.0 * t1 + vp * (t - t1)
+        } else {
+            let s = (t - t1 - t2).min(t3);
+            (v0 + vp) / 2.0 * t1 + vp * t2 + vp * s
+                - self.decel.inner_meters_per_second_squared() * s * s / 2.0
+        };
+        (dist_int.start + Distance::meters(dist)).min(dist_int.end)
+    }
+
+    pub fn speed_at(&self, time_int: &TimeInterval, now: Time) -> Speed {
+        if now >= time_int.end {
+            return self.end_speed;
+        }
+        let (t1, t2, t3) = self.phase_times();
+        let t = (now - time_int.start).inner_seconds();
+        if t <= t1 {
+            self.start_speed + self.accel * Duration::seconds(t)
+        } else if t <= t1 + t2 {
+            self.peak_speed
+        } else {
+            (self.peak_speed - self.decel * Duration::seconds((t - t1 - t2).min(t3)))
+                .max(self.end_speed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CAR_ACCEL, CAR_DECEL};
+
+    fn mps(x: f64) -> Speed {
+        Speed::meters_per_second(x)
+    }
+
+    fn profile(
+        dist: f64,
+        start_speed: Speed,
+        cruise_speed: Speed,
+        end_speed: Speed,
+    ) -> (SpeedProfile, TimeInterval, DistanceInterval) {
+        let dist_int =
+            DistanceInterval::new_driving(Distance::meters(10.0), Distance::meters(10.0 + dist));
+        let (profile, dt) = SpeedProfile::new(
+            dist_int.length(),
+            start_speed,
+            cruise_speed,
+            end_speed,
+            CAR_ACCEL,
+            CAR_DECEL,
+        );
+        let start = Time::START_OF_DAY + Duration::seconds(5.0);
+        (profile, TimeInterval::new(start, start + dt), dist_int)
+    }
+
+    // Walk through the whole profile, checking the car only moves forwards, stays in bounds, and
+    // doesn't jump at the end.
+    fn check(profile: &SpeedProfile, time_int: &TimeInterval, dist_int: &DistanceInterval) {
+        let max_speed = profile.peak_speed;
+        let mut last_dist = dist_int.start;
+        let mut t = time_int.start;
+        while t < time_int.end {
+            let dist = profile.dist_at(time_int, dist_int, t);
+            assert!(dist >= last_dist, "went backwards at {}", t);
+            assert!(dist >= dist_int.start && dist <= dist_int.end);
+            let speed = profile.speed_at(time_int, t);
+            assert!(
+                speed >= Speed::ZERO && speed <= max_speed,
+                "{} at {}",
+                speed,
+                t
+            );
+            last_dist = dist;
+            t += Duration::seconds(0.1);
+        }
+        assert!(dist_int.end - last_dist < Distance::meters(0.5));
+        assert_eq!(
+            profile.dist_at(time_int, dist_int, time_int.end),
+            dist_int.end
+        );
+        assert_eq!(profile.speed_at(time_int, time_int.end), profile.end_speed);
+        // Before the end, the formulas should land on the end too
+        let almost_end = time_int.end - Duration::seconds(0.0001);
+        assert!(
+            dist_int.end - profile.dist_at(time_int, dist_int, almost_end) < Distance::meters(0.01)
+        );
+    }
+
+    #[test]
+    fn test_reaches_cruise_speed() {
+        let (p, time_int, dist_int) = profile(200.0, Speed::ZERO, mps(10.0), Speed::ZERO);
+        check(&p, &time_int, &dist_int);
+        assert_eq!(p.peak_speed, mps(10.0));
+        assert!(p.cruise_time > Duration::ZERO);
+        // 4s speeding up, 16.33s cruising, 3.33s braking
+        assert!(
+            (time_int.end - time_int.start - Duration::seconds(23.6667)).abs()
+                < Duration::seconds(0.01)
+        );
+    }
+
+    #[test]
+    fn test_triangle() {
+        // Too short to reach cruising speed before having to brake again
+        let (p, time_int, dist_int) = profile(10.0, Speed::ZERO, mps(30.0), Speed::ZERO);
+        check(&p, &time_int, &dist_int);
+        assert_eq!(p.cruise_time, Duration::ZERO);
+        assert!(p.peak_speed < mps(30.0));
+        let a = CAR_ACCEL.inner_meters_per_second_squared();
+        let b = CAR_DECEL.inner_meters_per_second_squared();
+        let expected = (2.0 * 10.0 / (1.0 / a + 1.0 / b)).sqrt();
+        assert!((p.peak_speed.inner_meters_per_second() - expected).abs() < 0.001);
+        assert_eq!(p.end_speed, Speed::ZERO);
+    }
+
+    #[test]
+    fn test_cant_stop_in_time() {
+        // Braking the whole way still isn't enough to stop, so the car arrives too fast
+        let (p, time_int, dist_int) = profile(10.0, mps(20.0), mps(20.0), Speed::ZERO);
+        check(&p, &time_int, &dist_int);
+        assert_eq!(p.peak_speed, mps(20.0));
+        assert_eq!(p.cruise_time, Duration::ZERO);
+        let b = CAR_DECEL.inner_meters_per_second_squared();
+        let expected = (20.0 * 20.0 - 2.0 * b * 10.0_f64).sqrt();
+        assert!((p.end_speed.inner_meters_per_second() - expected).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_cant_reach_end_speed() {
+        // Speeding up the whole way still doesn't reach the next road's speed
+        let (p, time_int, dist_int) = profile(5.0, Speed::ZERO, mps(20.0), mps(20.0));
+        check(&p, &time_int, &dist_int);
+        assert_eq!(p.peak_speed, p.end_speed);
+        let a = CAR_ACCEL.inner_meters_per_second_squared();
+        assert!((p.end_speed.inner_meters_per_second() - (2.0 * a * 5.0_f64).sqrt()).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_slower_road() {
+        // Entering a slower road means instantly dropping to its speed
+        let (p, time_int, dist_int) = profile(50.0, mps(20.0), mps(10.0), mps(10.0));
+        check(&p, &time_int, &dist_int);
+        assert_eq!(p.start_speed, mps(10.0));
+        assert_eq!(p.peak_speed, mps(10.0));
+        assert_eq!(time_int.end - time_int.start, Duration::seconds(5.0));
+    }
+}
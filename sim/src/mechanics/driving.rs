@@ -3,14 +3,14 @@ use crate::mechanics::Queue;
 use crate::{
     ActionAtEnd, AgentID, AgentProperties, CarID, Command, CreateCar, DistanceInterval,
     DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSimState, PersonID, Scheduler,
-    TimeInterval, TransitSimState, TripManager, UnzoomedAgent, Vehicle, WalkingSimState,
-    FOLLOWING_DISTANCE,
+    TimeInterval, TransitSimState, TripManager, UnzoomedAgent, Vehicle, VehicleType,
+    WalkingSimState, FOLLOWING_DISTANCE,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{LaneID, Map, Path, PathStep, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{LaneID, Map, Path, PathStep, Position, Traversable};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
 const TIME_TO_UNPARK: Duration = Duration::const_seconds(10.0);
 const TIME_TO_PARK: Duration = Duration::const_seconds(15.0);
@@ -19,6 +19,7 @@ const TIME_TO_WAIT_AT_STOP: Duration = Duration::const_seconds(10.0);
 // TODO Do something else.
 pub(crate) const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
 pub(crate) const BLIND_RETRY_TO_REACH_END_DIST: Duration = Duration::const_seconds(5.0);
+const BLIND_RETRY_TO_CHANGE_LANES: Duration = Duration::const_seconds(5.0);
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct DrivingSimState {
@@ -35,15 +36,24 @@ pub struct DrivingSimState {
     events: Vec<Event>,
 
     recalc_lanechanging: bool,
+    midblock_lanechanging: bool,
+    rerouting_pct: usize,
 }
 
 impl DrivingSimState {
-    pub fn new(map: &Map, recalc_lanechanging: bool) -> DrivingSimState {
+    pub fn new(
+        map: &Map,
+        recalc_lanechanging: bool,
+        midblock_lanechanging: bool,
+        rerouting_pct: usize,
+    ) -> DrivingSimState {
         let mut sim = DrivingSimState {
             cars: BTreeMap::new(),
             queues: BTreeMap::new(),
             events: Vec::new(),
             recalc_lanechanging,
+            midblock_lanechanging,
+            rerouting_pct,
         };
 
         for l in map.all_lanes() {
@@ -131,7 +141,13 @@ impl DrivingSimState {
                     }
                 }
 
-                car.state = car.crossing_state(params.start_dist, now, map);
+                // Vehicles coming in from off the map are already moving.
+                let start_speed = if map.get_i(map.get_l(first_lane).src_i).is_border() {
+                    car.cruise_speed(car.router.head(), map)
+                } else {
+                    Speed::ZERO
+                };
+                car.state = car.crossing_state(params.start_dist, start_speed, now, map);
             }
             scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             {
@@ -242,7 +258,7 @@ impl DrivingSimState {
         scheduler: &mut Scheduler,
     ) -> bool {
         match car.state {
-            CarState::Crossing(_, _) => {
+            CarState::Crossing(_, _, _) => {
                 car.state = CarState::Queued { blocked_since: now };
                 if car.router.last_step() {
                     // Immediately run update_car_with_distances.
@@ -256,6 +272,11 @@ impl DrivingSimState {
                         car.router.opportunistically_lanechange(&self.queues, map);
                     }
                     scheduler.push(now, Command::UpdateCar(car.vehicle.id));
+                } else if self.midblock_lanechanging && queue.cars[0] != car.vehicle.id {
+                    // Stuck behind somebody. Maybe we can go around them.
+                    if let Traversable::Lane(_) = queue.id {
+                        scheduler.update(now, Command::ChangeLanes(car.vehicle.id));
+                    }
                 }
             }
             CarState::Unparking(front, _, _) => {
@@ -273,14 +294,14 @@ impl DrivingSimState {
                         &mut self.events,
                     );
                 }
-                car.state = car.crossing_state(front, now, map);
+                car.state = car.crossing_state(front, Speed::ZERO, now, map);
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             }
             CarState::Idling(dist, _) => {
                 car.router = transit.bus_departed_from_stop(car.vehicle.id);
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
-                car.state = car.crossing_state(dist, now, map);
+                car.state = car.crossing_state(dist, Speed::ZERO, now, map);
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
 
                 // Update our follower, so they know we stopped idling.
@@ -301,6 +322,7 @@ impl DrivingSimState {
                                 follower.state = follower.crossing_state(
                                     // Since the follower was Queued, this must be where they are.
                                     dist - car.vehicle.length - FOLLOWING_DISTANCE,
+                                    Speed::ZERO,
                                     now,
                                     map,
                                 );
@@ -314,7 +336,7 @@ impl DrivingSimState {
                         // They weren't blocked. Note that there's no way the Crossing state could
                         // jump forwards here; the leader is still in front
                         // of them.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::Idling(_, _) => {}
@@ -364,7 +386,13 @@ impl DrivingSimState {
                     &mut self.events,
                 );
                 car.total_blocked_time += now - blocked_since;
-                car.state = car.crossing_state(Distance::ZERO, now, map);
+                // If we didn't have to wait, then we never stopped.
+                let start_speed = if blocked_since == now {
+                    car.cruise_speed(from, map).min(car.cruise_speed(goto, map))
+                } else {
+                    Speed::ZERO
+                };
+                car.state = car.crossing_state(Distance::ZERO, start_speed, now, map);
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                 self.events.push(Event::AgentEntersTraversable(
                     AgentID::Car(car.vehicle.id),
@@ -384,6 +412,8 @@ impl DrivingSimState {
                             Distance::ZERO,
                             car.vehicle.length + FOLLOWING_DISTANCE,
                         ),
+                        start_speed,
+                        car.cruise_speed(goto, map),
                         now,
                         map,
                     )
@@ -419,7 +449,7 @@ impl DrivingSimState {
         let our_dist = dists[idx].1;
 
         match car.state {
-            CarState::Crossing(_, _)
+            CarState::Crossing(_, _, _)
             | CarState::Unparking(_, _, _)
             | CarState::Idling(_, _)
             | CarState::WaitingToAdvance { .. } => unreachable!(),
@@ -476,7 +506,7 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::GotoLaneEnd) => {
                         car.total_blocked_time += now - blocked_since;
-                        car.state = car.crossing_state(our_dist, now, map);
+                        car.state = car.crossing_state(our_dist, Speed::ZERO, now, map);
                         scheduler
                             .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                         true
@@ -494,15 +524,18 @@ impl DrivingSimState {
                         false
                     }
                     Some(ActionAtEnd::BusAtStop) => {
-                        transit.bus_arrived_at_stop(
+                        car.total_blocked_time += now - blocked_since;
+                        if !transit.bus_arrived_at_stop(
                             now,
                             car.vehicle.id,
                             trips,
                             walking,
                             scheduler,
                             map,
-                        );
-                        car.total_blocked_time += now - blocked_since;
+                        ) {
+                            // The bus finished its scheduled run
+                            return false;
+                        }
                         car.state = CarState::Idling(
                             our_dist,
                             TimeInterval::new(now, now + TIME_TO_WAIT_AT_STOP),
@@ -521,8 +554,8 @@ impl DrivingSimState {
                         // to be slower otherwise. :(
                         /*
                         // If this car wasn't blocked at all, when would it reach its goal?
-                        let ideal_end_time = match car.crossing_state(our_dist, now, map) {
-                            CarState::Crossing(time_int, _) => time_int.end,
+                        let ideal_end_time = match car.crossing_state(our_dist, Speed::ZERO, now, map) {
+                            CarState::Crossing(time_int, _, _) => time_int.end,
                             _ => unreachable!(),
                         };
                         if ideal_end_time == now {
@@ -590,6 +623,178 @@ impl DrivingSimState {
         car.vehicle
     }
 
+    // After live map edits, cars whose path uses something that's gone get a new path from the
+    // end of their current lane. Cars that can't continue at all, including stuck_buses waiting at
+    // a stop that's been cut off from the rest of their route, are removed and returned; the
+    // caller has to deal with their trip. Queues are dropped and added to match the map. Returns
+    // (rerouted, removed).
+    pub fn handle_live_edits(
+        &mut self,
+        now: Time,
+        map: &Map,
+        stuck_buses: Vec<CarID>,
+        intersections: &mut IntersectionSimState,
+        scheduler: &mut Scheduler,
+    ) -> (Vec<CarID>, Vec<Vehicle>) {
+        let mut rerouted = Vec::new();
+        let mut evacuate = stuck_buses;
+        for car in self.cars.values_mut() {
+            let id = car.vehicle.id;
+            if evacuate.contains(&id) {
+                continue;
+            }
+            // The back of the car might still be somewhere that's gone
+            if car.last_steps.iter().any(|on| !still_exists(*on, map)) {
+                evacuate.push(id);
+                continue;
+            }
+            let broken = match car
+                .router
+                .get_path()
+                .find_broken_step(car.vehicle.vehicle_type.to_constraints(), map)
+            {
+                Some(idx) => idx,
+                None => {
+                    continue;
+                }
+            };
+            // Keep the current lane, or the lane after the current turn
+            let keep = match car.router.head() {
+                Traversable::Lane(_) => 0,
+                Traversable::Turn(_) => 1,
+            };
+            let old_next = car.router.maybe_next();
+            if broken <= keep || !car.router.reroute(keep, &car.vehicle, map, now) {
+                evacuate.push(id);
+                continue;
+            }
+
+            self.events
+                .push(Event::PathAmended(car.router.get_path().clone()));
+            if let CarState::WaitingToAdvance { .. } = car.state {
+                // Ask for the new turn instead
+                if let Some(Traversable::Turn(t)) = old_next {
+                    intersections.cancel_request(AgentID::Car(id), t);
+                }
+                scheduler.update(now, Command::UpdateCar(id));
+            }
+            rerouted.push(id);
+        }
+
+        let mut affected_queues = BTreeSet::new();
+        let evacuated = evacuate
+            .into_iter()
+            .map(|id| self.evacuate_car(id, intersections, scheduler, &mut affected_queues))
+            .collect();
+
+        // Everybody left the queues that're gone
+        self.queues.retain(|id, queue| {
+            if still_exists(*id, map) {
+                return true;
+            }
+            assert!(queue.cars.is_empty() && queue.laggy_head.is_none());
+            false
+        });
+        for l in map.all_lanes() {
+            let id = Traversable::Lane(l.id);
+            if l.lane_type.is_for_moving_vehicles() && !self.queues.contains_key(&id) {
+                self.queues.insert(id, Queue::new(id, map));
+            }
+        }
+        for t in map.all_turns().values() {
+            let id = Traversable::Turn(t.id);
+            if !t.between_sidewalks() && !self.queues.contains_key(&id) {
+                self.queues.insert(id, Queue::new(id, map));
+            }
+        }
+
+        // Anybody stuck behind a removed car can move again
+        for id in affected_queues {
+            let dists = match self.queues.get(&id) {
+                Some(queue) => queue.get_car_positions(now, &self.cars, &self.queues),
+                None => {
+                    continue;
+                }
+            };
+            for (follower_id, dist) in dists {
+                let follower = self.cars.get_mut(&follower_id).unwrap();
+                if let CarState::Queued { blocked_since } = follower.state {
+                    follower.total_blocked_time += now - blocked_since;
+                    follower.state = follower.crossing_state(dist, Speed::ZERO, now, map);
+                    scheduler.update(
+                        follower.state.get_end_time(),
+                        Command::UpdateCar(follower_id),
+                    );
+                }
+            }
+            if let Traversable::Lane(l) = id {
+                intersections.space_freed(now, map.get_l(l).src_i, scheduler, map);
+            }
+        }
+
+        (rerouted, evacuated)
+    }
+
+    // Like kill_stuck_car, but the car might be on or partly clipping into something that doesn't
+    // exist anymore, so don't look anything up in the map or wake anybody up here.
+    fn evacuate_car(
+        &mut self,
+        id: CarID,
+        intersections: &mut IntersectionSimState,
+        scheduler: &mut Scheduler,
+        affected_queues: &mut BTreeSet<Traversable>,
+    ) -> Vehicle {
+        let mut car = self.cars.remove(&id).unwrap();
+        let agent = AgentID::Car(id);
+
+        let head = car.router.head();
+        self.queues
+            .get_mut(&head)
+            .unwrap()
+            .cars
+            .retain(|c| *c != id);
+        affected_queues.insert(head);
+        match head {
+            Traversable::Lane(_) => {
+                self.queues
+                    .get_mut(&head)
+                    .unwrap()
+                    .free_reserved_space(&car);
+                if let Some(Traversable::Turn(t)) = car.router.maybe_next() {
+                    intersections.cancel_request(agent, t);
+                }
+            }
+            Traversable::Turn(t) => {
+                intersections.turn_abandoned(agent, t);
+                // Starting the turn reserved space in the next lane
+                if let Some(queue) = self.queues.get_mut(&car.router.next()) {
+                    queue.free_reserved_space(&car);
+                }
+            }
+        }
+
+        for on in car.last_steps.drain(..).collect::<Vec<_>>() {
+            let queue = self.queues.get_mut(&on).unwrap();
+            assert_eq!(queue.laggy_head, Some(id));
+            queue.laggy_head = None;
+            affected_queues.insert(on);
+            match on {
+                Traversable::Lane(_) => {
+                    queue.free_reserved_space(&car);
+                }
+                Traversable::Turn(t) => {
+                    intersections.turn_abandoned(agent, t);
+                }
+            }
+        }
+
+        intersections.vehicle_gone(id);
+        scheduler.cancel(Command::UpdateCar(id));
+        scheduler.cancel(Command::UpdateLaggyHead(id));
+        scheduler.cancel(Command::ChangeLanes(id));
+        car.vehicle
+    }
+
     fn delete_car(
         &mut self,
         car: &mut Car,
@@ -626,8 +831,21 @@ impl DrivingSimState {
 
         // We might've scheduled one of those using BLIND_RETRY_TO_CREEP_FORWARDS.
         scheduler.cancel(Command::UpdateLaggyHead(car.vehicle.id));
+        scheduler.cancel(Command::ChangeLanes(car.vehicle.id));
 
-        // Update the follower so that they don't suddenly jump forwards.
+        self.update_follower(&dists, idx, now, map, scheduler);
+    }
+
+    // The car at idx is leaving the queue. Update the follower so that they don't suddenly jump
+    // forwards.
+    fn update_follower(
+        &mut self,
+        dists: &Vec<(CarID, Distance)>,
+        idx: usize,
+        now: Time,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
         if idx != dists.len() - 1 {
             let (follower_id, follower_dist) = dists[idx + 1];
             let mut follower = self.cars.get_mut(&follower_id).unwrap();
@@ -638,17 +856,18 @@ impl DrivingSimState {
                 CarState::Queued { blocked_since } => {
                     // Prevent them from jumping forwards.
                     follower.total_blocked_time += now - blocked_since;
-                    follower.state = follower.crossing_state(follower_dist, now, map);
+                    follower.state = follower.crossing_state(follower_dist, Speed::ZERO, now, map);
                     scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
                     );
                 }
-                CarState::Crossing(_, _) => {
+                CarState::Crossing(_, _, _) => {
                     // If the follower was still Crossing, they might not've been blocked
                     // by leader yet. In that case, recalculating their Crossing state is a
                     // no-op.
-                    follower.state = follower.crossing_state(follower_dist, now, map);
+                    let speed = follower.current_speed(follower_dist, now);
+                    follower.state = follower.crossing_state(follower_dist, speed, now, map);
                     scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
@@ -663,6 +882,191 @@ impl DrivingSimState {
         }
     }
 
+    // Some drivers look for a faster way around the delays happening right now: how long cars have
+    // been stuck on each lane, and how long agents have been waiting for each turn.
+    pub fn reroute_cars(&mut self, now: Time, map: &Map, intersections: &IntersectionSimState) {
+        // How long cars have been stuck on each lane, and waiting for each turn
+        let mut delays: BTreeMap<Traversable, Duration> = BTreeMap::new();
+        for queue in self.queues.values() {
+            if let Traversable::Turn(_) = queue.id {
+                continue;
+            }
+            for id in &queue.cars {
+                match self.cars[id].state {
+                    CarState::Queued { blocked_since }
+                    | CarState::WaitingToAdvance { blocked_since } => {
+                        let worst = delays.entry(queue.id).or_insert(Duration::ZERO);
+                        *worst = worst.max(now - blocked_since);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for (t, delay) in intersections.current_turn_delays(now) {
+            delays.insert(Traversable::Turn(t), delay);
+        }
+
+        for car in self.cars.values_mut() {
+            // The same drivers every time. Buses stick to their routes, and bikes don't care as
+            // much about congestion.
+            if car.vehicle.id.0 % 100 >= self.rerouting_pct
+                || car.vehicle.vehicle_type != VehicleType::Car
+            {
+                continue;
+            }
+            // Somebody waiting to advance has already asked for their next turn.
+            match (&car.state, car.router.head()) {
+                (CarState::Crossing(_, _, _), Traversable::Lane(_))
+                | (CarState::Queued { .. }, Traversable::Lane(_)) => {}
+                _ => {
+                    continue;
+                }
+            }
+            if car.router.last_step() {
+                continue;
+            }
+            if car.router.reroute_around_delays(map, &delays, now) {
+                self.events
+                    .push(Event::PathAmended(car.router.get_path().clone()));
+            }
+        }
+    }
+
+    // A car stuck mid-block behind somebody slow or stopped (a bus at a stop, a car waiting to
+    // turn) tries to go around them, using an adjacent lane of the same road.
+    pub fn change_lanes(
+        &mut self,
+        id: CarID,
+        now: Time,
+        map: &Map,
+        intersections: &mut IntersectionSimState,
+        scheduler: &mut Scheduler,
+    ) {
+        // The car might've started moving again or disappeared since this was scheduled. If their
+        // back is still in the intersection, they can't swerve yet.
+        let (from, blocked_since) = match self.cars.get(&id) {
+            Some(car) if car.last_steps.is_empty() && !car.router.last_step() => {
+                match (&car.state, car.router.head()) {
+                    (CarState::Queued { blocked_since }, Traversable::Lane(l)) => {
+                        (l, *blocked_since)
+                    }
+                    _ => {
+                        return;
+                    }
+                }
+            }
+            _ => {
+                return;
+            }
+        };
+        let dists =
+            self.queues[&Traversable::Lane(from)].get_car_positions(now, &self.cars, &self.queues);
+        let idx = dists.iter().position(|(c, _)| *c == id).unwrap();
+        // Nobody in front of us; we're just waiting for the laggy head to clear.
+        if idx == 0 {
+            return;
+        }
+        let our_dist = dists[idx].1;
+
+        let car = &self.cars[&id];
+        let len = car.vehicle.length;
+        let lane = map.get_l(from);
+        let road = map.get_parent(from);
+        let (fwds, offset) = road.dir_and_offset(from);
+        let siblings = if fwds {
+            &road.children_forwards
+        } else {
+            &road.children_backwards
+        };
+
+        let mut worth_retrying = false;
+        // (room ahead, lane, dist, index in the queue, new path steps)
+        let mut best: Option<(Distance, LaneID, Distance, usize, Vec<PathStep>)> = None;
+        for (i, (to, lt)) in siblings.iter().enumerate() {
+            if (i + 1 != offset && i != offset + 1) || *lt != lane.lane_type {
+                continue;
+            }
+            let steps = match car.router.midblock_lanechange(*to, map) {
+                Some(steps) => steps,
+                None => {
+                    continue;
+                }
+            };
+            // From here, it's only a matter of waiting for a gap.
+            worth_retrying = true;
+
+            let queue = &self.queues[&Traversable::Lane(*to)];
+            if !queue.room_for_car(car) {
+                continue;
+            }
+            let dist = Position::new(from, our_dist)
+                .equiv_pos(*to, len, map)
+                .dist_along();
+            // Leave room for anybody about to enter the lane behind us.
+            if dist < len + FOLLOWING_DISTANCE {
+                continue;
+            }
+            let insert_idx =
+                match queue.get_idx_to_insert_car(dist, len, now, &self.cars, &self.queues) {
+                    Some(i) => i,
+                    None => {
+                        continue;
+                    }
+                };
+            let room_ahead = if insert_idx == 0 {
+                queue.geom_len - dist
+            } else {
+                let (leader, leader_dist) =
+                    queue.get_car_positions(now, &self.cars, &self.queues)[insert_idx - 1];
+                leader_dist - self.cars[&leader].vehicle.length - FOLLOWING_DISTANCE - dist
+            };
+            // Don't swerve just to get stuck again right away.
+            if room_ahead < len + FOLLOWING_DISTANCE {
+                continue;
+            }
+            if best
+                .as_ref()
+                .map(|(room, _, _, _, _)| room_ahead > *room)
+                .unwrap_or(true)
+            {
+                best = Some((room_ahead, *to, dist, insert_idx, steps));
+            }
+        }
+
+        let (_, to, dist, insert_idx, steps) = match best {
+            Some(x) => x,
+            None => {
+                if worth_retrying {
+                    scheduler.update(now + BLIND_RETRY_TO_CHANGE_LANES, Command::ChangeLanes(id));
+                }
+                return;
+            }
+        };
+
+        let mut car = self.cars.remove(&id).unwrap();
+        {
+            let queue = self.queues.get_mut(&Traversable::Lane(from)).unwrap();
+            assert_eq!(queue.cars.remove(idx).unwrap(), id);
+            queue.free_reserved_space(&car);
+            intersections.space_freed(now, lane.src_i, scheduler, map);
+        }
+        self.update_follower(&dists, idx, now, map, scheduler);
+        {
+            // The gap was checked above, so nobody behind us has to move.
+            let queue = self.queues.get_mut(&Traversable::Lane(to)).unwrap();
+            queue.cars.insert(insert_idx, id);
+            queue.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
+        }
+
+        car.router.change_lanes(steps, map);
+        self.events
+            .push(Event::PathAmended(car.router.get_path().clone()));
+        car.total_blocked_time += now - blocked_since;
+        car.state = car.crossing_state(dist, Speed::ZERO, now, map);
+        scheduler.update(car.state.get_end_time(), Command::UpdateCar(id));
+        self.cars.insert(id, car);
+    }
+
     pub fn update_laggy_head(
         &mut self,
         id: CarID,
@@ -715,6 +1119,8 @@ impl DrivingSimState {
                         dist_along_last,
                         self.cars[&id].vehicle.length + FOLLOWING_DISTANCE,
                     ),
+                    self.cars[&id].current_speed(dist_along_last, now),
+                    self.cars[&id].cruise_speed(currently_on, map),
                     now,
                     map,
                 )
@@ -794,7 +1200,7 @@ impl DrivingSimState {
                         // They weren't blocked. Note that there's no way the Crossing state
                         // could jump forwards here; the leader
                         // vanished from the end of the traversable.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::Idling(_, _) => {}
@@ -891,6 +1297,19 @@ impl DrivingSimState {
         }
     }
 
+    pub fn find_divergence(&self, other: &DrivingSimState) -> Option<String> {
+        if let Some(id) = abstutil::first_difference(&self.cars, &other.cars) {
+            return Some(format!("{} differs", id));
+        }
+        if let Some(on) = abstutil::first_difference(&self.queues, &other.queues) {
+            return Some(format!("queue on {:?} differs", on));
+        }
+        if self != other {
+            return Some("driving state differs".to_string());
+        }
+        None
+    }
+
     pub fn debug_lane(&self, id: LaneID) {
         if let Some(ref queue) = self.queues.get(&Traversable::Lane(id)) {
             println!("{}", abstutil::to_json(queue));
@@ -1022,3 +1441,11 @@ impl DrivingSimState {
         std::mem::replace(&mut self.events, Vec::new())
     }
 }
+
+// Does the queue for this still make sense after live map edits?
+fn still_exists(on: Traversable, map: &Map) -> bool {
+    match on {
+        Traversable::Lane(l) => map.get_l(l).lane_type.is_for_moving_vehicles(),
+        Traversable::Turn(t) => map.maybe_get_t(t).is_some(),
+    }
+}
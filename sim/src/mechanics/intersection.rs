@@ -1,10 +1,12 @@
 use crate::mechanics::car::Car;
 use crate::mechanics::Queue;
-use crate::{AgentID, AlertLocation, CarID, Command, Event, Scheduler, Speed, TripMode};
-use abstutil::{deserialize_btreemap, retain_btreeset, serialize_btreemap};
+use crate::{
+    AgentID, AlertLocation, CarID, Command, Event, Scheduler, Speed, TripMode, VehicleType,
+};
+use abstutil::{deserialize_btreemap, retain_btreemap, retain_btreeset, serialize_btreemap};
 use geom::{Duration, Time};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, Map, RoadID, Traversable,
+    ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, Map, Phase, RoadID, Traversable,
     TurnID, TurnPriority, TurnType,
 };
 use serde::{Deserialize, Serialize};
@@ -22,6 +24,8 @@ pub struct IntersectionSimState {
     // (x, y) means x is blocked by y. It's a many-to-many relationship. TODO Better data
     // structure.
     blocked_by: BTreeSet<(CarID, CarID)>,
+    // How long everybody who's started a turn anywhere waited for it, added up
+    finished_delay: Duration,
     events: Vec<Event>,
 }
 
@@ -35,6 +39,36 @@ struct State {
         deserialize_with = "deserialize_btreemap"
     )]
     waiting: BTreeMap<Request, Time>,
+    // Only for traffic signals
+    signal: Option<SignalState>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct SignalState {
+    current_phase: usize,
+    phase_started: Time,
+    // When the current phase is reconsidered. For fixed phases, that's when it ends.
+    phase_ends: Time,
+    // The last time somebody started a turn during this phase
+    last_served: Time,
+    // After an all-walk phase, vehicles can't start turns until this time
+    clearance_until: Time,
+}
+
+impl SignalState {
+    // Start off following the fixed timing, so offsets still work.
+    fn new(now: Time, signal: &ControlTrafficSignal, scheduler: &mut Scheduler) -> SignalState {
+        let (idx, phase, remaining) = signal.current_phase_and_remaining_time(now);
+        let phase_started = now - (phase.duration - remaining);
+        scheduler.push(now + remaining, Command::UpdateIntersection(signal.id));
+        SignalState {
+            current_phase: idx,
+            phase_started,
+            phase_ends: now + remaining,
+            last_served: phase_started,
+            clearance_until: phase_started,
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Debug)]
@@ -57,24 +91,51 @@ impl IntersectionSimState {
             dont_block_the_box,
             break_turn_conflict_cycles,
             blocked_by: BTreeSet::new(),
+            finished_delay: Duration::ZERO,
             events: Vec::new(),
         };
         for i in map.all_intersections() {
-            sim.state.insert(
-                i.id,
-                State {
-                    id: i.id,
-                    accepted: BTreeSet::new(),
-                    waiting: BTreeMap::new(),
-                },
-            );
+            let mut state = State {
+                id: i.id,
+                accepted: BTreeSet::new(),
+                waiting: BTreeMap::new(),
+                signal: None,
+            };
             if i.is_traffic_signal() && !use_freeform_policy_everywhere {
-                sim.update_intersection(Time::START_OF_DAY, i.id, map, scheduler);
+                state.signal = Some(SignalState::new(
+                    Time::START_OF_DAY,
+                    map.get_traffic_signal(i.id),
+                    scheduler,
+                ));
             }
+            sim.state.insert(i.id, state);
         }
         sim
     }
 
+    // After live map edits, forget requests for turns that're gone, and restart edited traffic
+    // signals from their fixed timing. The caller should wake up these intersections afterwards.
+    pub fn handle_live_edits(
+        &mut self,
+        now: Time,
+        map: &Map,
+        changed: &BTreeSet<IntersectionID>,
+        scheduler: &mut Scheduler,
+    ) {
+        for i in changed {
+            let state = self.state.get_mut(i).unwrap();
+            retain_btreemap(&mut state.waiting, |req, _| {
+                map.maybe_get_t(req.turn).is_some()
+            });
+
+            scheduler.cancel(Command::UpdateIntersection(*i));
+            state.signal = None;
+            if map.get_i(*i).is_traffic_signal() && !self.use_freeform_policy_everywhere {
+                state.signal = Some(SignalState::new(now, map.get_traffic_signal(*i), scheduler));
+            }
+        }
+    }
+
     pub fn nobody_headed_towards(&self, lane: LaneID, i: IntersectionID) -> bool {
         !self.state[&i]
             .accepted
@@ -102,6 +163,16 @@ impl IntersectionSimState {
         }
     }
 
+    // For agents removed mid-turn by live map edits. The turn might not exist anymore, and nobody
+    // is woken up here.
+    pub fn turn_abandoned(&mut self, agent: AgentID, turn: TurnID) {
+        let state = self.state.get_mut(&turn.parent).unwrap();
+        assert!(state.accepted.remove(&Request { agent, turn }));
+        if let AgentID::Car(car) = agent {
+            self.vehicle_gone(car);
+        }
+    }
+
     // For deleting cars
     pub fn cancel_request(&mut self, agent: AgentID, turn: TurnID) {
         let state = self.state.get_mut(&turn.parent).unwrap();
@@ -152,8 +223,13 @@ impl IntersectionSimState {
                 protected.push(req);
             }
         } else if let Some(ref signal) = map.maybe_get_traffic_signal(i) {
-            let (_, phase, _) = signal.current_phase_and_remaining_time(now);
+            let phase = &signal.phases[self.state[&i].signal.as_ref().unwrap().current_phase];
             for (req, _) in all {
+                // Trains don't care about the current phase
+                if is_train(req.agent) {
+                    protected.push(req);
+                    continue;
+                }
                 match phase.get_priority_of_turn(req.turn, signal) {
                     TurnPriority::Protected => {
                         protected.push(req);
@@ -195,17 +271,88 @@ impl IntersectionSimState {
 
     // This is only triggered for traffic signals.
     pub fn update_intersection(
-        &self,
+        &mut self,
         now: Time,
         id: IntersectionID,
         map: &Map,
         scheduler: &mut Scheduler,
     ) {
+        let signal = map.get_traffic_signal(id);
+        let state = self.state.get_mut(&id).unwrap();
+        let ss = state.signal.as_mut().unwrap();
+
+        // Keep an actuated phase going while it's still serving somebody
+        let phase = &signal.phases[ss.current_phase];
+        let mut extend_until = None;
+        if let Some(ref a) = phase.actuated {
+            let max_end = ss.phase_started + a.max_duration;
+            let until = if has_demand(phase, signal, &state.waiting) {
+                now + a.gap
+            } else {
+                ss.last_served + a.gap
+            };
+            if now < max_end && until > now {
+                extend_until = Some(until.min(max_end));
+            }
+        }
+
+        if let Some(t) = extend_until {
+            ss.phase_ends = t;
+        } else {
+            let num_phases = signal.phases.len();
+            let mut next = (ss.current_phase + 1) % num_phases;
+            // If every phase is skipped, this winds up back at the one after the current.
+            for _ in 0..num_phases {
+                let p = &signal.phases[next];
+                let skip = p
+                    .actuated
+                    .as_ref()
+                    .map(|a| a.skip_if_no_demand)
+                    .unwrap_or(false);
+                if skip && !has_demand(p, signal, &state.waiting) {
+                    next = (next + 1) % num_phases;
+                } else {
+                    break;
+                }
+            }
+            let clearance_until = if signal.phases[ss.current_phase].is_all_walk() {
+                now + signal.pedestrian_timing.scramble_clearance
+            } else {
+                now
+            };
+            *ss = SignalState {
+                current_phase: next,
+                phase_started: now,
+                phase_ends: now + signal.phases[next].duration,
+                last_served: now,
+                clearance_until,
+            };
+        }
+        let phase_ends = ss.phase_ends;
+
         self.wakeup_waiting(now, id, scheduler, map);
-        let (_, _, remaining) = map
-            .get_traffic_signal(id)
-            .current_phase_and_remaining_time(now);
-        scheduler.push(now + remaining, Command::UpdateIntersection(id));
+        scheduler.push(phase_ends, Command::UpdateIntersection(id));
+    }
+
+    // The index of the current phase, and how long until it's reconsidered. Actuated phases might
+    // be extended past that.
+    pub fn current_phase_and_remaining_time(
+        &self,
+        now: Time,
+        i: IntersectionID,
+        map: &Map,
+    ) -> (usize, Duration) {
+        let signal = map.get_traffic_signal(i);
+        match self.state[&i].signal {
+            // The signal might've been edited since the simulation started
+            Some(ref ss) if ss.current_phase < signal.phases.len() => {
+                (ss.current_phase, ss.phase_ends - now)
+            }
+            _ => {
+                let (idx, _, remaining) = signal.current_phase_and_remaining_time(now);
+                (idx, remaining)
+            }
+        }
     }
 
     // For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
@@ -286,10 +433,11 @@ impl IntersectionSimState {
             }
         }
 
-        // TODO For now, we're only interested in signals, and there's too much raw data to store
-        // for stop signs too.
         let state = self.state.get_mut(&turn.parent).unwrap();
         let delay = now - state.waiting.remove(&req).unwrap();
+        self.finished_delay += delay;
+        // TODO For now, we're only interested in signals, and there's too much raw data to store
+        // for stop signs too.
         if map.maybe_get_traffic_signal(state.id).is_some() {
             self.events.push(Event::IntersectionDelayMeasured(
                 turn.parent,
@@ -297,6 +445,11 @@ impl IntersectionSimState {
                 TripMode::from_agent(agent),
             ));
         }
+        if let Some(ref mut ss) = state.signal {
+            if !is_train(agent) && map.get_t(turn).turn_type != TurnType::SharedSidewalkCorner {
+                ss.last_served = now;
+            }
+        }
         state.accepted.insert(req);
         if self.break_turn_conflict_cycles {
             if let AgentID::Car(car) = agent {
@@ -359,6 +512,33 @@ impl IntersectionSimState {
         candidates
     }
 
+    // How long everybody currently waiting here has been waiting, added up
+    pub fn current_delay(&self, now: Time, i: IntersectionID) -> Duration {
+        self.state[&i]
+            .waiting
+            .values()
+            .fold(Duration::ZERO, |sum, t| sum + (now - *t))
+    }
+
+    // All of the delay at every intersection so far, including agents still waiting
+    pub fn total_delay(&self, now: Time) -> Duration {
+        self.state.keys().fold(self.finished_delay, |sum, i| {
+            sum + self.current_delay(now, *i)
+        })
+    }
+
+    // For every turn somebody's waiting to start, the longest anybody's waited so far
+    pub fn current_turn_delays(&self, now: Time) -> BTreeMap<TurnID, Duration> {
+        let mut delays = BTreeMap::new();
+        for state in self.state.values() {
+            for (req, t) in &state.waiting {
+                let worst = delays.entry(req.turn).or_insert(Duration::ZERO);
+                *worst = worst.max(now - *t);
+            }
+        }
+        delays
+    }
+
     // Weird way to measure this, but it works.
     pub fn worst_delay(
         &self,
@@ -415,6 +595,9 @@ impl IntersectionSimState {
         scheduler: &mut Scheduler,
         maybe_cars_and_queues: Option<(&BTreeMap<CarID, Car>, &BTreeMap<Traversable, Queue>)>,
     ) -> bool {
+        if self.train_waiting_to_cross(req, map) {
+            return false;
+        }
         if !self.handle_accepted_conflicts(req, map, maybe_cars_and_queues) {
             return false;
         }
@@ -469,7 +652,16 @@ impl IntersectionSimState {
             return true;
         }
 
-        let (_, phase, remaining_phase_time) = signal.current_phase_and_remaining_time(now);
+        // Trains preempt the signal. They just wait for anybody already crossing to clear out.
+        if is_train(req.agent) {
+            return self.handle_accepted_conflicts(req, map, maybe_cars_and_queues);
+        }
+        if self.train_waiting_to_cross(req, map) {
+            return false;
+        }
+
+        let ss = self.state[&req.turn.parent].signal.clone().unwrap();
+        let phase = &signal.phases[ss.current_phase];
 
         // Can't go at all this phase.
         let our_priority = phase.get_priority_of_turn(req.turn, signal);
@@ -477,6 +669,40 @@ impl IntersectionSimState {
             return false;
         }
 
+        // An actuated phase keeps going while people start turns, up to its max.
+        let (remaining_phase_time, longest_phase) = match phase.actuated {
+            Some(ref a) => (ss.phase_started + a.max_duration - now, a.max_duration),
+            None => (ss.phase_ends - now, phase.duration),
+        };
+
+        let timing = &signal.pedestrian_timing;
+        if turn.turn_type == TurnType::Crosswalk {
+            // Flashing don't walk. When the next phase starts, everybody waiting gets woken up.
+            if remaining_phase_time < timing.flashing_dont_walk {
+                return false;
+            }
+        } else {
+            // Vehicles might have to wait for a leading pedestrian interval, or for pedestrians to
+            // clear out after an all-walk phase.
+            let mut wait_until = ss.clearance_until;
+            if timing.leading_interval > Duration::ZERO
+                && phase.protected_groups.iter().any(|g| {
+                    g.crosswalk
+                        && signal.turn_groups[g]
+                            .members
+                            .iter()
+                            .any(|t| map.get_t(*t).conflicts_with(turn))
+                })
+            {
+                wait_until = wait_until.max(ss.phase_started + timing.leading_interval);
+            }
+            if now < wait_until {
+                // Like the yield case below, we own scheduling for req.agent.
+                scheduler.push(wait_until, Command::update_agent(req.agent));
+                return false;
+            }
+        }
+
         // Somebody might already be doing a Yield turn that conflicts with this one.
         if !self.handle_accepted_conflicts(req, map, maybe_cars_and_queues) {
             return false;
@@ -503,18 +729,24 @@ impl IntersectionSimState {
         // TODO Make sure we can optimistically finish this turn before an approaching
         // higher-priority vehicle wants to begin.
 
+        // With flashing don't walk, pedestrians who start crossing before it are allowed to finish
+        // during it.
+        if turn.turn_type == TurnType::Crosswalk && timing.flashing_dont_walk > Duration::ZERO {
+            return true;
+        }
+
         // Optimistically if nobody else is in the way, this is how long it'll take to finish the
         // turn. Don't start the turn if we won't finish by the time the light changes. If we get
         // it wrong, that's fine -- block the box a bit.
         let time_to_cross = turn.geom.length() / speed;
         if time_to_cross > remaining_phase_time {
             // Actually, we might have bigger problems...
-            if time_to_cross > phase.duration {
+            if time_to_cross > longest_phase {
                 self.events.push(Event::Alert(
                     AlertLocation::Intersection(req.turn.parent),
                     format!(
                         "{:?} is impossible to fit into phase duration of {}",
-                        req, phase.duration
+                        req, longest_phase
                     ),
                 ));
             } else {
@@ -525,6 +757,17 @@ impl IntersectionSimState {
         true
     }
 
+    // Nobody starts a turn that'd cut off a train waiting to cross. When the train finishes, it
+    // wakes everybody up again.
+    fn train_waiting_to_cross(&self, req: &Request, map: &Map) -> bool {
+        let turn = map.get_t(req.turn);
+        self.state[&req.turn.parent].waiting.keys().any(|other| {
+            is_train(other.agent)
+                && other.agent != req.agent
+                && map.get_t(other.turn).conflicts_with(turn)
+        })
+    }
+
     // If true, the request can go.
     fn handle_accepted_conflicts(
         &mut self,
@@ -619,6 +862,24 @@ impl IntersectionSimState {
     }
 }
 
+fn is_train(agent: AgentID) -> bool {
+    match agent {
+        AgentID::Car(CarID(_, VehicleType::Train)) => true,
+        _ => false,
+    }
+}
+
+// Is anybody waiting for a turn that this phase would let them make?
+fn has_demand(
+    phase: &Phase,
+    signal: &ControlTrafficSignal,
+    waiting: &BTreeMap<Request, Time>,
+) -> bool {
+    waiting.keys().any(|req| {
+        !is_train(req.agent) && phase.get_priority_of_turn(req.turn, signal) != TurnPriority::Banned
+    })
+}
+
 // TODO Sometimes a traffic signal is surrounded by tiny lanes with almost no capacity. Workaround
 // for now.
 fn allow_block_the_box(osm_node_id: i64) -> bool {
@@ -665,3 +926,461 @@ fn allow_conflicting_turns(osm_node_id: i64) -> bool {
     ]
     .contains(&osm_node_id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PedestrianID, PersonID};
+    use abstutil::Timer;
+    use map_model::{Actuated, EditCmd, EditIntersection, Turn};
+
+    fn car(id: usize) -> AgentID {
+        AgentID::Car(CarID(id, VehicleType::Car))
+    }
+
+    fn train(id: usize) -> AgentID {
+        AgentID::Car(CarID(id, VehicleType::Train))
+    }
+
+    // The map has one traffic signal
+    fn setup() -> (Map, IntersectionID, IntersectionSimState) {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        let i = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap()
+            .id;
+        let state = IntersectionSimState::new(&map, &mut Scheduler::new(), false, true, true);
+        (map, i, state)
+    }
+
+    // Two vehicle turns that cross each other
+    fn conflicting_turns(map: &Map, i: IntersectionID) -> (TurnID, TurnID) {
+        let turns: Vec<&Turn> = map
+            .get_turns_in_intersection(i)
+            .into_iter()
+            .filter(|t| !t.between_sidewalks())
+            .collect();
+        for t1 in &turns {
+            for t2 in &turns {
+                if t1.conflicts_with(t2) {
+                    return (t1.id, t2.id);
+                }
+            }
+        }
+        panic!("No conflicting turns at {}", i);
+    }
+
+    #[test]
+    fn test_is_train() {
+        assert!(is_train(train(0)));
+        assert!(!is_train(car(0)));
+        assert!(!is_train(AgentID::Car(CarID(0, VehicleType::Bus))));
+        assert!(!is_train(AgentID::Pedestrian(PedestrianID(0))));
+        // Riding a train doesn't count
+        assert!(!is_train(AgentID::BusPassenger(
+            PersonID(0),
+            CarID(0, VehicleType::Train)
+        )));
+    }
+
+    #[test]
+    fn test_train_waiting_to_cross() {
+        let (map, i, mut state) = setup();
+        let (t1, t2) = conflicting_turns(&map, i);
+        let car_req = Request {
+            agent: car(1),
+            turn: t2,
+        };
+        assert!(!state.train_waiting_to_cross(&car_req, &map));
+
+        // Other cars waiting don't hold anybody back
+        state.state.get_mut(&i).unwrap().waiting.insert(
+            Request {
+                agent: car(2),
+                turn: t1,
+            },
+            Time::START_OF_DAY,
+        );
+        assert!(!state.train_waiting_to_cross(&car_req, &map));
+
+        let train_req = Request {
+            agent: train(0),
+            turn: t1,
+        };
+        state
+            .state
+            .get_mut(&i)
+            .unwrap()
+            .waiting
+            .insert(train_req.clone(), Time::START_OF_DAY);
+        assert!(state.train_waiting_to_cross(&car_req, &map));
+        // The train doesn't wait for itself
+        assert!(!state.train_waiting_to_cross(&train_req, &map));
+
+        // Turns that don't cross the tracks can still go
+        let other = map
+            .get_turns_in_intersection(i)
+            .into_iter()
+            .find(|t| t.id != t1 && !t.conflicts_with(map.get_t(t1)))
+            .unwrap()
+            .id;
+        assert!(!state.train_waiting_to_cross(
+            &Request {
+                agent: car(1),
+                turn: other,
+            },
+            &map
+        ));
+    }
+
+    fn secs(x: f64) -> Time {
+        Time::START_OF_DAY + Duration::seconds(x)
+    }
+
+    // Every phase after the first lasts at least 10s, and at most 30s, ending once nobody's turned
+    // for 3s. The first phase keeps its fixed timing.
+    fn setup_actuated(skip_if_no_demand: bool) -> (Map, IntersectionID, IntersectionSimState) {
+        let (mut map, i, _) = setup();
+        let mut signal = map.get_traffic_signal(i).clone();
+        assert!(signal.phases.len() >= 2);
+        for phase in signal.phases.iter_mut().skip(1) {
+            phase.duration = Duration::seconds(10.0);
+            phase.actuated = Some(Actuated {
+                max_duration: Duration::seconds(30.0),
+                gap: Duration::seconds(3.0),
+                skip_if_no_demand,
+            });
+        }
+        let mut state = retime(&mut map, signal);
+        start_phase(&mut state, i, 1, secs(0.0), secs(10.0));
+        (map, i, state)
+    }
+
+    // Edit the signal's timing, and start the simulation over
+    fn retime(map: &mut Map, signal: ControlTrafficSignal) -> IntersectionSimState {
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeIntersection {
+            i: signal.id,
+            old: EditIntersection::TrafficSignal(map.get_traffic_signal(signal.id).clone()),
+            new: EditIntersection::TrafficSignal(signal),
+        });
+        map.apply_edits(edits, &mut Timer::throwaway());
+        IntersectionSimState::new(map, &mut Scheduler::new(), false, true, true)
+    }
+
+    fn request(
+        state: &mut IntersectionSimState,
+        agent: AgentID,
+        turn: TurnID,
+        now: Time,
+        map: &Map,
+    ) -> bool {
+        state.maybe_start_turn(
+            agent,
+            turn,
+            Speed::meters_per_second(10.0),
+            now,
+            map,
+            &mut Scheduler::new(),
+            None,
+        )
+    }
+
+    fn start_phase(
+        state: &mut IntersectionSimState,
+        i: IntersectionID,
+        idx: usize,
+        start: Time,
+        end: Time,
+    ) {
+        *state.state.get_mut(&i).unwrap().signal.as_mut().unwrap() = SignalState {
+            current_phase: idx,
+            phase_started: start,
+            phase_ends: end,
+            last_served: start,
+            clearance_until: start,
+        };
+    }
+
+    // A vehicle turn that can go during one phase, but not another
+    fn turn_only_in(map: &Map, i: IntersectionID, allowed: usize, banned: usize) -> TurnID {
+        let signal = map.get_traffic_signal(i);
+        map.get_turns_in_intersection(i)
+            .into_iter()
+            .find(|t| {
+                !t.between_sidewalks()
+                    && signal.phases[allowed].get_priority_of_turn(t.id, signal)
+                        != TurnPriority::Banned
+                    && signal.phases[banned].get_priority_of_turn(t.id, signal)
+                        == TurnPriority::Banned
+            })
+            .unwrap()
+            .id
+    }
+
+    fn wait(state: &mut IntersectionSimState, i: IntersectionID, agent: AgentID, turn: TurnID) {
+        state
+            .state
+            .get_mut(&i)
+            .unwrap()
+            .waiting
+            .insert(Request { agent, turn }, Time::START_OF_DAY);
+    }
+
+    fn signal_state(state: &IntersectionSimState, i: IntersectionID) -> SignalState {
+        state.state[&i].signal.clone().unwrap()
+    }
+
+    #[test]
+    fn test_total_delay_counts_stop_signs() {
+        let (mut map, i, _) = setup();
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeIntersection {
+            i,
+            old: EditIntersection::TrafficSignal(map.get_traffic_signal(i).clone()),
+            new: EditIntersection::StopSign(ControlStopSign::new(&map, i)),
+        });
+        map.apply_edits(edits, &mut Timer::throwaway());
+        let mut state = IntersectionSimState::new(&map, &mut Scheduler::new(), false, true, true);
+
+        let (turn, _) = conflicting_turns(&map, i);
+        wait(&mut state, i, car(1), turn);
+        let now = Time::START_OF_DAY + Duration::minutes(1);
+        assert_eq!(state.total_delay(now), Duration::minutes(1));
+        assert!(request(&mut state, car(1), turn, now, &map));
+        // Only signals record each delay as an event, but stop signs still count
+        assert!(state.collect_events().is_empty());
+        assert_eq!(
+            state.total_delay(now + Duration::minutes(5)),
+            Duration::minutes(1)
+        );
+    }
+
+    #[test]
+    fn test_has_demand() {
+        let (map, i, _) = setup();
+        let signal = map.get_traffic_signal(i);
+        let t = turn_only_in(&map, i, 0, 1);
+        let mut waiting = BTreeMap::new();
+        assert!(!has_demand(&signal.phases[0], signal, &waiting));
+
+        // Trains don't count; they preempt the signal anyway
+        waiting.insert(
+            Request {
+                agent: train(0),
+                turn: t,
+            },
+            Time::START_OF_DAY,
+        );
+        assert!(!has_demand(&signal.phases[0], signal, &waiting));
+
+        waiting.insert(
+            Request {
+                agent: car(1),
+                turn: t,
+            },
+            Time::START_OF_DAY,
+        );
+        assert!(has_demand(&signal.phases[0], signal, &waiting));
+        assert!(!has_demand(&signal.phases[1], signal, &waiting));
+    }
+
+    #[test]
+    fn test_actuated_gap_out() {
+        let (map, i, mut state) = setup_actuated(false);
+        let num_phases = map.get_traffic_signal(i).phases.len();
+
+        // Somebody turned recently, so wait for the gap
+        state
+            .state
+            .get_mut(&i)
+            .unwrap()
+            .signal
+            .as_mut()
+            .unwrap()
+            .last_served = secs(8.0);
+        state.update_intersection(secs(10.0), i, &map, &mut Scheduler::new());
+        let ss = signal_state(&state, i);
+        assert_eq!(ss.current_phase, 1);
+        assert_eq!(ss.phase_ends, secs(11.0));
+
+        // Nobody else showed up
+        state.update_intersection(secs(11.0), i, &map, &mut Scheduler::new());
+        let ss = signal_state(&state, i);
+        assert_eq!(ss.current_phase, 2 % num_phases);
+        assert_eq!(ss.phase_started, secs(11.0));
+    }
+
+    #[test]
+    fn test_actuated_max_green() {
+        let (map, i, mut state) = setup_actuated(false);
+        let num_phases = map.get_traffic_signal(i).phases.len();
+        let t = turn_only_in(&map, i, 1, 0);
+        wait(&mut state, i, car(1), t);
+
+        // Keep extending while somebody's waiting...
+        state.update_intersection(secs(10.0), i, &map, &mut Scheduler::new());
+        assert_eq!(signal_state(&state, i).phase_ends, secs(13.0));
+        // ...but not past the max
+        state.update_intersection(secs(28.0), i, &map, &mut Scheduler::new());
+        assert_eq!(signal_state(&state, i).phase_ends, secs(30.0));
+        state.update_intersection(secs(30.0), i, &map, &mut Scheduler::new());
+        let ss = signal_state(&state, i);
+        assert_eq!(ss.current_phase, 2 % num_phases);
+        assert_eq!(ss.phase_started, secs(30.0));
+    }
+
+    #[test]
+    fn test_actuated_skip_phases_without_demand() {
+        // Without skipping, the signal just goes around
+        let (map, i, mut state) = setup_actuated(false);
+        let num_phases = map.get_traffic_signal(i).phases.len();
+        start_phase(&mut state, i, num_phases - 1, secs(0.0), secs(10.0));
+        state.update_intersection(secs(10.0), i, &map, &mut Scheduler::new());
+        assert_eq!(signal_state(&state, i).current_phase, 0);
+        state.update_intersection(secs(40.0), i, &map, &mut Scheduler::new());
+        assert_eq!(signal_state(&state, i).current_phase, 1);
+
+        // The fixed phase always runs, but the rest are skipped when nobody's waiting
+        let (map, i, mut state) = setup_actuated(true);
+        start_phase(&mut state, i, 0, secs(0.0), secs(10.0));
+        state.update_intersection(secs(10.0), i, &map, &mut Scheduler::new());
+        assert_eq!(signal_state(&state, i).current_phase, 0);
+
+        // Go to the first phase with somebody waiting
+        let t = turn_only_in(&map, i, num_phases - 1, 0);
+        wait(&mut state, i, car(1), t);
+        state.update_intersection(secs(40.0), i, &map, &mut Scheduler::new());
+        let ss = signal_state(&state, i);
+        assert_ne!(ss.current_phase, 0);
+        let signal = map.get_traffic_signal(i);
+        assert!(has_demand(
+            &signal.phases[ss.current_phase],
+            signal,
+            &state.state[&i].waiting
+        ));
+        for idx in 1..ss.current_phase {
+            assert!(!has_demand(
+                &signal.phases[idx],
+                signal,
+                &state.state[&i].waiting
+            ));
+        }
+    }
+
+    #[test]
+    fn test_leading_pedestrian_interval() {
+        let (mut map, i, _) = setup();
+        let signal = map.get_traffic_signal(i).clone();
+        // A vehicle turn that has to wait for a crosswalk in the same phase. Since they conflict,
+        // the vehicle turn only yields.
+        let (idx, t) = signal
+            .phases
+            .iter()
+            .enumerate()
+            .find_map(|(idx, phase)| {
+                let crosswalks: Vec<TurnID> = phase
+                    .protected_groups
+                    .iter()
+                    .filter(|g| g.crosswalk)
+                    .flat_map(|g| signal.turn_groups[g].members.clone())
+                    .collect();
+                map.get_turns_in_intersection(i)
+                    .into_iter()
+                    .find(|t| {
+                        !t.between_sidewalks()
+                            && phase.get_priority_of_turn(t.id, &signal) == TurnPriority::Yield
+                            && crosswalks.iter().any(|c| t.conflicts_with(map.get_t(*c)))
+                    })
+                    .map(|t| (idx, t.id))
+            })
+            .unwrap();
+
+        // Without a leading interval, the vehicle just yields briefly
+        let mut state = retime(&mut map, signal.clone());
+        start_phase(&mut state, i, idx, secs(0.0), secs(30.0));
+        request(&mut state, car(1), t, secs(2.0), &map);
+        assert!(request(&mut state, car(1), t, secs(2.5), &map));
+
+        let mut timed = signal;
+        timed.pedestrian_timing.leading_interval = Duration::seconds(5.0);
+        let mut state = retime(&mut map, timed);
+        start_phase(&mut state, i, idx, secs(0.0), secs(30.0));
+        request(&mut state, car(1), t, secs(2.0), &map);
+        assert!(!request(&mut state, car(1), t, secs(4.0), &map));
+        assert!(request(&mut state, car(1), t, secs(6.0), &map));
+    }
+
+    #[test]
+    fn test_flashing_dont_walk() {
+        let (mut map, i, _) = setup();
+        let signal = map.get_traffic_signal(i).clone();
+        let (idx, t) = signal
+            .phases
+            .iter()
+            .enumerate()
+            .find_map(|(idx, phase)| {
+                phase
+                    .protected_groups
+                    .iter()
+                    .find(|g| g.crosswalk)
+                    .map(|g| (idx, signal.turn_groups[g].members[0]))
+            })
+            .unwrap();
+        let ped = |id| AgentID::Pedestrian(PedestrianID(id));
+
+        // Without flashing don't walk, pedestrians start whenever they can finish in time
+        let mut state = retime(&mut map, signal.clone());
+        start_phase(&mut state, i, idx, secs(0.0), secs(30.0));
+        assert!(request(&mut state, ped(1), t, secs(22.0), &map));
+
+        let mut timed = signal;
+        timed.pedestrian_timing.flashing_dont_walk = Duration::seconds(10.0);
+        let mut state = retime(&mut map, timed);
+        start_phase(&mut state, i, idx, secs(0.0), secs(30.0));
+        assert!(request(&mut state, ped(1), t, secs(15.0), &map));
+        assert!(!request(&mut state, ped(2), t, secs(22.0), &map));
+    }
+
+    #[test]
+    fn test_scramble_clearance() {
+        let (mut map, i, _) = setup();
+        let mut signal = map.get_traffic_signal(i).clone();
+        assert!(signal.convert_to_ped_scramble());
+        signal.pedestrian_timing.scramble_clearance = Duration::seconds(4.0);
+        let walk = signal.phases.iter().position(|p| p.is_all_walk()).unwrap();
+        let next = (walk + 1) % signal.phases.len();
+        let t = map
+            .get_turns_in_intersection(i)
+            .into_iter()
+            .find(|t| {
+                !t.between_sidewalks()
+                    && signal.phases[next].get_priority_of_turn(t.id, &signal)
+                        != TurnPriority::Banned
+            })
+            .unwrap()
+            .id;
+
+        let mut state = retime(&mut map, signal);
+        start_phase(&mut state, i, walk, secs(0.0), secs(10.0));
+        state.update_intersection(secs(10.0), i, &map, &mut Scheduler::new());
+        let ss = signal_state(&state, i);
+        assert_eq!(ss.current_phase, next);
+        assert_eq!(ss.clearance_until, secs(14.0));
+
+        // Vehicles wait for pedestrians to clear out
+        request(&mut state, car(1), t, secs(10.0), &map);
+        assert!(!request(&mut state, car(1), t, secs(12.0), &map));
+        assert!(request(&mut state, car(1), t, secs(15.0), &map));
+
+        // Other phases don't need any clearance
+        let ends = ss.phase_ends;
+        state.update_intersection(ends, i, &map, &mut Scheduler::new());
+        assert_eq!(signal_state(&state, i).clearance_until, ends);
+    }
+}
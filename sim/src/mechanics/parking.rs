@@ -6,7 +6,7 @@ use abstutil::{
 use geom::{Distance, PolyLine, Pt2D};
 use map_model::{
     BuildingID, Lane, LaneID, LaneType, Map, ParkingLotID, PathConstraints, PathStep, Position,
-    Traversable, TurnID,
+    RoadID, Traversable, TurnID,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
@@ -98,9 +98,45 @@ impl ParkingSimState {
         sim
     }
 
+    // After live map edits, rebuild on-street parking along the changed roads. If a parking lane
+    // is gone, cars already parked there or headed to a spot there can stay, but nobody else can
+    // find it.
+    pub fn handle_live_edits(
+        &mut self,
+        map: &Map,
+        changed_roads: &BTreeSet<RoadID>,
+        timer: &mut Timer,
+    ) {
+        for r in changed_roads {
+            for l in map.get_r(*r).all_lanes() {
+                let old = self.onstreet_lanes.remove(&l);
+                if let Some(ref lane) = old {
+                    self.driving_to_parking_lanes.remove(lane.driving_lane, l);
+                }
+
+                if let Some(lane) = ParkingLane::new(map.get_l(l), map, timer) {
+                    self.driving_to_parking_lanes.insert(lane.driving_lane, l);
+                    self.onstreet_lanes.insert(l, lane);
+                } else if let Some(lane) = old {
+                    if lane.spots().into_iter().any(|spot| !self.is_free(spot)) {
+                        self.onstreet_lanes.insert(l, lane);
+                    }
+                }
+            }
+        }
+    }
+
     pub fn get_free_onstreet_spots(&self, l: LaneID) -> Vec<ParkingSpot> {
         let mut spots: Vec<ParkingSpot> = Vec::new();
         if let Some(lane) = self.onstreet_lanes.get(&l) {
+            // Skip lanes that only linger after live map edits
+            if !self
+                .driving_to_parking_lanes
+                .get(lane.driving_lane)
+                .contains(&l)
+            {
+                return spots;
+            }
             for spot in lane.spots() {
                 if self.is_free(spot) {
                     spots.push(spot);
@@ -7,7 +7,8 @@ use crate::{
 use abstutil::{deserialize_multimap, serialize_multimap, MultiMap};
 use geom::{Distance, Duration, Line, PolyLine, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, Map, ParkingLotID, Path, PathStep, Traversable, SIDEWALK_THICKNESS,
+    BuildingID, BusRouteID, Map, ParkingLotID, Path, PathConstraints, PathStep, Traversable,
+    SIDEWALK_THICKNESS,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -320,6 +321,79 @@ impl WalkingSimState {
         };
     }
 
+    // After live map edits, pedestrians whose path uses something that's gone get a new path from
+    // the end of their current sidewalk. Anybody who can't continue is removed and returned; the
+    // caller has to deal with their trip. Returns (rerouted, removed).
+    pub fn handle_live_edits(
+        &mut self,
+        now: Time,
+        map: &Map,
+        intersections: &mut IntersectionSimState,
+        scheduler: &mut Scheduler,
+    ) -> (Vec<PedestrianID>, Vec<PedestrianID>) {
+        let mut rerouted = Vec::new();
+        let mut evacuate = Vec::new();
+        for ped in self.peds.values_mut() {
+            // TODO These would also have to be removed from TransitSimState. Sidewalks can't be
+            // edited anyway.
+            if let PedState::WaitingForBus(_, _) = ped.state {
+                continue;
+            }
+            let broken = match ped.path.find_broken_step(PathConstraints::Pedestrian, map) {
+                Some(idx) => idx,
+                None => {
+                    continue;
+                }
+            };
+            let keep = match ped.path.current_step() {
+                PathStep::Lane(_) | PathStep::ContraflowLane(_) => 0,
+                PathStep::Turn(_) => 1,
+            };
+            if broken <= keep {
+                evacuate.push(ped.id);
+                continue;
+            }
+            let old_next = ped.path.next_step();
+            if !ped
+                .path
+                .reroute(keep, PathConstraints::Pedestrian, map, now)
+            {
+                evacuate.push(ped.id);
+                continue;
+            }
+
+            self.events.push(Event::PathAmended(ped.path.clone()));
+            if let PedState::WaitingToTurn(_, _) = ped.state {
+                // Ask for the new turn instead
+                if let PathStep::Turn(t) = old_next {
+                    intersections.cancel_request(AgentID::Pedestrian(ped.id), t);
+                }
+                scheduler.update(now, Command::UpdatePed(ped.id));
+            }
+            rerouted.push(ped.id);
+        }
+
+        for id in &evacuate {
+            let ped = self.peds.remove(id).unwrap();
+            let agent = AgentID::Pedestrian(*id);
+            let on = ped.path.current_step();
+            self.peds_per_traversable.remove(on.as_traversable(), *id);
+            match on {
+                PathStep::Turn(t) => {
+                    intersections.turn_abandoned(agent, t);
+                }
+                PathStep::Lane(_) | PathStep::ContraflowLane(_) => {
+                    if let PedState::WaitingToTurn(_, _) = ped.state {
+                        intersections.cancel_request(agent, ped.path.next_step().as_turn());
+                    }
+                }
+            }
+            scheduler.cancel(Command::UpdatePed(*id));
+        }
+
+        (rerouted, evacuate)
+    }
+
     pub fn debug_ped(&self, id: PedestrianID) {
         if let Some(ped) = self.peds.get(&id) {
             println!("{}", abstutil::to_json(ped));
@@ -328,6 +402,16 @@ impl WalkingSimState {
         }
     }
 
+    pub fn find_divergence(&self, other: &WalkingSimState) -> Option<String> {
+        if let Some(id) = abstutil::first_difference(&self.peds, &other.peds) {
+            return Some(format!("{} differs", id));
+        }
+        if self != other {
+            return Some("walking state differs".to_string());
+        }
+        None
+    }
+
     pub fn agent_properties(&self, id: PedestrianID, now: Time) -> AgentProperties {
         let p = &self.peds[&id];
         let time_spent_waiting = match p.state {
@@ -5,6 +5,7 @@ pub use pandemic::{Cmd, PandemicModel};
 use rand::Rng;
 use rand_distr::{Distribution, Exp, Normal};
 use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::ops;
 
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
@@ -50,6 +51,23 @@ impl ops::Sub for AnyTime {
     }
 }
 
+// An infinite time means something never happens. JSON can't represent infinity, so write None
+// instead.
+impl Serialize for AnyTime {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let value = if self.is_finite() { Some(self.0) } else { None };
+        value.serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for AnyTime {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<AnyTime, D::Error> {
+        Ok(AnyTime(
+            <Option<f64>>::deserialize(d)?.unwrap_or(std::f64::INFINITY),
+        ))
+    }
+}
+
 impl From<Time> for AnyTime {
     fn from(t: Time) -> AnyTime {
         AnyTime(t.inner_seconds())
@@ -68,7 +86,7 @@ impl From<f64> for AnyTime {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum StateEvent {
     Exposition,
     Incubation,
@@ -77,7 +95,7 @@ pub enum StateEvent {
     Death,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
     s: StateEvent,
     p_hosp: f64,  // probability of people being hospitalized after infection
@@ -149,7 +167,7 @@ impl Event {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum State {
     Sane((Event, Time)),
     Exposed((Event, Time)),
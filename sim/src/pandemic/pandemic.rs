@@ -1,5 +1,6 @@
 use crate::pandemic::{AnyTime, State};
 use crate::{CarID, Event, OffMapLocation, Person, PersonID, Scheduler, TripPhaseType};
+use abstutil::{deserialize_btreemap, serialize_btreemap};
 use geom::{Duration, Time};
 use map_model::{BuildingID, BusStopID};
 use rand::Rng;
@@ -11,7 +12,7 @@ use std::collections::BTreeMap;
 // TODO If two people are in the same shared space indefinitely and neither leaves, we don't model
 // transmission. It only occurs when people leave a space.
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PandemicModel {
     pop: BTreeMap<PersonID, State>,
 
@@ -352,12 +353,17 @@ impl PandemicModel {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
 struct SharedSpace<T: Ord> {
     // Since when has a person been in some shared space?
     // TODO This is an awkward data structure; abstutil::MultiMap is also bad, because key removal
     // would require knowing the time. Want something closer to
     // https://guava.dev/releases/19.0/api/docs/com/google/common/collect/Table.html.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
     occupants: BTreeMap<T, Vec<(PersonID, Time)>>,
 }
 
@@ -410,6 +416,13 @@ impl<T: Ord> SharedSpace<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        AlertHandler, IndividTrip, PersonSpec, Scenario, Sim, SimOptions, SpawnTrip, TripMode,
+    };
+    use abstutil::Timer;
+    use geom::LonLat;
+    use map_model::Map;
+    use rand::SeedableRng;
 
     fn time(x: usize) -> Time {
         Time::START_OF_DAY + Duration::hours(x)
@@ -469,4 +482,96 @@ mod tests {
             Some(vec![(person3, Duration::hours(5))])
         );
     }
+
+    // The synthetic maps have no buildings, so everybody commutes between off-map places. Each
+    // pair of places is shared by enough people that some start out infectious.
+    fn commuters(map: &Map) -> Scenario {
+        let place = |parcel_id: usize| OffMapLocation {
+            parcel_id,
+            gps: LonLat::new(0.0, 0.0),
+        };
+        let trip = |depart: Duration, from: OffMapLocation, to: OffMapLocation| IndividTrip {
+            depart: Time::START_OF_DAY + depart,
+            trip: SpawnTrip::Remote {
+                from,
+                to,
+                trip_time: Duration::minutes(1),
+                mode: TripMode::Drive,
+            },
+            cancelled: false,
+        };
+
+        let mut scenario = Scenario::empty(map, "pandemic_commuters");
+        for id in 0..20_000 {
+            let home = place(2 * (id % 20));
+            let work = place(2 * (id % 20) + 1);
+            scenario.people.push(PersonSpec {
+                id: PersonID(id),
+                orig_id: None,
+                trips: vec![
+                    trip(Duration::minutes(5), home.clone(), work.clone()),
+                    trip(Duration::minutes(30), work, home),
+                ],
+            });
+        }
+        scenario
+    }
+
+    fn new_sim(map: &Map, timer: &mut Timer) -> Sim {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let scenario = commuters(map);
+
+        let mut opts = SimOptions::new("pandemic_savestate");
+        opts.enable_pandemic_model = Some(XorShiftRng::from_seed([42; 16]));
+        opts.alerts = AlertHandler::Silence;
+        let mut sim = Sim::new(map, opts, timer);
+        scenario.instantiate(&mut sim, map, &mut rng, timer);
+        sim
+    }
+
+    fn pandemic_bytes(sim: &Sim) -> Vec<u8> {
+        bincode::serialize(sim.get_pandemic_model().unwrap()).unwrap()
+    }
+
+    // Only about 1 in 2,000 people start out infectious, so this needs a big population and takes
+    // a while. Run with --ignored.
+    #[test]
+    #[ignore]
+    fn test_resume_from_savestate() {
+        let mut timer = Timer::throwaway();
+        let map = Map::new(abstutil::path_synthetic_map("signal_fan_in"), &mut timer);
+
+        let mut uninterrupted = new_sim(&map, &mut timer);
+        uninterrupted.timed_step(&map, Duration::hours(1), &mut None, &mut timer);
+
+        let mut before = new_sim(&map, &mut timer);
+        before.timed_step(&map, Duration::minutes(2), &mut None, &mut timer);
+        // Otherwise there's nothing to compare
+        assert!(
+            uninterrupted.get_pandemic_model().unwrap().count_sane()
+                < before.get_pandemic_model().unwrap().count_sane(),
+            "nobody was infected"
+        );
+        for path in vec![
+            format!("{}/pandemic_savestate.bin", std::env::temp_dir().display()),
+            format!("{}/pandemic_savestate.json", std::env::temp_dir().display()),
+        ] {
+            before.save_as(path.clone(), false);
+            let mut resumed = Sim::load_savestate(path.clone(), &map, &mut timer).unwrap();
+            assert_eq!(pandemic_bytes(&resumed), pandemic_bytes(&before));
+
+            resumed.timed_step(&map, Duration::minutes(58), &mut None, &mut timer);
+            assert!(
+                resumed == uninterrupted,
+                "resuming from {} changed the sim",
+                path
+            );
+            assert_eq!(
+                pandemic_bytes(&resumed),
+                pandemic_bytes(&uninterrupted),
+                "resuming from {} changed the pandemic model",
+                path
+            );
+        }
+    }
 }
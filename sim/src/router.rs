@@ -2,14 +2,18 @@ use crate::mechanics::Queue;
 use crate::{
     Event, ParkingSimState, ParkingSpot, PersonID, SidewalkSpot, TripID, TripPhaseType, Vehicle,
 };
-use geom::Distance;
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BuildingID, IntersectionID, Map, Path, PathConstraints, PathRequest, PathStep, Position,
-    Traversable, TurnID,
+    BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
+    Position, Traversable, TurnID,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+// Only look for another route when the current one has at least this much delay ahead, and only
+// switch if it saves this much time.
+const MIN_TIME_SAVED_TO_REROUTE: Duration = Duration::const_seconds(30.0);
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Router {
     // Front is always the current step
@@ -127,10 +131,59 @@ impl Router {
         }
     }
 
+    // Does the vehicle need to come to a stop at get_end_dist?
+    pub fn stops_at_end(&self) -> bool {
+        match self.goal {
+            Goal::EndAtBorder { .. } => false,
+            Goal::ParkNearBuilding { .. }
+            | Goal::BikeThenStop { .. }
+            | Goal::FollowBusRoute { .. } => true,
+        }
+    }
+
     pub fn get_path(&self) -> &Path {
         &self.path
     }
 
+    // The map changed mid-simulation. Keep the path up to the lane at idx, then find a new way to
+    // the same destination. False if there isn't one.
+    pub fn reroute(&mut self, idx: usize, vehicle: &Vehicle, map: &Map, now: Time) -> bool {
+        self.path
+            .reroute(idx, vehicle.vehicle_type.to_constraints(), map, now)
+    }
+
+    // Look for a faster way to the same destination from the end of the current lane, given the
+    // delays happening right now. Only for cars. True if the path changed.
+    pub fn reroute_around_delays(
+        &mut self,
+        map: &Map,
+        delays: &BTreeMap<Traversable, Duration>,
+        now: Time,
+    ) -> bool {
+        let delay_ahead = self
+            .path
+            .get_steps()
+            .iter()
+            .filter_map(|step| delays.get(&step.as_traversable()))
+            .fold(Duration::ZERO, |sum, d| sum + *d);
+        if delay_ahead < MIN_TIME_SAVED_TO_REROUTE {
+            return false;
+        }
+
+        let req = self.path.reroute_request(0, PathConstraints::Car, map);
+        let (new_path, new_cost) = match map.pathfind_with_delays(req, delays, now) {
+            Some(pair) => pair,
+            None => {
+                return false;
+            }
+        };
+        let old_cost = self.path.cost_with_delays(0, map, delays, now);
+        if new_cost + MIN_TIME_SAVED_TO_REROUTE > old_cost {
+            return false;
+        }
+        self.path.replace_from(0, new_path, map)
+    }
+
     // Returns the step just finished
     pub fn advance(
         &mut self,
@@ -379,7 +432,244 @@ impl Router {
         self.path.modify_step(3, PathStep::Turn(turn2), map);
     }
 
+    // Could the path continue from another lane of the current road? Only the current lane and
+    // the next turn (and maybe the lane after it) can change. Returns the steps to swap in.
+    pub fn midblock_lanechange(&self, to: LaneID, map: &Map) -> Option<Vec<PathStep>> {
+        let steps = self.path.get_steps();
+        let current_turn = match steps.get(1) {
+            Some(PathStep::Turn(t)) => *t,
+            _ => {
+                return None;
+            }
+        };
+
+        let same_target = TurnID {
+            parent: current_turn.parent,
+            src: to,
+            dst: current_turn.dst,
+        };
+        if map.maybe_get_t(same_target).is_some() {
+            return Some(vec![PathStep::Lane(to), PathStep::Turn(same_target)]);
+        }
+
+        // Otherwise wind up on a sibling of the next lane, as long as we can still make the turn
+        // after that. Don't mess with the last lane; the goal depends on it.
+        if steps.len() < 5 {
+            return None;
+        }
+        let next_lane = match steps[4] {
+            PathStep::Lane(l) => l,
+            _ => {
+                return None;
+            }
+        };
+        let orig_target_lane = current_turn.dst;
+        let parent = map.get_parent(orig_target_lane);
+        let next_parent = map.get_l(next_lane).src_i;
+        let orig_lt = map.get_l(orig_target_lane).lane_type;
+        let siblings = if parent.is_forwards(orig_target_lane) {
+            &parent.children_forwards
+        } else {
+            &parent.children_backwards
+        };
+        siblings.iter().find_map(|(l, lt)| {
+            let turn1 = TurnID {
+                parent: current_turn.parent,
+                src: to,
+                dst: *l,
+            };
+            let turn2 = TurnID {
+                parent: next_parent,
+                src: *l,
+                dst: next_lane,
+            };
+            if orig_lt == *lt
+                && map.maybe_get_t(turn1).is_some()
+                && map.maybe_get_t(turn2).is_some()
+            {
+                Some(vec![
+                    PathStep::Lane(to),
+                    PathStep::Turn(turn1),
+                    PathStep::Lane(*l),
+                    PathStep::Turn(turn2),
+                ])
+            } else {
+                None
+            }
+        })
+    }
+
+    // Apply the result of midblock_lanechange.
+    pub fn change_lanes(&mut self, steps: Vec<PathStep>, map: &Map) {
+        for (idx, step) in steps.into_iter().enumerate() {
+            if idx == 0 {
+                self.path.change_current_lane(step.as_lane(), map);
+            } else {
+                self.path.modify_step(idx, step, map);
+            }
+        }
+    }
+
     pub fn replace_path_for_serialization(&mut self, path: Path) -> Path {
         std::mem::replace(&mut self.path, path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abstutil::Timer;
+    use map_model::LaneType;
+
+    // Each step follows from the previous
+    fn assert_connected(steps: Vec<PathStep>) {
+        for pair in steps.windows(2) {
+            match (pair[0], pair[1]) {
+                (PathStep::Lane(l), PathStep::Turn(t)) => assert_eq!(l, t.src),
+                (PathStep::Turn(t), PathStep::Lane(l)) => assert_eq!(t.dst, l),
+                x => panic!("Steps don't connect: {:?}", x),
+            }
+        }
+    }
+
+    // Routers starting on a lane and ending at every border lane reachable from there
+    fn routers_from(start: LaneID, map: &Map) -> Vec<Router> {
+        map.all_lanes()
+            .iter()
+            .filter(|l| l.lane_type == LaneType::Driving && map.get_i(l.dst_i).is_border())
+            .filter_map(|l| {
+                let req = PathRequest {
+                    start: Position::new(start, Distance::ZERO),
+                    end: Position::new(l.id, l.length()),
+                    constraints: PathConstraints::Car,
+                };
+                let path = map.pathfind(req, Time::START_OF_DAY)?;
+                Some(Router::end_at_border(path, l.length(), l.dst_i))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_midblock_lanechange() {
+        // The road between the two signals has two driving lanes each way
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_double"),
+            &mut Timer::throwaway(),
+        );
+        let mut siblings = Vec::new();
+        for r in map.all_roads() {
+            for lanes in vec![&r.children_forwards, &r.children_backwards] {
+                for pair in lanes.windows(2) {
+                    if pair[0].1 == LaneType::Driving && pair[1].1 == LaneType::Driving {
+                        siblings.push((pair[0].0, pair[1].0));
+                        siblings.push((pair[1].0, pair[0].0));
+                    }
+                }
+            }
+        }
+        assert!(!siblings.is_empty());
+
+        let mut changes = 0;
+        for (from, to) in siblings {
+            for mut router in routers_from(from, &map) {
+                let orig_steps = router.get_path().get_steps().clone();
+                if orig_steps.len() == 1 {
+                    // Nothing to change without a turn
+                    assert_eq!(router.midblock_lanechange(to, &map), None);
+                    continue;
+                }
+                let steps = match router.midblock_lanechange(to, &map) {
+                    Some(steps) => steps,
+                    None => {
+                        continue;
+                    }
+                };
+                assert_eq!(steps[0], PathStep::Lane(to));
+                // Only the current lane, the next turn, and maybe the lane and turn after that
+                // change
+                assert!(steps.len() == 2 || steps.len() == 4);
+                for step in &steps {
+                    if let PathStep::Turn(t) = step {
+                        assert!(map.maybe_get_t(*t).is_some());
+                    }
+                }
+
+                router.change_lanes(steps.clone(), &map);
+                let new_steps = router.get_path().get_steps().clone();
+                assert_eq!(router.head(), Traversable::Lane(to));
+                assert_eq!(new_steps.len(), orig_steps.len());
+                for (idx, step) in new_steps.iter().enumerate() {
+                    if idx >= steps.len() {
+                        assert_eq!(*step, orig_steps[idx]);
+                    }
+                }
+                assert_connected(new_steps.into_iter().collect());
+                changes += 1;
+            }
+        }
+        assert!(changes > 0);
+    }
+    #[test]
+    fn test_reroute_around_delays() {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_double"),
+            &mut Timer::throwaway(),
+        );
+        let now = Time::START_OF_DAY;
+        let mut rerouted = 0;
+        for start in map.all_lanes() {
+            if start.lane_type != LaneType::Driving || !map.get_i(start.src_i).is_border() {
+                continue;
+            }
+            for orig in routers_from(start.id, &map) {
+                let orig_steps = orig.get_path().get_steps().clone();
+                // Nothing's slow yet
+                assert!(!orig
+                    .clone()
+                    .reroute_around_delays(&map, &BTreeMap::new(), now));
+
+                // Jam each lane past the current one, except for the last
+                for step in orig_steps
+                    .iter()
+                    .skip(1)
+                    .take(orig_steps.len().saturating_sub(2))
+                {
+                    let busy = match step {
+                        PathStep::Lane(l) => Traversable::Lane(*l),
+                        _ => {
+                            continue;
+                        }
+                    };
+
+                    // A short delay isn't worth the trouble
+                    let mut router = orig.clone();
+                    let mut delays = BTreeMap::new();
+                    delays.insert(busy, Duration::seconds(10.0));
+                    assert!(!router.reroute_around_delays(&map, &delays, now));
+                    assert_eq!(router.get_path().get_steps(), &orig_steps);
+
+                    delays.insert(busy, Duration::hours(1));
+                    let req = orig
+                        .get_path()
+                        .reroute_request(0, PathConstraints::Car, &map);
+                    let way_around = map
+                        .pathfind_with_delays(req, &delays, now)
+                        .map(|(path, _)| !path.get_steps().contains(step))
+                        .unwrap_or(false);
+                    assert_eq!(router.reroute_around_delays(&map, &delays, now), way_around);
+                    if !way_around {
+                        assert_eq!(router.get_path().get_steps(), &orig_steps);
+                        continue;
+                    }
+                    let steps = router.get_path().get_steps().clone();
+                    assert_eq!(steps[0], orig_steps[0]);
+                    assert_eq!(steps.back(), orig_steps.back());
+                    assert!(!steps.contains(step));
+                    assert_connected(steps.into_iter().collect());
+                    rerouted += 1;
+                }
+            }
+        }
+        assert!(rerouted > 0);
+    }
+}
@@ -3,7 +3,7 @@ use crate::{
 };
 use derivative::Derivative;
 use geom::{Duration, Histogram, Time};
-use map_model::{IntersectionID, Path, PathRequest};
+use map_model::{BusRouteID, IntersectionID, Path, PathRequest};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::btree_map::Entry;
@@ -18,11 +18,20 @@ pub enum Command {
     UpdateCar(CarID),
     // Distinguish this from UpdateCar to avoid confusing things
     UpdateLaggyHead(CarID),
+    // A car stuck mid-block looks for a gap in an adjacent lane
+    ChangeLanes(CarID),
     UpdatePed(PedestrianID),
     UpdateIntersection(IntersectionID),
     Callback(Duration),
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
+    // The scheduled departure from the first stop of a run
+    StartBus(BusRouteID, usize, Time),
+    // Indexes into the scenario's temporary edits
+    StartTemporaryEdit(usize),
+    EndTemporaryEdit(usize),
+    // Some drivers periodically look for a faster route
+    RerouteCars,
 }
 
 impl Command {
@@ -41,11 +50,16 @@ impl Command {
             Command::StartTrip(id, _, _, _) => CommandType::StartTrip(*id),
             Command::UpdateCar(id) => CommandType::Car(*id),
             Command::UpdateLaggyHead(id) => CommandType::CarLaggyHead(*id),
+            Command::ChangeLanes(id) => CommandType::CarLaneChange(*id),
             Command::UpdatePed(id) => CommandType::Ped(*id),
             Command::UpdateIntersection(id) => CommandType::Intersection(*id),
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::FinishRemoteTrip(t) => CommandType::FinishRemoteTrip(*t),
+            Command::StartBus(r, idx, t) => CommandType::StartBus(*r, *idx, *t),
+            Command::StartTemporaryEdit(idx) => CommandType::StartTemporaryEdit(*idx),
+            Command::EndTemporaryEdit(idx) => CommandType::EndTemporaryEdit(*idx),
+            Command::RerouteCars => CommandType::RerouteCars,
         }
     }
 }
@@ -57,11 +71,16 @@ pub enum CommandType {
     StartTrip(TripID),
     Car(CarID),
     CarLaggyHead(CarID),
+    CarLaneChange(CarID),
     Ped(PedestrianID),
     Intersection(IntersectionID),
     Callback,
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
+    StartBus(BusRouteID, usize, Time),
+    StartTemporaryEdit(usize),
+    EndTemporaryEdit(usize),
+    RerouteCars,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -214,6 +233,23 @@ impl Scheduler {
         }
     }
 
+    pub fn find_divergence(&self, other: &Scheduler) -> Option<String> {
+        if let Some(cmd_type) =
+            abstutil::first_difference(&self.queued_commands, &other.queued_commands)
+        {
+            return Some(format!(
+                "command {:?} differs: {:?} vs {:?}",
+                cmd_type,
+                self.queued_commands.get(cmd_type),
+                other.queued_commands.get(cmd_type)
+            ));
+        }
+        if self != other {
+            return Some("scheduler differs".to_string());
+        }
+        None
+    }
+
     pub fn describe_stats(&self) -> String {
         format!("delta times for events: {}", self.delta_times.describe())
     }
@@ -224,15 +260,16 @@ impl Scheduler {
     // serialize paths inside Router for live agents. We need to defer calling make_router and just
     // store the input in CreateCar.
     // TODO Rethink all of this; probably broken by StartTrip.
-    pub fn get_requests_for_savestate(&self) -> Vec<PathRequest> {
+    // Also returns when each agent will leave, since drivers might use costs for that hour.
+    pub fn get_requests_for_savestate(&self) -> Vec<(Time, PathRequest)> {
         let mut reqs = Vec::new();
-        for (cmd, _) in self.queued_commands.values() {
+        for (cmd, time) in self.queued_commands.values() {
             match cmd {
                 Command::SpawnCar(ref create_car, _) => {
-                    reqs.push(create_car.req.clone());
+                    reqs.push((*time, create_car.req.clone()));
                 }
                 Command::SpawnPed(ref create_ped) => {
-                    reqs.push(create_ped.req.clone());
+                    reqs.push((*time, create_ped.req.clone()));
                 }
                 _ => {}
             }
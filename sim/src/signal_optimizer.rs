@@ -0,0 +1,251 @@
+use crate::{Scenario, Sim, SimFlags};
+use abstutil::Timer;
+use geom::{Duration, Time};
+use map_model::{ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map, MapEdits};
+use std::collections::BTreeMap;
+
+// Phases are never made shorter than this.
+const MIN_PHASE_DURATION: Duration = Duration::const_seconds(5.0);
+const INITIAL_STEP: Duration = Duration::const_seconds(8.0);
+const FINAL_STEP: Duration = Duration::const_seconds(1.0);
+
+// Searches for timing at some traffic signals that minimizes the total delay while the scenario
+// runs until end_time. Retiming one signal can just push the queue to the next intersection over,
+// so delay counts at every intersection, signals and stop signs alike, not only at the signals
+// being changed. Each candidate changes one signal's offset or one phase's duration by a step, and
+// is kept if the delay improves. When a whole round finds nothing better, the step is halved, until
+// it's too small or max_rounds pass.
+//
+// Every candidate is a full run of the scenario, so this is slow. Afterwards, the map has the best
+// timing applied, and the edits to get there are returned. Edits already on the map are kept.
+pub fn optimize_signals(
+    map: &mut Map,
+    scenario: &Scenario,
+    flags: &SimFlags,
+    intersections: Vec<IntersectionID>,
+    end_time: Time,
+    max_rounds: usize,
+    timer: &mut Timer,
+) -> MapEdits {
+    for i in &intersections {
+        if map.maybe_get_traffic_signal(*i).is_none() {
+            panic!("Can't optimize {}; it isn't a traffic signal", i);
+        }
+    }
+    let base_edits = map.get_edits().clone();
+    let orig: BTreeMap<IntersectionID, ControlTrafficSignal> = intersections
+        .iter()
+        .map(|i| (*i, map.get_traffic_signal(*i).clone()))
+        .collect();
+
+    let mut best = orig.clone();
+    let mut best_delay = measure(map, scenario, flags, end_time);
+    timer.note(format!("Delay with the current timing: {}", best_delay));
+
+    let mut step = INITIAL_STEP;
+    for round in 1..=max_rounds {
+        let mut improved = false;
+        timer.start_iter(
+            format!("optimize signals, round {} (step {})", round, step),
+            intersections.len(),
+        );
+        for i in &intersections {
+            timer.next();
+            for candidate in neighbors(&best[i], step) {
+                let mut signals = best.clone();
+                signals.insert(*i, candidate);
+                apply(map, &base_edits, &orig, &signals);
+                let delay = measure(map, scenario, flags, end_time);
+                if delay < best_delay {
+                    best_delay = delay;
+                    best = signals;
+                    improved = true;
+                }
+            }
+        }
+        timer.note(format!("After round {}, delay is {}", round, best_delay));
+
+        if !improved {
+            step = step / 2.0;
+            if step < FINAL_STEP {
+                break;
+            }
+        }
+    }
+
+    apply(map, &base_edits, &orig, &best)
+}
+
+// Every timing one step away from this signal
+fn neighbors(signal: &ControlTrafficSignal, step: Duration) -> Vec<ControlTrafficSignal> {
+    let mut results = Vec::new();
+
+    let cycle_length = signal.cycle_length();
+    if step < cycle_length {
+        for dt in vec![step, cycle_length - step] {
+            let mut s = signal.clone();
+            s.offset = (s.offset + dt) % cycle_length;
+            results.push(s);
+        }
+    }
+
+    for idx in 0..signal.phases.len() {
+        let mut s = signal.clone();
+        s.phases[idx].duration += step;
+        results.push(s);
+
+        if signal.phases[idx].duration - step >= MIN_PHASE_DURATION {
+            let mut s = signal.clone();
+            s.phases[idx].duration -= step;
+            results.push(s);
+        }
+    }
+
+    results
+}
+
+fn apply(
+    map: &mut Map,
+    base_edits: &MapEdits,
+    orig: &BTreeMap<IntersectionID, ControlTrafficSignal>,
+    signals: &BTreeMap<IntersectionID, ControlTrafficSignal>,
+) -> MapEdits {
+    let mut edits = base_edits.clone();
+    for (i, signal) in signals {
+        if signal != &orig[i] {
+            edits.commands.push(EditCmd::ChangeIntersection {
+                i: *i,
+                old: EditIntersection::TrafficSignal(orig[i].clone()),
+                new: EditIntersection::TrafficSignal(signal.clone()),
+            });
+        }
+    }
+
+    // Signal timing doesn't affect pathfinding costs, so there's no need to recalculate that.
+    map.apply_edits(edits.clone(), &mut Timer::throwaway());
+    edits
+}
+
+fn measure(map: &mut Map, scenario: &Scenario, flags: &SimFlags, end_time: Time) -> Duration {
+    let mut timer = Timer::throwaway();
+    let mut sim = Sim::new(map, flags.opts.clone(), &mut timer);
+    scenario.instantiate(&mut sim, map, &mut flags.make_rng(), &mut timer);
+    sim.timed_step_with_edits(map, end_time - sim.time(), &mut None, &mut timer);
+    let delay = sim.total_delay();
+
+    if let Some(edits) = sim.remove_temporary_edits(map) {
+        map.apply_edits(edits, &mut timer);
+        map.recalculate_pathfinding_after_edits(&mut timer);
+    }
+    delay
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::Distance;
+    use map_model::{LaneType, PathConstraints, PathRequest, Position};
+
+    fn setup() -> (Map, IntersectionID) {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        let i = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap()
+            .id;
+        (map, i)
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let (map, i) = setup();
+        let signal = map.get_traffic_signal(i);
+        let cycle_length = signal.cycle_length();
+        let step = Duration::seconds(8.0);
+
+        let results = neighbors(signal, step);
+        // Two offsets, and every phase gets longer and shorter
+        assert_eq!(results.len(), 2 + 2 * signal.phases.len());
+        assert_eq!(results[0].offset, (signal.offset + step) % cycle_length);
+        assert_eq!(
+            results[1].offset,
+            (signal.offset + cycle_length - step) % cycle_length
+        );
+        for s in &results {
+            assert_ne!(s, signal);
+            for p in &s.phases {
+                assert!(p.duration >= MIN_PHASE_DURATION);
+            }
+        }
+
+        // Phases can't get too short, and the offset can't move by a whole cycle
+        let mut short = signal.clone();
+        for p in short.phases.iter_mut() {
+            p.duration = MIN_PHASE_DURATION + Duration::seconds(1.0);
+        }
+        let results = neighbors(&short, short.cycle_length());
+        assert_eq!(results.len(), short.phases.len());
+        for s in &results {
+            assert_eq!(s.offset, short.offset);
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let (mut map, i) = setup();
+        let base_edits = map.get_edits().clone();
+        let orig: BTreeMap<IntersectionID, ControlTrafficSignal> =
+            vec![(i, map.get_traffic_signal(i).clone())]
+                .into_iter()
+                .collect();
+
+        let mut signals = orig.clone();
+        signals.get_mut(&i).unwrap().phases[0].duration += Duration::seconds(5.0);
+        let edits = apply(&mut map, &base_edits, &orig, &signals);
+        assert_eq!(edits.commands.len(), base_edits.commands.len() + 1);
+        assert_eq!(map.get_traffic_signal(i), &signals[&i]);
+
+        // Retiming doesn't need pathfinding to be recalculated
+        let lanes: Vec<_> = map
+            .all_lanes()
+            .iter()
+            .filter(|l| l.lane_type == LaneType::Driving)
+            .collect();
+        map.pathfind(
+            PathRequest {
+                start: Position::new(lanes[0].id, Distance::ZERO),
+                end: Position::new(lanes[lanes.len() - 1].id, Distance::ZERO),
+                constraints: PathConstraints::Car,
+            },
+            Time::START_OF_DAY,
+        );
+
+        // Going back to the original timing doesn't leave an edit behind
+        let edits = apply(&mut map, &base_edits, &orig, &orig);
+        assert_eq!(edits.commands.len(), base_edits.commands.len());
+        assert_eq!(map.get_traffic_signal(i), &orig[&i]);
+    }
+
+    #[test]
+    fn test_nothing_to_improve() {
+        let (mut map, i) = setup();
+        let orig = map.get_traffic_signal(i).clone();
+        // With nobody around, there's no delay to reduce
+        let scenario = Scenario::empty(&map, "empty");
+        let edits = optimize_signals(
+            &mut map,
+            &scenario,
+            &SimFlags::synthetic_test("signal_single", "optimize_signals"),
+            vec![i],
+            Time::START_OF_DAY + Duration::minutes(1),
+            10,
+            &mut Timer::throwaway(),
+        );
+        assert!(edits.commands.is_empty());
+        assert_eq!(map.get_traffic_signal(i), &orig);
+    }
+}
@@ -1,26 +1,29 @@
 use crate::{
     AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DrawCarInput, DrawPedCrowdInput,
-    DrawPedestrianInput, DrivingSimState, Event, GetDrawAgents, IntersectionSimState, OrigPersonID,
-    PandemicModel, ParkedCar, ParkingSimState, ParkingSpot, PedestrianID, Person, PersonID,
-    PersonState, Router, Scheduler, SidewalkPOI, SidewalkSpot, TransitSimState, TripEndpoint,
-    TripID, TripManager, TripMode, TripPhaseType, TripResult, TripSpawner, UnzoomedAgent, Vehicle,
-    VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, MIN_CAR_LENGTH,
+    DrawPedestrianInput, DrivingSimState, Event, EventLog, EventLogSink, GetDrawAgents,
+    IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSimState, ParkingSpot,
+    PedestrianID, Person, PersonID, PersonState, Router, Scheduler, SidewalkPOI, SidewalkSpot,
+    TemporaryEdit, TemporaryEditsState, TransitSimState, TripEndpoint, TripID, TripManager,
+    TripMode, TripPhaseType, TripResult, TripSpawner, UnzoomedAgent, Vehicle, VehicleSpec,
+    VehicleType, WalkingSimState, BUS_LENGTH, MIN_CAR_LENGTH, TRAIN_LENGTH,
 };
 use abstutil::Timer;
 use derivative::Derivative;
 use geom::{Distance, Duration, PolyLine, Pt2D, Speed, Time};
 use instant::Instant;
 use map_model::{
-    BuildingID, BusRoute, BusRouteID, IntersectionID, LaneID, Map, ParkingLotID, Path,
+    BuildingID, BusRoute, BusRouteID, IntersectionID, LaneID, Map, MapEdits, ParkingLotID, Path,
     PathConstraints, PathRequest, PathStep, Position, RoadID, Traversable,
 };
 use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::panic;
 
 // TODO Do something else.
 const BLIND_RETRY_TO_SPAWN: Duration = Duration::const_seconds(5.0);
+// How often some drivers look for a faster route
+const REROUTE_PERIOD: Duration = Duration::const_seconds(300.0);
 
 #[derive(Serialize, Deserialize, Clone, Derivative)]
 #[derivative(PartialEq)]
@@ -32,10 +35,10 @@ pub struct Sim {
     transit: TransitSimState,
     trips: TripManager,
     #[derivative(PartialEq = "ignore")]
-    #[serde(skip_serializing, skip_deserializing)]
     pandemic: Option<PandemicModel>,
     scheduler: Scheduler,
     time: Time,
+    temporary_edits: TemporaryEditsState,
 
     // TODO Reconsider these
     pub(crate) map_name: String,
@@ -52,10 +55,18 @@ pub struct Sim {
     #[derivative(PartialEq = "ignore")]
     #[serde(skip_serializing, skip_deserializing)]
     analytics: Analytics,
+    // Only filled out in the middle of saving or loading a savestate that should include
+    // Analytics.
+    #[derivative(PartialEq = "ignore")]
+    saved_analytics: Option<Analytics>,
 
     #[derivative(PartialEq = "ignore")]
     #[serde(skip_serializing, skip_deserializing)]
     alerts: AlertHandler,
+
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    event_log: EventLogSink,
 }
 
 #[derive(Clone)]
@@ -64,10 +75,16 @@ pub struct SimOptions {
     pub use_freeform_policy_everywhere: bool,
     pub dont_block_the_box: bool,
     pub recalc_lanechanging: bool,
+    // Let cars stuck behind a slow or stopped vehicle pass it in an adjacent lane
+    pub midblock_lanechanging: bool,
     pub break_turn_conflict_cycles: bool,
     pub enable_pandemic_model: Option<XorShiftRng>,
     pub alerts: AlertHandler,
     pub pathfinding_upfront: bool,
+    // Write every event to this file. See EventLog.
+    pub event_log: Option<String>,
+    // Percent of drivers who periodically look for a faster way around current congestion
+    pub rerouting_pct: usize,
 }
 
 #[derive(Clone)]
@@ -93,10 +110,13 @@ impl SimOptions {
             use_freeform_policy_everywhere: false,
             dont_block_the_box: true,
             recalc_lanechanging: true,
+            midblock_lanechanging: false,
             break_turn_conflict_cycles: true,
             enable_pandemic_model: None,
             alerts: AlertHandler::Print,
             pathfinding_upfront: false,
+            event_log: None,
+            rerouting_pct: 0,
         }
     }
 }
@@ -105,8 +125,16 @@ impl SimOptions {
 impl Sim {
     pub fn new(map: &Map, opts: SimOptions, timer: &mut Timer) -> Sim {
         let mut scheduler = Scheduler::new();
+        if opts.rerouting_pct > 0 {
+            scheduler.push(Time::START_OF_DAY + REROUTE_PERIOD, Command::RerouteCars);
+        }
         Sim {
-            driving: DrivingSimState::new(map, opts.recalc_lanechanging),
+            driving: DrivingSimState::new(
+                map,
+                opts.recalc_lanechanging,
+                opts.midblock_lanechanging,
+                opts.rerouting_pct,
+            ),
             parking: ParkingSimState::new(map, timer),
             walking: WalkingSimState::new(),
             intersections: IntersectionSimState::new(
@@ -125,6 +153,7 @@ impl Sim {
             },
             scheduler,
             time: Time::START_OF_DAY,
+            temporary_edits: TemporaryEditsState::new(),
 
             map_name: map.get_name().to_string(),
             // TODO
@@ -134,6 +163,8 @@ impl Sim {
             alerts: opts.alerts,
 
             analytics: Analytics::new(),
+            saved_analytics: None,
+            event_log: EventLogSink(opts.event_log.map(EventLog::new)),
         }
     }
 
@@ -205,11 +236,14 @@ impl Sim {
 
         let start = SidewalkSpot::building(b, map).sidewalk_pos;
         let end = SidewalkSpot::parking_spot(spot, map, &self.parking).sidewalk_pos;
-        let path = map.pathfind(PathRequest {
-            start,
-            end,
-            constraints: PathConstraints::Pedestrian,
-        })?;
+        let path = map.pathfind(
+            PathRequest {
+                start,
+                end,
+                constraints: PathConstraints::Pedestrian,
+            },
+            self.time,
+        )?;
         Some((path, start.dist_along()))
     }
 
@@ -231,22 +265,39 @@ impl Sim {
         self.parking.add_parked_car(ParkedCar { vehicle, spot });
     }
 
-    pub fn seed_bus_route(&mut self, route: &BusRoute, map: &Map, timer: &mut Timer) -> Vec<CarID> {
+    // If capacity isn't specified, use the default for the type of vehicle.
+    pub fn seed_bus_route(
+        &mut self,
+        route: &BusRoute,
+        capacity: Option<usize>,
+        map: &Map,
+        timer: &mut Timer,
+    ) -> Vec<CarID> {
         let mut results: Vec<CarID> = Vec::new();
+        let legs = self
+            .transit
+            .create_empty_route(route, capacity, self.time, map);
+
+        // Follow the timetable, if there is one. It repeats every day, so departures that already
+        // happened today start tomorrow.
+        if route.has_timetable() {
+            for (first_stop, departures) in route.scheduled_starts() {
+                for t in departures {
+                    let mut t = *t;
+                    while t < self.time {
+                        t += Duration::hours(24);
+                    }
+                    self.scheduler
+                        .push(t, Command::StartBus(route.id, first_stop, t));
+                }
+            }
+            return results;
+        }
 
         // Try to spawn just ONE bus anywhere.
         // TODO Be more realistic. One bus per stop is too much, one is too little.
-        for (next_stop_idx, req, mut path, end_dist) in
-            self.transit.create_empty_route(route, map).into_iter()
-        {
-            // For now, no desire for randomness. Caller can pass in list of specs if that ever
-            // changes.
-            let vehicle = VehicleSpec {
-                vehicle_type: VehicleType::Bus,
-                length: BUS_LENGTH,
-                max_speed: None,
-            }
-            .make(CarID(self.trips.new_car_id(), VehicleType::Bus), None);
+        for (next_stop_idx, req, mut path, end_dist) in legs {
+            let vehicle = self.new_transit_vehicle(route);
             let id = vehicle.id;
 
             loop {
@@ -286,7 +337,7 @@ impl Sim {
                     &self.parking,
                     &mut self.scheduler,
                 ) {
-                    self.transit.bus_created(id, route.id, next_stop_idx);
+                    self.transit.bus_created(id, route.id, next_stop_idx, None);
                     self.analytics.record_demand(&path, map);
                     results.push(id);
                     return results;
@@ -302,6 +353,86 @@ impl Sim {
         results
     }
 
+    fn start_scheduled_bus(
+        &mut self,
+        route: &BusRoute,
+        first_stop: usize,
+        departure: Time,
+        map: &Map,
+    ) {
+        let vehicle = self.new_transit_vehicle(route);
+        let id = vehicle.id;
+
+        let (req, path, end_dist) = match self.transit.path_to_first_stop(
+            route.id,
+            first_stop,
+            vehicle.length,
+            departure,
+            map,
+        ) {
+            Some(x) => x,
+            None => {
+                println!(
+                    "WARNING: Nowhere to start the {} bus on {} ({})",
+                    departure, route.name, route.id
+                );
+                self.schedule_next_bus(route.id, first_stop, departure);
+                return;
+            }
+        };
+        if self.driving.start_car_on_lane(
+            self.time,
+            CreateCar {
+                start_dist: vehicle.length,
+                vehicle,
+                req,
+                router: Router::follow_bus_route(path.clone(), end_dist),
+                maybe_parked_car: None,
+                trip_and_person: None,
+            },
+            map,
+            &self.intersections,
+            &self.parking,
+            &mut self.scheduler,
+        ) {
+            self.transit.bus_created(
+                id,
+                route.id,
+                first_stop,
+                Some((departure, first_stop, route.last_stop(first_stop))),
+            );
+            self.analytics.record_demand(&path, map);
+            self.schedule_next_bus(route.id, first_stop, departure);
+        } else {
+            self.scheduler.push(
+                self.time + BLIND_RETRY_TO_SPAWN,
+                Command::StartBus(route.id, first_stop, departure),
+            );
+        }
+    }
+
+    // The same departure tomorrow
+    fn schedule_next_bus(&mut self, route: BusRouteID, first_stop: usize, departure: Time) {
+        let t = departure + Duration::hours(24);
+        self.scheduler
+            .push(t, Command::StartBus(route, first_stop, t));
+    }
+
+    // For now, no desire for randomness. Caller can pass in list of specs if that ever changes.
+    fn new_transit_vehicle(&mut self, route: &BusRoute) -> Vehicle {
+        let (vehicle_type, length) = if route.route_type == PathConstraints::Train {
+            (VehicleType::Train, TRAIN_LENGTH)
+        } else {
+            (VehicleType::Bus, BUS_LENGTH)
+        };
+        VehicleSpec {
+            vehicle_type,
+            length,
+            max_speed: None,
+        }
+        .make(CarID(self.trips.new_car_id(), vehicle_type), None)
+    }
+
     pub fn set_name(&mut self, name: String) {
         self.run_name = name;
     }
@@ -373,7 +504,7 @@ impl GetDrawAgents for Sim {
 // Running
 impl Sim {
     // Advances time as minimally as possible, also limited by max_dt. Returns true if the callback
-    // said to halt the sim.
+    // said to halt the sim, or if temporary map edits need to be applied.
     fn minimal_step(
         &mut self,
         map: &Map,
@@ -411,7 +542,8 @@ impl Sim {
         halt
     }
 
-    // If true, halt simulation because the callback said so.
+    // If true, halt simulation because the callback said so, or temporary map edits need to be
+    // applied.
     fn do_step(
         &mut self,
         map: &Map,
@@ -547,6 +679,15 @@ impl Sim {
                     &mut self.walking,
                 );
             }
+            Command::ChangeLanes(car) => {
+                self.driving.change_lanes(
+                    car,
+                    self.time,
+                    map,
+                    &mut self.intersections,
+                    &mut self.scheduler,
+                );
+            }
             Command::UpdateLaggyHead(car) => {
                 self.driving.update_laggy_head(
                     car,
@@ -594,6 +735,24 @@ impl Sim {
                     &mut self.scheduler,
                 );
             }
+            Command::StartBus(r, first_stop, departure) => {
+                self.start_scheduled_bus(map.get_br(r), first_stop, departure, map);
+            }
+            Command::RerouteCars => {
+                self.scheduler
+                    .push(self.time + REROUTE_PERIOD, Command::RerouteCars);
+                self.driving
+                    .reroute_cars(self.time, map, &self.intersections);
+            }
+            // Stop here, so the map can be changed before anything else happens.
+            Command::StartTemporaryEdit(idx) => {
+                self.temporary_edits.start(idx);
+                halt = true;
+            }
+            Command::EndTemporaryEdit(idx) => {
+                self.temporary_edits.end(idx);
+                halt = true;
+            }
         }
 
         // Record events at precisely the time they occur.
@@ -613,6 +772,9 @@ impl Sim {
             if let Some(ref mut m) = self.pandemic {
                 m.handle_event(self.time, &ev, &mut self.scheduler);
             }
+            if let Some(ref mut log) = self.event_log.0 {
+                log.record(self.time, &ev);
+            }
 
             self.analytics.event(ev, self.time, map);
         }
@@ -763,6 +925,10 @@ impl Sim {
                 last_sim_time = self.time();
             }
             callback(self, map);
+            // The caller has to apply these and call this again
+            if self.temporary_edits.is_dirty() {
+                break;
+            }
             if self.is_done() {
                 println!(
                     "{}: speed = {:.2}x, {}",
@@ -840,6 +1006,35 @@ impl Sim {
         path
     }
 
+    // Like save, but also keeps everything measured so far, so that dashboards are still correct
+    // after resuming.
+    pub fn save_with_analytics(&mut self) -> String {
+        let path = self.save_path(self.time);
+        self.save_as(path.clone(), true);
+        path
+    }
+
+    // Paths ending in .json are written as JSON, anything else as binary. Savestates normally skip
+    // Analytics to stay small; include_analytics changes that.
+    pub fn save_as(&mut self, path: String, include_analytics: bool) {
+        let restore = self.scheduler.before_savestate();
+        if include_analytics {
+            self.saved_analytics =
+                Some(std::mem::replace(&mut self.analytics, Analytics::default()));
+        }
+
+        if path.ends_with(".json") {
+            abstutil::write_json(path, self);
+        } else {
+            abstutil::write_binary(path, self);
+        }
+
+        if let Some(analytics) = self.saved_analytics.take() {
+            self.analytics = analytics;
+        }
+        self.scheduler.after_savestate(restore);
+    }
+
     pub fn find_previous_savestate(&self, base_time: Time) -> Option<String> {
         abstutil::find_prev_file(self.save_path(base_time))
     }
@@ -853,16 +1048,30 @@ impl Sim {
         map: &Map,
         timer: &mut Timer,
     ) -> Result<Sim, std::io::Error> {
-        let mut sim: Sim = abstutil::maybe_read_binary(path, timer)?;
+        let mut sim = Sim::read_savestate(path, timer)?;
         sim.restore_paths(map, timer);
         Ok(sim)
     }
 
+    // Call restore_paths after this, once the right map is loaded.
+    pub fn read_savestate(path: String, timer: &mut Timer) -> Result<Sim, std::io::Error> {
+        let mut sim: Sim = if path.ends_with(".json") {
+            abstutil::maybe_read_json(path, timer)?
+        } else {
+            abstutil::maybe_read_binary(path, timer)?
+        };
+        // If the savestate didn't include Analytics, then it's empty and doesn't record anything.
+        if let Some(analytics) = sim.saved_analytics.take() {
+            sim.analytics = analytics;
+        }
+        Ok(sim)
+    }
+
     pub fn restore_paths(&mut self, map: &Map, timer: &mut Timer) {
         let paths = timer.parallelize(
             "calculate paths",
             self.scheduler.get_requests_for_savestate(),
-            |req| map.pathfind(req).unwrap(),
+            |(departure, req)| map.pathfind(req, departure).unwrap(),
         );
         self.scheduler.after_savestate(paths);
     }
@@ -933,15 +1142,31 @@ impl Sim {
         vec![
             (
                 "Route".to_string(),
-                map.get_br(self.transit.bus_route(car)).name.clone(),
+                map.get_br(self.transit.bus_route(car).unwrap())
+                    .name
+                    .clone(),
+            ),
+            (
+                "Passengers".to_string(),
+                format!(
+                    "{} / {}",
+                    passengers.len(),
+                    self.transit
+                        .capacity(self.transit.bus_route(car).unwrap())
+                        .unwrap()
+                ),
             ),
-            ("Passengers".to_string(), passengers.len().to_string()),
         ]
     }
 
+    // None if the route hasn't been seeded
+    pub fn bus_capacity(&self, route: BusRouteID) -> Option<usize> {
+        self.transit.capacity(route)
+    }
+
     pub fn bus_route_id(&self, maybe_bus: CarID) -> Option<BusRouteID> {
-        if maybe_bus.1 == VehicleType::Bus {
-            Some(self.transit.bus_route(maybe_bus))
+        if maybe_bus.1 == VehicleType::Bus || maybe_bus.1 == VehicleType::Train {
+            self.transit.bus_route(maybe_bus)
         } else {
             None
         }
@@ -1004,7 +1229,12 @@ impl Sim {
     }
 
     pub fn lookup_car_id(&self, idx: usize) -> Option<CarID> {
-        for vt in &[VehicleType::Car, VehicleType::Bike, VehicleType::Bus] {
+        for vt in &[
+            VehicleType::Car,
+            VehicleType::Bike,
+            VehicleType::Bus,
+            VehicleType::Train,
+        ] {
             let id = CarID(idx, *vt);
             if self.driving.does_car_exist(id) {
                 return Some(id);
@@ -1081,6 +1311,15 @@ impl Sim {
     pub fn get_blocked_by(&self, a: AgentID) -> HashSet<AgentID> {
         self.intersections.get_blocked_by(a)
     }
+    // For traffic signals. The index of the current phase and how long until it might change.
+    pub fn current_phase_and_remaining_time(
+        &self,
+        i: IntersectionID,
+        map: &Map,
+    ) -> (usize, Duration) {
+        self.intersections
+            .current_phase_and_remaining_time(self.time, i, map)
+    }
 
     pub fn location_of_buses(&self, route: BusRouteID, map: &Map) -> Vec<(CarID, Pt2D)> {
         let mut results = Vec::new();
@@ -1123,6 +1362,11 @@ impl Sim {
         self.trips.bldg_to_people(b)
     }
 
+    // All of the delay at every intersection so far, including agents still waiting
+    pub(crate) fn total_delay(&self) -> Duration {
+        self.intersections.total_delay(self.time)
+    }
+
     pub fn worst_delay(
         &self,
         map: &Map,
@@ -1145,6 +1389,171 @@ impl Sim {
     }
 }
 
+// Live map edits
+impl Sim {
+    // Instead of resetting the simulation after map edits, patch it up. Call this after
+    // Map::apply_edits and recalculate_pathfinding_after_edits, with the roads and intersections
+    // that apply_edits reported as changed. Agents whose path uses something that's gone are
+    // rerouted from the end of their current lane. Agents that can't continue, because they're on
+    // something that's gone or there's no other way, are removed and their trip is aborted.
+    // Returns how many agents were rerouted and removed.
+    pub fn handle_live_edits(
+        &mut self,
+        map: &Map,
+        changed_roads: &BTreeSet<RoadID>,
+        changed_intersections: &BTreeSet<IntersectionID>,
+        timer: &mut Timer,
+    ) -> (usize, usize) {
+        let now = self.time;
+        self.edits_name = map.get_edits().edits_name.clone();
+
+        self.intersections
+            .handle_live_edits(now, map, changed_intersections, &mut self.scheduler);
+        self.parking.handle_live_edits(map, changed_roads, timer);
+        let stuck_buses = self.transit.handle_live_edits(now, map);
+
+        let (rerouted_cars, evacuated_cars) = self.driving.handle_live_edits(
+            now,
+            map,
+            stuck_buses,
+            &mut self.intersections,
+            &mut self.scheduler,
+        );
+        let (rerouted_peds, evacuated_peds) =
+            self.walking
+                .handle_live_edits(now, map, &mut self.intersections, &mut self.scheduler);
+
+        let rerouted: Vec<AgentID> = rerouted_cars
+            .into_iter()
+            .map(AgentID::Car)
+            .chain(rerouted_peds.into_iter().map(AgentID::Pedestrian))
+            .collect();
+        let mut evacuated = Vec::new();
+        for vehicle in evacuated_cars {
+            let id = vehicle.id;
+            evacuated.push(AgentID::Car(id));
+            if self.transit.bus_route(id).is_some() {
+                for person in self.transit.bus_evacuated(id) {
+                    self.trips.agent_evacuated(
+                        now,
+                        AgentID::BusPassenger(person, id),
+                        None,
+                        &mut self.parking,
+                        &mut self.scheduler,
+                        map,
+                    );
+                }
+            } else {
+                self.trips.agent_evacuated(
+                    now,
+                    AgentID::Car(id),
+                    Some(vehicle),
+                    &mut self.parking,
+                    &mut self.scheduler,
+                    map,
+                );
+            }
+        }
+        for id in evacuated_peds {
+            evacuated.push(AgentID::Pedestrian(id));
+            self.trips.agent_evacuated(
+                now,
+                AgentID::Pedestrian(id),
+                None,
+                &mut self.parking,
+                &mut self.scheduler,
+                map,
+            );
+        }
+
+        for i in changed_intersections {
+            // Nobody waits at a closed intersection
+            if !map.get_i(*i).is_closed() {
+                self.intersections
+                    .space_freed(now, *i, &mut self.scheduler, map);
+            }
+        }
+
+        timer.note(format!(
+            "Applied live map edits: rerouted {} agents, removed {} that couldn't continue",
+            rerouted.len(),
+            evacuated.len()
+        ));
+        let counts = (rerouted.len(), evacuated.len());
+        self.dispatch_events(
+            vec![Event::MapEdited {
+                edits_name: self.edits_name.clone(),
+                rerouted,
+                evacuated,
+            }],
+            map,
+        );
+        counts
+    }
+}
+
+// Temporary map edits
+impl Sim {
+    pub fn schedule_temporary_edit(
+        &mut self,
+        edit: TemporaryEdit,
+        map: &Map,
+    ) -> Result<(), String> {
+        self.temporary_edits
+            .schedule(edit, map, &mut self.scheduler)
+    }
+
+    // Stepping the simulation stops early when temporary edits start or end. If this returns
+    // something, the map should switch to these edits. Then recalculate pathfinding and call
+    // handle_live_edits.
+    pub fn update_temporary_edits(&mut self, map: &Map) -> Option<MapEdits> {
+        self.temporary_edits.update_edits(map)
+    }
+
+    // Does all of the above, for callers that don't need to do anything else when the map
+    // changes. Returns false if no temporary edits started or ended since the last call, meaning
+    // stepping didn't stop early because of them.
+    pub fn apply_temporary_edits(&mut self, map: &mut Map, timer: &mut Timer) -> bool {
+        if !self.temporary_edits.is_dirty() {
+            return false;
+        }
+        if let Some(edits) = self.update_temporary_edits(map) {
+            let (roads, _, _, intersections) = map.apply_edits(edits, timer);
+            map.recalculate_pathfinding_after_edits(timer);
+            self.handle_live_edits(map, &roads, &intersections, timer);
+        }
+        true
+    }
+
+    // Like timed_step, but instead of stopping early when temporary edits start or end, this
+    // applies them to the map and keeps going.
+    pub fn timed_step_with_edits(
+        &mut self,
+        map: &mut Map,
+        dt: Duration,
+        maybe_cb: &mut Option<Box<dyn SimCallback>>,
+        timer: &mut Timer,
+    ) {
+        let end_time = self.time + dt;
+        loop {
+            self.timed_step(map, end_time - self.time, maybe_cb, timer);
+            if !self.apply_temporary_edits(map, timer) {
+                break;
+            }
+        }
+    }
+
+    // Before resetting the simulation or letting the player edit the map, the map should switch to
+    // these edits.
+    pub fn remove_temporary_edits(&mut self, map: &Map) -> Option<MapEdits> {
+        self.temporary_edits.remove_from(map)
+    }
+
+    pub fn has_temporary_edits(&self) -> bool {
+        self.temporary_edits.has_any()
+    }
+}
+
 // Invasive debugging
 impl Sim {
     pub fn kill_stuck_car(&mut self, id: CarID, map: &Map) {
@@ -1170,6 +1579,39 @@ impl Sim {
         }
     }
 
+    // Two simulations that started the same way should stay equal. If they don't, this describes
+    // the first difference, like which agent or command went wrong.
+    pub fn find_divergence(&self, other: &Sim) -> Option<String> {
+        if self.time != other.time {
+            return Some(format!("time is {} vs {}", self.time, other.time));
+        }
+        if let Some(diff) = self.driving.find_divergence(&other.driving) {
+            return Some(diff);
+        }
+        if let Some(diff) = self.walking.find_divergence(&other.walking) {
+            return Some(diff);
+        }
+        if let Some(diff) = self.scheduler.find_divergence(&other.scheduler) {
+            return Some(diff);
+        }
+        if self.parking != other.parking {
+            return Some("parking state differs".to_string());
+        }
+        if self.intersections != other.intersections {
+            return Some("intersection state differs".to_string());
+        }
+        if self.transit != other.transit {
+            return Some("transit state differs".to_string());
+        }
+        if self.trips != other.trips {
+            return Some("trip state differs".to_string());
+        }
+        if self != other {
+            return Some("map or edits name differs".to_string());
+        }
+        None
+    }
+
     pub fn clear_alerts(&mut self) -> Vec<(Time, AlertLocation, String)> {
         std::mem::replace(&mut self.analytics.alerts, Vec::new())
     }
@@ -1209,3 +1651,342 @@ pub struct AgentProperties {
     pub lanes_crossed: usize,
     pub total_lanes: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IndividTrip, PersonSpec, Scenario, SimOptions, SpawnTrip, FOLLOWING_DISTANCE};
+    use map_model::{BusStopID, EditCmd, LaneType, TurnID, TurnPriority};
+    use rand::SeedableRng;
+
+    fn analytics_bytes(sim: &Sim) -> Vec<u8> {
+        bincode::serialize(sim.get_analytics()).unwrap()
+    }
+
+    #[test]
+    fn test_savestate_keeps_analytics() {
+        let mut timer = Timer::throwaway();
+        let map = Map::new(abstutil::path_synthetic_map("signal_single"), &mut timer);
+        let mut sim = Sim::new(&map, SimOptions::new("analytics_savestate"), &mut timer);
+        sim.timed_step(&map, Duration::minutes(5), &mut None, &mut timer);
+
+        // The synthetic map has no buses, so make up some measurements
+        let route = BusRouteID(0);
+        let stop = BusStopID {
+            sidewalk: LaneID(0),
+            idx: 0,
+        };
+        let t = Time::START_OF_DAY + Duration::minutes(1);
+        sim.analytics.bus_loads.push((t, route, stop, 3));
+        sim.analytics.bus_pass_ups.push((t, route, stop));
+        sim.analytics.traversal_times.insert(
+            (0, Traversable::Lane(LaneID(0))),
+            (Duration::seconds(30.0), 2),
+        );
+        let expected = analytics_bytes(&sim);
+
+        for path in vec![
+            format!("{}/analytics_savestate.bin", std::env::temp_dir().display()),
+            format!(
+                "{}/analytics_savestate.json",
+                std::env::temp_dir().display()
+            ),
+        ] {
+            sim.save_as(path.clone(), true);
+            // Saving doesn't lose anything from the live sim
+            assert_eq!(analytics_bytes(&sim), expected);
+
+            let resumed = Sim::load_savestate(path.clone(), &map, &mut timer).unwrap();
+            assert_eq!(
+                analytics_bytes(&resumed),
+                expected,
+                "{} didn't keep Analytics",
+                path
+            );
+            assert_eq!(resumed.get_analytics().bus_loads.len(), 1);
+            assert_eq!(resumed.get_analytics().bus_pass_ups.len(), 1);
+            assert_eq!(resumed.get_analytics().traversal_times.len(), 1);
+
+            // By default, savestates skip Analytics
+            sim.save_as(path.clone(), false);
+            let resumed = Sim::load_savestate(path.clone(), &map, &mut timer).unwrap();
+            assert!(resumed.get_analytics().bus_loads.is_empty());
+            assert!(resumed.get_analytics().traversal_times.is_empty());
+        }
+    }
+
+    // Three cars wait at a red light in one of two lanes headed the same way. Returns how many of
+    // the two cars stuck behind the first go around into the other lane.
+    fn cars_changing_lanes(midblock_lanechanging: bool) -> usize {
+        let mut timer = Timer::throwaway();
+        let map = Map::new(abstutil::path_synthetic_map("signal_double"), &mut timer);
+        let mut opts = SimOptions::new("midblock_lanechanging");
+        opts.midblock_lanechanging = midblock_lanechanging;
+        let mut sim = Sim::new(&map, opts, &mut timer);
+
+        // (lane, sibling, destination lane, the turns from both at the signal)
+        let mut best: Option<(LaneID, LaneID, LaneID, Vec<TurnID>)> = None;
+        for r in map.all_roads() {
+            for lanes in vec![&r.children_forwards, &r.children_backwards] {
+                for pair in lanes.windows(2) {
+                    if pair[0].1 != LaneType::Driving || pair[1].1 != LaneType::Driving {
+                        continue;
+                    }
+                    for (l1, l2) in vec![(pair[0].0, pair[1].0), (pair[1].0, pair[0].0)] {
+                        if map.maybe_get_traffic_signal(map.get_l(l1).dst_i).is_none() {
+                            continue;
+                        }
+                        for dst in map.all_lanes() {
+                            if dst.lane_type != LaneType::Driving
+                                || !map.get_i(dst.dst_i).is_border()
+                            {
+                                continue;
+                            }
+                            let req = PathRequest {
+                                start: Position::new(l1, Distance::ZERO),
+                                end: Position::new(dst.id, dst.length()),
+                                constraints: PathConstraints::Car,
+                            };
+                            let router = match map.pathfind(req, sim.time) {
+                                Some(path) => Router::end_at_border(path, dst.length(), dst.dst_i),
+                                None => {
+                                    continue;
+                                }
+                            };
+                            let steps = router.get_path().get_steps();
+                            if let (Some(PathStep::Turn(t1)), Some(new_steps)) =
+                                (steps.get(1), router.midblock_lanechange(l2, &map))
+                            {
+                                let t2 = match new_steps[1] {
+                                    PathStep::Turn(t) => t,
+                                    _ => unreachable!(),
+                                };
+                                if best
+                                    .as_ref()
+                                    .map(|(l, _, _, _)| {
+                                        map.get_l(l1).length() > map.get_l(*l).length()
+                                    })
+                                    .unwrap_or(true)
+                                {
+                                    best = Some((l1, l2, dst.id, vec![*t1, t2]));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let (l1, l2, dst, turns) = best.expect("no lanes to change between");
+
+        let len = MIN_CAR_LENGTH;
+        let spacing = len + FOLLOWING_DISTANCE + Distance::meters(1.0);
+        let lane_len = map.get_l(l1).length();
+        assert!(lane_len > spacing * 4.0);
+
+        // Wait for the start of a phase where both lanes have a red light
+        let i = map.get_l(l1).dst_i;
+        let signal = map.get_traffic_signal(i);
+        loop {
+            let (idx, remaining) = sim
+                .intersections
+                .current_phase_and_remaining_time(sim.time, i, &map);
+            if remaining >= Duration::seconds(25.0)
+                && turns.iter().all(|t| {
+                    signal.phases[idx].get_priority_of_turn(*t, signal) == TurnPriority::Banned
+                })
+            {
+                break;
+            }
+            assert!(sim.time < Time::START_OF_DAY + Duration::minutes(10));
+            sim.timed_step(&map, Duration::seconds(1.0), &mut None, &mut timer);
+        }
+
+        let mut cars = Vec::new();
+        for idx in 0..3 {
+            let start_dist = lane_len - Distance::meters(1.0) - spacing * (idx as f64);
+            let req = PathRequest {
+                start: Position::new(l1, start_dist),
+                end: Position::new(dst, map.get_l(dst).length()),
+                constraints: PathConstraints::Car,
+            };
+            let path = map.pathfind(req.clone(), sim.time).unwrap();
+            let vehicle = VehicleSpec {
+                vehicle_type: VehicleType::Car,
+                length: len,
+                max_speed: None,
+            }
+            .make(CarID(idx, VehicleType::Car), None);
+            let id = vehicle.id;
+            assert!(sim.driving.start_car_on_lane(
+                sim.time,
+                CreateCar {
+                    vehicle,
+                    router: Router::end_at_border(
+                        path,
+                        map.get_l(dst).length(),
+                        map.get_l(dst).dst_i
+                    ),
+                    req,
+                    start_dist,
+                    maybe_parked_car: None,
+                    trip_and_person: None,
+                },
+                &map,
+                &sim.intersections,
+                &sim.parking,
+                &mut sim.scheduler,
+            ));
+            cars.push(id);
+        }
+
+        sim.timed_step(&map, Duration::seconds(15.0), &mut None, &mut timer);
+        let lane_of = |id: CarID| sim.get_draw_car(id, &map).unwrap().on;
+        // Nobody's in front of the first car
+        assert_eq!(lane_of(cars[0]), Traversable::Lane(l1));
+        cars[1..]
+            .iter()
+            .filter(|id| {
+                let on = lane_of(**id);
+                assert!(on == Traversable::Lane(l1) || on == Traversable::Lane(l2));
+                on == Traversable::Lane(l2)
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_midblock_lanechanging() {
+        assert!(cars_changing_lanes(true) > 0);
+        // The option is off by default
+        assert!(!SimOptions::new("test").midblock_lanechanging);
+        assert_eq!(cars_changing_lanes(false), 0);
+    }
+    // People driving and walking between every pair of borders, one every few seconds
+    fn border_to_border(map: &Map) -> Scenario {
+        let mut s = Scenario::empty(map, "border_to_border");
+        let borders: Vec<IntersectionID> = map
+            .all_intersections()
+            .iter()
+            .filter(|i| i.is_border())
+            .map(|i| i.id)
+            .collect();
+        let mut depart = Time::START_OF_DAY;
+        for from in &borders {
+            for to in &borders {
+                if from == to {
+                    continue;
+                }
+                for mode in vec![TripMode::Drive, TripMode::Walk] {
+                    if let Some(trip) = SpawnTrip::new(
+                        TripEndpoint::Border(*from, None),
+                        TripEndpoint::Border(*to, None),
+                        mode,
+                        map,
+                    ) {
+                        s.people.push(PersonSpec {
+                            id: PersonID(s.people.len()),
+                            orig_id: None,
+                            trips: vec![IndividTrip {
+                                depart,
+                                trip,
+                                cancelled: false,
+                            }],
+                        });
+                        depart += Duration::seconds(5.0);
+                    }
+                }
+            }
+        }
+        s
+    }
+
+    // The lane of some type between the two signals with the most agents on it right now
+    fn busiest_lane(sim: &Sim, map: &Map, lt: LaneType) -> (LaneID, Vec<AgentID>) {
+        map.all_lanes()
+            .iter()
+            .filter(|l| {
+                l.lane_type == lt
+                    && !map.get_i(l.src_i).is_border()
+                    && !map.get_i(l.dst_i).is_border()
+            })
+            .map(|l| {
+                let agents: Vec<AgentID> = sim
+                    .active_agents()
+                    .into_iter()
+                    .filter(|a| {
+                        sim.get_path(*a)
+                            .map(|path| path.current_step().as_traversable())
+                            == Some(Traversable::Lane(l.id))
+                    })
+                    .collect();
+                (l.id, agents)
+            })
+            .max_by_key(|(_, agents)| agents.len())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_live_edits_with_agents() {
+        let mut timer = Timer::throwaway();
+        let mut map = Map::new(abstutil::path_synthetic_map("signal_double"), &mut timer);
+        let mut sim = Sim::new(&map, SimOptions::new("live_edits"), &mut timer);
+        border_to_border(&map).instantiate(
+            &mut sim,
+            &map,
+            &mut XorShiftRng::from_seed([42; 16]),
+            &mut timer,
+        );
+        sim.timed_step(&map, Duration::minutes(3), &mut None, &mut timer);
+
+        // Take away a driving lane and a sidewalk between the signals, with people on them
+        let (driving_lane, cars) = busiest_lane(&sim, &map, LaneType::Driving);
+        let (sidewalk, peds) = busiest_lane(&sim, &map, LaneType::Sidewalk);
+        assert!(!cars.is_empty());
+        assert!(!peds.is_empty());
+        let mut edits = map.get_edits().clone();
+        edits.edits_name = "live_edits".to_string();
+        edits.commands.push(EditCmd::ChangeLaneType {
+            id: driving_lane,
+            lt: LaneType::Bus,
+            orig_lt: LaneType::Driving,
+        });
+        edits.commands.push(EditCmd::ChangeLaneType {
+            id: sidewalk,
+            lt: LaneType::Driving,
+            orig_lt: LaneType::Sidewalk,
+        });
+        let (roads, _, _, intersections) = map.apply_edits(edits, &mut timer);
+        map.recalculate_pathfinding_after_edits(&mut timer);
+        let aborted = |sim: &Sim| {
+            sim.get_analytics()
+                .finished_trips
+                .iter()
+                .filter(|(_, _, mode, _)| mode.is_none())
+                .count()
+        };
+        let agents_before = sim.active_agents().len();
+        let aborted_before = aborted(&sim);
+        let (rerouted, evacuated) = sim.handle_live_edits(&map, &roads, &intersections, &mut timer);
+
+        // Everybody on the lanes that are gone had to leave, maybe along with others who couldn't
+        // find another way. Everybody else still has a path that works.
+        for agent in cars.iter().chain(peds.iter()) {
+            assert_eq!(sim.agent_to_trip(*agent), None, "{} is still around", agent);
+        }
+        assert!(evacuated >= cars.len() + peds.len());
+        assert_eq!(sim.active_agents().len(), agents_before - evacuated);
+        assert_eq!(aborted(&sim) - aborted_before, evacuated);
+        assert!(rerouted > 0);
+        for agent in sim.active_agents() {
+            if let Some(path) = sim.get_path(agent) {
+                let constraints = match agent {
+                    AgentID::Pedestrian(_) => PathConstraints::Pedestrian,
+                    _ => PathConstraints::Car,
+                };
+                assert_eq!(path.find_broken_step(constraints, &map), None);
+            }
+        }
+
+        // And the simulation keeps going
+        sim.timed_step(&map, Duration::minutes(5), &mut None, &mut timer);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::{Command, Scheduler, TemporaryChange, TemporaryEdit};
+use map_model::{EditCmd, EditIntersection, IntersectionID, LaneID, LaneType, Map, MapEdits};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Tracks which of the scenario's temporary edits are in effect. The simulation can't change the
+// map itself, so whoever owns the map asks for the edits to apply whenever this changes.
+//
+// The temporary edits always come after the map's own edits, so they're easy to peel off again.
+// If the map's edits change some other way while temporary ones are in effect, this gets confused.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct TemporaryEditsState {
+    edits: Vec<TemporaryEdit>,
+    active: BTreeSet<usize>,
+    // How many of the last commands in the map's edits come from here
+    num_applied_cmds: usize,
+    // The map's edits need to be updated
+    dirty: bool,
+}
+
+impl TemporaryEditsState {
+    pub fn new() -> TemporaryEditsState {
+        TemporaryEditsState {
+            edits: Vec::new(),
+            active: BTreeSet::new(),
+            num_applied_cmds: 0,
+            dirty: false,
+        }
+    }
+
+    pub fn schedule(
+        &mut self,
+        edit: TemporaryEdit,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) -> Result<(), String> {
+        if edit.start >= edit.end {
+            return Err(format!("{:?} ends before it starts", edit));
+        }
+        match edit.change {
+            TemporaryChange::CloseRoad(r) => {
+                check_bus_stops(closed_lanes(&edit.change, map), map)
+                    .map_err(|err| format!("Can't close {}: {}", r, err))?;
+            }
+            TemporaryChange::CloseLane(l) => {
+                if map.get_l(l).is_sidewalk() {
+                    return Err(format!(
+                        "Can't close {}; buildings along it need the sidewalk",
+                        l
+                    ));
+                }
+                check_bus_stops(vec![l], map)
+                    .map_err(|err| format!("Can't close {}: {}", l, err))?;
+            }
+            TemporaryChange::CloseIntersection(i) => {
+                if map.get_i(i).is_border() {
+                    return Err(format!("Can't close {}; it's a border", i));
+                }
+            }
+        }
+
+        let idx = self.edits.len();
+        scheduler.push(edit.start, Command::StartTemporaryEdit(idx));
+        scheduler.push(edit.end, Command::EndTemporaryEdit(idx));
+        self.edits.push(edit);
+        Ok(())
+    }
+
+    pub fn start(&mut self, idx: usize) {
+        self.active.insert(idx);
+        self.dirty = true;
+    }
+
+    pub fn end(&mut self, idx: usize) {
+        self.active.remove(&idx);
+        self.dirty = true;
+    }
+
+    pub fn has_any(&self) -> bool {
+        !self.edits.is_empty()
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    // Returns the edits the map should have now, if they're different from what it has.
+    pub fn update_edits(&mut self, map: &Map) -> Option<MapEdits> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+
+        let mut edits = map.get_edits().clone();
+        let applied = edits
+            .commands
+            .split_off(edits.commands.len() - self.num_applied_cmds);
+        // Remember what things looked like before any temporary edits
+        let mut lane_types: BTreeMap<LaneID, LaneType> = BTreeMap::new();
+        let mut intersections: BTreeMap<IntersectionID, EditIntersection> = BTreeMap::new();
+        for cmd in &applied {
+            match cmd {
+                EditCmd::ChangeLaneType { id, orig_lt, .. } => {
+                    lane_types.entry(*id).or_insert(*orig_lt);
+                }
+                EditCmd::ChangeIntersection { i, old, .. } => {
+                    intersections.entry(*i).or_insert_with(|| old.clone());
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        // Overlapping edits might close the same thing twice; only do it once.
+        let mut cmds = Vec::new();
+        for idx in &self.active {
+            let change = &self.edits[*idx].change;
+            for l in closed_lanes(change, map) {
+                let orig_lt = lane_types
+                    .get(&l)
+                    .cloned()
+                    .unwrap_or(map.get_l(l).lane_type);
+                if orig_lt != LaneType::Construction {
+                    cmds.push(EditCmd::ChangeLaneType {
+                        id: l,
+                        lt: LaneType::Construction,
+                        orig_lt,
+                    });
+                    lane_types.insert(l, LaneType::Construction);
+                }
+            }
+            if let TemporaryChange::CloseIntersection(i) = change {
+                let old = intersections
+                    .get(i)
+                    .cloned()
+                    .unwrap_or_else(|| map.get_i_edit(*i));
+                if old != EditIntersection::Closed {
+                    cmds.push(EditCmd::ChangeIntersection {
+                        i: *i,
+                        new: EditIntersection::Closed,
+                        old,
+                    });
+                    intersections.insert(*i, EditIntersection::Closed);
+                }
+            }
+        }
+
+        if cmds == applied {
+            return None;
+        }
+        self.num_applied_cmds = cmds.len();
+        edits.commands.extend(cmds);
+        Some(edits)
+    }
+
+    // Returns the map's edits without the temporary ones. After the map switches to these, the
+    // temporary edits still in effect are applied again by the next update_edits.
+    pub fn remove_from(&mut self, map: &Map) -> Option<MapEdits> {
+        if self.num_applied_cmds == 0 {
+            return None;
+        }
+        let mut edits = map.get_edits().clone();
+        edits
+            .commands
+            .truncate(edits.commands.len() - self.num_applied_cmds);
+        self.num_applied_cmds = 0;
+        self.dirty = true;
+        Some(edits)
+    }
+}
+
+fn closed_lanes(change: &TemporaryChange, map: &Map) -> Vec<LaneID> {
+    match change {
+        TemporaryChange::CloseRoad(r) => map
+            .get_r(*r)
+            .all_lanes()
+            .into_iter()
+            .filter(|l| {
+                let lane = map.get_l(*l);
+                !lane.is_sidewalk() && !lane.is_light_rail()
+            })
+            .collect(),
+        TemporaryChange::CloseLane(l) => vec![*l],
+        TemporaryChange::CloseIntersection(_) => Vec::new(),
+    }
+}
+
+// Bus stops need some driving or bus lane on their road.
+fn check_bus_stops(closed: Vec<LaneID>, map: &Map) -> Result<(), String> {
+    let roads: BTreeSet<_> = closed.iter().map(|l| map.get_l(*l).parent).collect();
+    for r in roads {
+        let road = map.get_r(r);
+        // Light rail stations stay on the tracks
+        if road
+            .all_bus_stops(map)
+            .into_iter()
+            .all(|bs| map.get_l(map.get_bs(bs).driving_pos.lane()).is_light_rail())
+        {
+            continue;
+        }
+        if !road.all_lanes().into_iter().any(|l| {
+            !closed.contains(&l)
+                && match map.get_l(l).lane_type {
+                    LaneType::Driving | LaneType::Bus => true,
+                    _ => false,
+                }
+        }) {
+            return Err(format!("bus stops on {} need a driving or bus lane", r));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abstutil::Timer;
+    use geom::{Duration, Time};
+
+    fn edit(start_hour: usize, end_hour: usize, change: TemporaryChange) -> TemporaryEdit {
+        TemporaryEdit {
+            start: Time::START_OF_DAY + Duration::hours(start_hour),
+            end: Time::START_OF_DAY + Duration::hours(end_hour),
+            change,
+        }
+    }
+
+    // Switch the map to the edits, if there are any
+    fn apply(map: &mut Map, edits: Option<MapEdits>) -> bool {
+        match edits {
+            Some(edits) => {
+                map.apply_edits(edits, &mut Timer::throwaway());
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[test]
+    fn test_overlapping_edits() {
+        let mut map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        let l = map
+            .all_lanes()
+            .iter()
+            .find(|l| l.lane_type == LaneType::Driving)
+            .unwrap()
+            .id;
+        let r = map.get_l(l).parent;
+        let road_lanes = closed_lanes(&TemporaryChange::CloseRoad(r), &map);
+        assert!(road_lanes.len() > 1);
+
+        let mut state = TemporaryEditsState::new();
+        let mut scheduler = Scheduler::new();
+        state
+            .schedule(
+                edit(1, 3, TemporaryChange::CloseRoad(r)),
+                &map,
+                &mut scheduler,
+            )
+            .unwrap();
+        state
+            .schedule(
+                edit(2, 4, TemporaryChange::CloseLane(l)),
+                &map,
+                &mut scheduler,
+            )
+            .unwrap();
+        assert!(state.has_any());
+        assert!(!apply(&mut map, state.update_edits(&map)));
+
+        // The whole road closes
+        state.start(0);
+        assert!(apply(&mut map, state.update_edits(&map)));
+        for id in &road_lanes {
+            assert_eq!(map.get_l(*id).lane_type, LaneType::Construction);
+        }
+        assert_eq!(map.get_edits().commands.len(), road_lanes.len());
+
+        // The lane is already closed, so the map doesn't need to change
+        state.start(1);
+        assert!(state.is_dirty());
+        assert!(!apply(&mut map, state.update_edits(&map)));
+        assert!(!state.is_dirty());
+
+        // When the road reopens, the lane stays closed, and it remembers what it was originally
+        state.end(0);
+        assert!(apply(&mut map, state.update_edits(&map)));
+        assert_eq!(
+            map.get_edits().commands,
+            vec![EditCmd::ChangeLaneType {
+                id: l,
+                lt: LaneType::Construction,
+                orig_lt: LaneType::Driving,
+            }]
+        );
+        for id in &road_lanes {
+            if *id != l {
+                assert_ne!(map.get_l(*id).lane_type, LaneType::Construction);
+            }
+        }
+
+        // Taking the temporary edits away restores the lane, until the next update
+        assert!(apply(&mut map, state.remove_from(&map)));
+        assert!(map.get_edits().commands.is_empty());
+        assert_eq!(map.get_l(l).lane_type, LaneType::Driving);
+        assert!(!apply(&mut map, state.remove_from(&map)));
+        assert!(apply(&mut map, state.update_edits(&map)));
+        assert_eq!(map.get_l(l).lane_type, LaneType::Construction);
+
+        state.end(1);
+        assert!(apply(&mut map, state.update_edits(&map)));
+        assert!(map.get_edits().commands.is_empty());
+        assert_eq!(map.get_l(l).lane_type, LaneType::Driving);
+    }
+
+    #[test]
+    fn test_bad_edits() {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        let border = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_border())
+            .unwrap()
+            .id;
+        let sidewalk = map.all_lanes().iter().find(|l| l.is_sidewalk()).unwrap().id;
+        let signal = map
+            .all_intersections()
+            .iter()
+            .find(|i| i.is_traffic_signal())
+            .unwrap()
+            .id;
+
+        let mut state = TemporaryEditsState::new();
+        let mut scheduler = Scheduler::new();
+        for bad in vec![
+            edit(2, 1, TemporaryChange::CloseIntersection(signal)),
+            edit(1, 2, TemporaryChange::CloseIntersection(border)),
+            edit(1, 2, TemporaryChange::CloseLane(sidewalk)),
+        ] {
+            assert!(state.schedule(bad, &map, &mut scheduler).is_err());
+        }
+        assert!(!state.has_any());
+    }
+}
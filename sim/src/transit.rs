@@ -1,11 +1,11 @@
 use crate::{
-    CarID, Event, PedestrianID, PersonID, Router, Scheduler, TripID, TripManager, TripPhaseType,
-    WalkingSimState,
+    AlertLocation, CarID, Event, PedestrianID, PersonID, Router, Scheduler, TripID, TripManager,
+    TripPhaseType, WalkingSimState, BUS_CAPACITY, TRAIN_CAPACITY,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Time};
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BusRoute, BusRouteID, BusStopID, Map, Path, PathConstraints, PathRequest, Position,
+    BusRoute, BusRouteID, BusStopID, Map, Path, PathConstraints, PathRequest, PathStep, Position,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -18,14 +18,19 @@ struct StopForRoute {
     id: BusStopID,
     driving_pos: Position,
     req: PathRequest,
-    path_to_next_stop: Path,
+    // None if live map edits cut this stop off from the next one. Buses end their run here.
+    path_to_next_stop: Option<Path>,
     next_stop_idx: StopIdx,
+    // How long after leaving the first stop a scheduled bus should get here
+    scheduled_offset: Duration,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 struct Route {
     stops: Vec<StopForRoute>,
     buses: Vec<CarID>,
+    // How many passengers fit on each bus
+    capacity: usize,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -35,6 +40,16 @@ struct Bus {
     // Where does each passenger want to deboard?
     passengers: Vec<(PersonID, BusStopID)>,
     state: BusState,
+    // Buses following a timetable run through some of the stops once, then vanish. Others loop
+    // forever.
+    schedule: Option<Schedule>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+struct Schedule {
+    departure: Time,
+    first_stop: StopIdx,
+    last_stop: StopIdx,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -81,12 +96,19 @@ impl TransitSimState {
     pub fn create_empty_route(
         &mut self,
         bus_route: &BusRoute,
+        capacity: Option<usize>,
+        now: Time,
         map: &Map,
     ) -> Vec<(StopIdx, PathRequest, Path, Distance)> {
         assert!(bus_route.stops.len() > 1);
 
         let route = Route {
             buses: Vec::new(),
+            capacity: capacity.unwrap_or(if bus_route.route_type == PathConstraints::Train {
+                TRAIN_CAPACITY
+            } else {
+                BUS_CAPACITY
+            }),
             stops: bus_route
                 .stops
                 .iter()
@@ -101,9 +123,9 @@ impl TransitSimState {
                     let req = PathRequest {
                         start: stop1.driving_pos,
                         end: map.get_bs(bus_route.stops[stop2_idx]).driving_pos,
-                        constraints: PathConstraints::Bus,
+                        constraints: bus_route.route_type,
                     };
-                    let path = map.pathfind(req.clone()).expect(&format!(
+                    let path = map.pathfind(req.clone(), now).expect(&format!(
                         "No route between bus stops {:?} and {:?}",
                         stop1_id, bus_route.stops[stop2_idx]
                     ));
@@ -111,8 +133,13 @@ impl TransitSimState {
                         id: *stop1_id,
                         driving_pos: stop1.driving_pos,
                         req,
-                        path_to_next_stop: path,
+                        path_to_next_stop: Some(path),
                         next_stop_idx: stop2_idx,
+                        scheduled_offset: bus_route
+                            .stop_offsets
+                            .get(idx)
+                            .cloned()
+                            .unwrap_or(Duration::ZERO),
                     }
                 })
                 .collect(),
@@ -125,7 +152,7 @@ impl TransitSimState {
                 (
                     s.next_stop_idx,
                     s.req.clone(),
-                    s.path_to_next_stop.clone(),
+                    s.path_to_next_stop.clone().unwrap(),
                     route.stops[s.next_stop_idx].driving_pos.dist_along(),
                 )
             })
@@ -134,7 +161,62 @@ impl TransitSimState {
         stops
     }
 
-    pub fn bus_created(&mut self, bus: CarID, route: BusRouteID, next_stop_idx: StopIdx) {
+    // A scheduled bus starts somewhere before the first stop of its run. Returns the request, path,
+    // and end distance for the bus to get there, starting as close to the stop as possible.
+    pub fn path_to_first_stop(
+        &self,
+        route: BusRouteID,
+        first_stop_idx: StopIdx,
+        vehicle_length: Distance,
+        departure: Time,
+        map: &Map,
+    ) -> Option<(PathRequest, Path, Distance)> {
+        let stops = &self.routes[&route].stops;
+        let first_stop = stops[first_stop_idx].driving_pos;
+        // The path from the previous stop leads there. For the very first stop, that's the path
+        // looping around from the last stop.
+        let prev_idx = if first_stop_idx == 0 {
+            stops.len() - 1
+        } else {
+            first_stop_idx - 1
+        };
+        for step in stops[prev_idx]
+            .path_to_next_stop
+            .as_ref()?
+            .get_steps()
+            .iter()
+            .rev()
+        {
+            let l = match step {
+                PathStep::Lane(l) => *l,
+                _ => continue,
+            };
+            let room = if l == first_stop.lane() {
+                first_stop.dist_along()
+            } else {
+                map.get_l(l).length()
+            };
+            if room > vehicle_length {
+                let req = PathRequest {
+                    start: Position::new(l, vehicle_length),
+                    end: first_stop,
+                    constraints: map.get_br(route).route_type,
+                };
+                let path = map.pathfind(req.clone(), departure)?;
+                return Some((req, path, first_stop.dist_along()));
+            }
+        }
+        None
+    }
+
+    // A bus following a timetable passes in (departure, first stop, last stop) of its run.
+    pub fn bus_created(
+        &mut self,
+        bus: CarID,
+        route: BusRouteID,
+        next_stop_idx: StopIdx,
+        schedule: Option<(Time, StopIdx, StopIdx)>,
+    ) {
         self.routes.get_mut(&route).unwrap().buses.push(bus);
         self.buses.insert(
             bus,
@@ -143,10 +225,16 @@ impl TransitSimState {
                 route,
                 passengers: Vec::new(),
                 state: BusState::DrivingToStop(next_stop_idx),
+                schedule: schedule.map(|(departure, first_stop, last_stop)| Schedule {
+                    departure,
+                    first_stop,
+                    last_stop,
+                }),
             },
         );
     }
 
+    // Returns false if the bus has finished its scheduled run and should vanish.
     pub fn bus_arrived_at_stop(
         &mut self,
         now: Time,
@@ -155,32 +243,63 @@ impl TransitSimState {
         walking: &mut WalkingSimState,
         scheduler: &mut Scheduler,
         map: &Map,
-    ) {
+    ) -> bool {
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
                 bus.state = BusState::AtStop(stop_idx);
-                let stop1 = self.routes[&bus.route].stops[stop_idx].id;
+                let route = &self.routes[&bus.route];
+                let stop1 = route.stops[stop_idx].id;
+                let lateness = bus.schedule.as_ref().map(|s| {
+                    now - (s.departure + route.stops[stop_idx].scheduled_offset
+                        - route.stops[s.first_stop].scheduled_offset)
+                });
                 self.events
-                    .push(Event::BusArrivedAtStop(id, bus.route, stop1));
+                    .push(Event::BusArrivedAtStop(id, bus.route, stop1, lateness));
 
                 // Deboard existing passengers.
                 let mut still_riding = Vec::new();
                 for (person, stop2) in bus.passengers.drain(..) {
                     if stop1 == stop2 {
-                        trips.person_left_bus(now, person, bus.car, map, scheduler);
+                        trips.person_left_bus(now, person, bus.car, stop1, map, scheduler);
                     } else {
                         still_riding.push((person, stop2));
                     }
                 }
                 bus.passengers = still_riding;
 
-                // Board new passengers.
+                if bus
+                    .schedule
+                    .as_ref()
+                    .map(|s| s.last_stop == stop_idx)
+                    .unwrap_or(false)
+                    || route.stops[stop_idx].path_to_next_stop.is_none()
+                {
+                    // The run is over, so anybody still riding has to get off here
+                    for (person, _) in bus.passengers.drain(..) {
+                        trips.person_left_bus(now, person, bus.car, stop1, map, scheduler);
+                    }
+                    self.routes
+                        .get_mut(&bus.route)
+                        .unwrap()
+                        .buses
+                        .retain(|b| *b != id);
+                    self.buses.remove(&id);
+                    return false;
+                }
+
+                // Board new passengers, until the bus is full.
+                let capacity = route.capacity;
                 let mut still_waiting = Vec::new();
                 for (ped, route, stop2, started_waiting) in
                     self.peds_waiting.remove(&stop1).unwrap_or_else(Vec::new)
                 {
-                    if bus.route == route {
+                    let wants_this_bus = bus.route == route
+                        && goes_to(&self.routes[&route], &bus.schedule, stop_idx, stop2);
+                    if wants_this_bus && bus.passengers.len() >= capacity {
+                        self.events.push(Event::BusPassedUpRider(id, route, stop1));
+                        still_waiting.push((ped, route, stop2, started_waiting));
+                    } else if wants_this_bus {
                         let (trip, person) = trips.ped_boarded_bus(
                             now,
                             ped,
@@ -194,7 +313,7 @@ impl TransitSimState {
                             Some(PathRequest {
                                 start: map.get_bs(stop1).driving_pos,
                                 end: map.get_bs(stop2).driving_pos,
-                                constraints: PathConstraints::Bus,
+                                constraints: map.get_br(route).route_type,
                             }),
                             TripPhaseType::RidingBus(route, stop1, bus.car),
                         ));
@@ -204,9 +323,10 @@ impl TransitSimState {
                     }
                 }
                 self.peds_waiting.insert(stop1, still_waiting);
+                true
             }
             BusState::AtStop(_) => unreachable!(),
-        };
+        }
     }
 
     pub fn bus_departed_from_stop(&mut self, id: CarID) -> Router {
@@ -218,10 +338,14 @@ impl TransitSimState {
                 let stop = &route.stops[stop_idx];
 
                 bus.state = BusState::DrivingToStop(stop.next_stop_idx);
-                self.events
-                    .push(Event::BusDepartedFromStop(id, bus.route, stop.id));
+                self.events.push(Event::BusDepartedFromStop(
+                    id,
+                    bus.route,
+                    stop.id,
+                    bus.passengers.len(),
+                ));
                 Router::follow_bus_route(
-                    stop.path_to_next_stop.clone(),
+                    stop.path_to_next_stop.clone().unwrap(),
                     route.stops[stop.next_stop_idx].driving_pos.dist_along(),
                 )
             }
@@ -244,7 +368,14 @@ impl TransitSimState {
         if let Some(route) = self.routes.get(&route_id) {
             for bus in &route.buses {
                 if let BusState::AtStop(idx) = self.buses[bus].state {
-                    if route.stops[idx].id == stop1 {
+                    if route.stops[idx].id == stop1
+                        && goes_to(route, &self.buses[bus].schedule, idx, stop2)
+                    {
+                        if self.buses[bus].passengers.len() >= route.capacity {
+                            self.events
+                                .push(Event::BusPassedUpRider(*bus, route_id, stop1));
+                            continue;
+                        }
                         self.buses
                             .get_mut(bus)
                             .unwrap()
@@ -256,7 +387,7 @@ impl TransitSimState {
                             Some(PathRequest {
                                 start: map.get_bs(stop1).driving_pos,
                                 end: map.get_bs(stop2).driving_pos,
-                                constraints: PathConstraints::Bus,
+                                constraints: map.get_br(route_id).route_type,
                             }),
                             TripPhaseType::RidingBus(route_id, stop1, *bus),
                         ));
@@ -278,16 +409,69 @@ impl TransitSimState {
         None
     }
 
+    // After live map edits, find new paths between stops that use something that's gone. If
+    // there's no way to the next stop anymore, that segment of the route is disabled until later
+    // edits fix it. Returns buses waiting at a stop that can't be left; the caller removes them.
+    // Buses already driving are handled separately.
+    pub fn handle_live_edits(&mut self, now: Time, map: &Map) -> Vec<CarID> {
+        let mut stuck = Vec::new();
+        for (id, route) in self.routes.iter_mut() {
+            for (idx, stop) in route.stops.iter_mut().enumerate() {
+                if let Some(ref path) = stop.path_to_next_stop {
+                    if path.find_broken_step(stop.req.constraints, map).is_none() {
+                        continue;
+                    }
+                }
+                let was_disabled = stop.path_to_next_stop.is_none();
+                stop.path_to_next_stop = map.pathfind(stop.req.clone(), now);
+                if stop.path_to_next_stop.is_some() {
+                    continue;
+                }
+                if !was_disabled {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Nil,
+                        format!(
+                            "After map edits, {} can't get from {} to the next stop",
+                            id, stop.id
+                        ),
+                    ));
+                }
+                for bus in &route.buses {
+                    if self.buses[bus].state == BusState::AtStop(idx) {
+                        stuck.push(*bus);
+                    }
+                }
+            }
+        }
+        stuck
+    }
+
+    // The bus was removed mid-route by live map edits. Returns the passengers still aboard.
+    pub fn bus_evacuated(&mut self, id: CarID) -> Vec<PersonID> {
+        let bus = self.buses.remove(&id).unwrap();
+        self.routes
+            .get_mut(&bus.route)
+            .unwrap()
+            .buses
+            .retain(|b| *b != id);
+        bus.passengers.into_iter().map(|(p, _)| p).collect()
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         self.events.drain(..).collect()
     }
 
+    pub fn capacity(&self, route: BusRouteID) -> Option<usize> {
+        self.routes.get(&route).map(|r| r.capacity)
+    }
+
     pub fn get_passengers(&self, bus: CarID) -> &Vec<(PersonID, BusStopID)> {
         &self.buses[&bus].passengers
     }
 
-    pub fn bus_route(&self, bus: CarID) -> BusRouteID {
-        self.buses[&bus].route
+    // None if the bus has finished its scheduled run
+    pub fn bus_route(&self, bus: CarID) -> Option<BusRouteID> {
+        self.buses.get(&bus).map(|b| b.route)
     }
 
     // also stop idx
@@ -314,3 +498,178 @@ impl TransitSimState {
         }
     }
 }
+
+// Will a bus at some stop reach stop2 before finishing its run? A run also ends early at a stop
+// cut off from the next one.
+fn goes_to(
+    route: &Route,
+    schedule: &Option<Schedule>,
+    stop_idx: StopIdx,
+    stop2: BusStopID,
+) -> bool {
+    let mut idx = stop_idx;
+    loop {
+        if route.stops[idx].path_to_next_stop.is_none()
+            || schedule
+                .as_ref()
+                .map(|s| s.last_stop == idx)
+                .unwrap_or(false)
+        {
+            return false;
+        }
+        idx = route.stops[idx].next_stop_idx;
+        if route.stops[idx].id == stop2 {
+            return true;
+        }
+        if idx == stop_idx {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VehicleType;
+    use abstutil::Timer;
+    use map_model::LaneType;
+
+    // A looping route with two stops on the same lane and one bus waiting at the first stop,
+    // carrying somebody to the second.
+    fn two_stop_route(map: &Map) -> (TransitSimState, BusRouteID, CarID, BusStopID, BusStopID) {
+        let lane = map
+            .all_lanes()
+            .iter()
+            .find(|l| l.lane_type == LaneType::Driving)
+            .unwrap();
+        let req = PathRequest {
+            start: Position::new(lane.id, Distance::ZERO),
+            end: Position::new(lane.id, lane.length() / 2.0),
+            constraints: PathConstraints::Bus,
+        };
+        let path = map.pathfind(req.clone(), Time::START_OF_DAY).unwrap();
+        // The stops are never looked up in the map
+        let stop1 = BusStopID {
+            sidewalk: lane.id,
+            idx: 0,
+        };
+        let stop2 = BusStopID {
+            sidewalk: lane.id,
+            idx: 1,
+        };
+        let route = BusRouteID(0);
+        let bus = CarID(0, VehicleType::Bus);
+
+        let mut transit = TransitSimState::new();
+        transit.routes.insert(
+            route,
+            Route {
+                stops: vec![
+                    StopForRoute {
+                        id: stop1,
+                        driving_pos: req.start,
+                        req: req.clone(),
+                        path_to_next_stop: Some(path.clone()),
+                        next_stop_idx: 1,
+                        scheduled_offset: Duration::ZERO,
+                    },
+                    StopForRoute {
+                        id: stop2,
+                        driving_pos: req.end,
+                        req: req.clone(),
+                        path_to_next_stop: Some(path),
+                        next_stop_idx: 0,
+                        scheduled_offset: Duration::ZERO,
+                    },
+                ],
+                buses: vec![bus],
+                capacity: 1,
+            },
+        );
+        transit.buses.insert(
+            bus,
+            Bus {
+                car: bus,
+                route,
+                passengers: vec![(PersonID(0), stop2)],
+                state: BusState::AtStop(0),
+                schedule: None,
+            },
+        );
+        (transit, route, bus, stop1, stop2)
+    }
+
+    #[test]
+    fn test_full_bus_leaves_riders_waiting() {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        let (mut transit, route, bus, stop1, stop2) = two_stop_route(&map);
+
+        let boarded = transit.ped_waiting_for_bus(
+            Time::START_OF_DAY,
+            PedestrianID(1),
+            TripID(1),
+            PersonID(1),
+            stop1,
+            route,
+            stop2,
+            &map,
+        );
+        assert_eq!(boarded, None);
+        assert_eq!(transit.get_passengers(bus).len(), 1);
+        assert_eq!(
+            transit.peds_waiting[&stop1],
+            vec![(PedestrianID(1), route, stop2, Time::START_OF_DAY)]
+        );
+        assert_eq!(
+            transit.collect_events(),
+            vec![Event::BusPassedUpRider(bus, route, stop1)]
+        );
+    }
+    #[test]
+    fn test_disabled_segment_ends_run() {
+        let map = Map::new(
+            abstutil::path_synthetic_map("signal_single"),
+            &mut Timer::throwaway(),
+        );
+        let (mut transit, route, bus, stop1, stop2) = two_stop_route(&map);
+        // Pretend live edits cut the first stop off from the second
+        transit.routes.get_mut(&route).unwrap().stops[0].path_to_next_stop = None;
+        transit.routes.get_mut(&route).unwrap().capacity = 2;
+
+        // The bus won't go anywhere, so nobody should board it
+        let boarded = transit.ped_waiting_for_bus(
+            Time::START_OF_DAY,
+            PedestrianID(1),
+            TripID(1),
+            PersonID(1),
+            stop1,
+            route,
+            stop2,
+            &map,
+        );
+        assert_eq!(boarded, None);
+        assert_eq!(transit.get_passengers(bus).len(), 1);
+        assert!(transit.collect_events().is_empty());
+
+        // Once the stops are connected again, the segment comes back and the bus can stay
+        assert!(transit
+            .handle_live_edits(Time::START_OF_DAY, &map)
+            .is_empty());
+        assert!(transit.routes[&route].stops[0].path_to_next_stop.is_some());
+        assert!(transit.collect_events().is_empty());
+        let boarded = transit.ped_waiting_for_bus(
+            Time::START_OF_DAY,
+            PedestrianID(2),
+            TripID(2),
+            PersonID(2),
+            stop1,
+            route,
+            stop2,
+            &map,
+        );
+        assert_eq!(boarded, Some(bus));
+    }
+}
@@ -269,7 +269,7 @@ impl TripManager {
             end,
             constraints: PathConstraints::Car,
         };
-        let path = if let Some(p) = map.pathfind(req.clone()) {
+        let path = if let Some(p) = map.pathfind(req.clone(), now) {
             p
         } else {
             self.events.push(Event::Alert(
@@ -339,7 +339,7 @@ impl TripManager {
             constraints: PathConstraints::Bike,
         };
         if let Some(router) = map
-            .pathfind(req.clone())
+            .pathfind(req.clone(), now)
             .and_then(|path| drive_to.make_router(path, map, VehicleType::Bike))
         {
             scheduler.push(
@@ -521,6 +521,8 @@ impl TripManager {
         now: Time,
         person: PersonID,
         bus: CarID,
+        // Usually where the rider wanted to go, unless the bus finished its run first
+        stop: BusStopID,
         map: &Map,
         scheduler: &mut Scheduler,
     ) {
@@ -529,10 +531,11 @@ impl TripManager {
             .remove(&AgentID::BusPassenger(person, bus))
             .unwrap()
             .0];
-        let start = match trip.legs.pop_front().unwrap() {
-            TripLeg::RideBus(_, stop) => SidewalkSpot::bus_stop(stop, map),
+        match trip.legs.pop_front().unwrap() {
+            TripLeg::RideBus(_, _) => {}
             _ => unreachable!(),
-        };
+        }
+        let start = SidewalkSpot::bus_stop(stop, map);
         self.people[person.0].on_bus.take().unwrap();
 
         if !trip.spawn_ped(
@@ -676,6 +679,24 @@ impl TripManager {
         self.events.push(Event::TripAborted(trip.id));
     }
 
+    // The agent was removed mid-trip, because live map edits left it no way to continue.
+    pub fn agent_evacuated(
+        &mut self,
+        now: Time,
+        agent: AgentID,
+        abandoned_vehicle: Option<Vehicle>,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) {
+        if let Some(trip) = self.active_trip_mode.remove(&agent) {
+            if let AgentID::BusPassenger(person, _) = agent {
+                self.people[person.0].on_bus = None;
+            }
+            self.abort_trip(now, trip, abandoned_vehicle, parking, scheduler, map);
+        }
+    }
+
     pub fn abort_trip(
         &mut self,
         now: Time,
@@ -928,7 +949,7 @@ impl TripManager {
     ) {
         assert!(!self.trips[trip.0].aborted);
         if !self.pathfinding_upfront && maybe_path.is_none() && maybe_req.is_some() {
-            maybe_path = map.pathfind(maybe_req.clone().unwrap());
+            maybe_path = map.pathfind(maybe_req.clone().unwrap(), now);
         }
 
         let person = &mut self.people[self.trips[trip.0].person.0];
@@ -1026,7 +1047,7 @@ impl TripManager {
                         end: walking_goal.sidewalk_pos,
                         constraints: PathConstraints::Pedestrian,
                     };
-                    if let Some(path) = map.pathfind(req.clone()) {
+                    if let Some(path) = map.pathfind(req.clone(), now) {
                         scheduler.push(
                             now,
                             Command::SpawnPed(CreatePedestrian {
@@ -1178,7 +1199,7 @@ impl TripManager {
                     self.abort_trip(now, trip, None, parking, scheduler, map);
                 }
             }
-            TripSpec::UsingTransit { start, stop1, .. } => {
+            TripSpec::UsingTransit { start, rides, .. } => {
                 assert_eq!(
                     person.state,
                     match start.connection {
@@ -1208,7 +1229,7 @@ impl TripManager {
                 );
                 person.state = PersonState::Trip(trip);
 
-                let walk_to = SidewalkSpot::bus_stop(stop1, map);
+                let walk_to = SidewalkSpot::bus_stop(rides[0].1, map);
                 let req = maybe_req.unwrap();
                 if let Some(path) = maybe_path {
                     scheduler.push(
@@ -1287,7 +1308,7 @@ impl Trip {
             end: walk_to.sidewalk_pos,
             constraints: PathConstraints::Pedestrian,
         };
-        let path = if let Some(p) = map.pathfind(req.clone()) {
+        let path = if let Some(p) = map.pathfind(req.clone(), now) {
             p
         } else {
             events.push(Event::Alert(
@@ -1362,7 +1383,7 @@ impl TripMode {
                 VehicleType::Car => TripMode::Drive,
                 VehicleType::Bike => TripMode::Bike,
                 // TODO Little confusing; this means buses, not bus riders.
-                VehicleType::Bus => TripMode::Transit,
+                VehicleType::Bus | VehicleType::Train => TripMode::Transit,
             },
             // TODO Now we can detangle this, right?
             AgentID::BusPassenger(_, _) => TripMode::Transit,
@@ -1466,34 +1487,37 @@ pub enum PersonState {
 }
 
 impl TripEndpoint {
-    pub(crate) fn start_sidewalk_spot(&self, map: &Map) -> SidewalkSpot {
+    pub(crate) fn start_sidewalk_spot(&self, map: &Map) -> Option<SidewalkSpot> {
         match self {
-            TripEndpoint::Bldg(b) => SidewalkSpot::building(*b, map),
+            TripEndpoint::Bldg(b) => Some(SidewalkSpot::building(*b, map)),
             TripEndpoint::Border(i, origin) => {
-                SidewalkSpot::start_at_border(*i, origin.clone(), map).unwrap()
+                SidewalkSpot::start_at_border(*i, origin.clone(), map)
             }
         }
     }
 
-    pub(crate) fn end_sidewalk_spot(&self, map: &Map) -> SidewalkSpot {
+    pub(crate) fn end_sidewalk_spot(&self, map: &Map) -> Option<SidewalkSpot> {
         match self {
-            TripEndpoint::Bldg(b) => SidewalkSpot::building(*b, map),
+            TripEndpoint::Bldg(b) => Some(SidewalkSpot::building(*b, map)),
             TripEndpoint::Border(i, destination) => {
-                SidewalkSpot::end_at_border(*i, destination.clone(), map).unwrap()
+                SidewalkSpot::end_at_border(*i, destination.clone(), map)
             }
         }
     }
 
-    pub(crate) fn driving_goal(&self, constraints: PathConstraints, map: &Map) -> DrivingGoal {
+    pub(crate) fn driving_goal(
+        &self,
+        constraints: PathConstraints,
+        map: &Map,
+    ) -> Option<DrivingGoal> {
         match self {
-            TripEndpoint::Bldg(b) => DrivingGoal::ParkNear(*b),
+            TripEndpoint::Bldg(b) => Some(DrivingGoal::ParkNear(*b)),
             TripEndpoint::Border(i, destination) => DrivingGoal::end_at_border(
-                map.get_i(*i).some_incoming_road(map).unwrap(),
+                map.get_i(*i).some_incoming_road(map)?,
                 constraints,
                 destination.clone(),
                 map,
-            )
-            .unwrap(),
+            ),
         }
     }
 }
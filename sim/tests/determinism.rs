@@ -0,0 +1,39 @@
+use abstutil::Timer;
+use geom::{Duration, Time};
+use map_model::Map;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use sim::{AlertHandler, ScenarioGenerator, Sim, SimOptions};
+
+// Running the same scenario twice should produce exactly the same simulation. Iterating over a
+// HashMap or depending on thread timing breaks this in subtle ways, so check every synthetic map.
+#[test]
+fn same_scenario_runs_the_same_way() {
+    let mut timer = Timer::throwaway();
+    for name in abstutil::list_all_objects(abstutil::path_all_synthetic_maps()) {
+        let map = Map::new(abstutil::path_synthetic_map(&name), &mut timer);
+        let mut sim1 = new_sim(&map, &name, &mut timer);
+        let mut sim2 = new_sim(&map, &name, &mut timer);
+
+        let checkpoint = Duration::minutes(5);
+        let end = Time::START_OF_DAY + Duration::hours(1);
+        while sim1.time() < end && !sim1.is_done() {
+            sim1.timed_step(&map, checkpoint, &mut None, &mut timer);
+            sim2.timed_step(&map, checkpoint, &mut None, &mut timer);
+            if let Some(diff) = sim1.find_divergence(&sim2) {
+                panic!("{} diverged by {}: {}", name, sim1.time(), diff);
+            }
+        }
+    }
+}
+
+fn new_sim(map: &Map, name: &str, timer: &mut Timer) -> Sim {
+    let mut rng = XorShiftRng::from_seed([42; 16]);
+    let scenario = ScenarioGenerator::small_run(map).generate(map, &mut rng, timer);
+
+    let mut opts = SimOptions::new(&format!("determinism_{}", name));
+    opts.alerts = AlertHandler::Silence;
+    let mut sim = Sim::new(map, opts, timer);
+    scenario.instantiate(&mut sim, map, &mut rng, timer);
+    sim
+}